@@ -0,0 +1,13801 @@
+// Basecalc: Your Towel in the Mathematical Cosmos
+// Copyright (C) 2024 Nick Spiker
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The basecalc engine: tokenizing, evaluating and formatting numbers in any base.
+//!
+//! The `basecalc` binary is a terminal front end over this crate. To embed the
+//! calculator in another program, use [`Calculator`].
+
+// AI notes:
+// 1. Key Pain Points Observed:
+// - Constantly needing to copy/paste long number strings
+// - Easy to make transcription errors
+// - Can't easily verify steps against expected values
+// - No way to track transformations systematically
+//
+// 2. Features Needed:
+// - Variables/stack for intermediate values
+// - Way to mark verification points/assertions
+// - Record/replay sequences of operations
+// - Step-by-step comparison with reference implementations
+// - Debug mode to show precision loss at each step, encoding patterns and set precision/base
+//
+// 3. Specific Additions:
+// - Store/name intermediate calculations, present formatted command and output to user
+// - Compare results within epsilon
+// - Track precision loss through operation chains
+// - Save/load common transformation sequences
+// - Built-in coordinate system transforms, int/float conversions, etc.
+// - All common operators should be defined and integrated
+//
+// 4. Syntax Suggestions:
+// - Store value: -> var_name
+// - Assert within epsilon: =~ expected_value
+// - Show precision loss: .precision
+// - Save sequence: .save transform_name
+
+use az::Cast;
+use chrono::Datelike;
+use colored::*;
+use dirs;
+use rug::ops::*;
+use rug::*;
+use std::fs;
+use std::io::Write;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use vsf::vsf::*;
+
+/// Where everything basecalc keeps lives, `BASECALC_STATE_DIR` if that is set and
+/// `basecalc` in the config directory otherwise
+fn get_basecalc_dir() -> PathBuf {
+    match std::env::var_os("BASECALC_STATE_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+            path.push("basecalc");
+            path
+        }
+    }
+}
+/// Where the state lives, `state.vsf` in the basecalc directory
+fn get_state_file_path() -> PathBuf {
+    let mut path = get_basecalc_dir();
+    // A read-only directory only matters once saving fails, which is reported then
+    fs::create_dir_all(&path).ok();
+    path.push("state.vsf");
+    path
+}
+/// Where the prompt keeps the entry being typed, beside the state file
+pub fn get_draft_file_path() -> PathBuf {
+    get_state_file_path().with_file_name("draft.txt")
+}
+fn get_workspace_dir() -> PathBuf {
+    let mut path = get_basecalc_dir();
+    path.push("workspaces");
+    path
+}
+/// Where a workspace saved by `:workspace save` lives, `<name>.vsf` in the
+/// `workspaces` folder of the basecalc directory
+fn workspace_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid workspace name '{}'!", name));
+    }
+    let mut path = get_workspace_dir();
+    path.push(format!("{}.vsf", name));
+    Ok(path)
+}
+pub fn save_state(state: &BasecalcState) -> std::io::Result<()> {
+    if state.newer_file {
+        return Ok(());
+    }
+    save_state_to(state, get_state_file_path())
+}
+/// Writes the state to a VSF file, by way of a temporary file so a crash can't
+/// leave half of one behind
+fn save_state_to(state: &BasecalcState, path: PathBuf) -> std::io::Result<()> {
+    let temp_path = path.with_extension("vsf-");
+
+    let vsf_data = create_vsf_data(state)?;
+
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(&vsf_data)?;
+    file.sync_all()?;
+
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+/// Loads the saved state, if there is one
+///
+/// A state file that can't be parsed is renamed aside rather than overwritten by the
+/// next save, and as much of its history as can still be read is kept, along with
+/// the defaults for everything else. One written by a newer basecalc is left where it
+/// is, and the defaults used in its place are never saved over it. Either way the
+/// user is told on stderr.
+pub fn load_state() -> Option<BasecalcState> {
+    let path = get_state_file_path();
+    debug_println(&mut format!("Attempting to load state from: {:?}", path));
+
+    if path.exists() {
+        match fs::read(&path) {
+            Ok(data) => {
+                debug_println("File read successfully");
+                match read_vsf(&data) {
+                    Ok(state) => {
+                        // Update the DEBUG atomic boolean
+                        DEBUG.store(state.debug, Ordering::Relaxed);
+                        debug_println(&format!("Debug mode set to: {}", state.debug));
+
+                        debug_println("State parsed successfully");
+                        Some(state)
+                    }
+                    Err(e) if e.kind() == ErrorKind::Unsupported => {
+                        eprintln!("{}", e);
+                        eprintln!(
+                            "The state file is left as it is, and this session starts with default settings that won't be saved."
+                        );
+                        let mut state = BasecalcState::new();
+                        state.newer_file = true;
+                        Some(state)
+                    }
+                    Err(e) => {
+                        eprintln!("Error parsing state file: {}", e);
+                        Some(recover_state(&data, path))
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Error reading state file: {}", e);
+                None
+            }
+        }
+    } else {
+        debug_println("State file does not exist");
+        None
+    }
+}
+/// Starts afresh from a state file that won't parse, keeping what history it can
+fn recover_state(data: &[u8], path: PathBuf) -> BasecalcState {
+    let mut state = BasecalcState::new();
+    state.history = salvage_history(data);
+    state.prune_history();
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let aside = path.with_file_name(format!("state-{}.corrupt", stamp));
+    match fs::rename(&path, &aside) {
+        Ok(()) => eprintln!("The unreadable file was moved to {}.", aside.display()),
+        Err(e) => eprintln!("The unreadable file couldn't be moved aside: {}", e),
+    }
+    // Saved straight away, so what was recovered survives a run that never saves
+    if let Err(e) = save_state_to(&state, path) {
+        eprintln!("Failed to save state: {}", e);
+    }
+    eprintln!(
+        "Starting with default settings and {} recovered history entr{}.",
+        state.history.len(),
+        if state.history.len() == 1 { "y" } else { "ies" }
+    );
+    state
+}
+/// Reads what it can of the history block of a damaged state file
+///
+/// The `history` label is looked for by its bytes rather than by walking the header,
+/// which may be what's broken, and entries are read until one fails, so a file cut
+/// short still gives up everything before the cut.
+fn salvage_history(data: &[u8]) -> Vec<String> {
+    let Ok(mut label) = VsfType::d("history".to_string()).flatten() else {
+        return Vec::new();
+    };
+    label.push(b':');
+    let Some(start) = data.windows(label.len()).position(|window| window == label) else {
+        return Vec::new();
+    };
+    without_panics(|| {
+        let mut pointer = start + label.len();
+        let mut offset = None;
+        let mut count = None;
+        while data.get(pointer).is_some_and(|&byte| byte != b')') {
+            match parse(data, &mut pointer)? {
+                VsfType::o(o) => offset = Some(o / 8),
+                VsfType::c(c) => count = Some(c),
+                _ => {}
+            }
+        }
+        Ok((offset, count))
+    })
+    .ok()
+    .and_then(|(offset, count)| Some((offset?, count?)))
+    .map(|(mut pointer, count)| {
+        let mut history = Vec::new();
+        while history.len() < count {
+            match without_panics(|| parse(data, &mut pointer)) {
+                Ok(VsfType::x(entry)) if entry.ends_with('\n') => {
+                    history.push(entry[..entry.len() - 1].to_string())
+                }
+                _ => break,
+            }
+        }
+        history
+    })
+    .unwrap_or_default()
+}
+/// Parses a whole state file, treating a read past its end as the damage it is
+fn read_vsf(data: &[u8]) -> Result<BasecalcState, Error> {
+    without_panics(|| parse_vsf(data, &mut 0))
+}
+thread_local! {
+    /// Set while this thread parses possibly damaged data, whose panics aren't printed
+    static QUIET_PANICS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+/// Runs a parse over possibly damaged data, where a bad length can send an index
+/// past the end, turning any panic into an error without printing it
+///
+/// The panic hook is wrapped once, the first time through, rather than swapped for
+/// each parse, so a panic on another thread meanwhile is still reported.
+fn without_panics<T>(run: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    static QUIET_HOOK: std::sync::Once = std::sync::Once::new();
+    QUIET_HOOK.call_once(|| {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !QUIET_PANICS.with(|quiet| quiet.get()) {
+                hook(info);
+            }
+        }));
+    });
+    QUIET_PANICS.with(|quiet| quiet.set(true));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(run));
+    QUIET_PANICS.with(|quiet| quiet.set(false));
+    result.unwrap_or_else(|_| Err(Error::new(ErrorKind::InvalidData, "Unexpected end of data")))
+}
+fn parse_vsf(data: &[u8], pointer: &mut usize) -> Result<BasecalcState, std::io::Error> {
+    debug_println(&format!("Starting VSF parsing"));
+
+    // Check magic number
+    if data.len() < 4 || &data[0..3] != b"R\xC3\x85" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Magic number does not match 'RÅ' at decimal offset {} bytes",
+                *pointer
+            ),
+        ));
+    }
+    *pointer = 3;
+    debug_println(&format!("Magic number 'RÅ' verified"));
+
+    // Check for opening angle bracket
+    if data[*pointer] != b'<' {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected header opening '<' after magic number at decimal offset {} bytes",
+                *pointer
+            ),
+        ));
+    }
+    *pointer += 1;
+    debug_println(&format!("Opening angle bracket '<' found"));
+
+    // Parse header length
+    let header_length = parse(data, pointer)?;
+    let header_length_bytes;
+    if let VsfType::b(length) = header_length {
+        if length % 8 != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Header length is not a multiple of 8 at decimal offset {} bytes",
+                    *pointer
+                ),
+            ));
+        }
+        header_length_bytes = length / 8;
+        debug_println(&format!(
+            "Header length: {} bits ({} bytes)",
+            length, header_length_bytes
+        ));
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected header length of type 'b' at decimal offset {} bytes",
+                *pointer
+            ),
+        ));
+    }
+
+    // Parse version and backward version
+    let first = parse(data, pointer)?;
+    let second = parse(data, pointer)?;
+
+    let (version, backward_version) = match (&first, &second) {
+        (VsfType::z(v), VsfType::y(bv)) => {
+            debug_println(&format!("Version: {}, Backward version: {}", v, bv));
+            (*v, *bv)
+        }
+        (VsfType::y(bv), VsfType::z(v)) => {
+            debug_println(&format!("Version: {}, Backward version: {}", v, bv));
+            (*v, *bv)
+        }
+        _ => {
+            return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected version (z) and backward version (y) at decimal offset {} bytes, found {:?} and {:?}",
+                *pointer, first, second
+            ),
+        ));
+        }
+    };
+
+    if backward_version > STATE_VERSION {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "Written by a newer basecalc, for version {} readers and up, and this is version {}!",
+                backward_version, STATE_VERSION
+            ),
+        ));
+    }
+
+    // Parse label definition count
+    let label_count_vsf = parse(data, pointer)?;
+    let label_count;
+    if let VsfType::c(count) = label_count_vsf {
+        label_count = count;
+        debug_println(&format!("Label count: {}", label_count));
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected label count 'c' at decimal offset {} bytes",
+                *pointer
+            ),
+        ));
+    }
+
+    let mut basecalc_offset = 0;
+    let mut basecalc_size = 0;
+    let mut basecalc_count = 0;
+
+    // Parse label definitions
+    debug_println(&format!("Parsing label definitions"));
+    for i in 0..label_count {
+        debug_println(&format!(
+            "Parsing label definition {}/{}",
+            i + 1,
+            label_count
+        ));
+        if data[*pointer] != b'(' {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Expected label set definition '(' at decimal offset {} bytes",
+                    *pointer
+                ),
+            ));
+        }
+        *pointer += 1;
+
+        if let VsfType::d(label_str) = parse(data, pointer)? {
+            debug_println(&format!("Found label: {}", label_str));
+            if label_str == "basecalc state" {
+                let mut offset = None;
+                let mut size = None;
+                let mut count = None;
+
+                // Parse offset, size, and count in any order
+                while data[*pointer] != b')' {
+                    match parse(data, pointer)? {
+                        VsfType::o(o) => {
+                            debug_println(&format!("basecalc state offset: {}", o));
+                            offset = Some(o);
+                        }
+                        VsfType::b(s) => {
+                            debug_println(&format!("basecalc state size: {}", s));
+                            size = Some(s);
+                        }
+                        VsfType::c(c) => {
+                            debug_println(&format!("basecalc state count: {}", c));
+                            count = Some(c);
+                        }
+                        _ => {
+                            debug_println(&format!(
+                                "Ignoring unknown type for future compatibility"
+                            ));
+                        }
+                    }
+                }
+
+                basecalc_offset = offset.ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "Missing offset for basecalc state")
+                })?;
+                basecalc_size = size.ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "Missing size for basecalc state")
+                })?;
+                basecalc_count = count.ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "Missing count for basecalc state")
+                })?;
+            } else {
+                debug_println(&format!("Skipping unknown label: {}", label_str));
+                // Skip other label definitions
+                while data[*pointer] != b')' {
+                    parse(data, pointer)?;
+                }
+            }
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Expected label 'd' at decimal offset {} bytes", *pointer),
+            ));
+        }
+
+        if data[*pointer] != b')' {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Expected ')' at end of label definition at decimal offset {} bytes",
+                    *pointer
+                ),
+            ));
+        }
+        *pointer += 1;
+    }
+
+    // Check for closing angle bracket
+    if data[*pointer] != b'>' {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Expected header closing '>' at decimal offset {} bytes",
+                *pointer
+            ),
+        ));
+    }
+    *pointer += 1;
+    debug_println(&format!("Header closing '>' found"));
+
+    if *pointer != header_length_bytes {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Header length mismatch: expected {} bytes, got {} bytes",
+                header_length_bytes, pointer
+            ),
+        ));
+    }
+
+    // Initialize basecalc state with default values
+    let mut base = 0;
+    let mut digits = 0;
+    let mut radians_flag: u8 = 3; // 3 indicates missing value
+    let mut angle = None;
+    let mut history = Vec::new();
+    let mut debug_flag = false;
+    let mut theme = None;
+    let mut colour = None;
+    let mut gradians = false;
+    let mut balanced = false;
+    let mut lowercase = false;
+    let mut mouse = true;
+    let mut history_max = DEFAULT_HISTORY_MAX;
+    let mut constants = String::new();
+    let mut variables = String::new();
+
+    let mut history_offset;
+    let mut history_size;
+    let mut history_count;
+
+    // Parse basecalc state if found
+    if basecalc_offset > 0 && basecalc_size > 0 && basecalc_count > 0 {
+        debug_println(&format!("Parsing basecalc state"));
+        // Move pointer to basecalc state data
+        *pointer = (basecalc_offset / 8) as usize;
+        debug_println(&format!(
+            "Moved pointer to basecalc state data at offset: {}",
+            *pointer
+        ));
+
+        // Parse label set
+        if data[*pointer] != b'[' {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Expected '[' for label set at decimal offset {} bytes",
+                    *pointer
+                ),
+            ));
+        }
+        *pointer += 1;
+
+        for i in 0..basecalc_count {
+            debug_println(&format!(
+                "Parsing basecalc state label {}/{}",
+                i + 1,
+                basecalc_count
+            ));
+            if data[*pointer] != b'(' {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Expected '(' for label at decimal offset {} bytes",
+                        *pointer
+                    ),
+                ));
+            }
+            *pointer += 1;
+
+            let label = parse(data, pointer)?;
+            if let VsfType::d(label_str) = label {
+                debug_println(&format!("Found basecalc state label: {}", label_str));
+                match label_str.as_str() {
+                    "base" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'base' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u3(value) = parse(data, pointer)? {
+                            base = value;
+                            debug_println(&format!("Parsed base: {}", base));
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u3 type for 'base' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "digits" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'digits' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        match parse(data, pointer)? {
+                            VsfType::u(value) => {
+                                digits = value as usize;
+                            }
+                            VsfType::u3(value) => {
+                                digits = value as usize;
+                            }
+                            VsfType::u4(value) => {
+                                digits = value as usize;
+                            }
+                            VsfType::u5(value) => {
+                                digits = value as usize;
+                            }
+                            VsfType::u6(value) => {
+                                digits = value as usize;
+                            }
+                            VsfType::u7(value) => {
+                                digits = value as usize;
+                            }
+                            _ => {
+                                return Err(Error::new(
+                                    ErrorKind::InvalidData,
+                                    format!(
+                                        "Expected u type for 'digits' at decimal offset {} bytes",
+                                        *pointer
+                                    ),
+                                ));
+                            }
+                        }
+                        debug_println(&format!("Parsed digits: {}", digits));
+                    }
+                    "history max" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'history max' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        history_max = match parse(data, pointer)? {
+                            VsfType::u(value) => value,
+                            VsfType::u3(value) => value as usize,
+                            VsfType::u4(value) => value as usize,
+                            VsfType::u5(value) => value as usize,
+                            VsfType::u6(value) => value as usize,
+                            _ => {
+                                return Err(Error::new(
+                                    ErrorKind::InvalidData,
+                                    format!(
+                                        "Expected u type for 'history max' at decimal offset {} bytes",
+                                        *pointer
+                                    ),
+                                ));
+                            }
+                        };
+                    }
+                    "radians" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'radians' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        let a = parse(data, pointer);
+                        if let VsfType::u0(value) = a? {
+                            radians_flag = if value { 1 } else { 0 };
+                            debug_println(&format!("Parsed radians: {}", radians_flag));
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'radians' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "history" => {
+                        let mut offset = None;
+                        let mut size = None;
+                        let mut count = None;
+
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'history' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+
+                        // Parse offset, size, and count in any order
+                        while data[*pointer] != b')' {
+                            match parse(data, pointer)? {
+                                VsfType::o(o) => {
+                                    debug_println(&format!("basecalc history offset: {}", o / 8));
+                                    offset = Some(o);
+                                }
+                                VsfType::b(s) => {
+                                    debug_println(&format!("basecalc history size: {}", s / 8));
+                                    size = Some(s);
+                                }
+                                VsfType::c(c) => {
+                                    debug_println(&format!("basecalc history count: {}", c));
+                                    count = Some(c);
+                                }
+                                _ => {
+                                    debug_println(&format!(
+                                        "Ignoring unknown type for future compatibility"
+                                    ));
+                                }
+                            }
+                        }
+
+                        history_offset = offset.ok_or_else(|| {
+                            Error::new(
+                                ErrorKind::InvalidData,
+                                "Missing offset for basecalc history",
+                            )
+                        })?;
+                        history_size = size.ok_or_else(|| {
+                            Error::new(ErrorKind::InvalidData, "Missing size for basecalc history")
+                        })?;
+                        history_count = count.ok_or_else(|| {
+                            Error::new(ErrorKind::InvalidData, "Missing count for basecalc history")
+                        })?;
+
+                        let mut history_pointer = (history_offset / 8) as usize;
+                        debug_println(&format!(
+                            "Moved pointer to basecalc history data at offset: {}",
+                            history_pointer
+                        ));
+
+                        // Parse history entries
+                        for entry in 0..history_count {
+                            debug_println(&format!(
+                                "Parsing basecalc history entry {}/{}",
+                                entry + 1,
+                                history_count
+                            ));
+                            match parse(data, &mut history_pointer)? {
+                                VsfType::x(mut entry) => {
+                                    if entry.ends_with('\n') {
+                                        entry.truncate(entry.len() - 1);
+                                    } else {
+                                        return Err(Error::new(
+                                            ErrorKind::InvalidData,
+                                            format!(
+                                                "Expected newline at end of history entry at decimal offset {} bytes",
+                                                history_pointer
+                                            ),
+                                        ));
+                                    }
+                                    debug_println(&format!("Parsed history entry: {}", entry));
+                                    history.push(entry);
+                                }
+                                _ => {
+                                    return Err(Error::new(
+                                        ErrorKind::InvalidData,
+                                        format!(
+                                            "Expected x type for history entry at decimal offset {} bytes",
+                                            history_pointer
+                                        ),
+                                    ));
+                                }
+                            }
+                        }
+                        if history_pointer != (history_offset + history_size) / 8 {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "History length mismatch: expected {} bytes, got {} bytes",
+                                    history_size, history_pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "DEBUG" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'DEBUG' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        let a = parse(data, pointer);
+                        if let VsfType::u0(value) = a? {
+                            debug_flag = value;
+                            debug_println(&format!("Parsed DEBUG: {}", debug_flag));
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type (boolean) for 'DEBUG' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "colour" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'colour' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u0(value) = parse(data, pointer)? {
+                            colour = Some(value);
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'colour' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "balanced" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'balanced' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u0(value) = parse(data, pointer)? {
+                            balanced = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'balanced' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "lowercase" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'lowercase' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u0(value) = parse(data, pointer)? {
+                            lowercase = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'lowercase' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "mouse" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'mouse' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u0(value) = parse(data, pointer)? {
+                            mouse = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'mouse' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "gradians" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'gradians' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::u0(value) = parse(data, pointer)? {
+                            gradians = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected u0 type for 'gradians' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "constants" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'constants' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::x(value) = parse(data, pointer)? {
+                            constants = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected x type for 'constants' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "variables" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'variables' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::x(value) = parse(data, pointer)? {
+                            variables = value;
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected x type for 'variables' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    "angle" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'angle' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        angle = match parse(data, pointer)? {
+                            VsfType::x(value) if value == "radians" => Some(AngleMode::Radians),
+                            VsfType::x(value) if value == "degrees" => Some(AngleMode::Degrees),
+                            VsfType::x(value) if value == "gradians" => Some(AngleMode::Gradians),
+                            _ => {
+                                return Err(Error::new(
+                                    ErrorKind::InvalidData,
+                                    format!(
+                                        "Expected angle units for 'angle' at decimal offset {} bytes",
+                                        *pointer
+                                    ),
+                                ));
+                            }
+                        };
+                    }
+                    "theme" => {
+                        if data[*pointer] != b':' {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected ':' after 'theme' label at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                        *pointer += 1;
+                        if let VsfType::x(value) = parse(data, pointer)? {
+                            theme = Some(value);
+                        } else {
+                            return Err(Error::new(
+                                ErrorKind::InvalidData,
+                                format!(
+                                    "Expected x type for 'theme' at decimal offset {} bytes",
+                                    *pointer
+                                ),
+                            ));
+                        }
+                    }
+                    _ => {
+                        debug_println(&format!(
+                            "Skipping unknown basecalc state label: {}",
+                            label_str
+                        ));
+                        // Skip unknown labels
+                        while data[*pointer] != b')' {
+                            if data[*pointer] == b':' {
+                                *pointer += 1;
+                            } else {
+                                parse(data, pointer)?;
+                            }
+                        }
+                    }
+                }
+            } else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Expected label of type 'd' at decimal offset {} bytes",
+                        *pointer
+                    ),
+                ));
+            }
+
+            if data[*pointer] != b')' {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Expected ')' after label value at decimal offset {} bytes",
+                        *pointer
+                    ),
+                ));
+            }
+            *pointer += 1;
+        }
+
+        if data[*pointer] != b']' {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Expected ']' at end of label set at decimal offset {} bytes",
+                    *pointer
+                ),
+            ));
+        }
+        *pointer += 1;
+        debug_println(&format!("Finished parsing basecalc state"));
+    } else {
+        debug_println(&format!("No basecalc state found in the file"));
+    }
+
+    // Anything missing takes its default, so a later layout is free to drop a label
+    let defaults = BasecalcState::new();
+    if base == 0 {
+        debug_println("Missing base, using the default");
+        base = defaults.base;
+    }
+    if digits == 0 {
+        debug_println("Missing digits, using the default");
+        digits = defaults.digits;
+    }
+    let angle = migrate_state(version, angle, radians_flag, gradians);
+    debug_println(&format!("Final parsed values:"));
+    debug_println(&format!("  Base: {}", base));
+    debug_println(&format!("  Digits: {}", digits));
+    debug_println(&format!("  Angles: {}", angle.name()));
+    debug_println(&format!("  History entries: {}", history.len()));
+
+    debug_println(&format!("VSF parsing completed successfully"));
+    let mut state = BasecalcState::new();
+    state.base = base;
+    // Only an odd base can be balanced, whatever the file says
+    state.balanced = balanced && base % 2 == 1;
+    state.lowercase = lowercase;
+    state.mouse = mouse;
+    state.digits = digits;
+    state.set_precision();
+    state.angle = angle;
+    state.history = history;
+    state.history_max = history_max;
+    state.prune_history();
+    state.debug = debug_flag;
+    state.colour = colour;
+    state.constants = constants_from_text(&constants, state.precision);
+    state.variables = constants_from_text(&variables, state.precision);
+    // A theme file that has since gone missing or broken falls back to the default
+    if let Some(colours) = theme.as_deref().and_then(|name| load_theme(name).ok()) {
+        state.colours = colours;
+        state.theme = theme.unwrap();
+    }
+    Ok(state)
+}
+pub struct EvalResult {
+    pub value: Complex,
+    pub assignment: Option<usize>, // Index of assigned variable, if this was an assignment
+    pub unit: Option<String>,      // Unit the value is shown in, if it has one
+    pub list: Option<Vec<Complex>>, // Elements of a list result, `value` being the first
+}
+#[derive(Clone)]
+pub struct Variable {
+    pub name: String,
+    pub value: Complex,
+    pub dimension: Dimension, // Values with units are stored in SI units
+    pub list: Option<Vec<Complex>>, // Elements when holding a list, `value` being the first
+    pub formula: Option<String>, // Expression from `:=`, worked out again whenever it's read
+    pub note: Option<String>, // Description from `:note`, shown by `:vars`
+    pub locked: bool, // Set by `:lock`, refusing assignment until `:unlock`
+}
+#[derive(Clone)]
+pub struct UserFunction {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub body: String,
+}
+/// A line entered this session, kept for `:export md`
+#[derive(Clone)]
+pub struct SessionLine {
+    pub input: String,
+    /// What it printed, without colour
+    pub output: String,
+    /// The base afterwards, such as `Hexadecimal (base 16)`
+    pub base: String,
+}
+/// Units for the angles that trig functions take and give
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            AngleMode::Radians => "radians",
+            AngleMode::Degrees => "degrees",
+            AngleMode::Gradians => "gradians",
+        }
+    }
+
+    /// Half a turn in these units, which is pi for radians
+    pub fn half_turn(self, precision: u32) -> Float {
+        match self {
+            AngleMode::Radians => Float::with_val(precision, rug::float::Constant::Pi),
+            AngleMode::Degrees => Float::with_val(precision, 180),
+            AngleMode::Gradians => Float::with_val(precision, 200),
+        }
+    }
+}
+
+/// Irrational bases results can be shown in, set by `:base @phi` or `:base @e`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Radix {
+    Phi,
+    E,
+}
+
+impl Radix {
+    pub fn name(self) -> &'static str {
+        match self {
+            Radix::Phi => "@phi",
+            Radix::E => "@e",
+        }
+    }
+
+    pub fn value(self, precision: u32) -> Float {
+        match self {
+            Radix::Phi => (Float::with_val(precision, 5).sqrt() + 1) / 2,
+            Radix::E => Float::with_val(precision, 1).exp(),
+        }
+    }
+}
+
+/// How results split their digits into groups, set by `:group`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Grouping {
+    /// Digits per group, 0 for no grouping
+    pub size: usize,
+    pub separator: char,
+    /// Whether the digits after the point are grouped too
+    pub fraction: bool,
+}
+
+impl Grouping {
+    /// Whether a separator goes after the digit `offset` places past the point,
+    /// counting the units digit as place 0
+    fn after(self, offset: isize) -> bool {
+        self.size > 0 && (offset - 1).rem_euclid(self.size as isize) == 0 && (offset <= 0 || self.fraction)
+    }
+}
+
+#[derive(Clone)]
+pub struct BasecalcState {
+    pub base: u8,
+    /// Whether the base is balanced, with digits from -k to k written as Z..1, 0, 1..k
+    pub(crate) balanced: bool,
+    /// Irrational base to show results in, entry staying in `base`
+    pub(crate) radix: Option<Radix>,
+    pub digits: usize,
+    pub(crate) precision: u32,
+    pub(crate) padding: u32,
+    pub angle: AngleMode,
+    pub current_entry: String,
+    pub history_index: usize,
+    pub history: Vec<String>,
+    /// Most history entries kept, the oldest dropping off first, from `:history max`
+    pub(crate) history_max: usize,
+    /// File each line and its results are added to, from `:record`
+    pub transcript: Option<String>,
+    /// Lines entered since launch and what they printed, for `:export md`
+    pub(crate) session: Vec<SessionLine>,
+    /// Elements of the last list result, for `:export csv`
+    pub(crate) last_list: Option<Vec<Complex>>,
+    /// Expressions from `:watch`, shown again after every line that succeeds
+    pub(crate) watches: Vec<String>,
+    pub debug: bool,
+    pub(crate) rand_state: rand::RandState<'static>,
+    pub(crate) prev_result: Complex,
+    pub(crate) results: Vec<Complex>,
+    pub colours: RGBValues,
+    /// Name of the theme the colours were loaded from, saved with the state
+    pub(crate) theme: String,
+    /// Whether to colour output: `None` follows the environment, see [`apply_colour_setting`]
+    pub(crate) colour: Option<bool>,
+    pub(crate) variables: Vec<Variable>,
+    /// Named constants from `:const`, saved with the state and never reassigned
+    pub(crate) constants: Vec<Variable>,
+    pub(crate) acc_operator: Option<char>,
+    pub(crate) accumulator: Option<Complex>,
+    pub(crate) functions: Vec<UserFunction>,
+    pub(crate) call_depth: usize,
+    /// `:=` variables being worked out, to catch circular definitions
+    pub(crate) resolving: Vec<String>,
+    pub(crate) epsilon: Option<Float>,
+    pub(crate) width: Option<u32>,
+    /// Longest repeating period to show as `0.1(6)` rather than truncating, from `:repeat`
+    pub(crate) period: Option<usize>,
+    /// Fractional digits to always show, without an exponent, from `:fixed`
+    pub(crate) fixed: Option<usize>,
+    /// Place values of the leading digit written out without an exponent, from the
+    /// low one up to just below the high one, from `:expthreshold`
+    pub(crate) exponent_range: Option<(isize, isize)>,
+    pub(crate) interval: bool,
+    /// Show complex results as `[magnitude; angle]` rather than `[real, imaginary]`
+    pub(crate) polar: bool,
+    /// Whether clicking in the entry at the prompt moves the cursor there, from `:mouse`,
+    /// saved with the state
+    pub mouse: bool,
+    /// Set when the state file was written by a newer basecalc, so it isn't saved over
+    pub(crate) newer_file: bool,
+    /// Show results as lines of JSON for other programs to read, from `:json` or `--json`
+    pub json: bool,
+    /// Show ten and eleven in base 12 as ↊ and ↋ rather than A and B, from `:digitset`
+    pub(crate) dozenal: bool,
+    /// Write digits from ten up in lowercase, from `:digitcase`, saved with the state
+    pub(crate) lowercase: bool,
+    pub(crate) grouping: Grouping,
+    /// Set from another thread to abandon the evaluation using this state
+    pub interrupt: Arc<AtomicBool>,
+}
+
+impl BasecalcState {
+    pub fn new() -> Self {
+        let base = 10;
+        let digits = 12;
+        let precision = 0;
+        let mut state = BasecalcState {
+            base,
+            balanced: false,
+            radix: None,
+            digits,
+            precision,
+            padding: 32,
+            angle: AngleMode::Radians,
+            current_entry: String::new(),
+            history_index: 0,
+            history: Vec::new(),
+            history_max: DEFAULT_HISTORY_MAX,
+            transcript: None,
+            session: Vec::new(),
+            last_list: None,
+            watches: Vec::new(),
+            debug: false,
+            rand_state: rand::RandState::new(),
+            prev_result: Complex::with_val(1, 0),
+            results: Vec::new(),
+            colours: RGBValues::dark(),
+            theme: "dark".to_string(),
+            colour: None,
+            variables: Vec::new(),
+            constants: Vec::new(),
+            acc_operator: None,
+            accumulator: None,
+            functions: Vec::new(),
+            call_depth: 0,
+            resolving: Vec::new(),
+            epsilon: None,
+            width: None,
+            period: None,
+            fixed: None,
+            exponent_range: None,
+            interval: false,
+            polar: false,
+            mouse: true,
+            newer_file: false,
+            json: false,
+            dozenal: false,
+            lowercase: false,
+            grouping: Grouping {
+                size: 3,
+                separator: ' ',
+                fraction: true,
+            },
+            interrupt: Arc::new(AtomicBool::new(false)),
+        };
+        state.set_precision();
+        state.prev_result = Complex::with_val(state.precision, 0);
+        state
+    }
+    /// Makes `value` the previous result and adds it to the results `&1`, `&2`, ... refer to
+    ///
+    /// Only the last `history_max` results are kept, like the history.
+    pub fn record_result(&mut self, value: Complex) {
+        self.results.push(value.clone());
+        self.prev_result = value;
+        self.prune_history();
+    }
+    /// Keeps a result, a number as the previous result and a list for `:export csv`
+    pub fn record(&mut self, result: EvalResult) {
+        match result.list {
+            Some(elements) => self.last_list = Some(elements),
+            None => self.record_result(result.value),
+        }
+    }
+    /// Adds a line and its uncoloured output to the session kept for `:export md`
+    pub fn record_line(&mut self, input: &str, output: &str) {
+        let name = match (self.radix, get_base_name(self.base)) {
+            (Some(radix), _) => format!("Base {}", radix.name()),
+            (None, Some(name)) if self.balanced => {
+                format!("Balanced {} (base {})", name.to_ascii_lowercase(), self.base)
+            }
+            (None, Some(name)) => format!("{} (base {})", name, self.base),
+            (None, None) => format!("Base {}", self.base),
+        };
+        self.session.push(SessionLine {
+            input: input.to_string(),
+            output: output.to_string(),
+            base: name,
+        });
+    }
+    /// Adds an entered line to the history, unless it repeats the one before
+    pub fn add_history(&mut self, entry: String) {
+        if self.history.last() != Some(&entry) {
+            self.history.push(entry);
+        }
+        self.prune_history();
+    }
+    /// Drops the oldest history entries and results beyond `history_max`
+    fn prune_history(&mut self) {
+        let excess = self.history.len().saturating_sub(self.history_max);
+        self.history.drain(..excess);
+        let excess = self.results.len().saturating_sub(self.history_max);
+        self.results.drain(..excess);
+    }
+    fn set_precision(&mut self) {
+        self.precision =
+            (self.digits as f64 * (self.base as f64).log2()).ceil() as u32 + self.padding;
+    }
+}
+impl Default for BasecalcState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// An error from [`Calculator::eval`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CalcError {
+    /// The input could not be parsed. `position` is the byte offset of the problem.
+    Syntax { message: String, position: usize },
+    /// The input parsed but could not be evaluated, e.g. an undefined variable
+    Evaluation(String),
+    /// The input was a command or definition. It took effect, but has no value.
+    NotAValue(String),
+}
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::Syntax { message, position } => {
+                write!(f, "{} (at position {})", message, position)
+            }
+            CalcError::Evaluation(message) | CalcError::NotAValue(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+impl std::error::Error for CalcError {}
+/// A calculator that can be embedded in other programs
+///
+/// Each calculator keeps its own base, precision, variables, functions and
+/// previous result, exactly like a basecalc session. Expressions are worked out
+/// without touching the terminal or any file, but commands do just what they do
+/// at the prompt, so `:export`, `:record` and `:workspace` write files and `:copy`
+/// takes the clipboard. Keep input you don't trust to expressions.
+///
+/// ```
+/// let mut calc = basecalc::Calculator::new();
+/// calc.eval("@x = 6").unwrap();
+/// let value = calc.eval("@x * 7").unwrap();
+/// assert_eq!(value.real().to_f64(), 42.0);
+/// assert_eq!(calc.format(&value), "42.");
+/// ```
+#[derive(Clone, Default)]
+pub struct Calculator {
+    pub state: BasecalcState,
+}
+impl Calculator {
+    pub fn new() -> Self {
+        Calculator {
+            state: BasecalcState::new(),
+        }
+    }
+    /// Wraps an existing state, such as one returned by [`load_state`]
+    pub fn with_state(state: BasecalcState) -> Self {
+        Calculator { state }
+    }
+    /// Evaluates an expression and makes it the previous result (`&`)
+    ///
+    /// Commands such as `:base C` and function definitions are carried out, but
+    /// return [`CalcError::NotAValue`] holding their message.
+    ///
+    /// Several statements can be separated by `;`, in which case they run in order
+    /// and the last one's result is returned. The first error stops the rest.
+    pub fn eval(&mut self, input: &str) -> Result<Complex, CalcError> {
+        let mut result = Err(CalcError::NotAValue(String::new()));
+        for (offset, statement) in split_statements(input) {
+            result = self.eval_statement(statement);
+            match result {
+                Err(CalcError::Syntax { message, position }) => {
+                    return Err(CalcError::Syntax {
+                        message,
+                        position: position + offset,
+                    })
+                }
+                Err(CalcError::Evaluation(_)) => return result,
+                _ => {}
+            }
+        }
+        result
+    }
+    fn eval_statement(&mut self, input: &str) -> Result<Complex, CalcError> {
+        let tokens = match tokenize(input, &mut self.state) {
+            Ok(tokens) => tokens,
+            Err((message, usize::MAX)) => return Err(CalcError::NotAValue(message)),
+            Err((message, position)) => return Err(CalcError::Syntax { message, position }),
+        };
+        let result = evaluate_statement(&tokens, &mut self.state).map_err(CalcError::Evaluation)?;
+        if result.list.is_some() {
+            return Err(CalcError::Evaluation("A list isn't a single value!".to_string()));
+        }
+        self.state.record_result(result.value.clone());
+        Ok(result.value)
+    }
+    /// Formats a value in the current base and digits, without colour or the
+    /// padding used to line up results at the prompt
+    pub fn format(&self, value: &Complex) -> String {
+        let formatted: String = num2string(value, &self.state)
+            .iter()
+            .map(|coloured| &**coloured)
+            .collect();
+        formatted.trim().to_string()
+    }
+}
+/// Brings the fields a file of an older layout keeps differently up to date
+///
+/// Each step takes a file from one version to the next, so a version 1 file goes
+/// thru every step in turn.
+///
+/// # Arguments
+/// * `version` - The layout the file was written in
+/// * `angle` - The `angle` label, which version 1 files don't have
+/// * `radians_flag`, `gradians` - The angle flags, 3 for a missing `radians`
+fn migrate_state(
+    version: usize,
+    mut angle: Option<AngleMode>,
+    radians_flag: u8,
+    gradians: bool,
+) -> AngleMode {
+    if version < 2 {
+        // Gradians came later, so files written before them only say radians or not
+        angle = match (radians_flag, gradians) {
+            (1, _) => Some(AngleMode::Radians),
+            (_, true) => Some(AngleMode::Gradians),
+            (0, _) => Some(AngleMode::Degrees),
+            _ => None,
+        };
+    }
+    angle.unwrap_or(BasecalcState::new().angle)
+}
+fn create_vsf_data(basecalc_state: &BasecalcState) -> Result<Vec<u8>, std::io::Error> {
+    let mut history_entries_combined = Vec::new();
+    for entry in &basecalc_state.history {
+        let entry_with_return = entry.clone() + "\n";
+        history_entries_combined.append(&mut VsfType::x(entry_with_return).flatten()?);
+    }
+    let mut vsf = vec!["RÅ".as_bytes().to_owned()];
+
+    // Header
+    let mut header_index = 0;
+    vsf[header_index].append(&mut b"<".to_vec());
+    let header_length_index = vsf.len();
+    let mut header_length = 42;
+    vsf.push(VsfType::b(header_length).flatten()?); // Placeholder for header length in bits, always first
+    header_index = vsf.len();
+    vsf.push(VsfType::z(STATE_VERSION).flatten()?); // Version
+    vsf[header_index].append(&mut VsfType::y(STATE_READABLE_FROM).flatten()?); // Backward version
+    vsf[header_index].append(&mut VsfType::c(1).flatten()?); // label definition count
+    vsf[header_index].append(&mut b"(".to_vec()); // Start of label definition
+    vsf[header_index].append(&mut VsfType::d("basecalc state".to_string()).flatten()?); // VsfType d for the data type
+    let label_offset_index = vsf.len();
+    let mut label_offset = 42;
+    vsf.push(VsfType::o(label_offset).flatten()?); // Placeholder for offset to basecalc state
+    let label_size_index = vsf.len();
+    let mut label_size = 42;
+    vsf.push(VsfType::b(label_size).flatten()?); // Placeholder for size of basecalc state
+    header_index = vsf.len();
+    // Number of elements in basecalc state, balanced, lowercase, mouse off, history max, gradians,
+    // colour, constants and variables only being saved once set
+    let gradians = basecalc_state.angle == AngleMode::Gradians;
+    let history_max = basecalc_state.history_max != DEFAULT_HISTORY_MAX;
+    let variables = constants_to_text(&basecalc_state.variables);
+    let element_count = 7
+        + basecalc_state.balanced as usize
+        + basecalc_state.lowercase as usize
+        + !basecalc_state.mouse as usize
+        + history_max as usize
+        + gradians as usize
+        + basecalc_state.colour.is_some() as usize
+        + !basecalc_state.constants.is_empty() as usize
+        + !variables.is_empty() as usize;
+    vsf.push(VsfType::c(element_count).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+    vsf[header_index].append(&mut b">".to_vec());
+    let header_end_index = vsf.len();
+
+    // Label set
+    header_index = vsf.len();
+    vsf.push(b"[".to_vec());
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("base".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::u3(basecalc_state.base).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    if basecalc_state.balanced {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("balanced".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u0(true).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    if basecalc_state.lowercase {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("lowercase".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u0(true).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    if !basecalc_state.mouse {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("mouse".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u0(false).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("digits".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::u(basecalc_state.digits).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("radians".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::u0(basecalc_state.angle == AngleMode::Radians).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    if gradians {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("gradians".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u0(true).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("angle".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::x(basecalc_state.angle.name().to_string()).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("history".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    let history_offset_index = vsf.len();
+    let mut history_offset = 42;
+    vsf.push(VsfType::o(history_offset).flatten()?);
+    header_index = vsf.len();
+    vsf.push(VsfType::b(history_entries_combined.len() * 8).flatten()?);
+    vsf[header_index].append(&mut VsfType::c(basecalc_state.history.len()).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    if history_max {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("history max".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u(basecalc_state.history_max).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("DEBUG".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::u0(basecalc_state.debug).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    vsf[header_index].append(&mut b"(".to_vec());
+    vsf[header_index].append(&mut VsfType::d("theme".to_string()).flatten()?);
+    vsf[header_index].append(&mut b":".to_vec());
+    vsf[header_index].append(&mut VsfType::x(basecalc_state.theme.clone()).flatten()?);
+    vsf[header_index].append(&mut b")".to_vec());
+
+    if let Some(colour) = basecalc_state.colour {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("colour".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::u0(colour).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    if !basecalc_state.constants.is_empty() {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("constants".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::x(constants_to_text(&basecalc_state.constants)).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    if !variables.is_empty() {
+        vsf[header_index].append(&mut b"(".to_vec());
+        vsf[header_index].append(&mut VsfType::d("variables".to_string()).flatten()?);
+        vsf[header_index].append(&mut b":".to_vec());
+        vsf[header_index].append(&mut VsfType::x(variables).flatten()?);
+        vsf[header_index].append(&mut b")".to_vec());
+    }
+
+    vsf[header_index].append(&mut b"]".to_vec());
+
+    let mut prev_header_length = 0;
+    let mut prev_label_offset = 0;
+    let mut prev_label_size = 0;
+    let mut prev_history_offset = 0;
+
+    while header_length != prev_header_length
+        || label_offset != prev_label_offset
+        || label_size != prev_label_size
+        || history_offset != prev_history_offset
+    {
+        prev_header_length = header_length;
+        prev_label_offset = label_offset;
+        prev_label_size = label_size;
+        prev_history_offset = history_offset;
+
+        header_length = 0;
+        for i in 0..header_end_index {
+            header_length += vsf[i].len();
+        }
+        vsf[header_length_index] = VsfType::b(header_length * 8).flatten()?;
+
+        label_offset = header_length;
+        vsf[label_offset_index] = VsfType::o(label_offset * 8).flatten()?;
+
+        label_size = 0;
+        for i in header_end_index..vsf.len() {
+            let mut vsfi = "".to_owned();
+            for index in 0..vsf[i].len() {
+                let id = vsf[i][index];
+                if id >= 32 && id <= 126 {
+                    vsfi.push(id as char);
+                } else {
+                    vsfi.push(' ');
+                }
+            }
+            label_size += vsf[i].len();
+        }
+        vsf[label_size_index] = VsfType::b(label_size * 8).flatten()?;
+
+        history_offset = label_offset + label_size;
+        vsf[history_offset_index] = VsfType::o(history_offset * 8).flatten()?;
+    }
+
+    vsf.push(history_entries_combined);
+
+    let vsf_vector: Vec<u8> = vsf.into_iter().flatten().collect();
+    if DEBUG.load(Ordering::Relaxed) {
+        print_colorized_vsf(&vsf_vector);
+    }
+    Ok(vsf_vector)
+}
+fn print_colorized_vsf(vsf_data: &[u8]) {
+    let mut first_line = String::new();
+    let mut second_line = String::new();
+
+    for &byte in vsf_data {
+        if is_keyboard_printable(byte) {
+            first_line.push_str(&format!("{}", (byte as char).to_string().green()));
+            second_line.push(' ');
+        } else {
+            let hex = format!("{:02X}", byte).as_bytes().to_owned();
+            first_line.push_str(&format!("{}", (hex[0] as char).to_string().red()));
+            second_line.push_str(&format!("{}", (hex[1] as char).to_string().red()));
+        }
+    }
+    let mut index_lines = Vec::new();
+    for line_count in 0..(vsf_data.len() as f64).log10().floor() as usize + 1 {
+        let mut line = String::new();
+        for i in 0..vsf_data.len() {
+            let i_trunc = i / (10usize).pow(line_count as u32);
+            if i_trunc > 0 {
+                line.push_str(&format!("{}", i_trunc % 10));
+            } else {
+                line.push(' ');
+            }
+        }
+        index_lines.push(line.blue());
+    }
+
+    println!("{}", second_line);
+    println!("{}", first_line);
+    for line in index_lines {
+        println!("{}", line);
+    }
+}
+fn is_keyboard_printable(byte: u8) -> bool {
+    match byte {
+        32..=126 => true, // Printable ASCII characters (including space)
+        _ => false,
+    }
+}
+pub fn print_settings(state: &BasecalcState) {
+    print!(
+        "{}",
+        "Currently ".truecolor(
+            state.colours.real_integer.0,
+            state.colours.real_integer.1,
+            state.colours.real_integer.2
+        )
+    );
+    print!(
+        "{}",
+        "Base: ".truecolor(
+            state.colours.lone_integer.0,
+            state.colours.lone_integer.1,
+            state.colours.lone_integer.2
+        )
+    );
+    let base_char = if state.base < 10 {
+        (state.base + b'0') as char
+    } else {
+        (state.base - 10 + b'A') as char
+    };
+    print!(
+        "{}",
+        base_char.to_string().truecolor(
+            state.colours.lone_fraction.0,
+            state.colours.lone_fraction.1,
+            state.colours.lone_fraction.2
+        )
+    );
+    let base_name = if state.balanced {
+        format!("Balanced {}", get_base_name(state.base).unwrap().to_ascii_lowercase())
+    } else {
+        get_base_name(state.base).unwrap().to_string()
+    };
+    print!(
+        " ({})",
+        base_name.truecolor(
+            state.colours.lone_fraction.0,
+            state.colours.lone_fraction.1,
+            state.colours.lone_fraction.2
+        )
+    );
+    print!(
+        "{}",
+        ", Digits: ".truecolor(
+            state.colours.lone_integer.0,
+            state.colours.lone_integer.1,
+            state.colours.lone_integer.2
+        )
+    );
+    print!(
+        "{}",
+        format_int(state.digits, state.base as usize, state.balanced).truecolor(
+            state.colours.lone_fraction.0,
+            state.colours.lone_fraction.1,
+            state.colours.lone_fraction.2
+        )
+    );
+    print!(
+        "{}",
+        ", Trig units: ".truecolor(
+            state.colours.lone_integer.0,
+            state.colours.lone_integer.1,
+            state.colours.lone_integer.2
+        )
+    );
+    println!(
+        "{}",
+        state.angle.name().truecolor(
+            state.colours.lone_fraction.0,
+            state.colours.lone_fraction.1,
+            state.colours.lone_fraction.2,
+        )
+    );
+}
+static OPERATORS: [(&str, char, u8, &str); 90] = [
+    // Basic arithmetic
+    ("+", '+', 2, "addition"),
+    ("-", '-', 2, "subtraction"),
+    ("*", '*', 2, "multiplication"),
+    ("/", '/', 2, "division"),
+    ("^", '^', 2, "exponentiation"),
+    ("%", '%', 2, "modulus"),
+    ("$", '$', 2, "log and base logarithm"),
+    ("#choose", 'C', 2, "binomial coefficient"),
+    ("#perm", 'R', 2, "permutations"),
+    ("#gcd", '⊓', 2, "greatest common divisor"),
+    ("#lcm", '⊔', 2, "least common multiple"),
+    // Bitwise operations on integer parts
+    ("#and", 'B', 2, "bitwise and"),
+    ("#or", 'V', 2, "bitwise or"),
+    ("#xor", 'X', 2, "bitwise exclusive or"),
+    ("<<", '<', 2, "shift left"),
+    (">>", '>', 2, "shift right"),
+    // Comparisons, giving 1 for true and 0 for false
+    ("<=", '≤', 2, "less than or equal"),
+    (">=", '≥', 2, "greater than or equal"),
+    ("<", '⋖', 2, "less than"),
+    (">", '⋗', 2, "greater than"),
+    ("==", '≡', 2, "equal"),
+    ("!=", '≠', 2, "not equal"),
+    ("#not", 'N', 1, "bitwise not"),
+    // Primes
+    ("#isprime", '℗', 1, "whether the integer part is prime"),
+    ("#nextprime", '⇥', 1, "next prime up"),
+    ("#prevprime", '⇤', 1, "next prime down"),
+    ("#totient", '⊘', 1, "count of coprimes up to n"),
+    ("#sigma", '⊜', 1, "sum of divisors"),
+    ("#tau", '⊛', 1, "count of divisors"),
+    ("#mobius", '⊝', 1, "Möbius function"),
+    ("#fib", 'ℱ', 1, "Fibonacci number"),
+    ("#lucas", 'Ⅼ', 1, "Lucas number"),
+    // IEEE-754 encodings
+    ("#fromf32", 'z', 1, "value of an f32 bit pattern"),
+    ("#fromf64", 'Z', 1, "value of an f64 bit pattern"),
+    // Bits and digits of the integer part
+    ("#bitlen", '⌗', 1, "bit length"),
+    ("#popcount", '⌘', 1, "count of one bits"),
+    ("#digitsum", '⅀', 1, "sum of digits in the current base"),
+    ("#digitalroot", 'ϱ', 1, "digit sum repeated down to one digit"),
+    ("#ndigits", '⌸', 1, "count of digits in the current base"),
+    ("#reverse", '⇆', 1, "digits in reverse order"),
+    ("#rotl", '⥀', 2, "rotate digits left"),
+    ("#rotr", '⥁', 2, "rotate digits right"),
+    // Parentheses
+    ("(", '(', 1, "left parenthesis"),
+    (")", ')', 1, "right parenthesis"),
+    // Common functions
+    ("#sqrt", 'q', 1, "square root"),
+    ("#abs", 'a', 1, "absolute value"),
+    ("#ln", 'l', 1, "natural logarithm"),
+    // Fixed bases ahead of #log, which they start with
+    ("#log2", '₂', 1, "base 2 logarithm"),
+    ("#log10", '⏨', 1, "base 10 logarithm"),
+    ("#log", 'L', 1, "base logarithm"),
+    // Hyperbolic functions, ahead of the trig names they start with
+    ("#sinh", 'h', 1, "hyperbolic sine"),
+    ("#cosh", 'k', 1, "hyperbolic cosine"),
+    ("#tanh", 'y', 1, "hyperbolic tangent"),
+    ("#asinh", 'H', 1, "inverse hyperbolic sine"),
+    ("#acosh", 'K', 1, "inverse hyperbolic cosine"),
+    ("#atanh", 'Y', 1, "inverse hyperbolic tangent"),
+    // Trigonometric functions
+    ("#sin", 's', 1, "sine"),
+    ("#cos", 'o', 1, "cosine"),
+    ("#tan", 't', 1, "tangent"),
+    ("#asin", 'S', 1, "inverse sine"),
+    ("#acos", 'O', 1, "inverse cosine"),
+    ("#atan", 'T', 1, "inverse tangent"),
+    // Rounding and parts
+    ("#ceil", 'c', 1, "gaussian ceiling"),
+    ("#floor", 'f', 1, "gaussian floor"),
+    ("#round", 'r', 1, "gaussian rounding"),
+    ("#int", 'I', 1, "integer part"),
+    ("#frac", 'F', 1, "fractional part"),
+    // Complex number operations
+    ("#re", 'e', 1, "real"),
+    ("#im", 'i', 1, "imaginary"),
+    ("#angle", 'A', 1, "complex angle"),
+    // Miscellaneous
+    ("#sign", 'g', 1, "sign"),
+    // The complement and inverse ahead of #erf, which they start with
+    ("#erfc", 'ξ', 1, "complementary error function"),
+    ("#erfinv", 'Ξ', 1, "inverse error function"),
+    ("#erf", 'x', 1, "error function"),
+    ("#phi", 'Φ', 1, "standard normal distribution"),
+    ("#gamma", 'j', 1, "gamma function"),
+    ("#zeta", 'ζ', 1, "Riemann zeta function"),
+    ("#digamma", 'ψ', 1, "digamma function"),
+    ("#dms", 'D', 1, "angle as degrees.minutes seconds"),
+    // Timestamps
+    ("#jd", 'ȷ', 1, "Julian day of a Unix time"),
+    ("#unix", 'ū', 1, "Unix time of a Julian day"),
+    // Statistics over the elements of a list
+    ("#sum", '∑', 1, "sum of a list, or of a series"),
+    ("#prod", '∏', 1, "product of a list, or of a series"),
+    ("#mean", 'μ', 1, "mean of a list"),
+    ("#median", 'm', 1, "median of a list"),
+    ("#stdev", 'σ', 1, "sample standard deviation of a list"),
+    ("#var", 'ς', 1, "sample variance of a list"),
+    ("#min", '↓', 1, "smallest of a list"),
+    ("#max", '↑', 1, "largest of a list"),
+    ("=", '=', 2, "assignment"),
+    // ("#max", 'M', 2, "maximum"),
+    // ("#min", 'm', 2, "minimum"),
+];
+static CONSTANTS: [(&str, char, &str); 8] = [
+    ("@pi", 'p', "Pi"),
+    ("@phi", 'P', "Golden ratio"),
+    ("@e", 'E', "Euler's number"),
+    ("@gamma", 'G', "Euler-Mascheroni constant"),
+    ("@rand", 'r', "Random number between 0 and 1"),
+    ("@grand", 'g', "Gaussian random number"),
+    ("@now", 'W', "Current Unix time in seconds"),
+    ("&", '&', "Previous result, &2 the one before"),
+];
+/// Physical constants under `@phys.`, as (name, value, unit, description)
+///
+/// Values are CODATA 2018, exact where the SI defines them. Derived constants have
+/// no value here and are worked out by `physical_constant` at the current precision.
+/// Underscores and case are ignored when matching names, so `@phys.kb` is `@phys.k_B`.
+static PHYSICAL_CONSTANTS: [(&str, &str, &str, &str); 18] = [
+    ("c", "299792458", "m/s", "Speed of light in vacuum"),
+    ("h", "6.62607015e-34", "J s", "Planck constant"),
+    ("hbar", "", "J s", "Reduced Planck constant"),
+    ("e", "1.602176634e-19", "C", "Elementary charge"),
+    ("k_B", "1.380649e-23", "J/K", "Boltzmann constant"),
+    ("N_A", "6.02214076e23", "1/mol", "Avogadro constant"),
+    ("R", "", "J/(mol K)", "Molar gas constant"),
+    ("sigma", "", "W/(m^2 K^4)", "Stefan-Boltzmann constant"),
+    ("G", "6.67430e-11", "m^3/(kg s^2)", "Newtonian constant of gravitation"),
+    ("g_n", "9.80665", "m/s^2", "Standard acceleration of gravity"),
+    ("atm", "101325", "Pa", "Standard atmosphere"),
+    ("m_e", "9.1093837015e-31", "kg", "Electron mass"),
+    ("m_p", "1.67262192369e-27", "kg", "Proton mass"),
+    ("m_n", "1.67492749804e-27", "kg", "Neutron mass"),
+    ("m_u", "1.66053906660e-27", "kg", "Atomic mass constant"),
+    ("eps0", "8.8541878128e-12", "F/m", "Vacuum electric permittivity"),
+    ("mu0", "1.25663706212e-6", "N/A^2", "Vacuum magnetic permeability"),
+    ("alpha", "7.2973525693e-3", "", "Fine-structure constant"),
+];
+/// Looks up a physical constant by name, ignoring case and underscores
+fn find_physical_constant(name: &str) -> Option<usize> {
+    let name = name.replace('_', "").to_ascii_lowercase();
+    PHYSICAL_CONSTANTS
+        .iter()
+        .position(|(constant, ..)| constant.replace('_', "").to_ascii_lowercase() == name)
+}
+/// Value of a physical constant at the given precision
+fn physical_constant(index: usize, precision: u32) -> Float {
+    let named = |name: &str| physical_constant(find_physical_constant(name).unwrap(), precision);
+    let pi = || Float::with_val(precision, rug::float::Constant::Pi);
+    match PHYSICAL_CONSTANTS[index] {
+        ("hbar", ..) => named("h") / (pi() * 2),
+        ("R", ..) => named("N_A") * named("k_B"),
+        // 2 pi^5 k^4 / (15 h^3 c^2)
+        ("sigma", ..) => {
+            pi().pow(5) * 2 * named("k_B").pow(4) / (named("h").pow(3) * named("c").pow(2) * 15)
+        }
+        (_, value, ..) => Float::with_val(precision, Float::parse(value).unwrap()),
+    }
+}
+/// Powers of the SI base units metre, kilogram, second, ampere, kelvin, mole and candela
+pub type Dimension = [i32; 7];
+pub const DIMENSIONLESS: Dimension = [0; 7];
+/// Units that can follow a number or be converted to with `#to`, as (name, size in
+/// SI units, dimension, whether SI prefixes like `k` and `m` may be put in front)
+static UNITS: [(&str, &str, Dimension, bool); 36] = [
+    // SI base units, with the gram standing in for the kilogram so `kg` is a prefix
+    ("m", "1", [1, 0, 0, 0, 0, 0, 0], true),
+    ("g", "1e-3", [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", "1", [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", "1", [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", "1", [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", "1", [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", "1", [0, 0, 0, 0, 0, 0, 1], true),
+    // Named derived units, which results are shown in when their dimension matches,
+    // see `dimension_label`
+    ("N", "1", [1, 1, -2, 0, 0, 0, 0], true),
+    ("J", "1", [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", "1", [2, 1, -3, 0, 0, 0, 0], true),
+    ("Pa", "1", [-1, 1, -2, 0, 0, 0, 0], true),
+    ("Hz", "1", [0, 0, -1, 0, 0, 0, 0], true),
+    ("C", "1", [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", "1", [2, 1, -3, -1, 0, 0, 0], true),
+    ("ohm", "1", [2, 1, -3, -2, 0, 0, 0], true),
+    ("F", "1", [-2, -1, 4, 2, 0, 0, 0], true),
+    ("T", "1", [0, 1, -2, -1, 0, 0, 0], true),
+    // Everything else
+    ("L", "1e-3", [3, 0, 0, 0, 0, 0, 0], true),
+    ("eV", "1.602176634e-19", [2, 1, -2, 0, 0, 0, 0], true),
+    ("Wh", "3600", [2, 1, -2, 0, 0, 0, 0], true),
+    ("cal", "4.184", [2, 1, -2, 0, 0, 0, 0], true),
+    ("bar", "1e5", [-1, 1, -2, 0, 0, 0, 0], true),
+    ("min", "60", [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", "3600", [0, 0, 1, 0, 0, 0, 0], false),
+    ("day", "86400", [0, 0, 1, 0, 0, 0, 0], false),
+    ("in", "0.0254", [1, 0, 0, 0, 0, 0, 0], false),
+    ("ft", "0.3048", [1, 0, 0, 0, 0, 0, 0], false),
+    ("yd", "0.9144", [1, 0, 0, 0, 0, 0, 0], false),
+    ("mi", "1609.344", [1, 0, 0, 0, 0, 0, 0], false),
+    ("nmi", "1852", [1, 0, 0, 0, 0, 0, 0], false),
+    ("lb", "0.45359237", [0, 1, 0, 0, 0, 0, 0], false),
+    ("oz", "0.028349523125", [0, 1, 0, 0, 0, 0, 0], false),
+    ("mph", "0.44704", [1, 0, -1, 0, 0, 0, 0], false),
+    ("kn", "0.514444444444444444444444444444444444", [1, 0, -1, 0, 0, 0, 0], false),
+    ("gal", "3.785411784e-3", [3, 0, 0, 0, 0, 0, 0], false),
+    ("atm", "101325", [-1, 1, -2, 0, 0, 0, 0], false),
+];
+static UNIT_PREFIXES: [(&str, &str); 10] = [
+    ("T", "1e12"),
+    ("G", "1e9"),
+    ("M", "1e6"),
+    ("k", "1e3"),
+    ("h", "1e2"),
+    ("c", "1e-2"),
+    ("m", "1e-3"),
+    ("u", "1e-6"),
+    ("n", "1e-9"),
+    ("p", "1e-12"),
+];
+/// Looks up a single unit such as `km` or `mph`, trying prefixes after whole names
+///
+/// # Returns
+/// * `Some((Float, Dimension))` - The size of the unit in SI units and its dimension
+/// * `None` - If there's no such unit
+fn find_unit(name: &str, precision: u32) -> Option<(Float, Dimension)> {
+    let parse = |value: &str| Float::with_val(precision, Float::parse(value).unwrap());
+    if let Some(&(_, size, dimension, _)) = UNITS.iter().find(|(unit, ..)| *unit == name) {
+        return Some((parse(size), dimension));
+    }
+    for &(prefix, scale) in &UNIT_PREFIXES {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            if let Some(&(_, size, dimension, _)) = UNITS.iter().find(|&&(unit, .., prefixed)| prefixed && unit == rest) {
+                return Some((parse(size) * parse(scale), dimension));
+            }
+        }
+    }
+    None
+}
+/// Parses a unit expression such as `m/s^2`, `kg m^2` or `J/(mol K)`
+///
+/// Units next to each other multiply, `/` divides by the unit after it and
+/// exponents are written in decimal whatever the base.
+///
+/// # Returns
+/// * `Ok((Float, Dimension))` - The size of the unit in SI units and its dimension
+/// * `Err(String)` - An error message if the unit isn't understood
+fn parse_unit(text: &str, precision: u32) -> Result<(Float, Dimension), String> {
+    let mut index = 0;
+    let unit = parse_unit_product(text.as_bytes(), &mut index, precision)?;
+    if index < text.len() {
+        return Err(format!("Unexpected '{}' in unit!", text[index..].chars().next().unwrap()));
+    }
+    Ok(unit)
+}
+fn parse_unit_product(input: &[u8], index: &mut usize, precision: u32) -> Result<(Float, Dimension), String> {
+    let mut size = Float::with_val(precision, 1);
+    let mut dimension = DIMENSIONLESS;
+    let mut divide = false;
+    let mut empty = true;
+    loop {
+        while *index < input.len() && (input[*index] == b' ' || input[*index] == b'*') {
+            *index += 1;
+        }
+        if *index >= input.len() || input[*index] == b')' {
+            break;
+        }
+        if input[*index] == b'/' {
+            divide = true;
+            *index += 1;
+            continue;
+        }
+        let (unit_size, unit_dimension) = if input[*index] == b'(' {
+            *index += 1;
+            let unit = parse_unit_product(input, index, precision)?;
+            if *index >= input.len() {
+                return Err("Mismatched parentheses in unit!".to_string());
+            }
+            *index += 1;
+            unit
+        } else {
+            let start = *index;
+            while *index < input.len() && input[*index].is_ascii_alphabetic() {
+                *index += 1;
+            }
+            let name = String::from_utf8_lossy(&input[start..*index]).to_string();
+            if name.is_empty() {
+                return Err(format!("Unexpected '{}' in unit!", input[start] as char));
+            }
+            find_unit(&name, precision).ok_or(format!("Unknown unit '{}'!", name))?
+        };
+        let mut power = 1;
+        if *index < input.len() && input[*index] == b'^' {
+            *index += 1;
+            let start = *index;
+            if *index < input.len() && input[*index] == b'-' {
+                *index += 1;
+            }
+            while *index < input.len() && input[*index].is_ascii_digit() {
+                *index += 1;
+            }
+            power = std::str::from_utf8(&input[start..*index])
+                .unwrap()
+                .parse::<i32>()
+                .map_err(|_| "Invalid unit exponent!".to_string())?;
+        }
+        if divide {
+            power = power.checked_neg().ok_or(EXPONENT_TOO_LARGE)?;
+            divide = false;
+        }
+        size *= unit_size.pow(power);
+        dimension = combine_dimensions(&dimension, &unit_dimension, power)?;
+        empty = false;
+    }
+    if empty || divide {
+        return Err("Incomplete unit!".to_string());
+    }
+    Ok((size, dimension))
+}
+/// The error for a unit exponent beyond what a `Dimension` can hold
+const EXPONENT_TOO_LARGE: &str = "Unit exponent too large!";
+/// Adds `scale` times the exponents of `b` to those of `a`, as when multiplying units
+///
+/// Exponents that don't fit are an error rather than wrapping around. `i32::MIN`
+/// is refused too, so every exponent can be negated.
+fn combine_dimensions(a: &Dimension, b: &Dimension, scale: i32) -> Result<Dimension, String> {
+    let mut dimension = *a;
+    for (total, part) in dimension.iter_mut().zip(b) {
+        *total = part
+            .checked_mul(scale)
+            .and_then(|part| total.checked_add(part))
+            .filter(|&total| total != i32::MIN)
+            .ok_or(EXPONENT_TOO_LARGE)?;
+    }
+    Ok(dimension)
+}
+/// Finds the unit starting at `index` in an expression, if there is one
+///
+/// A quoted unit (`"m/s"`) can be used in any base. Bare units (`3 km`) are only
+/// recognised where their first letter can't be a digit in the current base, so
+/// they never change the meaning of a number. A bare unit may carry a decimal
+/// exponent, as in `2 m^2`.
+///
+/// # Returns
+/// * `Some((String, usize))` - The unit's text and the index after it
+/// * `None` - If there is no unit here
+fn unit_at(input: &[u8], index: usize, base: u8, balanced: bool) -> Option<(String, usize)> {
+    let c = *input.get(index)?;
+    if c == b'"' {
+        let length = input[index + 1..].iter().position(|&c| c == b'"')?;
+        let text = String::from_utf8_lossy(&input[index + 1..index + 1 + length]).to_string();
+        return Some((text, index + length + 2));
+    }
+    if !c.is_ascii_alphabetic() || is_base_digit(c.to_ascii_uppercase() - b'A' + 10, base, balanced) {
+        return None;
+    }
+    let mut end = index;
+    while end < input.len() && input[end].is_ascii_alphabetic() {
+        end += 1;
+    }
+    let name = String::from_utf8_lossy(&input[index..end]).to_string();
+    find_unit(&name, 64)?;
+    if end + 1 < input.len() && input[end] == b'^' {
+        let mut exponent_end = end + 1;
+        if input[exponent_end] == b'-' {
+            exponent_end += 1;
+        }
+        let digits_start = exponent_end;
+        while exponent_end < input.len() && input[exponent_end].is_ascii_digit() {
+            exponent_end += 1;
+        }
+        if exponent_end > digits_start {
+            end = exponent_end;
+        }
+    }
+    Some((String::from_utf8_lossy(&input[index..end]).to_string(), end))
+}
+/// Names a dimension in SI units, using a named unit like `N` when one fits
+fn dimension_label(dimension: &Dimension) -> String {
+    if *dimension == DIMENSIONLESS {
+        return "no units".to_string();
+    }
+    let derived = ["N", "J", "W", "Pa", "Hz", "C", "V", "ohm", "F", "T"];
+    if let Some(&(name, ..)) = UNITS
+        .iter()
+        .find(|&&(name, _, unit, _)| derived.contains(&name) && unit == *dimension)
+    {
+        return name.to_string();
+    }
+    // Listed the way they're usually written, kilograms first
+    let order = [(1, "kg"), (0, "m"), (2, "s"), (3, "A"), (4, "K"), (5, "mol"), (6, "cd")];
+    let part = |name: &str, power: i32| {
+        if power == 1 {
+            name.to_string()
+        } else {
+            format!("{}^{}", name, power)
+        }
+    };
+    let numerator: Vec<String> = order
+        .iter()
+        .filter(|&&(i, _)| dimension[i] > 0)
+        .map(|&(i, name)| part(name, dimension[i]))
+        .collect();
+    let denominator: Vec<String> = order
+        .iter()
+        .filter(|&&(i, _)| dimension[i] < 0)
+        .map(|&(i, name)| part(name, -dimension[i]))
+        .collect();
+    let numerator = if numerator.is_empty() { "1".to_string() } else { numerator.join(" ") };
+    match denominator.len() {
+        0 => numerator,
+        1 => format!("{}/{}", numerator, denominator[0]),
+        _ => format!("{}/({})", numerator, denominator.join(" ")),
+    }
+}
+#[derive(Clone)]
+pub struct RGBValues {
+    pub lone_integer: (u8, u8, u8),
+    pub lone_fraction: (u8, u8, u8),
+    pub real_integer: (u8, u8, u8),
+    pub real_fraction: (u8, u8, u8),
+    pub imaginary_integer: (u8, u8, u8),
+    pub imaginary_fraction: (u8, u8, u8),
+    pub exponent: (u8, u8, u8),
+    pub decimal: (u8, u8, u8),
+    pub sign: (u8, u8, u8),
+    pub tilde: (u8, u8, u8),
+    pub carat: (u8, u8, u8),
+    pub error: (u8, u8, u8),
+    pub brackets: (u8, u8, u8),
+    pub comma: (u8, u8, u8),
+    pub colon: (u8, u8, u8),
+    pub nan: (u8, u8, u8),
+    pub message: (u8, u8, u8),
+}
+/// Names of the themes built into basecalc, see [`load_theme`]
+pub const BUILT_IN_THEMES: [&str; 3] = ["dark", "light", "monochrome"];
+impl RGBValues {
+    /// The original palette, for dark terminal backgrounds
+    pub fn dark() -> Self {
+        RGBValues {
+            lone_integer: (0x94, 0xc9, 0x9b),
+            lone_fraction: (0x6a, 0xce, 0xb0),
+            real_integer: (0x81, 0xc6, 0xdc),
+            real_fraction: (0xa5, 0xbe, 0xe7),
+            imaginary_integer: (0xe5, 0xae, 0xa0),
+            imaginary_fraction: (0xf9, 0xa0, 0xc8),
+            exponent: (0x9C, 0x27, 0xB0),
+            decimal: (0xFF, 0xff, 0xff),
+            sign: (0xF4, 0x43, 0x36),
+            tilde: (0x78, 0x90, 0xCC),
+            carat: (0xFF, 0xC1, 0x07),
+            error: (0xE5, 0x39, 0x35),
+            brackets: (0x8B, 0xC3, 0x4A),
+            comma: (0xBD, 0xBD, 0xBD),
+            colon: (0x78, 0x90, 0x9C),
+            nan: (0xc0, 0x0D, 0xfB),
+            message: (0x9E, 0x35, 0xe1),
+        }
+    }
+    /// Deeper shades that stay readable on light backgrounds
+    pub fn light() -> Self {
+        RGBValues {
+            lone_integer: (0x2E, 0x7D, 0x32),
+            lone_fraction: (0x00, 0x79, 0x6B),
+            real_integer: (0x15, 0x65, 0xC0),
+            real_fraction: (0x30, 0x3F, 0x9F),
+            imaginary_integer: (0xBF, 0x36, 0x0C),
+            imaginary_fraction: (0xAD, 0x14, 0x57),
+            exponent: (0x6A, 0x1B, 0x9A),
+            decimal: (0x21, 0x21, 0x21),
+            sign: (0xC6, 0x28, 0x28),
+            tilde: (0x28, 0x35, 0x93),
+            carat: (0xE6, 0x51, 0x00),
+            error: (0xB7, 0x1C, 0x1C),
+            brackets: (0x55, 0x8B, 0x2F),
+            comma: (0x61, 0x61, 0x61),
+            colon: (0x45, 0x5A, 0x64),
+            nan: (0x8E, 0x24, 0xAA),
+            message: (0x6A, 0x1B, 0x9A),
+        }
+    }
+    /// Greys only, told apart by brightness
+    pub fn monochrome() -> Self {
+        let bright = (0xFF, 0xFF, 0xFF);
+        let normal = (0xC8, 0xC8, 0xC8);
+        let dim = (0x90, 0x90, 0x90);
+        RGBValues {
+            lone_integer: bright,
+            lone_fraction: normal,
+            real_integer: bright,
+            real_fraction: normal,
+            imaginary_integer: bright,
+            imaginary_fraction: normal,
+            exponent: dim,
+            decimal: bright,
+            sign: bright,
+            tilde: dim,
+            carat: bright,
+            error: bright,
+            brackets: dim,
+            comma: dim,
+            colon: dim,
+            nan: bright,
+            message: normal,
+        }
+    }
+    /// The colour with a given field name, as used in theme files
+    fn field_mut(&mut self, name: &str) -> Option<&mut (u8, u8, u8)> {
+        Some(match name {
+            "lone_integer" => &mut self.lone_integer,
+            "lone_fraction" => &mut self.lone_fraction,
+            "real_integer" => &mut self.real_integer,
+            "real_fraction" => &mut self.real_fraction,
+            "imaginary_integer" => &mut self.imaginary_integer,
+            "imaginary_fraction" => &mut self.imaginary_fraction,
+            "exponent" => &mut self.exponent,
+            "decimal" => &mut self.decimal,
+            "sign" => &mut self.sign,
+            "tilde" => &mut self.tilde,
+            "carat" => &mut self.carat,
+            "error" => &mut self.error,
+            "brackets" => &mut self.brackets,
+            "comma" => &mut self.comma,
+            "colon" => &mut self.colon,
+            "nan" => &mut self.nan,
+            "message" => &mut self.message,
+            _ => return None,
+        })
+    }
+}
+/// Turns coloured output on or off according to `:color` and the environment
+///
+/// Unless `:color on` or `:color off` says otherwise, output is coloured only when
+/// stdout is a terminal, `NO_COLOR` is unset and `TERM` isn't `dumb`.
+pub fn apply_colour_setting(state: &BasecalcState) {
+    match state.colour {
+        Some(colour) => colored::control::set_override(colour),
+        None if std::env::var("TERM").is_ok_and(|term| term == "dumb") => {
+            colored::control::set_override(false)
+        }
+        None => colored::control::unset_override(),
+    }
+}
+
+/// Directory holding user theme files, `<name>.toml`
+fn get_theme_dir() -> PathBuf {
+    let mut path = get_basecalc_dir();
+    path.push("themes");
+    path
+}
+fn get_sequence_dir() -> PathBuf {
+    let mut path = get_basecalc_dir();
+    path.push("sequences");
+    path
+}
+/// Where a sequence saved by `:save` lives, `<name>.txt` in the `sequences` folder
+/// of the basecalc directory
+fn sequence_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid sequence name '{}'!", name));
+    }
+    let mut path = get_sequence_dir();
+    path.push(format!("{}.txt", name));
+    Ok(path)
+}
+/// Lists the sequences saved by `:save`, sorted by name
+fn available_sequences() -> Vec<String> {
+    let mut sequences: Vec<String> = fs::read_dir(get_sequence_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(extension) if extension == "txt" => {
+                    path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    sequences.sort();
+    sequences
+}
+/// Loads a colour theme by name
+///
+/// The built-in `dark`, `light` and `monochrome` themes come first, then
+/// `<name>.toml` in the `themes` folder of the basecalc directory. Theme files hold
+/// lines like `error = "#E53935"` or `error = [229, 57, 53]`, named after the
+/// [`RGBValues`] fields, and any colour left out keeps its `dark` value.
+///
+/// # Returns
+/// * `Ok(RGBValues)` - The theme's colours
+/// * `Err(String)` - If there is no such theme or its file is malformed
+pub fn load_theme(name: &str) -> Result<RGBValues, String> {
+    match name {
+        "dark" => return Ok(RGBValues::dark()),
+        "light" => return Ok(RGBValues::light()),
+        "monochrome" => return Ok(RGBValues::monochrome()),
+        _ => {}
+    }
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid theme name '{}'!", name));
+    }
+    let mut path = get_theme_dir();
+    path.push(format!("{}.toml", name));
+    let text = fs::read_to_string(&path).map_err(|_| format!("Unknown theme '{}'!", name))?;
+    parse_theme(&text).map_err(|(msg, line)| format!("Theme '{}' line {}: {}", name, line, msg))
+}
+/// Lists the built-in themes followed by any theme files, sorted by name
+pub fn available_themes() -> Vec<String> {
+    let mut themes: Vec<String> = BUILT_IN_THEMES.iter().map(|name| name.to_string()).collect();
+    let mut files: Vec<String> = fs::read_dir(get_theme_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(extension) if extension == "toml" => {
+                    path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+                }
+                _ => None,
+            }
+        })
+        .filter(|name| !themes.contains(name))
+        .collect();
+    files.sort();
+    themes.extend(files);
+    themes
+}
+/// Parses the `key = colour` lines of a theme file
+///
+/// # Returns
+/// * `Ok(RGBValues)` - The colours, starting from the dark theme
+/// * `Err((String, usize))` - An error message and its line number
+fn parse_theme(text: &str) -> Result<RGBValues, (String, usize)> {
+    let mut colours = RGBValues::dark();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        // '#' starts a comment, except at the start of a quoted hex colour
+        let line = match line.find('#') {
+            Some(hash) if !line[..hash].ends_with('"') => &line[..hash],
+            _ => line,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('[') {
+            continue; // Blank lines and [table] headers
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(("Expected 'name = colour'!".to_string(), number))?;
+        let key = key.trim();
+        let field = colours
+            .field_mut(key)
+            .ok_or((format!("Unknown colour '{}'!", key), number))?;
+        *field = parse_theme_colour(value.trim())
+            .ok_or(("Expected \"#RRGGBB\" or [r, g, b]!".to_string(), number))?;
+    }
+    Ok(colours)
+}
+fn parse_theme_colour(value: &str) -> Option<(u8, u8, u8)> {
+    if let Some(hex) = value.strip_prefix("\"#").and_then(|rest| rest.strip_suffix('"')) {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some((channel(0)?, channel(2)?, channel(4)?));
+    }
+    let list = value.strip_prefix('[')?.strip_suffix(']')?;
+    let channels: Vec<u8> = list
+        .split(',')
+        .map(|channel| channel.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match channels[..] {
+        [r, g, b] => Some((r, g, b)),
+        _ => None,
+    }
+}
+/// Environment variables that set up basecalc, and the settings they stand for
+const ENVIRONMENT_SETTINGS: [(&str, &str); 5] = [
+    ("BASECALC_BASE", "base"),
+    ("BASECALC_DIGITS", "digits"),
+    ("BASECALC_ANGLE", "angle"),
+    ("BASECALC_THEME", "theme"),
+    ("BASECALC_PADDING", "padding"),
+];
+/// Settings read from `config.toml` in the basecalc directory at every launch
+///
+/// Unlike the state file, basecalc never writes this one, so it holds the defaults
+/// you want back each time whatever the last session changed. Anything left out
+/// keeps its saved value. Environment variables and then command-line flags set the
+/// same things, on top.
+#[derive(Default, Debug, PartialEq)]
+pub struct Config {
+    base: Option<u8>,
+    digits: Option<usize>,
+    angle: Option<AngleMode>,
+    theme: Option<String>,
+    padding: Option<u32>,
+    json: Option<bool>,
+    /// Definition files run quietly once the settings are in place
+    load: Vec<String>,
+}
+impl Config {
+    /// Takes one setting, such as `base` and `16`, replacing any given before
+    ///
+    /// Numbers are plain decimal rather than digits in some base, and names may be
+    /// quoted or not.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .unwrap_or(value);
+        let integer = || value.parse::<usize>().map_err(|_| "Expected a whole number!".to_string());
+        match key {
+            "base" => match integer()? {
+                base @ 2..=36 => self.base = Some(base as u8),
+                _ => return Err("Base must be from 2 to 36!".to_string()),
+            },
+            "digits" => match integer()? {
+                0 => return Err("Digits must be positive!".to_string()),
+                digits => self.digits = Some(digits),
+            },
+            "angle" => {
+                self.angle = Some(match value.to_ascii_lowercase().as_str() {
+                    "radians" => AngleMode::Radians,
+                    "degrees" => AngleMode::Degrees,
+                    "gradians" => AngleMode::Gradians,
+                    _ => return Err("Expected radians, degrees or gradians!".to_string()),
+                })
+            }
+            "theme" => self.theme = Some(value.to_ascii_lowercase()),
+            "padding" => {
+                self.padding =
+                    Some(u32::try_from(integer()?).map_err(|_| "Padding is too large!".to_string())?)
+            }
+            "json" => {
+                self.json = Some(match value.to_ascii_lowercase().as_str() {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err("Expected on or off!".to_string()),
+                })
+            }
+            key => return Err(format!("Unknown setting '{}'!", key)),
+        }
+        Ok(())
+    }
+    /// Takes the settings given by `BASECALC_BASE` and friends, where they are set
+    ///
+    /// # Returns
+    /// * `Err(String)` - The first variable holding something unusable, after taking the rest
+    pub fn set_from_environment(&mut self) -> Result<(), String> {
+        let mut result = Ok(());
+        for (variable, key) in ENVIRONMENT_SETTINGS {
+            let Ok(value) = std::env::var(variable) else {
+                continue;
+            };
+            if value.trim().is_empty() {
+                continue;
+            }
+            if let Err(msg) = self.set(key, &value) {
+                result = result.and(Err(format!("{}={}: {}", variable, value, msg)));
+            }
+        }
+        result
+    }
+    /// Applies the settings over a state, then runs the `load` files
+    ///
+    /// Files are run a line at a time, blank lines and `//` comments skipped, as a
+    /// script would be. Relative paths are taken from the basecalc directory. Nothing
+    /// they print is shown unless it is an error.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - Problems with the theme or files, for the caller to report
+    pub fn apply(&self, state: &mut BasecalcState) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(base) = self.base {
+            state.base = base;
+            state.balanced = false;
+            state.radix = None;
+        }
+        if let Some(digits) = self.digits {
+            state.digits = digits;
+        }
+        if let Some(angle) = self.angle {
+            state.angle = angle;
+        }
+        if let Some(padding) = self.padding {
+            state.padding = padding;
+        }
+        if let Some(json) = self.json {
+            state.json = json;
+        }
+        state.set_precision();
+        if let Some(name) = &self.theme {
+            match load_theme(name) {
+                Ok(colours) => {
+                    state.colours = colours;
+                    state.theme = name.clone();
+                }
+                Err(msg) => problems.push(msg),
+            }
+        }
+        let mut directory = get_config_file_path();
+        directory.pop();
+        for file in &self.load {
+            let script = match fs::read_to_string(directory.join(file)) {
+                Ok(script) => script,
+                Err(e) => {
+                    problems.push(format!("Couldn't load {}: {}", file, e));
+                    continue;
+                }
+            };
+            for (number, line) in script.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with("//") {
+                    continue;
+                }
+                let (output, success) = transcript_output(line, state);
+                if !success {
+                    let msg = output.last().map_or("", |msg| msg.as_str());
+                    problems.push(format!("{} line {}: {}", file, number + 1, msg));
+                }
+            }
+        }
+        problems
+    }
+}
+fn get_config_file_path() -> PathBuf {
+    let mut path = get_basecalc_dir();
+    path.push("config.toml");
+    path
+}
+/// Reads `config.toml` from the basecalc directory
+///
+/// # Returns
+/// * `Ok(Config)` - The settings given, none if there is no such file
+/// * `Err(String)` - If the file is malformed
+pub fn load_config() -> Result<Config, String> {
+    match fs::read_to_string(get_config_file_path()) {
+        Ok(text) => parse_config(&text)
+            .map_err(|(msg, line)| format!("config.toml line {}: {}", line, msg)),
+        Err(_) => Ok(Config::default()),
+    }
+}
+/// Parses the `key = value` lines of `config.toml`
+///
+/// # Returns
+/// * `Ok(Config)` - The settings given
+/// * `Err((String, usize))` - An error message and its line number
+fn parse_config(text: &str) -> Result<Config, (String, usize)> {
+    let mut config = Config::default();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let line = match line.find('#') {
+            Some(hash) if line[..hash].matches('"').count() % 2 == 0 => &line[..hash],
+            _ => line,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('[') {
+            continue; // Blank lines and [table] headers
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(("Expected 'name = value'!".to_string(), number))?;
+        if key.trim() != "load" {
+            config.set(key.trim(), value).map_err(|msg| (msg, number))?;
+            continue;
+        }
+        let expected = || ("Expected a list of quoted file names!".to_string(), number);
+        let list = value
+            .trim()
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+            .ok_or_else(expected)?;
+        for file in list.split(',').map(str::trim).filter(|file| !file.is_empty()) {
+            let file = file
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(expected)?;
+            config.load.push(file.to_string());
+        }
+    }
+    Ok(config)
+}
+pub static DEBUG: AtomicBool = AtomicBool::new(false);
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Precedence {
+    Comparison,
+    BitwiseOr,
+    BitwiseXor,
+    BitwiseAnd,
+    Shift,
+    Addition,
+    Multiplication,
+    Exponentiation,
+    Unary,
+    Parenthesis,
+    Assignment,
+}
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Token {
+    operator: char,
+    operands: u8,
+    real_integer: Vec<u8>,
+    real_fraction: Vec<u8>,
+    imaginary_integer: Vec<u8>,
+    imaginary_fraction: Vec<u8>,
+    sign: (bool, bool),
+    var_index: Option<usize>,
+    arguments: Vec<String>,
+    /// A `[r; θ]` literal, holding magnitude and angle in place of real and imaginary
+    polar: bool,
+}
+use std::fmt;
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn number_vector_to_string(vec: &[u8]) -> String {
+            let mut s = String::new();
+            for i in 0..vec.len() {
+                let c = vec[i];
+                if c > 9 {
+                    s.push((c - 10 + b'A') as char);
+                } else {
+                    s.push((c + b'0') as char);
+                }
+            }
+            s
+        }
+        if self.operator as u8 > 1 {
+            write!(f, "{}:", self.operator)?;
+        } else if self.operator as u8 == 1 {
+            write!(f, "№:")?;
+        }
+
+        write!(f, "{}[", self.operands)?;
+
+        if self.sign.0 {
+            write!(f, "-")?;
+        } else {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", number_vector_to_string(&self.real_integer))?;
+        write!(f, ".{} ", number_vector_to_string(&self.real_fraction))?;
+        write!(f, "{} ", if self.polar { ';' } else { ',' })?;
+
+        if self.sign.1 {
+            write!(f, "-")?;
+        } else {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", number_vector_to_string(&self.imaginary_integer))?;
+        write!(f, ".{}", number_vector_to_string(&self.imaginary_fraction))?;
+
+        write!(f, "]")
+    }
+}
+impl Token {
+    fn new() -> Token {
+        Token {
+            operator: 0 as char,
+            operands: 0,
+            real_integer: Vec::new(),
+            real_fraction: Vec::new(),
+            imaginary_integer: Vec::new(),
+            imaginary_fraction: Vec::new(),
+            sign: (false, false),
+            var_index: None,
+            arguments: Vec::new(),
+            polar: false,
+        }
+    }
+}
+trait Modulus {
+    fn modulus(&self, modulor: Complex) -> Complex;
+}
+impl Modulus for Complex {
+    fn modulus(&self, modulor: Complex) -> Complex {
+        let real = if modulor.real().is_zero() {
+            Float::with_val(self.real().prec(), 0) // Avoid division by zero
+        } else {
+            self.real().clone()
+                - (modulor.real().clone() * (self.real().clone() / modulor.real().clone()).floor())
+        };
+        let imaginary = if modulor.imag().is_zero() {
+            Float::with_val(self.imag().prec(), 0) // Avoid division by zero
+        } else {
+            self.imag().clone()
+                - (modulor.imag().clone() * (self.imag().clone() / modulor.imag().clone()).floor())
+        };
+        Complex::with_val(self.prec(), (real, imaginary))
+    }
+}
+/// Tokenizes the input string into a vector of Tokens
+///
+/// # Arguments
+/// * `input_str` - The input string to tokenize
+/// * `base` - The current number base
+/// * `precision` - The current precision for calculations
+/// * `digits` - The number of digits to display in results
+/// * `radians` - Whether to use radians for trigonometric functions
+/// * `colours` - The colour scheme for output formatting
+///
+/// # Returns
+/// * `Ok(Vec<Token>)` - A vector of tokens if successful
+/// * `Err((String, usize))` - An error message and the position of the error
+/// Tokenizes the input string into a vector of Tokens
+///
+/// # Arguments
+/// * `input_str` - The input string to tokenize
+/// * `base` - The current number base
+/// * `precision` - The current precision for calculations
+/// * `digits` - The number of digits to display in results
+/// * `radians` - Whether to use radians for trigonometric functions
+/// * `colours` - The colour scheme for output formatting
+///
+/// # Returns
+/// * `Ok(Vec<Token>)` - A vector of tokens if successful
+/// * `Err((String, usize))` - An error message and the position of the error
+pub fn tokenize(input_str: &str, state: &mut BasecalcState) -> Result<Vec<Token>, (String, usize)> {
+    // Base 12 takes the dozenal digits ↊ and ↋ for A and B, whichever digit set is shown
+    let dozenal;
+    let input_str = if state.base == 12 && input_str.contains(['↊', '↋']) {
+        dozenal = input_str.replace('↊', "A").replace('↋', "B");
+        &dozenal
+    } else {
+        input_str
+    };
+    tokenize_expression(input_str, state)
+        .map_err(|(msg, pos)| explain_unknown_name(input_str, msg, pos, state))
+}
+/// Rewrites an error that lands on a misspelt `#operator` or `@name`
+///
+/// Typos like `#sine` otherwise surface as whatever the tokenizer tripped over
+/// next, such as a bad digit. If the error falls inside a name that doesn't exist,
+/// the message says so, suggesting the closest known name within a typo or two.
+fn explain_unknown_name(input_str: &str, msg: String, pos: usize, state: &BasecalcState) -> (String, usize) {
+    let input = input_str.as_bytes();
+    if pos >= input.len() || input_str.trim_start().starts_with(':') {
+        return (msg, pos);
+    }
+    // Walk back to the start of the name the error sits in, if any
+    let mut start = pos;
+    while start > 0 && input[start].is_ascii_alphanumeric() {
+        start -= 1;
+    }
+    let sigil = input[start];
+    if sigil != b'#' && sigil != b'@' {
+        return (msg, pos);
+    }
+    let mut end = start + 1;
+    while end < input.len() && input[end].is_ascii_alphabetic() {
+        end += 1;
+    }
+    let mut candidates: Vec<String> = Vec::new();
+    if sigil == b'#' {
+        candidates.extend(
+            OPERATORS
+                .iter()
+                .filter(|(name, ..)| name.starts_with('#'))
+                .map(|(name, ..)| name.to_string()),
+        );
+        candidates.push("#to".to_string());
+        candidates.extend(BOUND_OPERATORS.iter().map(|(name, ..)| name.to_string()));
+        candidates.extend(CALL_OPERATORS.iter().map(|(name, ..)| name.to_string()));
+    } else {
+        candidates.extend(
+            CONSTANTS
+                .iter()
+                .filter(|(name, ..)| name.starts_with('@'))
+                .map(|(name, ..)| name.to_string()),
+        );
+        candidates.extend(state.variables.iter().map(|variable| format!("@{}", variable.name)));
+        candidates.extend(state.constants.iter().map(|constant| format!("@{}", constant.name)));
+        candidates.extend(state.functions.iter().map(|function| format!("@{}", function.name)));
+        // Parameters are stored under names starting with a digit, which can't be typed
+        candidates.retain(|name| !name[1..].starts_with(|c: char| c.is_ascii_digit()));
+    }
+    // Names may end in digits, like #fromf32, as long as the whole thing matches
+    let mut word_end = end;
+    while word_end < input.len() && input[word_end].is_ascii_alphanumeric() {
+        word_end += 1;
+    }
+    let with_digits = input_str[start..word_end].to_ascii_lowercase();
+    if word_end > end && candidates.contains(&with_digits) {
+        end = word_end;
+    }
+    // Only functions can be called, so @nope(1) isn't taken for @now
+    if sigil == b'@' && input_str[word_end..].trim_start().starts_with('(') {
+        candidates.retain(|name| state.functions.iter().any(|function| name[1..] == function.name));
+    }
+    let word = input_str[start..end].to_ascii_lowercase();
+    if end <= start + 1 || candidates.contains(&word) {
+        return (msg, pos);
+    }
+    // The digits may belong to a misspelt name like #fromf33 instead of a number
+    let mut forms = vec![word];
+    if with_digits != forms[0] {
+        forms.push(with_digits);
+    }
+    // Ties go to names that extend or shorten the typo, so #sine suggests #sin over #sinh
+    let suggestion = forms
+        .iter()
+        .flat_map(|form| candidates.iter().map(move |candidate| (form, candidate)))
+        .map(|(form, candidate)| {
+            let related = form.starts_with(candidate.as_str()) || candidate.starts_with(form.as_str());
+            (edit_distance(form, candidate), !related, form, candidate)
+        })
+        .filter(|&(distance, _, form, _)| match form.len() {
+            0..=2 => false,
+            3..=4 => distance <= 1,
+            _ => distance <= 2,
+        })
+        .min_by_key(|&(distance, unrelated, ..)| (distance, unrelated));
+    let word = suggestion.map_or(&forms[0], |(_, _, form, _)| form);
+    let suggestion = suggestion.map(|(.., candidate)| candidate);
+    match (sigil, suggestion) {
+        (b'#', Some(suggestion)) => (
+            format!("Unknown operator '{}', did you mean {}?", word, suggestion),
+            start,
+        ),
+        (b'#', None) => (format!("Unknown operator '{}'!", word), start),
+        (_, Some(suggestion)) => (
+            format!("Unknown name '{}', did you mean {}?", word, suggestion),
+            start,
+        ),
+        _ => (msg, pos),
+    }
+}
+/// Levenshtein distance between two ASCII names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.as_bytes().iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+/// Whether what starts at `index` can take a number in front of it as a factor
+///
+/// That's a bracket, an `@` name or a `#` function of one value, but not the
+/// operators between two values such as `#choose` or `#and`.
+fn implies_multiplication(input: &[u8], index: usize) -> bool {
+    match input[index] {
+        b'(' | b'@' => true,
+        b'#' => {
+            let lower = input[index..].to_ascii_lowercase();
+            let called = |name: &str| lower.starts_with(name.as_bytes());
+            // #and and #or between two values, as in 12#and(10), are the bitwise ones
+            let between = parse_operator(input, index).0.operands == 2;
+            BOUND_OPERATORS.iter().any(|(name, ..)| called(name))
+                || (!between && CALL_OPERATORS.iter().any(|(name, ..)| called(name)))
+                || called("#to")
+                || parse_operator(input, index).0.operands == 1
+        }
+        _ => false,
+    }
+}
+fn tokenize_expression(input_str: &str, state: &mut BasecalcState) -> Result<Vec<Token>, (String, usize)> {
+    debug_println(&format!("\nTokenizing: {}", input_str));
+    debug_println(&format!(
+        "Initial state: base={}, precision={}, digits={}, angles={}",
+        state.base, state.precision, state.digits, state.angle.name()
+    ));
+
+    let input = input_str.as_bytes();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    let mut paren_count = 0;
+    let mut start = true;
+    let mut expect_number = true;
+    let mut follows_number = false;
+
+    while index < input.len() {
+        debug_println(&format!(
+            "Processing character at index {}: '{}'",
+            index, input[index] as char
+        ));
+
+        if input[index] == b' ' || input[index] == b'_' || input[index] == b'\t' {
+            debug_println(&format!("Skipping whitespace"));
+            index += 1;
+            continue;
+        }
+        if start && input[index] == b':' {
+            debug_println(&format!("Command detected, parsing command"));
+            match parse_command(input, index + 1, state) {
+                CommandResult::Success(msg) => return Err((msg, std::usize::MAX)),
+                CommandResult::Error(msg, pos) => return Err((msg, pos)),
+                CommandResult::Silent => return Err(("".to_string(), std::usize::MAX)),
+            }
+        }
+        if start {
+            if let Some(result) = parse_assertion(input_str, state) {
+                return match result {
+                    Ok(msg) => Err((msg, usize::MAX)),
+                    Err((msg, pos)) => Err((msg, pos)),
+                };
+            }
+        }
+        if start && input[index] == b'@' {
+            if let Some(result) = parse_destructuring(input, index, state) {
+                return match result {
+                    Ok(msg) => Err((msg, usize::MAX)),
+                    Err((msg, pos)) => Err((msg, pos)),
+                };
+            }
+            if let Some(result) = parse_dependent_definition(input, index, state) {
+                return match result {
+                    Ok(msg) => Err((msg, usize::MAX)),
+                    Err((msg, pos)) => Err((msg, pos)),
+                };
+            }
+            if let Some(result) = parse_function_definition(input, index, state) {
+                return match result {
+                    Ok(msg) => Err((msg, usize::MAX)),
+                    Err((msg, pos)) => Err((msg, pos)),
+                };
+            }
+        }
+        // A number right up against a constant, variable, bracket or function
+        // multiplies it, as in 2@pi, 3(4+5) and 2#sqrt2
+        if follows_number
+            && tokens.last().is_some_and(|token: &Token| token.operator == 1 as char)
+            && !matches!(input[index - 1], b' ' | b'_' | b'\t')
+            && implies_multiplication(input, index)
+        {
+            debug_println("Adding implicit multiplication token");
+            tokens.push(Token {
+                operator: '*',
+                operands: 2,
+                ..Token::new()
+            });
+            expect_number = true;
+            follows_number = false;
+        }
+        if input[index] == b'(' {
+            if !start && follows_number {
+                debug_println(&format!(
+                    "Error: Expected operator, found opening parenthesis"
+                ));
+                return Err((format!("Expected operator!"), index));
+            }
+            debug_println(&format!("Adding opening parenthesis token"));
+            tokens.push(Token {
+                operator: '(',
+                operands: 1,
+                ..Token::new()
+            });
+            paren_count += 1;
+            index += 1;
+            continue;
+        }
+        if input[index] == b')' {
+            if paren_count == 0 {
+                debug_println(&format!("Error: Mismatched parentheses"));
+                return Err((format!("Mismatched parentheses!"), index));
+            }
+            if !follows_number {
+                debug_println(&format!(
+                    "Error: Expected number before closing parenthesis"
+                ));
+                return Err((format!("Expected number!"), index));
+            }
+            debug_println(&format!("Adding closing parenthesis token"));
+            tokens.push(Token {
+                operator: ')',
+                operands: 1,
+                ..Token::new()
+            });
+            paren_count -= 1;
+            index += 1;
+            continue;
+        }
+        if expect_number && input[index..].to_ascii_lowercase().starts_with(b"#to") {
+            let mut open = index + 3;
+            while open < input.len() && (input[open] == b' ' || input[open] == b'\t') {
+                open += 1;
+            }
+            if open < input.len() && input[open] == b'(' {
+                let (mut arguments, new_index) = split_call_arguments(input, open)?;
+                if arguments.len() != 2 {
+                    return Err(("#to expects a value and a unit!".to_string(), index));
+                }
+                let unit = arguments.pop().unwrap();
+                let unit = unit.trim().trim_matches('"').to_string();
+                parse_unit(&unit, state.precision).map_err(|msg| (msg, index))?;
+                arguments.push(unit);
+                tokens.push(Token {
+                    operator: 'J',
+                    arguments,
+                    ..Token::new()
+                });
+                index = new_index;
+                start = false;
+                expect_number = false;
+                follows_number = true;
+                continue;
+            }
+        }
+        let series = [("#sum", '∑'), ("#prod", '∏')]
+            .into_iter()
+            .find(|(name, _)| input[index..].to_ascii_lowercase().starts_with(name.as_bytes()));
+        if let (true, Some((name, operator))) = (expect_number, series) {
+            let mut open = index + name.len();
+            while open < input.len() && (input[open] == b' ' || input[open] == b'\t') {
+                open += 1;
+            }
+            if open < input.len() && input[open] == b'(' {
+                let (mut arguments, new_index) = split_call_arguments(input, open)?;
+                // One argument is a list, as in #sum({1, 2}), four a series
+                if arguments.len() == 4 {
+                    arguments[0] = bound_variable_name(&arguments[0])
+                        .ok_or_else(|| (format!("Invalid index name '{}'!", arguments[0].trim()), open + 1))?;
+                    tokens.push(Token {
+                        operator,
+                        arguments,
+                        ..Token::new()
+                    });
+                    index = new_index;
+                    start = false;
+                    expect_number = false;
+                    follows_number = true;
+                    continue;
+                }
+                if arguments.len() != 1 {
+                    return Err((format!("{} expects a list, or an index, start, end and term!", name), index));
+                }
+            }
+        }
+        // Operators over an expression in a variable, which comes second
+        let bound = BOUND_OPERATORS
+            .into_iter()
+            .find(|(name, ..)| input[index..].to_ascii_lowercase().starts_with(name.as_bytes()));
+        if let (true, Some((name, operator, count, parts))) = (expect_number, bound) {
+            let mut open = index + name.len();
+            while open < input.len() && (input[open] == b' ' || input[open] == b'\t') {
+                open += 1;
+            }
+            if open < input.len() && input[open] == b'(' {
+                let (mut arguments, new_index) = split_call_arguments(input, open)?;
+                if arguments.len() != count {
+                    return Err((format!("{} expects {}!", name, parts), index));
+                }
+                arguments[1] = bound_variable_name(&arguments[1])
+                    .ok_or_else(|| (format!("Invalid variable name '{}'!", arguments[1].trim()), open + 1))?;
+                tokens.push(Token {
+                    operator,
+                    arguments,
+                    ..Token::new()
+                });
+                index = new_index;
+                start = false;
+                expect_number = false;
+                follows_number = true;
+                continue;
+            }
+        }
+        // Functions of several values, each argument an expression of its own
+        // #and, #or and #not are the logical calls where a value is expected, bracket or
+        // not, and the bitwise operators anywhere else
+        let call = CALL_OPERATORS.into_iter().find(|(name, ..)| {
+            input[index..].to_ascii_lowercase().starts_with(name.as_bytes())
+                && !input.get(index + name.len()).is_some_and(u8::is_ascii_alphanumeric)
+        });
+        let mut open = index + call.map_or(0, |(name, ..)| name.len());
+        while open < input.len() && (input[open] == b' ' || input[open] == b'\t') {
+            open += 1;
+        }
+        let bracketed = open < input.len() && input[open] == b'(';
+        // Without a bracket, #not is the bitwise one, as in #not 0
+        let bitwise = !bracketed && parse_operator(input, index).0.operator != '\0';
+        if let (true, false, Some((name, operator, fewest, most, parts))) = (expect_number, bitwise, call) {
+            if !bracketed {
+                return Err((format!("{} expects {} in parentheses!", name, parts), index));
+            }
+            let (arguments, new_index) = split_call_arguments(input, open)?;
+            if !(fewest..=most).contains(&arguments.len()) {
+                return Err((format!("{} expects {}!", name, parts), index));
+            }
+            tokens.push(Token {
+                operator,
+                arguments,
+                ..Token::new()
+            });
+            index = new_index;
+            start = false;
+            expect_number = false;
+            follows_number = true;
+            continue;
+        }
+        if expect_number && input[index] == b'{' {
+            let (elements, new_index) = split_call_arguments(input, index)?;
+            if elements.is_empty() {
+                return Err(("Empty list!".to_string(), index));
+            }
+            tokens.push(Token {
+                operator: 'M',
+                arguments: elements,
+                ..Token::new()
+            });
+            index = new_index;
+            start = false;
+            expect_number = false;
+            follows_number = true;
+            continue;
+        }
+        if expect_number {
+            debug_println(&format!("Expecting a number or constant"));
+            match parse_constant(input, index, state) {
+                Ok((token, new_index)) => {
+                    debug_println(&format!("Parsed constant: {}", token));
+                    tokens.push(token);
+                    index = new_index;
+                    start = false;
+                    expect_number = false;
+                    follows_number = true;
+                    continue;
+                }
+                Err((msg, pos)) => {
+                    if input[index] == b'@' || input[index] == b'&' {
+                        debug_println("Error: Invalid constant or variable");
+                        return Err((msg, pos));
+                    }
+                    debug_println(&format!("Not a constant, trying to parse as number"));
+                }
+            }
+            if let Some(result) = parse_dms(input, index, state.base, state.balanced)
+                .or_else(|| parse_duration(input, index, state.base, state.balanced))
+            {
+                let (token, new_index) = result?;
+                tokens.push(token);
+                index = new_index;
+                start = false;
+                expect_number = false;
+                follows_number = true;
+                continue;
+            }
+            // A unit on its own, like the s in m/s, is one of that unit
+            if let Some((unit, new_index)) = unit_at(input, index, state.base, state.balanced) {
+                parse_unit(&unit, state.precision).map_err(|msg| (msg, index))?;
+                tokens.push(Token {
+                    operator: 'U',
+                    arguments: vec![unit],
+                    ..Token::new()
+                });
+                index = new_index;
+                start = false;
+                expect_number = false;
+                follows_number = true;
+                continue;
+            }
+            match parse_number(input, state.base, state.balanced, index) {
+                Ok((token, new_index)) => {
+                    debug_println(&format!("Parsed number: {}", token));
+                    tokens.push(token);
+                    index = new_index;
+                    start = false;
+                    expect_number = false;
+                    follows_number = true;
+                    continue;
+                }
+                Err((msg, pos)) => {
+                    debug_println(&format!(
+                        "Failed to parse as number, attempting to parse as operator"
+                    ));
+                    let (mut token, new_index) = parse_operator(input, index);
+                    if token.operator == '\0' || token.operands == 2 {
+                        if token.operator == '-' {
+                            token.operator = 'n';
+                            token.operands = 1;
+                            debug_println(&format!("Parsed unary negation operator: {}", token));
+                            tokens.push(token);
+                            index = new_index;
+                            continue;
+                        } else {
+                            debug_println(&format!("Error: Invalid token"));
+                            return Err((msg, pos));
+                        }
+                    }
+                    debug_println(&format!("Parsed unary operator: {}", token));
+                    tokens.push(token);
+                    index = new_index;
+                    start = false;
+                    expect_number = true;
+                    continue;
+                }
+            }
+        }
+        if follows_number {
+            if let Some((unit, new_index)) = unit_at(input, index, state.base, state.balanced) {
+                parse_unit(&unit, state.precision).map_err(|msg| (msg, index))?;
+                debug_println(&format!("Adding unit token: {}", unit));
+                tokens.push(Token {
+                    operator: 'U',
+                    operands: 1,
+                    arguments: vec![unit],
+                    ..Token::new()
+                });
+                index = new_index;
+                continue;
+            }
+        }
+        // A % closing off an expression or bracket is a percentage, otherwise modulus
+        if input[index] == b'%' && follows_number && ends_operand(input, index + 1) {
+            mark_percentage(&mut tokens);
+            index += 1;
+            continue;
+        }
+        if input[index] == b'!' && follows_number && input.get(index + 1) != Some(&b'=') {
+            debug_println("Adding factorial token");
+            tokens.push(Token {
+                operator: '!',
+                operands: 1,
+                ..Token::new()
+            });
+            index += 1;
+            continue;
+        }
+        let (token, new_index) = parse_operator(input, index);
+        if token.operator == '\0' {
+            debug_println(&format!("Error: Invalid operator"));
+            return Err((format!("Invalid operator!"), new_index));
+        }
+        if token.operands == 1 && follows_number {
+            debug_println(&format!("Error: Expected binary operator, found unary"));
+            return Err((format!("Expected operator!"), index));
+        }
+        debug_println(&format!("Parsed operator: {}", token));
+        tokens.push(token);
+        index = new_index;
+        expect_number = true;
+        follows_number = false;
+    }
+
+    if paren_count != 0 {
+        debug_println(&format!("Error: Mismatched parentheses at end of input"));
+        return Err((format!("Mismatched parentheses!"), input.len()));
+    }
+
+    if tokens.is_empty() {
+        debug_println(&format!("Error: Empty expression"));
+        return Err((format!("Empty expression"), 0));
+    }
+
+    let last_token = tokens.last().unwrap();
+    if last_token.operands > 0 && !matches!(last_token.operator, ')' | '!' | 'U') {
+        debug_println(&format!("Error: Incomplete expression at end of input"));
+        return Err((format!("Incomplete expression!"), input.len()));
+    }
+
+    debug_println(&format!("Tokenization completed successfully"));
+    for (i, token) in tokens.iter().enumerate() {
+        debug_println(&format!("Token {}: {}", i, token));
+    }
+
+    Ok(tokens)
+}
+/// Whether nothing but the end of the input or a closing bracket or comma is at `index`
+fn ends_operand(input: &[u8], mut index: usize) -> bool {
+    while index < input.len() && (input[index] == b' ' || input[index] == b'_' || input[index] == b'\t') {
+        index += 1;
+    }
+    index == input.len() || matches!(input[index], b')' | b',' | b'}')
+}
+/// Turns the operand ending the tokens into a percentage
+///
+/// It's wrapped as `٪(b)`, a hundredth of it, and a `+` or `-` before it becomes
+/// `⊕` or `⊖` so that `120 + 15%` adds 15% of 120, as on a desk calculator.
+fn mark_percentage(tokens: &mut Vec<Token>) {
+    let postfix = |token: &Token| token.operator == '!' || (token.operator == 'U' && token.operands == 1);
+    let mut start = tokens.len() - 1;
+    while start > 0 && postfix(&tokens[start]) {
+        start -= 1;
+    }
+    if tokens[start].operator == ')' {
+        let mut depth = 0;
+        loop {
+            match tokens[start].operator {
+                ')' => depth += 1,
+                '(' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            start -= 1;
+        }
+    }
+    // Prefix operators like #sqrt go with their operand
+    while start > 0
+        && tokens[start - 1].operands == 1
+        && !matches!(tokens[start - 1].operator, '(' | ')')
+        && !postfix(&tokens[start - 1])
+    {
+        start -= 1;
+    }
+    let operator = |operator: char, operands: u8| Token {
+        operator,
+        operands,
+        ..Token::new()
+    };
+    tokens.insert(start, operator('٪', 1));
+    tokens.insert(start + 1, operator('(', 1));
+    tokens.push(operator(')', 1));
+    if start > 0 && tokens[start - 1].operands == 2 {
+        match tokens[start - 1].operator {
+            '+' => tokens[start - 1].operator = '⊕',
+            '-' => tokens[start - 1].operator = '⊖',
+            _ => {}
+        }
+    }
+}
+/// Evaluates a vector of tokens and returns the result
+///
+/// # Arguments
+/// * `tokens` - The vector of tokens to evaluate
+/// * `base` - The current number base
+/// * `precision` - The precision for calculations
+/// * `rand_state` - The random state for random number generation
+/// * `radians` - Whether to use radians for trigonometric functions
+///
+/// # Returns
+/// * `Ok(Complex)` - The result of the evaluation as a complex number
+/// * `Err(String)` - An error message if evaluation fails
+pub fn evaluate_tokens(tokens: &[Token], state: &mut BasecalcState) -> Result<EvalResult, String> {
+    debug_println("\nEvaluating tokens:");
+
+    if uses_lists(tokens, state) {
+        if uses_units(tokens, state) {
+            return Err("Lists can't hold values with units!".to_string());
+        }
+        return evaluate_with_lists(tokens, state);
+    }
+    if uses_units(tokens, state) {
+        return evaluate_with_units(tokens, state);
+    }
+
+    // Check for variable assignment pattern (var = expr)
+    if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        // Get variable name and index
+        let var_index = tokens[0].var_index.ok_or("Invalid variable reference")?;
+
+        // Evaluate the right-hand side expression
+        let mut output_queue: Vec<Complex> = Vec::new();
+        let mut operator_stack: Vec<char> = Vec::new();
+
+        // Process tokens after the '=' sign
+        let mut tokens = tokens[2..].iter().peekable();
+        while let Some(token) = tokens.next() {
+            check_interrupt(state)?;
+            match token.operands {
+                0 => {
+                    let mut value = evaluate_operand(token, state)?;
+                    value = apply_factorials(value, &mut tokens, state)?;
+                    while let Some(&op) = operator_stack.last() {
+                        if get_precedence(op) == Precedence::Unary {
+                            let operator = operator_stack.pop().unwrap();
+                            value = apply_unary_operator(operator, value, state)?;
+                        } else {
+                            break;
+                        }
+                    }
+                    output_queue.push(value);
+                }
+                1 => {
+                    if token.operator == '(' {
+                        operator_stack.push('(');
+                    } else if token.operator == ')' {
+                        while let Some(&op) = operator_stack.last() {
+                            if op == '(' {
+                                operator_stack.pop();
+                                break;
+                            }
+                            apply_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                        }
+                        if let Some(value) = output_queue.pop() {
+                            output_queue.push(apply_factorials(value, &mut tokens, state)?);
+                        }
+                    } else {
+                        operator_stack.push(token.operator);
+                    }
+                }
+                2 => {
+                    while let Some(&op) = operator_stack.last() {
+                        if op == '(' || get_precedence(token.operator) > get_precedence(op) {
+                            break;
+                        }
+                        apply_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    }
+                    operator_stack.push(token.operator);
+                }
+                _ => return Err(format!("Invalid token: {}", token)),
+            }
+        }
+
+        while let Some(op) = operator_stack.pop() {
+            if op == '(' {
+                return Err("Mismatched parentheses".to_string());
+            }
+            apply_operator(&mut output_queue, op, state)?;
+        }
+
+        if output_queue.len() != 1 {
+            return Err("Invalid expression".to_string());
+        }
+
+        let result = output_queue.pop().unwrap();
+        state.variables[var_index].value = result.clone();
+        state.variables[var_index].list = None;
+        state.variables[var_index].formula = None;
+        
+        Ok(EvalResult {
+            value: result,
+            assignment: Some(var_index),
+            unit: None,
+            list: None,
+        })
+
+    } else {
+        // Regular expression evaluation (unchanged)
+        let mut output_queue: Vec<Complex> = Vec::new();
+        let mut operator_stack: Vec<char> = Vec::new();
+
+        let mut tokens = tokens.iter().peekable();
+        while let Some(token) = tokens.next() {
+            debug_println(&format!("Processing token: {}", token));
+            check_interrupt(state)?;
+            match token.operands {
+                0 => {
+                    let mut value = evaluate_operand(token, state)?;
+                    debug_println(&format!("Processing number: {}", value));
+                    value = apply_factorials(value, &mut tokens, state)?;
+
+                    while let Some(&op) = operator_stack.last() {
+                        if get_precedence(op) == Precedence::Unary {
+                            debug_println(&format!("Applying stacked unary operator: {}", op));
+                            let operator = operator_stack.pop().unwrap();
+                            value = apply_unary_operator(operator, value, state)?;
+                        } else {
+                            break;
+                        }
+                    }
+
+                    debug_println(&format!("Pushed processed number to output queue: {}", value));
+                    output_queue.push(value);
+                }
+                1 => {
+                    debug_println(&format!("Processing unary operator: {}", token.operator));
+                    if token.operator == '(' {
+                        operator_stack.push('(');
+                        debug_println("Pushed opening parenthesis to stack");
+                    } else if token.operator == ')' {
+                        while let Some(&op) = operator_stack.last() {
+                            if op == '(' {
+                                operator_stack.pop();
+                                break;
+                            }
+                            apply_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                        }
+                        if let Some(value) = output_queue.pop() {
+                            output_queue.push(apply_factorials(value, &mut tokens, state)?);
+                        }
+                        if let Some(&op) = operator_stack.last() {
+                            if get_precedence(op) == Precedence::Unary {
+                                apply_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                            }
+                        }
+                    } else {
+                        debug_println(&format!("Pushed unary operator to stack: {}", token.operator));
+                        operator_stack.push(token.operator);
+                    }
+                }
+                2 => {
+                    while let Some(&op) = operator_stack.last() {
+                        if op == '(' || get_precedence(token.operator) > get_precedence(op) {
+                            break;
+                        }
+                        apply_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    }
+                    operator_stack.push(token.operator);
+                    debug_println(&format!("Pushed binary operator to stack: {}", token.operator));
+                }
+                _ => return Err(format!("Invalid token: {}", token)),
+            }
+            debug_println(&format!("Output queue: {:?}", output_queue));
+            debug_println(&format!("Operator stack: {:?}", operator_stack));
+        }
+
+        while let Some(op) = operator_stack.pop() {
+            if op == '(' {
+                return Err("Mismatched parentheses".to_string());
+            }
+            debug_println(&format!("Applying remaining operator: {}", op));
+            apply_operator(&mut output_queue, op, state)?;
+        }
+
+        if output_queue.len() != 1 {
+            return Err("Invalid expression".to_string());
+        }
+
+        Ok(EvalResult {
+            value: output_queue.pop().unwrap(),
+            assignment: None,
+            unit: None,
+            list: None,
+        })
+    }
+}
+/// A value along with its units, kept in SI units
+///
+/// `display` holds the unit and its size when `#to` asked for the value in
+/// something other than SI units.
+#[derive(Clone)]
+struct Quantity {
+    value: Complex,
+    dimension: Dimension,
+    display: Option<(String, Float)>,
+}
+impl Quantity {
+    fn plain(value: Complex) -> Quantity {
+        Quantity {
+            value,
+            dimension: DIMENSIONLESS,
+            display: None,
+        }
+    }
+}
+/// Whether an expression needs `evaluate_with_units` rather than plain numbers
+fn uses_units(tokens: &[Token], state: &BasecalcState) -> bool {
+    tokens.iter().any(|token| match token.operator {
+        'U' | 'J' | '⏱' => true,
+        'v' => token
+            .var_index
+            .is_some_and(|index| state.variables[index].dimension != DIMENSIONLESS),
+        _ => false,
+    })
+}
+/// Evaluates an expression with units, such as `3 m / 2 s` or `#to(60 km/h, "mph")`
+///
+/// The value is worked out in SI units and shown in them, naming the unit
+/// (`1.5 m/s`), unless `#to` picks another. Units don't pass through `&` or user
+/// functions, which only see the number shown.
+///
+/// # Returns
+/// * `Ok(EvalResult)` - The value and the unit it's shown in
+/// * `Err(String)` - An error message if evaluation fails or the units don't match
+fn evaluate_with_units(tokens: &[Token], state: &mut BasecalcState) -> Result<EvalResult, String> {
+    let (assignment, tokens) = if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        (Some(tokens[0].var_index.ok_or("Invalid variable reference")?), &tokens[2..])
+    } else {
+        (None, tokens)
+    };
+    let quantity = evaluate_quantity(tokens, state)?;
+    if let Some(index) = assignment {
+        state.variables[index].value = quantity.value.clone();
+        state.variables[index].dimension = quantity.dimension;
+        state.variables[index].list = None;
+        state.variables[index].formula = None;
+    }
+    let (value, unit) = match quantity.display {
+        Some((unit, size)) => (quantity.value / size, Some(unit)),
+        None if quantity.dimension == DIMENSIONLESS => (quantity.value, None),
+        None => (quantity.value, Some(dimension_label(&quantity.dimension))),
+    };
+    Ok(EvalResult { value, assignment, unit, list: None })
+}
+fn evaluate_quantity(tokens: &[Token], state: &mut BasecalcState) -> Result<Quantity, String> {
+    let mut output_queue: Vec<Quantity> = Vec::new();
+    let mut operator_stack: Vec<char> = Vec::new();
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        check_interrupt(state)?;
+        match token.operands {
+            0 => {
+                let mut value = operand_quantity(token, state)?;
+                value = apply_quantity_postfix(value, &mut tokens, state)?;
+                while let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) != Precedence::Unary {
+                        break;
+                    }
+                    value = unary_quantity(operator_stack.pop().unwrap(), value, state)?;
+                }
+                output_queue.push(value);
+            }
+            1 if token.operator == '(' => operator_stack.push('('),
+            1 if token.operator == ')' => {
+                while let Some(op) = operator_stack.pop() {
+                    if op == '(' {
+                        break;
+                    }
+                    apply_quantity_operator(&mut output_queue, op, state)?;
+                }
+                if let Some(value) = output_queue.pop() {
+                    output_queue.push(apply_quantity_postfix(value, &mut tokens, state)?);
+                }
+                if let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) == Precedence::Unary {
+                        apply_quantity_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    }
+                }
+            }
+            1 => operator_stack.push(token.operator),
+            _ => {
+                while let Some(&op) = operator_stack.last() {
+                    if op == '(' || get_precedence(token.operator) > get_precedence(op) {
+                        break;
+                    }
+                    apply_quantity_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                }
+                operator_stack.push(token.operator);
+            }
+        }
+    }
+    while let Some(op) = operator_stack.pop() {
+        if op == '(' {
+            return Err("Mismatched parentheses".to_string());
+        }
+        apply_quantity_operator(&mut output_queue, op, state)?;
+    }
+    match output_queue.pop() {
+        Some(quantity) if output_queue.is_empty() => Ok(quantity),
+        _ => Err("Invalid expression".to_string()),
+    }
+}
+fn operand_quantity(token: &Token, state: &mut BasecalcState) -> Result<Quantity, String> {
+    match token.operator {
+        'U' => {
+            let (size, dimension) = parse_unit(&token.arguments[0], state.precision)?;
+            Ok(Quantity {
+                value: Complex::with_val(state.precision, size),
+                dimension,
+                display: None,
+            })
+        }
+        '⏱' => {
+            let mut milliseconds = Complex::with_val(state.precision, 0);
+            for (part, (_, scale)) in token.arguments.iter().zip(DURATION_MARKS) {
+                if let Ok((number, _)) = parse_number(part.as_bytes(), state.base, state.balanced, 0) {
+                    milliseconds += token2num(&number, state) * scale;
+                }
+            }
+            let seconds: Complex = milliseconds / 1000;
+            Ok(Quantity {
+                value: if token.sign.0 { -seconds } else { seconds },
+                dimension: parse_unit("s", state.precision)?.1,
+                display: None,
+            })
+        }
+        'J' => {
+            if token.arguments[0].trim_start().starts_with(':') {
+                return Err("Commands are not allowed inside expressions!".to_string());
+            }
+            let tokens = tokenize(&token.arguments[0], state).map_err(|(msg, _)| msg)?;
+            if uses_lists(&tokens, state) {
+                return Err("Lists can't hold values with units!".to_string());
+            }
+            let quantity = evaluate_quantity(&tokens, state)?;
+            let unit = token.arguments[1].clone();
+            let (size, dimension) = parse_unit(&unit, state.precision)?;
+            if quantity.dimension != dimension {
+                return Err(format!(
+                    "Can't convert {} to {}!",
+                    dimension_label(&quantity.dimension),
+                    unit
+                ));
+            }
+            Ok(Quantity {
+                display: Some((unit, size)),
+                ..quantity
+            })
+        }
+        'v' => Ok(Quantity {
+            value: token2num(token, state),
+            dimension: token.var_index.map_or(DIMENSIONLESS, |index| state.variables[index].dimension),
+            display: None,
+        }),
+        _ => Ok(Quantity::plain(evaluate_operand(token, state)?)),
+    }
+}
+/// Applies any factorials and units that follow an operand, as in `3 m` or `(1+2) s`
+fn apply_quantity_postfix(
+    mut quantity: Quantity,
+    tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>,
+    state: &BasecalcState,
+) -> Result<Quantity, String> {
+    loop {
+        match tokens.peek() {
+            Some(token) if token.operator == '!' => {
+                if quantity.dimension != DIMENSIONLESS {
+                    return Err(format!(
+                        "Factorial needs a value without units, not {}!",
+                        dimension_label(&quantity.dimension)
+                    ));
+                }
+                quantity.value = apply_factorials(quantity.value, tokens, state)?;
+            }
+            Some(token) if token.operator == 'U' && token.operands == 1 => {
+                let (size, dimension) = parse_unit(&token.arguments[0], state.precision)?;
+                quantity.value *= size;
+                for (total, part) in quantity.dimension.iter_mut().zip(dimension) {
+                    *total += part;
+                }
+                quantity.display = None;
+                tokens.next();
+            }
+            _ => return Ok(quantity),
+        }
+    }
+}
+fn unary_quantity(op: char, quantity: Quantity, state: &BasecalcState) -> Result<Quantity, String> {
+    let dimension = quantity.dimension;
+    let (dimension, display) = match op {
+        _ if dimension == DIMENSIONLESS => (DIMENSIONLESS, None),
+        // Flipping the sign or dropping it doesn't depend on the unit
+        'n' | 'a' => (dimension, quantity.display),
+        '٪' => (dimension, None),
+        // Parts and rounding keep the units, working in SI units
+        'e' | 'i' | 'c' | 'f' | 'r' | 'I' | 'F' => (dimension, None),
+        'g' | 'A' => (DIMENSIONLESS, None),
+        'q' if dimension.iter().all(|power| power % 2 == 0) => (dimension.map(|power| power / 2), None),
+        _ => {
+            return Err(format!(
+                "{} needs a value without units, not {}!",
+                operator_name(op),
+                dimension_label(&dimension)
+            ))
+        }
+    };
+    Ok(Quantity {
+        value: apply_unary_operator(op, quantity.value, state)?,
+        dimension,
+        display,
+    })
+}
+fn apply_quantity_operator(
+    output_queue: &mut Vec<Quantity>,
+    op: char,
+    state: &mut BasecalcState,
+) -> Result<(), String> {
+    if get_precedence(op) == Precedence::Unary {
+        let value = output_queue.pop().ok_or("Invalid expression")?;
+        output_queue.push(unary_quantity(op, value, state)?);
+        return Ok(());
+    }
+    let (b, a) = match (output_queue.pop(), output_queue.pop()) {
+        (Some(b), Some(a)) => (b, a),
+        _ => return Err(format!("Not enough operands for {}", op)),
+    };
+    let mut dimension = a.dimension;
+    match op {
+        '+' | '-' | '%' => {
+            if a.dimension != b.dimension {
+                return Err(format!(
+                    "Units don't match, {} and {}!",
+                    dimension_label(&a.dimension),
+                    dimension_label(&b.dimension)
+                ));
+            }
+        }
+        '≤' | '≥' | '⋖' | '⋗' | '≡' | '≠' => {
+            if a.dimension != b.dimension {
+                return Err(format!(
+                    "Units don't match, {} and {}!",
+                    dimension_label(&a.dimension),
+                    dimension_label(&b.dimension)
+                ));
+            }
+            dimension = DIMENSIONLESS;
+        }
+        // A percentage of a quantity has its units
+        '⊕' | '⊖' if b.dimension == DIMENSIONLESS => {}
+        '*' | '/' => {
+            let sign = if op == '*' { 1 } else { -1 };
+            dimension = combine_dimensions(&dimension, &b.dimension, sign)?;
+        }
+        '^' if b.dimension == DIMENSIONLESS => {
+            if a.dimension != DIMENSIONLESS {
+                // Half powers are fine as long as every unit comes out whole, as in #sqrt
+                let twice = Float::with_val(state.precision, b.value.real() * 2);
+                if !b.value.imag().is_zero() || !twice.is_integer() {
+                    return Err("Units can only be raised to whole powers!".to_string());
+                }
+                let twice = twice.to_integer().and_then(|twice| twice.to_i32()).ok_or(EXPONENT_TOO_LARGE)?;
+                let doubled = combine_dimensions(&DIMENSIONLESS, &dimension, twice)?;
+                if doubled.iter().any(|power| power % 2 != 0) {
+                    return Err("Units can only be raised to whole powers!".to_string());
+                }
+                dimension = doubled.map(|power| power / 2);
+            }
+        }
+        _ if a.dimension == DIMENSIONLESS && b.dimension == DIMENSIONLESS => {}
+        _ => {
+            return Err(format!("{} needs values without units!", operator_name(op)));
+        }
+    }
+    let mut values = vec![a.value, b.value];
+    apply_operator(&mut values, op, state)?;
+    output_queue.push(Quantity {
+        value: values.pop().ok_or("Invalid expression")?,
+        dimension,
+        display: None,
+    });
+    Ok(())
+}
+/// Whether an expression needs `evaluate_with_lists`, having a list or a variable holding one
+///
+/// The variable being assigned to doesn't count, so it can go back to holding a lone value.
+fn uses_lists(tokens: &[Token], state: &BasecalcState) -> bool {
+    let tokens = if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        &tokens[2..]
+    } else {
+        tokens
+    };
+    tokens.iter().any(|token| match token.operator {
+        'M' | '⟲' | '⧖' | 'Ǝ' | '⊠' | 'ℬ' => true,
+        'v' => token
+            .var_index
+            .is_some_and(|index| state.variables[index].list.is_some()),
+        _ => false,
+    })
+}
+/// A value in `evaluate_list`, where a lone number goes with every element of a list
+enum ListValue {
+    Lone(Complex),
+    List(Vec<Complex>),
+}
+impl ListValue {
+    fn map(self, mut f: impl FnMut(Complex) -> Result<Complex, String>) -> Result<ListValue, String> {
+        Ok(match self {
+            ListValue::Lone(value) => ListValue::Lone(f(value)?),
+            ListValue::List(elements) => {
+                ListValue::List(elements.into_iter().map(f).collect::<Result<_, _>>()?)
+            }
+        })
+    }
+}
+/// Evaluates an expression with lists, such as `{1, 2, 3} * 2` or `#sin{0, @pi/2}`
+///
+/// Operators work element by element, a lone number going with every element,
+/// and functions apply to each element in turn. Lists don't pass through `&` or
+/// user functions.
+///
+/// # Returns
+/// * `Ok(EvalResult)` - The elements of the resulting list
+/// * `Err(String)` - An error message if evaluation fails or list lengths differ
+fn evaluate_with_lists(tokens: &[Token], state: &mut BasecalcState) -> Result<EvalResult, String> {
+    let (assignment, tokens) = if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        (Some(tokens[0].var_index.ok_or("Invalid variable reference")?), &tokens[2..])
+    } else {
+        (None, tokens)
+    };
+    let (value, list) = match evaluate_list(tokens, state)? {
+        ListValue::Lone(value) => (value, None),
+        ListValue::List(elements) => (elements[0].clone(), Some(elements)),
+    };
+    if let Some(index) = assignment {
+        state.variables[index].value = value.clone();
+        state.variables[index].dimension = DIMENSIONLESS;
+        state.variables[index].list = list.clone();
+        state.variables[index].formula = None;
+    }
+    Ok(EvalResult {
+        value,
+        assignment,
+        unit: None,
+        list,
+    })
+}
+fn evaluate_list(tokens: &[Token], state: &mut BasecalcState) -> Result<ListValue, String> {
+    let mut output_queue: Vec<ListValue> = Vec::new();
+    let mut operator_stack: Vec<char> = Vec::new();
+    let mut tokens = tokens.iter().peekable();
+    while let Some(token) = tokens.next() {
+        check_interrupt(state)?;
+        match token.operands {
+            0 => {
+                let mut value = operand_list(token, state)?;
+                value = apply_list_factorials(value, &mut tokens, state)?;
+                while let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) != Precedence::Unary {
+                        break;
+                    }
+                    output_queue.push(value);
+                    apply_list_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    value = output_queue.pop().unwrap();
+                }
+                output_queue.push(value);
+            }
+            1 if token.operator == '(' => operator_stack.push('('),
+            1 if token.operator == ')' => {
+                while let Some(op) = operator_stack.pop() {
+                    if op == '(' {
+                        break;
+                    }
+                    apply_list_operator(&mut output_queue, op, state)?;
+                }
+                if let Some(value) = output_queue.pop() {
+                    output_queue.push(apply_list_factorials(value, &mut tokens, state)?);
+                }
+                if let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) == Precedence::Unary {
+                        apply_list_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    }
+                }
+            }
+            1 => operator_stack.push(token.operator),
+            _ => {
+                while let Some(&op) = operator_stack.last() {
+                    if op == '(' || get_precedence(token.operator) > get_precedence(op) {
+                        break;
+                    }
+                    apply_list_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                }
+                operator_stack.push(token.operator);
+            }
+        }
+    }
+    while let Some(op) = operator_stack.pop() {
+        if op == '(' {
+            return Err("Mismatched parentheses".to_string());
+        }
+        apply_list_operator(&mut output_queue, op, state)?;
+    }
+    match output_queue.pop() {
+        Some(value) if output_queue.is_empty() => Ok(value),
+        _ => Err("Invalid expression".to_string()),
+    }
+}
+fn operand_list(token: &Token, state: &mut BasecalcState) -> Result<ListValue, String> {
+    match token.operator {
+        'M' => {
+            let mut elements = Vec::new();
+            for element in &token.arguments {
+                if let Some(dots) = find_range(element) {
+                    elements.extend(list_range(&element[..dots], &element[dots + 2..], state)?);
+                    continue;
+                }
+                if element.trim_start().starts_with(':') {
+                    return Err("Commands are not allowed inside expressions!".to_string());
+                }
+                let tokens = match tokenize(element, state) {
+                    Ok(tokens) => tokens,
+                    // Definitions and assertions have no value to put in the list
+                    Err((_, usize::MAX)) => return Err("Expected an expression!".to_string()),
+                    Err((msg, _)) => return Err(msg),
+                };
+                let result = evaluate_tokens(&tokens, state)?;
+                if result.list.is_some() {
+                    return Err("Lists can't hold other lists!".to_string());
+                }
+                if result.unit.is_some() {
+                    return Err("Lists can't hold values with units!".to_string());
+                }
+                elements.push(result.value);
+            }
+            Ok(ListValue::List(elements))
+        }
+        'v' => match token.var_index.and_then(|index| state.variables[index].list.clone()) {
+            Some(elements) => Ok(ListValue::List(elements)),
+            None => Ok(ListValue::Lone(evaluate_operand(token, state)?)),
+        },
+        '⟲' => Ok(ListValue::List(evaluate_iteration(token, state)?)),
+        '⧖' => Ok(ListValue::List(evaluate_civil(token, state)?)),
+        'Ǝ' => Ok(ListValue::List(evaluate_egcd(token, state)?)),
+        '⊠' => Ok(ListValue::List(evaluate_factor(token, state)?)),
+        'ℬ' => Ok(ListValue::List(evaluate_bernoulli(token, state)?)),
+        _ => Ok(ListValue::Lone(evaluate_operand(token, state)?)),
+    }
+}
+/// Most elements a `{a..b}` range may have
+const MAX_RANGE: u32 = 1_000_000;
+/// Finds the `..` of a range among a list's elements, outside any brackets
+fn find_range(element: &str) -> Option<usize> {
+    let bytes = element.as_bytes();
+    let mut depth = 0;
+    for i in 0..bytes.len().saturating_sub(1) {
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b'.' if depth == 0 && bytes[i + 1] == b'.' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+/// Expands a `{a..b}` range into every integer from a to b, counting down if b is smaller
+fn list_range(first: &str, last: &str, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let mut ends = Vec::new();
+    for end in [first, last] {
+        if end.trim().is_empty() {
+            return Err("Ranges need a start and an end!".to_string());
+        }
+        let value = evaluate_slice(end, 0, state).map_err(|(msg, _)| msg)?;
+        match integer_real_part(&value) {
+            Some(end) if value.imag().is_zero() && value.real().is_integer() => ends.push(end),
+            _ => return Err("Ranges need whole numbers at each end!".to_string()),
+        }
+    }
+    let count = Integer::from(&ends[1] - &ends[0]).abs() + 1u32;
+    if count > MAX_RANGE {
+        return Err("Range too long!".to_string());
+    }
+    let step = if ends[1] < ends[0] { -1 } else { 1 };
+    let mut value = ends[0].clone();
+    let mut elements = Vec::new();
+    for _ in 0..count.to_u32().unwrap_or(0) {
+        elements.push(Complex::with_val(state.precision, &value));
+        value += step;
+    }
+    Ok(elements)
+}
+/// Applies any postfix `!` tokens that follow an operand to each element
+fn apply_list_factorials(
+    value: ListValue,
+    tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>,
+    state: &BasecalcState,
+) -> Result<ListValue, String> {
+    let mut factorials = Vec::new();
+    while let Some(token) = tokens.next_if(|token| token.operator == '!') {
+        factorials.push(token.clone());
+    }
+    value.map(|element| apply_factorials(element, &mut factorials.iter().peekable(), state))
+}
+fn apply_list_operator(
+    output_queue: &mut Vec<ListValue>,
+    op: char,
+    state: &mut BasecalcState,
+) -> Result<(), String> {
+    let mut apply = |operands: Vec<Complex>| -> Result<Complex, String> {
+        let mut values = operands;
+        apply_operator(&mut values, op, state)?;
+        values.pop().ok_or_else(|| "Invalid expression".to_string())
+    };
+    if get_precedence(op) == Precedence::Unary {
+        let value = match output_queue.pop().ok_or("Invalid expression")? {
+            ListValue::List(elements) if is_aggregate(op) => {
+                ListValue::Lone(aggregate(op, &elements, state)?)
+            }
+            value => value.map(|element| apply(vec![element]))?,
+        };
+        output_queue.push(value);
+        return Ok(());
+    }
+    let (b, a) = match (output_queue.pop(), output_queue.pop()) {
+        (Some(b), Some(a)) => (b, a),
+        _ => return Err(format!("Not enough operands for {}", op)),
+    };
+    let result = match (a, b) {
+        (ListValue::Lone(a), b) => b.map(|b| apply(vec![a.clone(), b]))?,
+        (a, ListValue::Lone(b)) => a.map(|a| apply(vec![a, b.clone()]))?,
+        (ListValue::List(a), ListValue::List(b)) => {
+            if a.len() != b.len() {
+                return Err(format!(
+                    "Lists have different lengths, {} and {}!",
+                    a.len(),
+                    b.len()
+                ));
+            }
+            let elements = a.into_iter().zip(b).map(|(a, b)| apply(vec![a, b]));
+            ListValue::List(elements.collect::<Result<_, _>>()?)
+        }
+    };
+    output_queue.push(result);
+    Ok(())
+}
+/// Stops an evaluation once its state has been interrupted, e.g. by Ctrl-C
+fn check_interrupt(state: &BasecalcState) -> Result<(), String> {
+    if state.interrupt.load(Ordering::Relaxed) {
+        Err("Interrupted!".to_string())
+    } else {
+        Ok(())
+    }
+}
+/// Resolves an operand token to its value, calling user functions as needed
+fn evaluate_operand(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    match token.operator {
+        'u' => call_function(token.var_index.unwrap(), &token.arguments, state),
+        '∑' | '∏' => evaluate_series(token, state),
+        '∫' => evaluate_integral(token, state),
+        '∂' => evaluate_derivative(token, state),
+        '↻' => Ok(evaluate_iteration(token, state)?.pop().unwrap()),
+        '⧗' => evaluate_date(token, state),
+        'ϖ' | 'Ŧ' | 'ℓ' | 'ω' | 'Ψ' | '⊏' | '⊸' | '↦' => evaluate_call(token, state),
+        '⁇' => evaluate_conditional(token, state),
+        '∧' | '∨' | '¬' => evaluate_logical(token, state),
+        _ => Ok(token2num(token, state)),
+    }
+}
+/// Applies any postfix `!` tokens that follow an operand
+///
+/// Factorial binds tighter than prefix operators, so `#sqrt 4!` is the root of 24.
+fn apply_factorials(
+    mut value: Complex,
+    tokens: &mut std::iter::Peekable<std::slice::Iter<Token>>,
+    state: &BasecalcState,
+) -> Result<Complex, String> {
+    while tokens.next_if(|token| token.operator == '!').is_some() {
+        if value.imag().is_zero() && value.real().is_integer() && *value.real() < 0 {
+            return Err("Factorial is undefined for negative integers!".to_string());
+        }
+        value += 1;
+        value = gamma(&value, state)?;
+    }
+    Ok(value)
+}
+/// Tokenizes and evaluates a sub-expression such as a function argument or body
+fn evaluate_string(expression: &str, state: &mut BasecalcState) -> Result<Complex, String> {
+    if expression.trim_start().starts_with(':') {
+        return Err("Commands are not allowed inside expressions!".to_string());
+    }
+    let tokens = tokenize(expression, state).map_err(|(msg, _)| msg)?;
+    let result = evaluate_tokens(&tokens, state)?;
+    if result.list.is_some() {
+        return Err("Functions take and give lone values, not lists!".to_string());
+    }
+    Ok(result.value)
+}
+/// Deepest nesting of user function calls before giving up
+const MAX_CALL_DEPTH: usize = 256;
+/// Calls a user-defined function
+///
+/// Arguments are evaluated in the caller's scope, then bound to the parameters
+/// as temporary variables which shadow any global variables of the same name.
+/// Variables are restored afterwards, so a function body cannot modify them.
+///
+/// # Arguments
+/// * `function_index` - Index into `state.functions`
+/// * `arguments` - The unevaluated argument expressions
+/// * `state` - The current calculator state
+///
+/// # Returns
+/// * `Ok(Complex)` - The value of the function body
+/// * `Err(String)` - An error message if evaluation fails
+fn call_function(
+    function_index: usize,
+    arguments: &[String],
+    state: &mut BasecalcState,
+) -> Result<Complex, String> {
+    if state.call_depth >= MAX_CALL_DEPTH {
+        return Err("Recursion too deep!".to_string());
+    }
+    check_interrupt(state)?;
+    let function = state.functions[function_index].clone();
+    state.call_depth += 1;
+    let mut values = Vec::new();
+    for argument in arguments {
+        match evaluate_string(argument, state) {
+            Ok(value) => values.push(value),
+            Err(err) => {
+                state.call_depth -= 1;
+                return Err(err);
+            }
+        }
+    }
+    let saved_variables = state.variables.clone();
+    for (i, value) in values.into_iter().enumerate() {
+        state.variables.insert(
+            0,
+            Variable {
+                name: parameter_variable_name(i),
+                value,
+                dimension: DIMENSIONLESS,
+                list: None,
+                formula: None,
+                note: None,
+                locked: false,
+            },
+        );
+    }
+    let result = evaluate_string(
+        &substitute_parameters(&function.body, &function.parameters),
+        state,
+    );
+    state.variables = saved_variables;
+    state.call_depth -= 1;
+    result.map_err(|err| {
+        if err.starts_with("In @") {
+            err
+        } else {
+            format!("In @{}: {}", function.name, err)
+        }
+    })
+}
+/// Evaluates `#sum(k, a, b, body)` or `#prod(...)`, over every whole k from a to b
+///
+/// The index is bound like a function parameter, by `with_bound_variable`. An
+/// empty range, with b below a, gives 0 for a sum and 1 for a product.
+fn evaluate_series(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let product = token.operator == '∏';
+    let mut bounds = Vec::new();
+    for bound in &token.arguments[1..3] {
+        let value = evaluate_string(bound, state)?;
+        match integer_real_part(&value) {
+            Some(bound) if value.imag().is_zero() && value.real().is_integer() => bounds.push(bound),
+            _ => return Err(format!("{} bounds must be whole numbers!", operator_name(token.operator))),
+        }
+    }
+    with_bound_variable(&token.arguments[3], &token.arguments[0], state, |tokens, state| {
+        series_terms(tokens, product, &bounds[0], &bounds[1], state)
+    })
+}
+/// Adds or multiplies the terms of a series, the index being the first variable
+fn series_terms(
+    tokens: &[Token],
+    product: bool,
+    first: &Integer,
+    last: &Integer,
+    state: &mut BasecalcState,
+) -> Result<Complex, String> {
+    let mut total = Complex::with_val(state.precision, if product { 1 } else { 0 });
+    let mut index = first.clone();
+    while index <= *last {
+        state.variables[0].value = Complex::with_val(state.precision, &index);
+        let term = evaluate_tokens(tokens, state)?;
+        if term.list.is_some() {
+            return Err("Series terms must be lone values, not lists!".to_string());
+        }
+        if product {
+            total *= term.value;
+        } else {
+            total += term.value;
+        }
+        index += 1;
+    }
+    Ok(total)
+}
+/// Evaluates `body` with `name` bound to a temporary variable, which `run` sets
+/// before each evaluation of the tokens
+///
+/// The variable is the first one, shadowing any of the same name like a function
+/// parameter does, and variables are restored afterwards.
+fn with_bound_variable<T>(
+    body: &str,
+    name: &str,
+    state: &mut BasecalcState,
+    run: impl FnOnce(&[Token], &mut BasecalcState) -> Result<T, String>,
+) -> Result<T, String> {
+    if state.call_depth >= MAX_CALL_DEPTH {
+        return Err("Recursion too deep!".to_string());
+    }
+    // Named by depth, so one bound inside another's body gets its own variable
+    let variable = format!("0b{}", state.call_depth);
+    let body = substitute_names(body, &[name.to_string()], std::slice::from_ref(&variable));
+    let saved_variables = state.variables.clone();
+    state.variables.insert(
+        0,
+        Variable {
+            name: variable,
+            value: Complex::with_val(state.precision, 0),
+            dimension: DIMENSIONLESS,
+            list: None,
+            formula: None,
+            note: None,
+            locked: false,
+        },
+    );
+    state.call_depth += 1;
+    let result = match tokenize(&body, state) {
+        Ok(tokens) if !tokens.is_empty() => run(&tokens, state),
+        Ok(_) | Err((_, usize::MAX)) => Err("Expected an expression!".to_string()),
+        Err((msg, _)) => Err(msg),
+    };
+    state.call_depth -= 1;
+    state.variables = saved_variables;
+    result
+}
+/// Evaluates the tokens of a bound expression with the first variable set to `x`
+fn bound_value(tokens: &[Token], x: &Complex, state: &mut BasecalcState) -> Result<Complex, String> {
+    state.variables[0].value = x.clone();
+    let result = evaluate_tokens(tokens, state)?;
+    if result.list.is_some() {
+        return Err("Expected a single value, not a list!".to_string());
+    }
+    Ok(result.value)
+}
+/// Operators applying to an expression in the variable named second, with how
+/// many arguments they take and what those are
+const BOUND_OPERATORS: [(&str, char, usize, &str); 4] = [
+    ("#integrate", '∫', 4, "an expression, variable, start and end"),
+    ("#deriv", '∂', 3, "an expression, variable and point"),
+    ("#iterate", '↻', 4, "an expression, variable, start and count"),
+    ("#orbit", '⟲', 4, "an expression, variable, start and count"),
+];
+/// Functions taking several expressions, with the fewest and most arguments and what those are
+const CALL_OPERATORS: [(&str, char, usize, usize, &str); 17] = [
+    ("#if", '⁇', 3, 3, "a condition, a then and an else"),
+    ("#and", '∧', 2, usize::MAX, "two or more conditions"),
+    ("#or", '∨', 2, usize::MAX, "two or more conditions"),
+    ("#not", '¬', 1, 1, "one condition"),
+    ("#date", '⧗', 3, 3, "a year, month and day"),
+    ("#civil", '⧖', 1, 1, "a Unix time"),
+    ("#pct", 'ϖ', 2, 2, "a part and a whole"),
+    ("#atan2", 'Ŧ', 2, 2, "a y and an x"),
+    ("#logn", 'ℓ', 2, 2, "a value and a base"),
+    ("#lambertw", 'ω', 1, 2, "a value and optionally a branch"),
+    ("#polygamma", 'Ψ', 2, 2, "an order and a value"),
+    ("#egcd", 'Ǝ', 2, 2, "two integers"),
+    ("#factor", '⊠', 1, 1, "an integer"),
+    ("#bernoulli", 'ℬ', 1, 1, "an index"),
+    ("#clamp", '⊏', 3, 3, "a value, a low and a high"),
+    ("#lerp", '⊸', 3, 3, "a start, an end and a fraction"),
+    ("#maprange", '↦', 5, 5, "a value, its range and the range to map it to"),
+];
+/// Evaluates the functions of several numbers in `CALL_OPERATORS`, given their arguments
+fn evaluate_call(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let mut values = Vec::new();
+    for argument in &token.arguments {
+        values.push(evaluate_string(argument, state)?);
+    }
+    match token.operator {
+        // What percent the part is of the whole
+        'ϖ' => {
+            if values[1].is_zero() {
+                return Err("#pct of zero is undefined!".to_string());
+            }
+            Ok(values[0].clone() / &values[1] * 100)
+        }
+        // The angle of the point (x, y), in whichever quadrant it's in
+        'Ŧ' => {
+            if values.iter().any(|value| !value.imag().is_zero()) {
+                return Err("#atan2 needs real values!".to_string());
+            }
+            let angle = values[0].real().clone().atan2(values[1].real());
+            Ok(angle_from_radians(Complex::with_val(state.precision, angle), state))
+        }
+        'ℓ' => {
+            if values[1].is_zero() || values[1] == 1 {
+                return Err("#logn base can't be 0 or 1!".to_string());
+            }
+            Ok(values[0].clone().ln() / values[1].clone().ln())
+        }
+        'ω' => {
+            let branch = match values.get(1) {
+                None => 0,
+                Some(value) => match integer_real_part(value).and_then(|branch| branch.to_i32()) {
+                    Some(branch) if value.imag().is_zero() && value.real().is_integer() => branch,
+                    _ => return Err("#lambertw branch must be a whole number!".to_string()),
+                },
+            };
+            lambert_w(&values[0], branch, state)
+        }
+        'Ψ' => match integer_real_part(&values[0]) {
+            Some(order) if values[0].imag().is_zero() && values[0].real().is_integer() && order >= 0 => {
+                match order.to_u32().filter(|&order| order <= MAX_POLYGAMMA_ORDER) {
+                    Some(order) => polygamma(order, &values[1], state),
+                    None => Err("#polygamma order too large!".to_string()),
+                }
+            }
+            _ => Err("#polygamma order must be a whole number!".to_string()),
+        },
+        // The value held between the low and the high
+        '⊏' => {
+            if values.iter().any(|value| !value.imag().is_zero()) {
+                return Err("#clamp needs real values!".to_string());
+            }
+            if values[1].real() > values[2].real() {
+                return Err("#clamp low is above the high!".to_string());
+            }
+            if values[0].real() < values[1].real() {
+                Ok(values[1].clone())
+            } else if values[0].real() > values[2].real() {
+                Ok(values[2].clone())
+            } else {
+                Ok(values[0].clone())
+            }
+        }
+        // The fraction t of the way from a to b
+        '⊸' => Ok(values[0].clone() + (values[1].clone() - &values[0]) * &values[2]),
+        // Where x is in the range a1 to b1, scaled to the range a2 to b2
+        '↦' => {
+            if values[1] == values[2] {
+                return Err("#maprange can't map from an empty range!".to_string());
+            }
+            let fraction = (values[0].clone() - &values[1]) / (values[2].clone() - &values[1]);
+            Ok(values[3].clone() + (values[4].clone() - &values[3]) * fraction)
+        }
+        _ => Err(format!("Unknown operator: {}", token.operator)),
+    }
+}
+/// Evaluates `#if(cond, then, else)`, where any non-zero condition is true
+///
+/// Only the branch taken is evaluated, so the other may be undefined there, as
+/// in a recursive function's base case.
+fn evaluate_conditional(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let condition = evaluate_string(&token.arguments[0], state)?;
+    if condition.real().is_nan() || condition.imag().is_nan() {
+        return Err("#if condition is undefined!".to_string());
+    }
+    let branch = if condition.is_zero() { 2 } else { 1 };
+    evaluate_string(&token.arguments[branch], state)
+}
+/// Evaluates the logical `#and(...)`, `#or(...)` and `#not(...)`, giving 1 or 0
+///
+/// Any non-zero value is true. Conditions are taken in order and only until the
+/// answer is known, so later ones may be undefined once it is.
+fn evaluate_logical(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    // #and stops at the first false condition, #or and #not at the first true one
+    let stop = token.operator != '∧';
+    let mut answer = !stop;
+    for argument in &token.arguments {
+        let condition = evaluate_string(argument, state)?;
+        if condition.real().is_nan() || condition.imag().is_nan() {
+            return Err(format!("{} condition is undefined!", logical_name(token.operator)));
+        }
+        if condition.is_zero() != stop {
+            answer = stop;
+            break;
+        }
+    }
+    if token.operator == '¬' {
+        answer = !answer;
+    }
+    Ok(Complex::with_val(state.precision, answer as u32))
+}
+/// The name a logical operator is written with
+fn logical_name(operator: char) -> &'static str {
+    match operator {
+        '∧' => "#and",
+        '∨' => "#or",
+        _ => "#not",
+    }
+}
+/// Most Halley steps `#lambertw` takes before giving up
+const MAX_LAMBERT_STEPS: usize = 200;
+/// The branch `branch` of the Lambert W function, the w with w e^w = z
+///
+/// Starts from the series about the branch point -1/e when close to it, Winitzki's
+/// approximation elsewhere on the principal branch, or the asymptotic
+/// ln z + 2πik - ln(ln z + 2πik) on the others, then polishes the guess with
+/// Halley's method at a few more bits than the working precision.
+fn lambert_w(z: &Complex, branch: i32, state: &BasecalcState) -> Result<Complex, String> {
+    let precision = state.precision + 32;
+    if z.is_zero() {
+        return if branch == 0 {
+            Ok(Complex::with_val(state.precision, 0))
+        } else {
+            Err("#lambertw is infinite at 0 off the principal branch!".to_string())
+        };
+    }
+    let z = Complex::with_val(precision, z);
+    let modulus = |value: &Complex| Complex::with_val(precision, value.abs_ref()).real().clone();
+    let e = Float::with_val(precision, 1).exp();
+    let tolerance = Float::with_val(precision, 1) >> (state.precision + 8);
+    // p is the distance from the branch point, as sqrt(2 (e z + 1))
+    let offset = Complex::with_val(precision, z.clone() * &e + 1u32);
+    // Within rounding of -1/e, where the two real branches meet
+    let rounding = Float::with_val(precision, 1) >> state.precision.saturating_sub(4);
+    if (branch == 0 || branch == -1) && modulus(&offset) <= rounding {
+        return Ok(Complex::with_val(state.precision, -1));
+    }
+    let p = (offset * 2u32).sqrt();
+    // Branch -1 only reaches the branch point along the negative reals
+    let near_branch_point = modulus(&p) < 2
+        && (branch == 0 || (branch == -1 && z.imag().is_zero() && z.real().is_sign_negative()));
+    let mut w = match branch {
+        0 | -1 if near_branch_point => {
+            // W = -1 + p - p^2/3 + 11 p^3/72, with p negated on the lower branch
+            let p = if branch == 0 { p } else { -p };
+            let p2 = Complex::with_val(precision, p.square_ref());
+            let p3 = Complex::with_val(precision, &p2 * &p);
+            p - p2 / 3u32 + p3 * 11u32 / 72u32 - 1u32
+        }
+        0 => {
+            // ln(1 + z) (1 - ln(1 + ln(1 + z)) / (2 + ln(1 + z)))
+            let log = Complex::with_val(precision, &z + 1u32).ln();
+            let log_log = Complex::with_val(precision, &log + 1u32).ln();
+            let ratio = log_log / Complex::with_val(precision, &log + 2u32);
+            log * (1u32 - ratio)
+        }
+        _ => {
+            let two_pi = Float::with_val(precision, rug::float::Constant::Pi) * 2u32 * branch;
+            let log = z.clone().ln() + Complex::with_val(precision, (0, two_pi));
+            let log_log = log.clone().ln();
+            log - log_log
+        }
+    };
+    for _ in 0..MAX_LAMBERT_STEPS {
+        check_interrupt(state)?;
+        let exp = w.clone().exp();
+        let residual = Complex::with_val(precision, &w * &exp) - &z;
+        let w1 = Complex::with_val(precision, &w + 1u32);
+        if w1.is_zero() {
+            break;
+        }
+        // Halley: w -= f / (f' - f'' f / (2 f')), with f = w e^w - z
+        let derivative = Complex::with_val(precision, &exp * &w1);
+        let correction = Complex::with_val(precision, &w + 2u32) * &residual / (w1 * 2u32);
+        let step = residual / (derivative - correction);
+        w -= &step;
+        if modulus(&step) <= tolerance.clone() * modulus(&w).max(&Float::with_val(precision, 1)) {
+            return Ok(Complex::with_val(state.precision, w));
+        }
+    }
+    Err("#lambertw didn't converge!".to_string())
+}
+/// Seconds in a day, ignoring leap seconds as Unix time does
+const SECONDS_PER_DAY: u32 = 86400;
+/// Julian day of the Unix epoch, 1970-01-01 00:00 UTC
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+/// Days from 0001-01-01 to the Unix epoch, as chrono counts them
+const UNIX_EPOCH_CE_DAYS: i64 = 719163;
+/// Evaluates `#date(y, m, d)`, the Unix time at the start of that day in UTC
+fn evaluate_date(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let mut parts = Vec::new();
+    for argument in &token.arguments {
+        let value = evaluate_string(argument, state)?;
+        match integer_real_part(&value).and_then(|part| part.to_i32()) {
+            Some(part) if value.imag().is_zero() && value.real().is_integer() => parts.push(part),
+            _ => return Err("#date needs a whole year, month and day!".to_string()),
+        }
+    }
+    let date = u32::try_from(parts[1])
+        .ok()
+        .zip(u32::try_from(parts[2]).ok())
+        .and_then(|(month, day)| chrono::NaiveDate::from_ymd_opt(parts[0], month, day))
+        .ok_or("Invalid date!")?;
+    let days = date.num_days_from_ce() as i64 - UNIX_EPOCH_CE_DAYS;
+    Ok(Complex::with_val(state.precision, days) * SECONDS_PER_DAY)
+}
+/// Evaluates `#civil(t)`, the year, month and day in UTC of a Unix time
+fn evaluate_civil(token: &Token, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let time = evaluate_string(&token.arguments[0], state)?;
+    if !time.imag().is_zero() || !time.real().is_finite() {
+        return Err("#civil needs a real Unix time!".to_string());
+    }
+    let date = (time.real().clone() / SECONDS_PER_DAY)
+        .floor()
+        .to_integer()
+        .and_then(|days| days.to_i64())
+        .and_then(|days| days.checked_add(UNIX_EPOCH_CE_DAYS))
+        .and_then(|days| i32::try_from(days).ok())
+        .and_then(chrono::NaiveDate::from_num_days_from_ce_opt)
+        .ok_or("Date out of range!")?;
+    Ok([date.year(), date.month() as i32, date.day() as i32]
+        .iter()
+        .map(|&part| Complex::with_val(state.precision, part))
+        .collect())
+}
+/// Evaluates `#egcd(a, b)` to `{g, x, y}`, the gcd and Bézout coefficients with a·x + b·y = g
+fn evaluate_egcd(token: &Token, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let a = whole_operand(&evaluate_string(&token.arguments[0], state)?, "#egcd")?;
+    let b = whole_operand(&evaluate_string(&token.arguments[1], state)?, "#egcd")?;
+    let (g, x, y) = a.extended_gcd(b, Integer::new());
+    Ok([g, x, y]
+        .iter()
+        .map(|part| Complex::with_val(state.precision, part))
+        .collect())
+}
+/// Evaluates `#factor(n)` to a list of [prime, exponent] pairs, smallest prime first
+///
+/// Negative numbers lead with [-1, 1], and 1 is just [1, 1].
+fn evaluate_factor(token: &Token, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let n = whole_operand(&evaluate_string(&token.arguments[0], state)?, "#factor")?;
+    if n.is_zero() {
+        return Err("#factor needs a nonzero integer!".to_string());
+    }
+    let mut pairs = Vec::new();
+    if n < 0 {
+        pairs.push((Integer::from(-1), 1));
+    }
+    pairs.extend(prime_powers(n.abs(), state)?);
+    if pairs.is_empty() {
+        pairs.push((Integer::from(1), 1));
+    }
+    Ok(pairs
+        .iter()
+        .map(|(prime, count)| Complex::with_val(state.precision, (prime, *count)))
+        .collect())
+}
+/// Largest index `#bernoulli` takes
+const MAX_BERNOULLI_INDEX: u32 = 1 << 14;
+/// Evaluates `#bernoulli(n)` to `{numerator, denominator}`, B(1) being -1/2
+///
+/// The denominator of B(n) is the product of the primes p with p - 1 dividing n,
+/// by von Staudt-Clausen, which leaves the numerator to round out of
+/// B(n) = (-1)^(n/2 + 1) 2 n! ζ(n) / (2π)^n.
+fn evaluate_bernoulli(token: &Token, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let n = whole_operand(&evaluate_string(&token.arguments[0], state)?, "#bernoulli")?;
+    if n < 0 {
+        return Err("#bernoulli needs a non-negative index!".to_string());
+    }
+    let n = n
+        .to_u32()
+        .filter(|&n| n <= MAX_BERNOULLI_INDEX)
+        .ok_or("#bernoulli index too large!")?;
+    let (numerator, denominator) = match n {
+        0 => (Integer::from(1), Integer::from(1)),
+        1 => (Integer::from(-1), Integer::from(2)),
+        _ if n % 2 == 1 => (Integer::new(), Integer::from(1)),
+        _ => {
+            let mut denominator = Integer::from(1);
+            for divisor in 1..=n {
+                if n % divisor == 0 && is_prime(&Integer::from(divisor + 1)) {
+                    denominator *= divisor + 1;
+                }
+            }
+            // |B(n)| < 2 n! / (2π)^n < n^n, plus room for the denominator and rounding
+            let work = n * (32 - n.leading_zeros()) + denominator.significant_bits() + 64;
+            let two_pi = Float::with_val(work, rug::float::Constant::Pi) * 2u32;
+            let factorial = Float::with_val(work, Integer::from(Integer::factorial(n)));
+            let size = Float::with_val(work, n).zeta() * 2u32 * factorial / two_pi.pow(n) * &denominator;
+            let mut numerator = size.round().to_integer().ok_or("#bernoulli index too large!")?;
+            if n % 4 == 0 {
+                numerator = -numerator;
+            }
+            (numerator, denominator)
+        }
+    };
+    Ok(vec![
+        Complex::with_val(state.precision, numerator),
+        Complex::with_val(state.precision, denominator),
+    ])
+}
+/// Most times `#integrate` halves its step before giving up
+const MAX_QUADRATURE_LEVEL: usize = 12;
+/// Evaluates `#integrate(expr, x, a, b)`, along the straight line from a to b
+fn evaluate_integral(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let start = evaluate_string(&token.arguments[2], state)?;
+    let end = evaluate_string(&token.arguments[3], state)?;
+    if [&start, &end].iter().any(|bound| !bound.real().is_finite() || !bound.imag().is_finite()) {
+        return Err("#integrate bounds must be finite!".to_string());
+    }
+    with_bound_variable(&token.arguments[0], &token.arguments[1], state, |tokens, state| {
+        tanh_sinh(tokens, &start, &end, state)
+    })
+}
+/// Integrates the bound expression from a to b by tanh-sinh quadrature
+///
+/// Substituting x = (a + b)/2 + (b - a)/2 * tanh(pi/2 * sinh(u)) squeezes the
+/// ends of the interval so hard that the trapezoid rule in u converges about
+/// as fast as the step halves, even with singularities at the ends. The
+/// integrand is never evaluated at the ends themselves.
+fn tanh_sinh(
+    tokens: &[Token],
+    start: &Complex,
+    end: &Complex,
+    state: &mut BasecalcState,
+) -> Result<Complex, String> {
+    let precision = state.precision;
+    if start == end {
+        return Ok(Complex::with_val(precision, 0));
+    }
+    // A few bits short of full precision, to allow for rounding in the sums
+    let tolerance = Float::with_val(precision, 2).pow(16 - precision as i32);
+    let cutoff = Float::with_val(precision, &tolerance * &tolerance);
+    let half = Complex::with_val(precision, end - start) / 2;
+    let middle = Complex::with_val(precision, start + end) / 2;
+    let half_pi = Float::with_val(precision, rug::float::Constant::Pi) / 2;
+    let mut sum = bound_value(tokens, &middle, state)? * &half_pi;
+    let mut h = Float::with_val(precision, 1);
+    let mut previous: Option<Complex> = None;
+    for level in 0..=MAX_QUADRATURE_LEVEL {
+        // After the first level, only the new points halfway between the old ones
+        let (mut k, stride) = if level == 0 { (1, 1) } else { (1, 2) };
+        loop {
+            check_interrupt(state)?;
+            let u = Float::with_val(precision, &h * k);
+            let y: Float = Float::with_val(precision, u.sinh_ref()) * &half_pi;
+            let weight = Float::with_val(precision, u.cosh_ref()) * &half_pi
+                / Float::with_val(precision, y.cosh_ref()).square();
+            // Distance from the nearer end as a fraction of the half-width, 1 - tanh(y)
+            let gap = Float::with_val(precision, 2) / (Float::with_val(precision, y * 2).exp() + 1);
+            let offset = Complex::with_val(precision, &half * &gap);
+            let low = Complex::with_val(precision, start + &offset);
+            let high = Complex::with_val(precision, end - &offset);
+            // Points that round onto an end are left out, the other end may still need them
+            if weight < cutoff || (low == *start && high == *end) {
+                break;
+            }
+            if low != *start {
+                sum += bound_value(tokens, &low, state)? * &weight;
+            }
+            if high != *end {
+                sum += bound_value(tokens, &high, state)? * &weight;
+            }
+            k += stride;
+        }
+        let estimate: Complex = Complex::with_val(precision, &sum * &h) * &half;
+        if let Some(previous) = &previous {
+            let change = Float::with_val(precision, Complex::with_val(precision, &estimate - previous).abs_ref());
+            let scale = Float::with_val(precision, estimate.abs_ref()).max(&Float::with_val(precision, 1));
+            if change <= Float::with_val(precision, &tolerance * scale) {
+                return Ok(estimate);
+            }
+        }
+        previous = Some(estimate);
+        h /= 2;
+    }
+    Err("#integrate didn't converge!".to_string())
+}
+/// Rows of the table `#deriv` extrapolates over, each halving the step
+const DERIVATIVE_ROWS: usize = 32;
+/// Evaluates `#deriv(expr, x, point)`, the derivative of expr at the point
+///
+/// The expression is evaluated at twice the usual precision, so that the
+/// differences keep enough digits once they cancel. The result is an error if
+/// its estimated error would show in the displayed digits.
+fn evaluate_derivative(token: &Token, state: &mut BasecalcState) -> Result<Complex, String> {
+    let point = evaluate_string(&token.arguments[2], state)?;
+    let precision = state.precision;
+    state.precision *= 2;
+    let result = with_bound_variable(&token.arguments[0], &token.arguments[1], state, |tokens, state| {
+        ridders(tokens, &point, state)
+    });
+    state.precision = precision;
+    let (slope, error) = result?;
+    let scale = Float::with_val(precision, slope.abs_ref()).max(&Float::with_val(precision, 1));
+    if error > Float::with_val(precision, 2).pow(16 - precision as i32) * scale {
+        return Err(format!(
+            "#deriv didn't converge, error about {}!",
+            format_real(&Float::with_val(precision, error), state)
+        ));
+    }
+    Ok(Complex::with_val(precision, slope))
+}
+/// Differentiates the bound expression at a point by Ridders' method
+///
+/// Central differences with the step halving each row are extrapolated
+/// towards a step of zero, as in Richardson's method. The extrapolation that
+/// agrees best with its neighbours wins, which copes with steps too large for
+/// the function at first, as the extra precision keeps small steps from
+/// drowning in rounding.
+///
+/// # Returns
+/// * `Ok((Complex, Float))` - The derivative and an estimate of its error
+/// * `Err(String)` - An error message if evaluation fails
+fn ridders(tokens: &[Token], point: &Complex, state: &mut BasecalcState) -> Result<(Complex, Float), String> {
+    let precision = state.precision;
+    let scale = Float::with_val(precision, point.abs_ref()).max(&Float::with_val(precision, 1));
+    let mut h = Complex::with_val(precision, scale / 16);
+    let mut table: Vec<Vec<Complex>> = Vec::new();
+    let mut best = Complex::with_val(precision, 0);
+    let mut error = Float::with_val(precision, rug::float::Special::Infinity);
+    for row in 0..DERIVATIVE_ROWS {
+        check_interrupt(state)?;
+        let above = bound_value(tokens, &Complex::with_val(precision, point + &h), state)?;
+        let below = bound_value(tokens, &Complex::with_val(precision, point - &h), state)?;
+        let mut current = vec![(above - below) / Complex::with_val(precision, &h * 2)];
+        // Halving the step quarters the leading error term, then the next, and so on
+        let mut factor = Float::with_val(precision, 4);
+        for column in 1..=row {
+            let extrapolated = (Complex::with_val(precision, &current[column - 1] * &factor)
+                - &table[row - 1][column - 1])
+                / Float::with_val(precision, &factor - 1);
+            let change = Float::with_val(
+                precision,
+                Complex::with_val(precision, &extrapolated - &current[column - 1]).abs_ref(),
+            )
+            .max(&Float::with_val(
+                precision,
+                Complex::with_val(precision, &extrapolated - &table[row - 1][column - 1]).abs_ref(),
+            ));
+            if change <= error {
+                error = change;
+                best = extrapolated.clone();
+            }
+            current.push(extrapolated);
+            factor *= 4;
+        }
+        table.push(current);
+        h /= 2;
+    }
+    Ok((best, error))
+}
+/// Evaluates `#iterate(expr, x, x0, n)`, feeding expr its own value n times
+/// starting from x0, or `#orbit(...)` which keeps every value along the way
+///
+/// # Returns
+/// * `Ok(Vec<Complex>)` - The final value, or for `#orbit` all n + 1 of them
+/// * `Err(String)` - An error message if evaluation fails
+fn evaluate_iteration(token: &Token, state: &mut BasecalcState) -> Result<Vec<Complex>, String> {
+    let orbit = token.operator == '⟲';
+    let name = if orbit { "#orbit" } else { "#iterate" };
+    let start = evaluate_string(&token.arguments[2], state)?;
+    let value = evaluate_string(&token.arguments[3], state)?;
+    let count = match integer_real_part(&value).and_then(|count| count.to_u64()) {
+        Some(count) if value.imag().is_zero() && value.real().is_integer() => count,
+        _ => return Err(format!("{} count must be a whole number!", name)),
+    };
+    if orbit && count >= MAX_RANGE as u64 {
+        return Err("Orbit too long!".to_string());
+    }
+    with_bound_variable(&token.arguments[0], &token.arguments[1], state, |tokens, state| {
+        let mut values = vec![start];
+        for _ in 0..count {
+            check_interrupt(state)?;
+            let next = bound_value(tokens, values.last().unwrap(), state)?;
+            if !orbit {
+                values.clear();
+            }
+            values.push(next);
+        }
+        Ok(values)
+    })
+}
+/// Size of a `:plot` in characters, each braille character holding two by four dots
+const PLOT_COLUMNS: usize = 64;
+const PLOT_ROWS: usize = 16;
+/// Draws an expression in the first variable from `from` to `to` in braille, for `:plot`
+///
+/// The expression is sampled once per dot column and neighbouring samples are joined
+/// up, so steep stretches stay unbroken. The vertical axis is scaled to fit what was
+/// found, and wherever the value isn't a real number the curve has a gap.
+///
+/// # Returns
+/// * `Ok(String)` - The plot with its axes, labelled in the current base
+/// * `Err(String)` - If evaluation fails or nothing in the range is real
+fn plot_function(
+    tokens: &[Token],
+    from: &Float,
+    to: &Float,
+    state: &mut BasecalcState,
+) -> Result<String, String> {
+    let (width, height) = (PLOT_COLUMNS * 2, PLOT_ROWS * 4);
+    let step = Float::with_val(state.precision, to - from) / (width - 1) as u32;
+    let mut samples = Vec::with_capacity(width);
+    for i in 0..width {
+        check_interrupt(state)?;
+        let x = Complex::with_val(state.precision, from + Float::with_val(state.precision, &step * i as u32));
+        let y = bound_value(tokens, &x, state)?;
+        let real = y.imag().is_zero() || y.imag().clone().abs() <= y.real().clone().abs() >> 32;
+        samples.push(Some(y.real().to_f64()).filter(|y| real && y.is_finite()));
+    }
+    let (mut low, mut high) = samples
+        .iter()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &y| (low.min(y), high.max(y)));
+    if low > high {
+        return Err("Nothing to plot, the expression isn't real anywhere in the range!".to_string());
+    }
+    if high - low <= high.abs().max(low.abs()) * 1e-12 {
+        low -= 1.0;
+        high += 1.0;
+    }
+    let row_of = |y: f64| ((high - y) / (high - low) * (height - 1) as f64).round() as usize;
+    let mut curve = vec![[0u8; PLOT_COLUMNS]; PLOT_ROWS];
+    let mut axis = vec![[0u8; PLOT_COLUMNS]; PLOT_ROWS];
+    let dot = |grid: &mut Vec<[u8; PLOT_COLUMNS]>, column: usize, row: usize| {
+        const BITS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+        grid[row / 4][column / 2] |= BITS[column % 2][row % 4];
+    };
+    let zero_row = (low <= 0.0 && high >= 0.0).then(|| row_of(0.0));
+    if let Some(row) = zero_row {
+        (0..width).for_each(|column| dot(&mut axis, column, row));
+    }
+    let mut previous: Option<usize> = None;
+    for (column, sample) in samples.iter().enumerate() {
+        let Some(y) = sample else {
+            previous = None;
+            continue;
+        };
+        let row = row_of(*y);
+        let (top, bottom) = match previous {
+            Some(before) => (row.min((before + row) / 2), row.max((before + row) / 2)),
+            None => (row, row),
+        };
+        (top..=bottom).for_each(|row| dot(&mut curve, column, row));
+        if let Some(before) = previous {
+            // The other half of the join goes in the column before
+            let (top, bottom) = (before.min((before + row) / 2), before.max((before + row) / 2));
+            (top..=bottom).for_each(|row| dot(&mut curve, column - 1, row));
+        }
+        previous = Some(row);
+    }
+
+    let mut label_state = state.clone();
+    label_state.digits = 4;
+    label_state.set_precision();
+    let label = |value: f64| format_real(&Float::with_val(label_state.precision, value), &label_state);
+    let colour = |text: &str, (r, g, b): (u8, u8, u8)| text.truecolor(r, g, b).to_string();
+    let mut labels = vec![String::new(); PLOT_ROWS];
+    labels[0] = label(high);
+    labels[PLOT_ROWS - 1] = label(low);
+    if let Some(row) = zero_row.filter(|row| row / 4 != 0 && row / 4 != PLOT_ROWS - 1) {
+        labels[row / 4] = label(0.0);
+    }
+    let margin = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+    let mut plot = String::new();
+    for (row, label) in labels.iter().enumerate() {
+        let tick = if label.is_empty() { '│' } else { '┤' };
+        plot.push_str(&colour(&format!("{:>margin$} {}", label, tick), state.colours.message));
+        for column in 0..PLOT_COLUMNS {
+            let (curve, axis) = (curve[row][column], axis[row][column]);
+            let glyph = char::from_u32(0x2800 + (curve | axis) as u32).unwrap_or(' ').to_string();
+            plot.push_str(&match (curve, axis) {
+                (0, 0) => " ".to_string(),
+                (0, _) => colour(&glyph, state.colours.brackets),
+                _ => colour(&glyph, state.colours.real_integer),
+            });
+        }
+        plot.push('\n');
+    }
+    let (first, last) = (format_real(from, &label_state), format_real(to, &label_state));
+    plot.push_str(&colour(
+        &format!("{:>margin$} └{}\n", "", "─".repeat(PLOT_COLUMNS)),
+        state.colours.message,
+    ));
+    let gap = (PLOT_COLUMNS + 1).saturating_sub(first.chars().count() + last.chars().count());
+    plot.push_str(&colour(
+        &format!("{:>margin$}  {}{}{}", "", first, " ".repeat(gap), last),
+        state.colours.message,
+    ));
+    Ok(plot)
+}
+/// Most pixels a `:cplot` has across or down, two pixels stacked in each character
+const CPLOT_PIXELS: usize = 64;
+/// Colour for a complex value in a `:cplot`
+///
+/// The hue follows the argument, red along the positive reals, and the lightness
+/// runs from black at zeros to white at poles, with faint bands at each doubling
+/// of the magnitude.
+fn domain_colour(z: &Complex) -> (u8, u8, u8) {
+    let (re, im) = (z.real().to_f64(), z.imag().to_f64());
+    if re.is_nan() || im.is_nan() {
+        return (128, 128, 128);
+    }
+    let magnitude = re.hypot(im);
+    let mut lightness = magnitude.atan() * std::f64::consts::FRAC_2_PI;
+    if magnitude > 0.0 && magnitude.is_finite() {
+        lightness *= 0.85 + 0.15 * magnitude.log2().rem_euclid(1.0);
+    }
+    let hue = (im.atan2(re) / std::f64::consts::TAU).rem_euclid(1.0) * 6.0;
+    let chroma = 1.0 - (2.0 * lightness - 1.0).abs();
+    let second = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let lift = lightness - chroma / 2.0;
+    let channel = |c: f64| ((c + lift) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
+/// Domain colours an expression in the first variable over a rectangle, for `:cplot`
+///
+/// Each character is an upper half block, its two colours giving two pixels,
+/// so the pixels come out roughly square. The longer side of the rectangle
+/// gets the most pixels and the other keeps its shape.
+///
+/// # Returns
+/// * `Ok(String)` - The image between its upper right and lower left corners
+/// * `Err(String)` - If evaluation fails or is interrupted
+fn cplot_function(
+    tokens: &[Token],
+    low: &Complex,
+    high: &Complex,
+    state: &mut BasecalcState,
+) -> Result<String, String> {
+    let width = Float::with_val(state.precision, high.real() - low.real());
+    let height = Float::with_val(state.precision, high.imag() - low.imag());
+    let aspect = Float::with_val(state.precision, &height / &width).to_f64();
+    let (columns, rows) = if aspect > 1.0 {
+        (((CPLOT_PIXELS as f64 / aspect).round() as usize).max(1), CPLOT_PIXELS / 2)
+    } else {
+        (CPLOT_PIXELS, ((CPLOT_PIXELS as f64 * aspect / 2.0).round() as usize).max(1))
+    };
+    let step_re = width / columns as u32;
+    let step_im = height / (rows * 2) as u32;
+    let mut pixels = Vec::with_capacity(rows * 2);
+    for row in 0..rows * 2 {
+        let im = Float::with_val(state.precision, high.imag() - Float::with_val(state.precision, &step_im * (row as f64 + 0.5)));
+        let mut line = Vec::with_capacity(columns);
+        for column in 0..columns {
+            check_interrupt(state)?;
+            let re = Float::with_val(state.precision, low.real() + Float::with_val(state.precision, &step_re * (column as f64 + 0.5)));
+            let z = Complex::with_val(state.precision, (re, &im));
+            line.push(domain_colour(&bound_value(tokens, &z, state)?));
+        }
+        pixels.push(line);
+    }
+
+    let mut label_state = state.clone();
+    label_state.digits = 4;
+    label_state.set_precision();
+    let (r, g, b) = state.colours.message;
+    let corner = format_complex(high, &label_state);
+    let mut plot = format!("{:>columns$}\n", corner).truecolor(r, g, b).to_string();
+    for pair in pixels.chunks(2) {
+        for (top, bottom) in pair[0].iter().zip(&pair[1]) {
+            plot.push_str(&"▀".truecolor(top.0, top.1, top.2).on_truecolor(bottom.0, bottom.1, bottom.2).to_string());
+        }
+        plot.push('\n');
+    }
+    plot.push_str(&format_complex(low, &label_state).truecolor(r, g, b).to_string());
+    Ok(plot)
+}
+/// Draws the magnitudes of `values` as a line of block characters for `:spark`
+///
+/// The smallest magnitude gets the lowest block and the largest the full one,
+/// followed by the two as labels in the current base. Magnitudes that aren't
+/// finite are left as gaps.
+fn sparkline(values: &[Complex], state: &BasecalcState) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let magnitudes: Vec<Float> = values
+        .iter()
+        .map(|value| Float::with_val(state.precision, value.abs_ref()))
+        .collect();
+    let finite = magnitudes.iter().filter(|magnitude| magnitude.is_finite());
+    let (Some(low), Some(high)) = (finite.clone().min_by(|a, b| a.total_cmp(b)), finite.max_by(|a, b| a.total_cmp(b)))
+    else {
+        return " ".repeat(values.len());
+    };
+    let range = Float::with_val(state.precision, high - low);
+    let line: String = magnitudes
+        .iter()
+        .map(|magnitude| match magnitude.is_finite() {
+            false => ' ',
+            true if range.is_zero() => BLOCKS[3],
+            true => {
+                let level = Float::with_val(state.precision, magnitude - low) / &range * (BLOCKS.len() - 1) as u32;
+                BLOCKS[level.to_f64().round() as usize]
+            }
+        })
+        .collect();
+    let mut label_state = state.clone();
+    label_state.digits = 4;
+    label_state.set_precision();
+    let (r, g, b) = state.colours.message;
+    let labels = format!("  {} to {}", format_real(low, &label_state), format_real(high, &label_state));
+    format!("{}{}", line, labels.truecolor(r, g, b))
+}
+/// Size of a `:fractal` in characters, each one holding two pixels stacked
+const FRACTAL_COLUMNS: usize = 64;
+const FRACTAL_ROWS: usize = 24;
+/// Renders the Mandelbrot set, or a Julia set for `julia`, around `center` for `:fractal`
+///
+/// Every orbit is iterated at the working precision, so zooms far past what
+/// doubles can resolve still come out sharp. The iteration limit grows with
+/// the zoom to keep the deeper boundaries detailed.
+///
+/// # Returns
+/// * `Ok(String)` - The image with a caption naming the view
+/// * `Err(String)` - If the zoom is beyond the precision or the render is interrupted
+fn render_fractal(
+    center: &Complex,
+    zoom: &Float,
+    julia: Option<&Complex>,
+    state: &BasecalcState,
+) -> Result<String, String> {
+    let precision = state.precision;
+    let step = Float::with_val(precision, 4 / Float::with_val(precision, zoom)) / FRACTAL_COLUMNS as u32;
+    let scale = Float::with_val(precision, center.abs_ref()).max(&Float::with_val(precision, 1));
+    if step < (scale >> (precision as i32 - 8)) {
+        return Err("Zoomed in past the precision, raise :digits!".to_string());
+    }
+    let limit = (64.0 + 32.0 * zoom.to_f64().max(1.0).log2()).min(20000.0) as usize;
+    let (width, height) = (FRACTAL_COLUMNS as f64, (FRACTAL_ROWS * 2) as f64);
+    let mut pixels = Vec::with_capacity(FRACTAL_ROWS * 2);
+    for row in 0..FRACTAL_ROWS * 2 {
+        let mut line = Vec::with_capacity(FRACTAL_COLUMNS);
+        for column in 0..FRACTAL_COLUMNS {
+            check_interrupt(state)?;
+            let offset = Complex::with_val(
+                precision,
+                (
+                    Float::with_val(precision, &step * (column as f64 + 0.5 - width / 2.0)),
+                    Float::with_val(precision, &step * (height / 2.0 - row as f64 - 0.5)),
+                ),
+            );
+            let point = Complex::with_val(precision, center + offset);
+            let (mut z, c) = match julia {
+                Some(c) => (point, c.clone()),
+                None => (Complex::new(precision), point),
+            };
+            let escaped = (0..limit).find(|_| {
+                z.square_mut();
+                z += &c;
+                let (re, im) = (z.real().to_f64(), z.imag().to_f64());
+                re * re + im * im > 4.0
+            });
+            line.push(match escaped {
+                None => (0, 0, 0),
+                Some(n) => {
+                    let phase = n as f64 / 24.0 * std::f64::consts::TAU;
+                    let channel = |shift: f64| ((0.5 - 0.5 * (phase + shift).cos()) * 255.0).round() as u8;
+                    (channel(0.0), channel(0.8), channel(1.6))
+                }
+            });
+        }
+        pixels.push(line);
+    }
+
+    let mut plot = String::new();
+    for pair in pixels.chunks(2) {
+        for (top, bottom) in pair[0].iter().zip(&pair[1]) {
+            plot.push_str(&"▀".truecolor(top.0, top.1, top.2).on_truecolor(bottom.0, bottom.1, bottom.2).to_string());
+        }
+        plot.push('\n');
+    }
+    let caption = format!(
+        "{} at zoom {}, {} iterations",
+        format_complex(center, state),
+        format_real(zoom, state),
+        format_int(limit, state.base as usize, state.balanced)
+    );
+    let (r, g, b) = state.colours.message;
+    plot.push_str(&caption.truecolor(r, g, b).to_string());
+    Ok(plot)
+}
+/// Splits `<expr> @x` into the expression and the lowercased variable name
+///
+/// The variable is the last word, the expression everything before it. Errors
+/// give `usage` or an invalid name along with an offset into `text`.
+fn split_bound_variable<'a>(text: &'a str, usage: &str) -> Result<(&'a str, String), (String, usize)> {
+    let head = text.trim_end();
+    let name_start = head.rfind([' ', '\t']).map_or(0, |i| i + 1);
+    let expression = &head[..name_start];
+    let Some(name) = head[name_start..].strip_prefix('@') else {
+        return Err((usage.to_string(), name_start));
+    };
+    let name = name.to_ascii_lowercase();
+    if expression.trim().is_empty() {
+        return Err((usage.to_string(), 0));
+    }
+    if name.is_empty()
+        || !name.as_bytes()[0].is_ascii_alphabetic()
+        || !name.bytes().all(|c| c.is_ascii_alphanumeric())
+    {
+        return Err(("Invalid variable name!".to_string(), name_start));
+    }
+    Ok((expression, name))
+}
+/// Where `word` appears last in `text` as a whole word, ignoring case
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let lower = text.to_ascii_lowercase();
+    lower.rmatch_indices(word).map(|(i, _)| i).find(|&i| {
+        let bytes = lower.as_bytes();
+        (i == 0 || bytes[i - 1].is_ascii_whitespace())
+            && bytes.get(i + word.len()).is_none_or(|c| c.is_ascii_whitespace())
+    })
+}
+/// Most steps `:solve` takes before giving up on a root
+const MAX_SOLVE_STEPS: usize = 200;
+/// Finds a root near `guess` of an expression in the first variable, for `:solve`
+///
+/// Takes Newton steps, with the slope from a central difference. Once the
+/// function is seen to change sign along the real line, steps that would leave
+/// the bracketing pair of points bisect it instead.
+///
+/// # Returns
+/// * `Ok((Complex, Complex))` - The root and the expression's value there
+/// * `Err(String)` - An error message if evaluation fails or no root is found
+fn solve_root(
+    tokens: &[Token],
+    guess: Complex,
+    state: &mut BasecalcState,
+) -> Result<(Complex, Complex), String> {
+    let precision = state.precision;
+    let tolerance = Float::with_val(precision, 2).pow(8 - precision as i32);
+    let spacing = Float::with_val(precision, 2).pow(-(precision as i32 / 3));
+    let mut x = guess;
+    let mut fx = bound_value(tokens, &x, state)?;
+    // The points either side of a sign change, negative then positive
+    let mut bracket: Option<(Float, Float)> = None;
+    for _ in 0..MAX_SOLVE_STEPS {
+        check_interrupt(state)?;
+        if fx.is_zero() {
+            return Ok((x, fx));
+        }
+        let scale = Float::with_val(precision, x.abs_ref()).max(&Float::with_val(precision, 1));
+        let h = Complex::with_val(precision, &spacing * scale);
+        let above = bound_value(tokens, &Complex::with_val(precision, &x + &h), state)?;
+        let below = bound_value(tokens, &Complex::with_val(precision, &x - &h), state)?;
+        let slope = (above - below) / (h * 2);
+        let mut next = Complex::with_val(precision, &x - Complex::with_val(precision, &fx / &slope));
+        if let Some((negative, positive)) = &bracket {
+            let inside = next.imag().is_zero()
+                && *next.real() > negative.clone().min(positive)
+                && *next.real() < positive.clone().max(negative);
+            if !inside {
+                next = Complex::with_val(precision, Float::with_val(precision, negative + positive) / 2);
+            }
+        }
+        if !next.real().is_finite() || !next.imag().is_finite() {
+            break;
+        }
+        let f_next = bound_value(tokens, &next, state)?;
+        if x.imag().is_zero() && fx.imag().is_zero() && next.imag().is_zero() && f_next.imag().is_zero() {
+            match &mut bracket {
+                Some((negative, _)) if *f_next.real() < 0 => *negative = next.real().clone(),
+                Some((_, positive)) => *positive = next.real().clone(),
+                None if *fx.real() < 0 && *f_next.real() > 0 => {
+                    bracket = Some((x.real().clone(), next.real().clone()))
+                }
+                None if *fx.real() > 0 && *f_next.real() < 0 => {
+                    bracket = Some((next.real().clone(), x.real().clone()))
+                }
+                None => {}
+            }
+        }
+        let step = Float::with_val(precision, Complex::with_val(precision, &next - &x).abs_ref());
+        let scale = Float::with_val(precision, next.abs_ref()).max(&Float::with_val(precision, 1));
+        x = next;
+        fx = f_next;
+        if step <= Float::with_val(precision, &tolerance * scale) {
+            return Ok((x, fx));
+        }
+    }
+    Err("No root found near the guess!".to_string())
+}
+/// Normalizes the variable named in `#sum`, `#integrate` and the like, which may
+/// be written bare or with its `@`
+fn bound_variable_name(argument: &str) -> Option<String> {
+    let name = argument
+        .trim()
+        .trim_start_matches('@')
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '_')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) || !name.bytes().all(|c| c.is_ascii_alphanumeric()) {
+        None
+    } else {
+        Some(name)
+    }
+}
+/// Name of the temporary variable bound to the nth function parameter
+///
+/// The leading digit keeps it from colliding with the built-in constants.
+fn parameter_variable_name(index: usize) -> String {
+    format!("0p{}", index)
+}
+/// Rewrites parameter names in a function body as references to their bound variables
+///
+/// A parameter may be written bare (`x`) or as a variable (`@x`). Only whole
+/// alphanumeric words are replaced, so digits such as the `2` in `2*x` or the
+/// hex digits in `#sin` are left alone.
+fn substitute_parameters(body: &str, parameters: &[String]) -> String {
+    let variables: Vec<String> = (0..parameters.len()).map(parameter_variable_name).collect();
+    substitute_names(body, parameters, &variables)
+}
+/// Rewrites each of `names` in an expression as a reference to the matching variable,
+/// in the same way as `substitute_parameters`
+fn substitute_names(body: &str, names: &[String], variables: &[String]) -> String {
+    let bytes = body.as_bytes();
+    let mut result = String::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        // Other characters are copied whole, however many bytes they take
+        if !bytes[index].is_ascii_alphanumeric() {
+            while index < bytes.len() && !bytes[index].is_ascii_alphanumeric() {
+                index += 1;
+            }
+            result.push_str(&body[start..index]);
+            continue;
+        }
+        while index < bytes.len() && bytes[index].is_ascii_alphanumeric() {
+            index += 1;
+        }
+        let word = &body[start..index];
+        let prefix = if start > 0 { bytes[start - 1] } else { b' ' };
+        let parameter = names.iter().position(|p| p.eq_ignore_ascii_case(word));
+        match parameter {
+            Some(i) if prefix == b'@' => result.push_str(&variables[i]),
+            Some(i) if prefix != b'#' => {
+                result.push('@');
+                result.push_str(&variables[i]);
+            }
+            _ => result.push_str(word),
+        }
+    }
+    result
+}
+/// Splits the parenthesized, comma separated arguments of a function call, or
+/// the elements of a `{...}` list
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The index of the opening parenthesis or brace
+///
+/// # Returns
+/// * `Ok((Vec<String>, usize))` - The argument expressions and the index after `)` or `}`
+/// * `Err((String, usize))` - An error message and the position of the error
+fn split_call_arguments(input: &[u8], index: usize) -> Result<(Vec<String>, usize), (String, usize)> {
+    let (close, part, brackets) = if input[index] == b'{' {
+        (b'}', "element", "braces")
+    } else {
+        (b')', "argument", "parentheses")
+    };
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut start = index + 1;
+    for i in index + 1..input.len() {
+        match input[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            c if c == close && depth == 0 => {
+                let argument = String::from_utf8_lossy(&input[start..i]).to_string();
+                if argument.trim().is_empty() {
+                    if !arguments.is_empty() {
+                        return Err((format!("Missing {}!", part), i));
+                    }
+                } else {
+                    arguments.push(argument);
+                }
+                return Ok((arguments, i + 1));
+            }
+            b')' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                let argument = String::from_utf8_lossy(&input[start..i]).to_string();
+                if argument.trim().is_empty() {
+                    return Err((format!("Missing {}!", part), i));
+                }
+                arguments.push(argument);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Err((format!("Mismatched {}!", brackets), input.len()))
+}
+/// Parses an assignment that splits a complex value, such as `@re, @im = #sqrt(-4 + 3i)`
+///
+/// The real part goes to the first variable and the imaginary part to the
+/// second, each created if need be. The whole value becomes the previous result.
+///
+/// # Returns
+/// * `None` - If the input doesn't start with two variables and an `=`
+/// * `Some(Ok(String))` - Both parts were assigned, with a message showing them
+/// * `Some(Err((String, usize)))` - An error message and the position of the error
+fn parse_destructuring(
+    input: &[u8],
+    index: usize,
+    state: &mut BasecalcState,
+) -> Option<Result<String, (String, usize)>> {
+    let (first, first_start, first_end) = read_variable_name(input, index);
+    let mut comma = first_end;
+    while comma < input.len() && (input[comma] == b' ' || input[comma] == b'\t') {
+        comma += 1;
+    }
+    if first.is_empty() || input.get(comma) != Some(&b',') || !input[comma + 1..].trim_ascii_start().starts_with(b"@") {
+        return None;
+    }
+    let (second, second_start, second_end) = read_variable_name(input, comma + 1);
+    let mut equals = second_end;
+    while equals < input.len() && (input[equals] == b' ' || input[equals] == b'\t') {
+        equals += 1;
+    }
+    if second.is_empty() || input.get(equals) != Some(&b'=') || matches!(input.get(equals + 1), Some(b'=' | b'~')) {
+        return None;
+    }
+    if first == second {
+        return Some(Err(("The two parts need different variables!".to_string(), second_start)));
+    }
+    for (name, start) in [(&first, first_start), (&second, second_start)] {
+        if !name.as_bytes()[0].is_ascii_alphabetic() {
+            return Some(Err(("Invalid variable name!".to_string(), start)));
+        }
+        if CONSTANTS.iter().any(|(built_in, ..)| built_in.eq_ignore_ascii_case(&format!("@{}", name))) {
+            return Some(Err((format!("@{} is already built in!", name), start)));
+        }
+        if state.constants.iter().any(|c| &c.name == name) {
+            return Some(Err((format!("@{} is a constant, change it with :const!", name), start)));
+        }
+        if state.variables.iter().any(|v| &v.name.to_ascii_lowercase() == name && v.locked) {
+            return Some(Err((format!("@{} is locked, :unlock it to change it!", name), start)));
+        }
+    }
+    let body = String::from_utf8_lossy(&input[equals + 1..]).to_string();
+    let value = match evaluate_slice(&body, equals + 1, state) {
+        Ok(value) => value,
+        Err(err) => return Some(Err(err)),
+    };
+    let parts = [
+        Complex::with_val(state.precision, value.real()),
+        Complex::with_val(state.precision, value.imag()),
+    ];
+    for (name, part) in [first.clone(), second.clone()].into_iter().zip(parts) {
+        let variable = Variable {
+            name: name.clone(),
+            value: part,
+            dimension: DIMENSIONLESS,
+            list: None,
+            formula: None,
+            note: None,
+            locked: false,
+        };
+        match state.variables.iter().position(|v| v.name.to_ascii_lowercase() == name) {
+            Some(pos) => {
+                // Notes stay with the variable, as they do for `=`
+                let note = state.variables[pos].note.take();
+                state.variables[pos] = Variable { note, ..variable };
+            }
+            None => state.variables.push(variable),
+        }
+    }
+    state.record_result(value.clone());
+    Some(Ok(format!(
+        "@{} = {}, @{} = {}",
+        first,
+        format_real(value.real(), state),
+        second,
+        format_real(value.imag(), state)
+    )))
+}
+/// Parses a dependent variable definition such as `@area := @pi * @r^2`
+///
+/// The formula is kept with the variable and worked out again each time the
+/// variable is read, so it follows the variables it depends on. It's worked out
+/// once straight away, and a formula that fails, circular ones included, leaves
+/// the variable as it was.
+///
+/// # Returns
+/// * `None` - If the input is not a dependent definition
+/// * `Some(Ok(String))` - The definition was stored, with its current value to display
+/// * `Some(Err((String, usize)))` - An error message and the position of the error
+fn parse_dependent_definition(
+    input: &[u8],
+    index: usize,
+    state: &mut BasecalcState,
+) -> Option<Result<String, (String, usize)>> {
+    let mut name = String::new();
+    let mut curr_index = index + 1;
+    while curr_index < input.len()
+        && (input[curr_index].is_ascii_alphanumeric()
+            || input[curr_index] == b' '
+            || input[curr_index] == b'_'
+            || input[curr_index] == b'\t')
+    {
+        if input[curr_index].is_ascii_alphanumeric() {
+            name.push(input[curr_index].to_ascii_lowercase() as char);
+        }
+        curr_index += 1;
+    }
+    if name.is_empty() || !input[curr_index..].starts_with(b":=") {
+        return None;
+    }
+    if !name.as_bytes()[0].is_ascii_alphabetic() {
+        return Some(Err(("Invalid variable name!".to_string(), index)));
+    }
+    if CONSTANTS.iter().any(|(built_in, ..)| built_in.eq_ignore_ascii_case(&format!("@{}", name))) {
+        return Some(Err((format!("@{} is already built in!", name), index)));
+    }
+    if state.constants.iter().any(|c| c.name == name) {
+        return Some(Err((format!("@{} is a constant, change it with :const!", name), index)));
+    }
+    let body = String::from_utf8_lossy(&input[curr_index + 2..]).trim().to_string();
+    if body.is_empty() {
+        return Some(Err(("Missing formula!".to_string(), input.len())));
+    }
+    if body.starts_with(':') {
+        return Some(Err(("Commands are not allowed inside expressions!".to_string(), curr_index + 2)));
+    }
+
+    if state.variables.iter().any(|v| v.name == name && v.locked) {
+        return Some(Err((format!("@{} is locked, :unlock it to change it!", name), index)));
+    }
+    let saved_variables = state.variables.clone();
+    let pos = match state.variables.iter().position(|v| v.name == name) {
+        Some(pos) => pos,
+        None => {
+            state.variables.push(Variable {
+                name: name.clone(),
+                value: Complex::with_val(state.precision, 0),
+                dimension: DIMENSIONLESS,
+                list: None,
+                formula: None,
+                note: None,
+                locked: false,
+            });
+            state.variables.len() - 1
+        }
+    };
+    state.variables[pos].formula = Some(body.clone());
+    if let Err(msg) = refresh_dependent(pos, state) {
+        state.variables = saved_variables;
+        return Some(Err((msg, curr_index + 2)));
+    }
+    let value = format_complex(&state.variables[pos].value, state);
+    Some(Ok(format!("@{} := {}, now {}", name, body, value)))
+}
+/// Works out a `:=` variable's formula again, storing the value in the variable
+///
+/// The formula is evaluated as an assignment, so units and lists are kept just as
+/// `=` would keep them. Reading a variable whose formula is already being worked
+/// out means the definitions go round in a circle.
+fn refresh_dependent(pos: usize, state: &mut BasecalcState) -> Result<(), String> {
+    let name = state.variables[pos].name.clone();
+    let Some(formula) = state.variables[pos].formula.clone() else {
+        return Ok(());
+    };
+    if state.resolving.contains(&name) {
+        return Err(format!("Circular definition of @{}!", name));
+    }
+    state.resolving.push(name.clone());
+    let result = tokenize(&format!("@{} = {}", name, formula), state)
+        .map_err(|(msg, _)| msg)
+        .and_then(|tokens| evaluate_tokens(&tokens, state));
+    state.resolving.pop();
+    // The assignment dropped the formula, which still holds
+    if let Some(variable) = state.variables.iter_mut().find(|v| v.name == name) {
+        variable.formula = Some(formula);
+    }
+    result.map(|_| ()).map_err(|msg| {
+        if msg.starts_with("In @") || msg.starts_with("Circular") {
+            msg
+        } else {
+            format!("In @{}: {}", name, msg)
+        }
+    })
+}
+/// Parses a function definition such as `@f(x, y) = x^2 + #sin(y)`
+///
+/// # Returns
+/// * `None` - If the input is not a function definition
+/// * `Some(Ok(String))` - The definition was stored, with a message to display
+/// * `Some(Err((String, usize)))` - An error message and the position of the error
+fn parse_function_definition(
+    input: &[u8],
+    index: usize,
+    state: &mut BasecalcState,
+) -> Option<Result<String, (String, usize)>> {
+    let mut name = String::new();
+    let mut curr_index = index + 1;
+    while curr_index < input.len()
+        && (input[curr_index].is_ascii_alphanumeric()
+            || input[curr_index] == b' '
+            || input[curr_index] == b'_'
+            || input[curr_index] == b'\t')
+    {
+        if input[curr_index].is_ascii_alphanumeric() {
+            name.push(input[curr_index].to_ascii_lowercase() as char);
+        }
+        curr_index += 1;
+    }
+    if name.is_empty() || curr_index >= input.len() || input[curr_index] != b'(' {
+        return None;
+    }
+    let (parameters, body_index) = match split_call_arguments(input, curr_index) {
+        Ok(parsed) => parsed,
+        Err(_) => return None,
+    };
+    let mut equals = body_index;
+    while equals < input.len() && (input[equals] == b' ' || input[equals] == b'\t') {
+        equals += 1;
+    }
+    if equals >= input.len() || input[equals] != b'=' {
+        return None;
+    }
+
+    let mut parameter_names: Vec<String> = Vec::new();
+    for parameter in &parameters {
+        let parameter_name: String = parameter
+            .trim()
+            .trim_start_matches('@')
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if parameter_name.is_empty() || !parameter_name.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return Some(Err((
+                format!("Invalid parameter name '{}'!", parameter.trim()),
+                curr_index,
+            )));
+        }
+        if parameter_names.contains(&parameter_name) {
+            return Some(Err((
+                format!("Duplicate parameter '{}'!", parameter_name),
+                curr_index,
+            )));
+        }
+        parameter_names.push(parameter_name);
+    }
+    let body = String::from_utf8_lossy(&input[equals + 1..]).trim().to_string();
+    if body.is_empty() {
+        return Some(Err(("Missing function body!".to_string(), input.len())));
+    }
+
+    // Store first so recursive definitions can refer to themselves
+    let function = UserFunction {
+        name: name.clone(),
+        parameters: parameter_names.clone(),
+        body: body.clone(),
+    };
+    let previous = state.functions.iter().position(|f| f.name == name);
+    let saved_functions = state.functions.clone();
+    match previous {
+        Some(pos) => state.functions[pos] = function,
+        None => state.functions.push(function),
+    }
+
+    // Check the body tokenizes with the parameters bound
+    let saved_variables = state.variables.clone();
+    for i in 0..parameter_names.len() {
+        state.variables.insert(
+            0,
+            Variable {
+                name: parameter_variable_name(i),
+                value: Complex::with_val(state.precision, 0),
+                dimension: DIMENSIONLESS,
+                list: None,
+                formula: None,
+                note: None,
+                locked: false,
+            },
+        );
+    }
+    let check = tokenize(&substitute_parameters(&body, &parameter_names), state);
+    state.variables = saved_variables;
+    if let Err((msg, _)) = check {
+        state.functions = saved_functions;
+        return Some(Err((msg, equals + 1)));
+    }
+
+    Some(Ok(format!(
+        "Defined @{}({}).",
+        name,
+        parameter_names.join(", ")
+    )))
+}
+/// Parses `&` or an indexed reference such as `&3`, the third most recent result
+///
+/// The index is written in the current base, and `&1` is the same as `&`.
+///
+/// # Returns
+/// * `Ok((Token, usize))` - The reference token and the index after it
+/// * `Err((String, usize))` - An error message and position if there is no such result
+fn parse_result_reference(
+    input: &[u8],
+    index: usize,
+    state: &BasecalcState,
+) -> Result<(Token, usize), (String, usize)> {
+    let mut end = index + 1;
+    let mut n = 0usize;
+    while end < input.len() {
+        match (input[end] as char).to_digit(state.base as u32) {
+            Some(digit) => n = n.saturating_mul(state.base as usize).saturating_add(digit as usize),
+            None => break,
+        }
+        end += 1;
+    }
+    if end == index + 1 {
+        return Ok((
+            Token {
+                operator: '&',
+                ..Token::new()
+            },
+            end,
+        ));
+    }
+    if n == 0 {
+        return Err(("Result references start at &1!".to_string(), index));
+    }
+    if n > state.results.len() {
+        let kept = if state.results.len() >= state.history_max { "kept" } else { "so far" };
+        return Err((
+            format!(
+                "Only {} results {}!",
+                format_int(state.results.len(), state.base as usize, state.balanced),
+                kept
+            ),
+            index,
+        ));
+    }
+    Ok((
+        Token {
+            operator: '&',
+            var_index: Some(state.results.len() - n),
+            ..Token::new()
+        },
+        end,
+    ))
+}
+/// Checks `actual =~ expected` when the input contains a top-level `=~`
+///
+/// Both sides are evaluated and compared within the current epsilon, which the
+/// absolute or the relative error must not exceed. The left side becomes the
+/// previous result either way.
+///
+/// # Returns
+/// * `None` - If the input is not an assertion
+/// * `Some(Ok(String))` - The passing report
+/// * `Some(Err((String, usize)))` - The failing report or an error, and its position
+fn parse_assertion(
+    input_str: &str,
+    state: &mut BasecalcState,
+) -> Option<Result<String, (String, usize)>> {
+    let input = input_str.as_bytes();
+    let mut depth = 0;
+    let mut split = None;
+    for i in 0..input.len().saturating_sub(1) {
+        match input[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth > 0 => depth -= 1,
+            b'=' if depth == 0 && input[i + 1] == b'~' => {
+                split = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let split = split?;
+    let actual = evaluate_slice(&input_str[..split], 0, state);
+    let expected = evaluate_slice(&input_str[split + 2..], split + 2, state);
+    let (actual, expected) = match (actual, expected) {
+        (Ok(actual), Ok(expected)) => (actual, expected),
+        (Err(err), _) | (_, Err(err)) => return Some(Err(err)),
+    };
+    state.record_result(actual.clone());
+
+    let error = Float::with_val(state.precision, (actual - &expected).abs().real());
+    let magnitude = Float::with_val(state.precision, expected.abs().real());
+    let epsilon = match &state.epsilon {
+        Some(epsilon) => epsilon.clone(),
+        None => default_epsilon(state),
+    };
+    let mut report = format!("absolute error {}", format_real(&error, state));
+    let mut pass = error <= epsilon;
+    if !magnitude.is_zero() {
+        let relative = error / magnitude;
+        report.push_str(&format!(", relative error {}", format_real(&relative, state)));
+        pass |= relative <= epsilon;
+    }
+    if pass {
+        Some(Ok(format!("Pass, {}", report)))
+    } else {
+        Some(Err((
+            format!("Fail, {}, epsilon {}", report, format_real(&epsilon, state)),
+            split,
+        )))
+    }
+}
+/// Evaluates part of a line, with error positions offset to match the whole line
+fn evaluate_slice(
+    input_str: &str,
+    offset: usize,
+    state: &mut BasecalcState,
+) -> Result<Complex, (String, usize)> {
+    if input_str.trim_start().starts_with(':') {
+        return Err(("Commands are not allowed in assertions!".to_string(), offset));
+    }
+    let tokens = match tokenize(input_str, state) {
+        Ok(tokens) => tokens,
+        Err((_, usize::MAX)) => return Err(("Expected an expression!".to_string(), offset)),
+        Err((msg, pos)) => return Err((msg, pos + offset)),
+    };
+    if tokens.is_empty() {
+        return Err(("Expected an expression!".to_string(), offset));
+    }
+    match evaluate_tokens(&tokens, state) {
+        Ok(result) if result.list.is_some() => Err(("Expected a single value, not a list!".to_string(), offset)),
+        Ok(result) => Ok(result.value),
+        Err(msg) => Err((msg, offset)),
+    }
+}
+/// Writes constants one per line as `name real imaginary`, in decimal with enough
+/// digits to read back exactly
+///
+/// A list follows as `{real:imaginary,...}`, with no spaces so it reads back as one field.
+fn constants_to_text(constants: &[Variable]) -> String {
+    let mut text = String::new();
+    for constant in constants {
+        text.push_str(&format!(
+            "{} {} {}",
+            constant.name,
+            constant.value.real().to_string_radix(10, None),
+            constant.value.imag().to_string_radix(10, None)
+        ));
+        if let Some(list) = &constant.list {
+            let elements: Vec<String> = list
+                .iter()
+                .map(|element| {
+                    format!(
+                        "{}:{}",
+                        element.real().to_string_radix(10, None),
+                        element.imag().to_string_radix(10, None)
+                    )
+                })
+                .collect();
+            text.push_str(&format!(" {{{}}}", elements.join(",")));
+        }
+        if constant.dimension != DIMENSIONLESS {
+            for exponent in constant.dimension {
+                text.push_str(&format!(" {}", exponent));
+            }
+        }
+        if constant.locked {
+            text.push_str(" locked");
+        }
+        if let Some(note) = &constant.note {
+            text.push_str(&format!(" \"{}\"", note));
+        }
+        if let Some(formula) = &constant.formula {
+            text.push_str(&format!(" := {}", formula));
+        }
+        text.push('\n');
+    }
+    text
+}
+/// Reads constants written by `constants_to_text`, skipping any malformed lines
+fn constants_from_text(text: &str, precision: u32) -> Vec<Variable> {
+    let mut constants = Vec::new();
+    for line in text.lines() {
+        // A quoted note comes next and a `:=` variable's formula last, either of
+        // which may hold the other's marker, so the note is read up to its quote
+        let split = [line.find(" \""), line.find(" := ")].into_iter().flatten().min();
+        let (line, rest) = line.split_at(split.unwrap_or(line.len()));
+        let (note, rest) = match rest.strip_prefix(" \"").and_then(|rest| rest.split_once('"')) {
+            Some((note, rest)) => (Some(note.to_string()), rest),
+            None => (None, rest),
+        };
+        let formula = rest.strip_prefix(" := ").map(str::to_string);
+        let (line, locked) = match line.strip_suffix(" locked") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let mut parts = line.split_whitespace().peekable();
+        let (Some(name), Some(real), Some(imaginary)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let list = match parts.next_if(|part| part.starts_with('{')) {
+            Some(part) => match list_from_text(part, precision) {
+                Some(list) => Some(list),
+                None => continue,
+            },
+            None => None,
+        };
+        // Units follow as the seven SI exponents, when there are any
+        let exponents: Vec<i32> = parts.filter_map(|part| part.parse().ok()).collect();
+        let dimension = exponents.try_into().unwrap_or(DIMENSIONLESS);
+        if let (Ok(real), Ok(imaginary)) = (Float::parse(real), Float::parse(imaginary)) {
+            constants.push(Variable {
+                name: name.to_string(),
+                value: Complex::with_val(precision, (real, imaginary)),
+                dimension,
+                list,
+                formula,
+                note,
+                locked,
+            });
+        }
+    }
+    constants
+}
+/// Reads the `{real:imaginary,...}` elements of a saved list
+fn list_from_text(text: &str, precision: u32) -> Option<Vec<Complex>> {
+    let elements = text.strip_prefix('{')?.strip_suffix('}')?;
+    elements
+        .split(',')
+        .map(|element| {
+            let (real, imaginary) = element.split_once(':')?;
+            let (real, imaginary) = (Float::parse(real).ok()?, Float::parse(imaginary).ok()?);
+            Some(Complex::with_val(precision, (real, imaginary)))
+        })
+        .collect()
+}
+/// The epsilon used by `=~` until `:epsilon` sets one: a unit in the last displayed digit
+fn default_epsilon(state: &BasecalcState) -> Float {
+    Float::with_val(state.precision, state.base).pow(-(state.digits as i32))
+}
+/// Formats a complex number in the current base without colour or padding
+fn format_complex(value: &Complex, state: &BasecalcState) -> String {
+    coloured_vec_to_string(&num2string(value, state)).trim().to_string()
+}
+/// Formats a real number in the current base without colour or padding
+fn format_real(value: &Float, state: &BasecalcState) -> String {
+    coloured_vec_to_string(&num2string(&Complex::with_val(state.precision, value), state))
+        .trim()
+        .to_string()
+}
+/// Evaluates the tokens of a whole statement, as entered at the prompt
+///
+/// Unlike `evaluate_tokens`, which also works out arguments, conditions and the
+/// like, the result goes into the running total when `:acc` mode is active.
+pub fn evaluate_statement(tokens: &[Token], state: &mut BasecalcState) -> Result<EvalResult, String> {
+    let mut result = evaluate_tokens(tokens, state)?;
+    accumulate(&mut result, state)?;
+    Ok(result)
+}
+/// Combines a statement's result with the running accumulator when `:acc` mode is active
+///
+/// The result becomes the updated total. Assignments, lists and values with
+/// units are left out of it.
+///
+/// # Returns
+/// * `Ok(())` - If the result was combined, or left alone when inactive
+/// * `Err(String)` - An error message if the operation fails
+fn accumulate(result: &mut EvalResult, state: &mut BasecalcState) -> Result<(), String> {
+    let op = match state.acc_operator {
+        Some(op) if result.assignment.is_none() && result.list.is_none() && result.unit.is_none() => op,
+        _ => return Ok(()),
+    };
+    let combined = match state.accumulator.take() {
+        Some(acc) => {
+            let mut output_queue = vec![acc, result.value.clone()];
+            apply_operator(&mut output_queue, op, state)?;
+            output_queue.pop().unwrap()
+        }
+        None => result.value.clone(),
+    };
+    state.accumulator = Some(combined.clone());
+    result.value = combined;
+    Ok(())
+}
+fn apply_operator(
+    output_queue: &mut Vec<Complex>,
+    op: char,
+    state: &mut BasecalcState,
+) -> Result<(), String> {
+    debug_println(&format!("Applying operator: {}", op));
+    match op {
+        '+' | '-' | '*' | '/' | '^' | '%' | '$' => apply_binary_operator(output_queue, op)?,
+        '⊕' | '⊖' => {
+            if let (Some(b), Some(a)) = (output_queue.pop(), output_queue.pop()) {
+                let factor = if op == '⊕' { 1 + b } else { 1 - b };
+                output_queue.push(a * factor);
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        'B' | 'V' | 'X' | '<' | '>' => {
+            if let (Some(b), Some(a)) = (output_queue.pop(), output_queue.pop()) {
+                let a = bitwise_operand(&a)?;
+                let b = bitwise_operand(&b)?;
+                let result = match op {
+                    'B' => a & b,
+                    'V' => a | b,
+                    'X' => a ^ b,
+                    _ => {
+                        let left = (op == '<') == (b >= 0);
+                        let shift = match b.abs().to_u32() {
+                            Some(shift) if shift <= MAX_SHIFT => shift,
+                            _ => return Err("Shift amount too large!".to_string()),
+                        };
+                        if left {
+                            a << shift
+                        } else {
+                            a >> shift
+                        }
+                    }
+                };
+                output_queue.push(Complex::with_val(state.precision, result));
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        '⥀' | '⥁' => {
+            if let (Some(k), Some(n)) = (output_queue.pop(), output_queue.pop()) {
+                let name = operator_name(op);
+                let n = whole_operand(&n, name)?;
+                let k = whole_operand(&k, name)?;
+                let mut digits = integer_digits(&n, state.base);
+                // Any count wraps around to less than one full turn
+                let turn = Integer::from(digits.len());
+                let k = k.rem_euc(&turn).to_usize().unwrap_or(0);
+                if op == '⥀' {
+                    digits.rotate_left(k);
+                } else {
+                    digits.rotate_right(k);
+                }
+                let result = digits_integer(&digits, state.base);
+                output_queue.push(Complex::with_val(state.precision, if n < 0 { -result } else { result }));
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        '≤' | '≥' | '⋖' | '⋗' | '≡' | '≠' => {
+            if let (Some(b), Some(a)) = (output_queue.pop(), output_queue.pop()) {
+                let result = compare(op, &a, &b)?;
+                output_queue.push(Complex::with_val(state.precision, result as u32));
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        'C' | 'R' => {
+            if let (Some(k), Some(n)) = (output_queue.pop(), output_queue.pop()) {
+                output_queue.push(combinatorics(op, &n, &k, state)?);
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        '⊓' | '⊔' => {
+            if let (Some(b), Some(a)) = (output_queue.pop(), output_queue.pop()) {
+                let name = operator_name(op);
+                let a = whole_operand(&a, name)?;
+                let b = whole_operand(&b, name)?;
+                let result = if op == '⊓' { a.gcd(&b) } else { a.lcm(&b) };
+                output_queue.push(Complex::with_val(state.precision, result));
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        'n' | 'a' | 'O' | 'o' | 'S' | 'T' | 'c' | 'f' | 'F' | 'i' | 'I' | 'l' | 'L' | '₂' | '⏨' | 'e' | 'r'
+        | 'g' | 's' | 'q' | 't' | 'A' | 'x' | 'ξ' | 'Ξ' | 'Φ' | 'h' | 'k' | 'y' | 'H' | 'K' | 'Y' | 'j' | 'ζ' | 'ψ'
+        | 'N' | '℗' | '⇥' | '⇤' | '⊘' | '⊜' | '⊛' | '⊝' | 'ℱ' | 'Ⅼ' | '⌗' | '⌘' | '⅀' | 'ϱ' | '⌸' | '⇆' | 'z'
+        | 'Z' | 'D' | 'ȷ' | 'ū' | '٪' | '∑' | '∏' | 'μ' | 'm' | 'σ' | 'ς' | '↓' | '↑' => {
+            if let Some(value) = output_queue.pop() {
+                let result = apply_unary_operator(op, value, state)?;
+                output_queue.push(result);
+            } else {
+                return Err(format!("Not enough operands for {}", op));
+            }
+        }
+        _ => return Err(format!("Unknown operator: {}", op)),
+    }
+    Ok(())
+}
+fn get_precedence(op: char) -> Precedence {
+    match op {
+        '≤' | '≥' | '⋖' | '⋗' | '≡' | '≠' => Precedence::Comparison,
+        'V' => Precedence::BitwiseOr,
+        'X' => Precedence::BitwiseXor,
+        'B' => Precedence::BitwiseAnd,
+        '<' | '>' | '⥀' | '⥁' => Precedence::Shift,
+        '+' | '-' | '⊕' | '⊖' => Precedence::Addition,
+        '*' | '/' | '%' | 'C' | 'R' | '⊓' | '⊔' => Precedence::Multiplication,
+        '^' | '$' => Precedence::Exponentiation,
+        'n' | 'a' | 'O' | 'o' | 'S' | 'T' | 'c' | 'f' | 'F' | 'i' | 'I' | 'l' | 'L' | '₂' | '⏨' | 'e' | 'r'
+        | 'g' | 's' | 'q' | 't' | 'A' | 'x' | 'ξ' | 'Ξ' | 'Φ' | 'h' | 'k' | 'y' | 'H' | 'K' | 'Y' | 'j' | 'ζ' | 'ψ'
+        | 'N' | '℗' | '⇥' | '⇤' | '⊘' | '⊜' | '⊛' | '⊝' | 'ℱ' | 'Ⅼ' | '⌗' | '⌘' | '⅀' | 'ϱ' | '⌸' | '⇆' | 'z' | 'Z'
+        | 'D' | 'ȷ' | 'ū' | '٪' | '∑' | '∏' | 'μ' | 'm' | 'σ' | 'ς' | '↓' | '↑' => Precedence::Unary,
+        '(' | ')' => Precedence::Parenthesis,
+        '=' => Precedence::Assignment,
+        _ => Precedence::Addition, // Default to lowest precedence for unknown operators
+    }
+}
+/// The base of `#log`, `#log2` or `#log10`, only the first following `:base`
+fn log_base(op: char, state: &BasecalcState) -> u8 {
+    match op {
+        '₂' => 2,
+        '⏨' => 10,
+        _ => state.base,
+    }
+}
+/// Converts an angle in the current units to radians for the trig functions
+fn angle_to_radians(value: Complex, state: &BasecalcState) -> Complex {
+    if state.angle == AngleMode::Radians {
+        return value;
+    }
+    let pi = Float::with_val(state.precision, rug::float::Constant::Pi);
+    value * pi / state.angle.half_turn(state.precision)
+}
+/// Converts an angle in radians, as the inverse trig functions give, to the current units
+fn angle_from_radians(value: Complex, state: &BasecalcState) -> Complex {
+    if state.angle == AngleMode::Radians {
+        return value;
+    }
+    value * state.angle.half_turn(state.precision)
+        / Float::with_val(state.precision, rug::float::Constant::Pi)
+}
+/// Whether an operator reduces a list to one value, rather than applying to each element
+fn is_aggregate(op: char) -> bool {
+    matches!(op, '∑' | '∏' | 'μ' | 'm' | 'σ' | 'ς' | '↓' | '↑')
+}
+/// Reduces the elements of a list to one value, for `#sum`, `#mean` and the like
+///
+/// A lone value counts as a list of one. `#var` and `#stdev` are of a sample, so
+/// need at least two values, and `#median`, `#min` and `#max` need real ones.
+fn aggregate(op: char, elements: &[Complex], state: &BasecalcState) -> Result<Complex, String> {
+    let count = elements.len() as u32;
+    let sum = || {
+        elements
+            .iter()
+            .fold(Complex::with_val(state.precision, 0), |total, element| total + element)
+    };
+    let result = match op {
+        '∑' => sum(),
+        '∏' => elements
+            .iter()
+            .fold(Complex::with_val(state.precision, 1), |total, element| total * element),
+        'μ' => sum() / count,
+        'σ' | 'ς' => {
+            if count < 2 {
+                return Err(format!("{} needs at least two values!", operator_name(op)));
+            }
+            let mean = sum() / count;
+            // Complex values spread by their distance from the mean
+            let squares = elements.iter().fold(Float::with_val(state.precision, 0), |total, element| {
+                let difference = Complex::with_val(state.precision, element - &mean);
+                total + Float::with_val(state.precision, difference.abs_ref()).square()
+            });
+            let variance = squares / (count - 1);
+            Complex::with_val(state.precision, if op == 'σ' { variance.sqrt() } else { variance })
+        }
+        _ => {
+            if elements.iter().any(|element| !element.imag().is_zero()) {
+                return Err(format!("{} needs real values!", operator_name(op)));
+            }
+            let mut reals: Vec<&Float> = elements.iter().map(|element| element.real()).collect();
+            reals.sort_by(|a, b| a.total_cmp(b));
+            let middle = reals.len() / 2;
+            let value = match op {
+                '↓' => reals[0].clone(),
+                '↑' => reals[reals.len() - 1].clone(),
+                _ if reals.len() % 2 == 1 => reals[middle].clone(),
+                _ => Float::with_val(state.precision, reals[middle - 1] + reals[middle]) / 2,
+            };
+            Complex::with_val(state.precision, value)
+        }
+    };
+    Ok(result)
+}
+fn apply_unary_operator(
+    op: char,
+    value: Complex,
+    state: &BasecalcState,
+) -> Result<Complex, String> {
+    debug_println(&format!(
+        "Applying unary operator: {} to value: {}",
+        op, value
+    ));
+    let result = match op {
+        'n' => -value,
+        'a' => value.abs(),
+        'S' => {
+            angle_from_radians(value.asin(), state)
+        }
+        'O' => {
+            angle_from_radians(value.acos(), state)
+        }
+        'T' => {
+            angle_from_radians(value.atan(), state)
+        }
+        'c' => gaussian_ceil(&value),
+        'f' => gaussian_floor(&value),
+        'F' => fractional_part(&value),
+        'i' => Complex::with_val(state.precision, (value.imag(), 0)),
+        'I' => integer_part(&value),
+        'l' => value.ln(),
+        'L' | '₂' | '⏨' => value.ln() / Float::with_val(state.precision, log_base(op, state)).ln(),
+        'e' => Complex::with_val(state.precision, (value.real(), 0)),
+        'r' => gaussian_round(&value),
+        'g' => sign(&value),
+        'q' => value.sqrt(),
+        's' => {
+            angle_to_radians(value, state).sin()
+        }
+        'o' => {
+            angle_to_radians(value, state).cos()
+        }
+        't' => {
+            angle_to_radians(value, state).tan()
+        }
+        'j' => gamma(&value, state)?,
+        'ζ' => zeta(&value, state)?,
+        'ψ' => polygamma(0, &value, state)?,
+        'D' => {
+            if !value.imag().is_zero() {
+                return Err("#dms needs a real angle!".to_string());
+            }
+            // Minutes and seconds each take two digits, so need at least 60 of them
+            if state.base < 8 {
+                return Err("#dms needs base 8 or above!".to_string());
+            }
+            let mut degrees = value.real().clone();
+            if state.angle != AngleMode::Degrees {
+                degrees = degrees * 180 / state.angle.half_turn(state.precision);
+            }
+            let negative = degrees.is_sign_negative();
+            degrees.abs_mut();
+            let whole = degrees.clone().floor();
+            let minutes: Float = (degrees - &whole) * 60;
+            let whole_minutes = minutes.clone().floor();
+            let seconds: Float = (minutes - &whole_minutes) * 60;
+            let place = Float::with_val(state.precision, state.base).square();
+            let packed = whole + whole_minutes / &place + seconds / place.square();
+            Complex::with_val(state.precision, if negative { -packed } else { packed })
+        }
+        'ȷ' => value / SECONDS_PER_DAY + Float::with_val(state.precision, UNIX_EPOCH_JD),
+        'ū' => (value - Float::with_val(state.precision, UNIX_EPOCH_JD)) * SECONDS_PER_DAY,
+        '٪' => value / 100,
+        'N' => Complex::with_val(state.precision, !bitwise_operand(&value)?),
+        '℗' => Complex::with_val(state.precision, is_prime(&whole_operand(&value, "#isprime")?) as u8),
+        '⇥' => Complex::with_val(state.precision, next_prime(whole_operand(&value, "#nextprime")?, true)?),
+        '⇤' => Complex::with_val(state.precision, next_prime(whole_operand(&value, "#prevprime")?, false)?),
+        '⌸' => {
+            let digits = integer_digits(&whole_operand(&value, "#ndigits")?, state.base);
+            Complex::with_val(state.precision, digits.len())
+        }
+        '⇆' => {
+            let n = whole_operand(&value, "#reverse")?;
+            let mut digits = integer_digits(&n, state.base);
+            digits.reverse();
+            let result = digits_integer(&digits, state.base);
+            Complex::with_val(state.precision, if n < 0 { -result } else { result })
+        }
+        '⌗' | '⌘' | '⅀' | 'ϱ' => {
+            let n = whole_operand(&value, operator_name(op))?.abs();
+            let result = match op {
+                '⌗' => Integer::from(n.significant_bits()),
+                '⌘' => Integer::from(n.count_ones().unwrap_or(0)),
+                '⅀' => Integer::from(integer_digits(&n, state.base).iter().map(|&digit| digit as u32).sum::<u32>()),
+                // Digit sums keep n modulo base - 1, ending on base - 1 rather than 0
+                _ if n.is_zero() => n,
+                _ => (n - 1u32) % (state.base as u32 - 1) + 1u32,
+            };
+            Complex::with_val(state.precision, result)
+        }
+        'ℱ' | 'Ⅼ' => {
+            let name = operator_name(op);
+            let n = whole_operand(&value, name)?;
+            let index = n
+                .clone()
+                .abs()
+                .to_u32()
+                .filter(|&index| index <= MAX_FIBONACCI_INDEX)
+                .ok_or_else(|| format!("{} index too large!", name))?;
+            // F(-n) = (-1)^(n + 1) F(n) and L(-n) = (-1)^n L(n)
+            let flip = n < 0 && (index % 2 == 0) == (op == 'ℱ');
+            let result = if op == 'ℱ' {
+                Integer::from(Integer::fibonacci(index))
+            } else {
+                Integer::from(Integer::lucas(index))
+            };
+            Complex::with_val(state.precision, if flip { -result } else { result })
+        }
+        '⊘' | '⊜' | '⊛' | '⊝' => {
+            let name = operator_name(op);
+            let n = whole_operand(&value, name)?;
+            if n <= 0 {
+                return Err(format!("{} needs a positive integer!", name));
+            }
+            Complex::with_val(state.precision, divisor_function(op, n, state)?)
+        }
+        _ if is_aggregate(op) => aggregate(op, std::slice::from_ref(&value), state)?,
+        'z' | 'Z' => {
+            let width = if op == 'z' { 32 } else { 64 };
+            let bits = bitwise_operand(&value)?
+                .to_u64()
+                .filter(|bits| width == 64 || *bits <= u32::MAX as u64)
+                .ok_or_else(|| format!("Bit pattern must fit in {} unsigned bits!", width))?;
+            let decoded = if width == 32 {
+                f32::from_bits(bits as u32) as f64
+            } else {
+                f64::from_bits(bits)
+            };
+            // Every f64 is exact in 53 bits, even when the display precision is lower
+            Complex::with_val(state.precision.max(53), decoded)
+        }
+        'h' => value.sinh(),
+        'k' => value.cosh(),
+        'y' => value.tanh(),
+        'H' => value.asinh(),
+        'K' => value.acosh(),
+        'Y' => value.atanh(),
+        'A' => {
+            let rad_result =
+                Complex::with_val(state.precision, value.imag().clone().atan2(value.real()));
+            angle_from_radians(rad_result, state)
+        }
+
+        'x' => error_function(&value, false, state),
+        'ξ' => error_function(&value, true, state),
+        'Ξ' => inverse_error_function(&value, state)?,
+        // Φ(z) = erfc(-z/√2) / 2
+        'Φ' => {
+            let root_two = Float::with_val(state.precision + 8, 2).sqrt();
+            error_function(&Complex::with_val(state.precision + 8, -value / root_two), true, state) / 2u32
+        }
+
+        _ => return Err(format!("Unknown unary operator: {}", op)),
+    };
+    debug_println(&format!("Result of unary operation: {}", result));
+    Ok(result)
+}
+/// Compares two values for `<`, `>`, `<=`, `>=`, `==` and `!=`
+///
+/// Comparisons are exact, so a guard like `x > 0` holds however small x is and
+/// whatever `:digits` shows. `=~` is there for equality within an epsilon.
+/// Only real numbers can be put in order.
+fn compare(op: char, a: &Complex, b: &Complex) -> Result<bool, String> {
+    if matches!(op, '≡' | '≠') {
+        return Ok((a == b) == (op == '≡'));
+    }
+    if !a.imag().is_zero() || !b.imag().is_zero() {
+        return Err(format!("{} needs real numbers, complex ones have no order!", operator_name(op)));
+    }
+    let (a, b) = (a.real(), b.real());
+    Ok(match op {
+        '≤' => a <= b,
+        '≥' => a >= b,
+        '⋖' => a < b,
+        _ => a > b,
+    })
+}
+/// Applies an operator to the operands on the output queue
+///
+/// # Arguments
+/// * `output_queue` - The queue of operands and intermediate results
+/// * `op` - The operator to apply
+/// * `precision` - The precision for calculations
+/// * `rand_state` - The random state for random number generation
+/// * `base` - The current number base
+/// * `radians` - Whether to use radians for trigonometric functions
+///
+/// # Returns
+/// * `Ok(())` - If the operation was successful
+/// * `Err(String)` - An error message if the operation fails
+fn apply_binary_operator(output_queue: &mut Vec<Complex>, op: char) -> Result<(), String> {
+    debug_println(&format!("Applying binary operator: {}", op));
+
+    if let (Some(b), Some(a)) = (output_queue.pop(), output_queue.pop()) {
+        let result = match op {
+            '%' => a.modulus(b),
+            '^' => a.pow(&b),
+            '$' => a.ln() / b.ln(),
+            '*' => a * b,
+            '+' => a + b,
+            '-' => a - b,
+            '/' => a / b,
+            _ => return Err(format!("Unknown binary operator: {}", op)),
+        };
+        debug_println(&format!("Result after binary operation: {:?}", result));
+        output_queue.push(result);
+    } else {
+        return Err(format!(
+            "Not enough operands for {}!",
+            OPERATORS
+                .iter()
+                .find(|&&(_, symbol, _, _)| symbol == op)
+                .map(|(_, _, _, description)| description)
+                .unwrap_or(&"unknown operator")
+        ));
+    }
+    Ok(())
+}
+/// Breaks a number rounded to an IEEE-754 single (`f32`) or double into its fields
+///
+/// # Returns
+/// * `String` - The hex encoding, the sign, exponent and mantissa fields, and the
+///   rounding error against the full precision value
+fn describe_float_bits(value: &Float, single: bool, state: &BasecalcState) -> String {
+    let (name, exponent_bits, mantissa_bits, bits, rounded) = if single {
+        let f = value.to_f32();
+        ("f32", 8, 23, f.to_bits() as u64, f as f64)
+    } else {
+        let f = value.to_f64();
+        ("f64", 11, 52, f.to_bits(), f)
+    };
+    let bias = (1i64 << (exponent_bits - 1)) - 1;
+    let sign = bits >> (exponent_bits + mantissa_bits);
+    let exponent = (bits >> mantissa_bits) & ((1 << exponent_bits) - 1);
+    let mantissa = bits & ((1 << mantissa_bits) - 1);
+    let power = |p: i64| format!("2^{}", format_integer(&Integer::from(p), state.base));
+    let class = if exponent == (1 << exponent_bits) - 1 {
+        if mantissa == 0 {
+            "infinity".to_string()
+        } else {
+            "NaN".to_string()
+        }
+    } else if exponent == 0 {
+        if mantissa == 0 {
+            "zero".to_string()
+        } else {
+            format!("subnormal, {}", power(1 - bias))
+        }
+    } else {
+        power(exponent as i64 - bias)
+    };
+    let mut message = format!(
+        "{} 0x{:0width$X}\nsign {}, exponent 0x{:X} ({}), mantissa 0x{:0mantissa_width$X}",
+        name,
+        bits,
+        sign,
+        exponent,
+        class,
+        mantissa,
+        width = ((1 + exponent_bits + mantissa_bits) / 4) as usize,
+        mantissa_width = ((mantissa_bits + 3) / 4) as usize,
+    );
+    if !value.is_finite() {
+        return message;
+    }
+    if !rounded.is_finite() {
+        message.push_str("\nOut of range, rounds to infinity.");
+        return message;
+    }
+    let precision = state.precision.max(64);
+    let error = Float::with_val(precision, rounded) - value;
+    message.push_str(&format!("\nrounding error {}", format_real(&error, state)));
+    if !value.is_zero() {
+        let relative = Float::with_val(precision, &error / value).abs();
+        message.push_str(&format!(", relative {}", format_real(&relative, state)));
+    }
+    message
+}
+/// Splits a line into its `;` separated statements, skipping blank ones
+///
+/// A `;` inside square brackets belongs to a `[r; θ]` polar literal and doesn't split,
+/// nor does one inside quotes, as in the text of a `:note`.
+///
+/// # Returns
+/// * `Vec<(usize, &str)>` - Each statement with its byte offset in the line, so
+///   error positions can point into the whole line
+pub fn split_statements(line: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut depth = 0usize;
+    let mut offset = 0;
+    let mut quoted = false;
+    for (i, c) in line.bytes().enumerate() {
+        match c {
+            b'"' => quoted = !quoted,
+            _ if quoted => {}
+            b'[' => depth += 1,
+            b']' => depth = depth.saturating_sub(1),
+            b';' if depth == 0 => {
+                if !line[offset..i].trim().is_empty() {
+                    statements.push((offset, &line[offset..i]));
+                }
+                offset = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !line[offset..].trim().is_empty() {
+        statements.push((offset, &line[offset..]));
+    }
+    statements
+}
+/// Colours the entry line as it's typed, using the output palette
+///
+/// Numbers take the lone, real or imaginary colours depending on whether they sit
+/// in a `[real, imaginary]` pair, operators and functions the exponent colour,
+/// constants the tilde colour and variables the message colour. Brackets without
+/// a partner are flagged in the error colour. Commands are coloured as a whole.
+pub fn highlight_entry(line: &str, colours: &RGBValues) -> Vec<ColoredString> {
+    let paint = |text: &str, (r, g, b): (u8, u8, u8)| text.truecolor(r, g, b);
+    if line.trim_start().starts_with(':') {
+        return vec![paint(line, colours.colon)];
+    }
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let end_of = |i: usize| chars.get(i).map_or(line.len(), |&(index, _)| index);
+
+    // Pair up brackets first, so an opening one can be flagged before its partner is typed
+    let mut matched = vec![false; chars.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        match c {
+            '(' | '[' | '{' => open.push(i),
+            ')' | ']' | '}' => {
+                let partner = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                if let Some(&j) = open.last() {
+                    if chars[j].1 == partner {
+                        open.pop();
+                        matched[i] = true;
+                        matched[j] = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut highlighted = Vec::new();
+    // None outside brackets, then whether we're past the comma of a complex pair
+    let mut imaginary: Option<bool> = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let run_end = |from: usize, accept: &dyn Fn(char) -> bool| {
+            let mut j = from;
+            while j < chars.len() && accept(chars[j].1) {
+                j += 1;
+            }
+            j
+        };
+        let (end, colour) = match c {
+            ' ' | '_' | '\t' => {
+                highlighted.push(line[start..end_of(i + 1)].normal());
+                i += 1;
+                continue;
+            }
+            '#' => (run_end(i + 1, &|c| c.is_ascii_alphanumeric()), colours.exponent),
+            '"' => ((run_end(i + 1, &|c| c != '"') + 1).min(chars.len()), colours.message),
+            '&' => (run_end(i + 1, &|c| c.is_ascii_alphanumeric()), colours.tilde),
+            '@' => {
+                let end = run_end(i + 1, &|c| c.is_ascii_alphanumeric());
+                let name = line[start..end_of(end)].to_ascii_lowercase();
+                if name == "@phys" && chars.get(end).is_some_and(|&(_, c)| c == '.') {
+                    let end = run_end(end + 1, &|c| c.is_ascii_alphanumeric() || c == '_');
+                    (end, colours.tilde)
+                } else {
+                    let constant = CONSTANTS.iter().any(|&(constant, _, _)| constant == name);
+                    (end, if constant { colours.tilde } else { colours.message })
+                }
+            }
+            '.' => {
+                let end = run_end(i + 1, &|c| c.is_ascii_alphanumeric());
+                highlighted.push(paint(".", colours.decimal));
+                let colour = match imaginary {
+                    None => colours.lone_fraction,
+                    Some(false) => colours.real_fraction,
+                    Some(true) => colours.imaginary_fraction,
+                };
+                highlighted.push(paint(&line[end_of(i + 1)..end_of(end)], colour));
+                i = end;
+                continue;
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let end = run_end(i, &|c| c.is_ascii_alphanumeric());
+                let colour = match imaginary {
+                    None => colours.lone_integer,
+                    Some(false) => colours.real_integer,
+                    Some(true) => colours.imaginary_integer,
+                };
+                (end, colour)
+            }
+            '(' | ')' | '[' | ']' | '{' | '}' => {
+                match c {
+                    '[' => imaginary = Some(false),
+                    ']' => imaginary = None,
+                    _ => {}
+                }
+                (i + 1, if matched[i] { colours.brackets } else { colours.error })
+            }
+            ',' => {
+                if imaginary.is_some() {
+                    imaginary = Some(true);
+                }
+                (i + 1, colours.comma)
+            }
+            ';' if imaginary.is_some() => {
+                imaginary = Some(true);
+                (i + 1, colours.comma)
+            }
+            _ => (i + 1, colours.exponent),
+        };
+        highlighted.push(paint(&line[start..end_of(end)], colour));
+        i = end;
+    }
+    highlighted
+}
+/// Evaluates a partly typed line for the prompt's live preview
+///
+/// The state should be a scratch copy, since assignments and function definitions
+/// still take effect. Commands are never run, and lines drawing random numbers
+/// aren't previewed, as the result shown would not be the one entered.
+///
+/// # Returns
+/// * `Some(String)` - The result, uncoloured
+/// * `None` - If the line is a command, random, or doesn't evaluate
+pub fn preview(line: &str, state: &mut BasecalcState) -> Option<String> {
+    if DEBUG.load(Ordering::Relaxed) {
+        return None;
+    }
+    // Each statement runs in turn, and the last one is what's previewed
+    let mut shown = None;
+    for (_, statement) in split_statements(line) {
+        if statement.trim_start().starts_with(':') {
+            return None;
+        }
+        let tokens = match tokenize(statement, state) {
+            Ok(tokens) => tokens,
+            // Definitions and assertions give a message rather than a value
+            Err((_, usize::MAX)) => continue,
+            Err(_) => return None,
+        };
+        if tokens.iter().any(|token| token.operands == 0 && matches!(token.operator, 'r' | 'g')) {
+            return None;
+        }
+        let result = evaluate_statement(&tokens, state).ok()?;
+        if result.list.is_none() {
+            state.record_result(result.value.clone());
+        }
+        let mut result_vec = result2string(&result, state);
+        if let Some(unit) = result.unit {
+            result_vec.push(format!(" {}", unit).normal());
+        }
+        shown = Some(coloured_vec_to_string(&result_vec));
+    }
+    shown
+}
+/// Re-runs a transcript written by `:record`, checking each line prints what it did then
+///
+/// The lines run on a copy of the state, so the session carries on undisturbed.
+/// Numbers only need to agree to a unit in the last digit shown, so a transcript
+/// recorded at one precision can check a run at another.
+///
+/// # Returns
+/// * `Ok(usize)` - How many lines were replayed, all agreeing
+/// * `Err(String)` - Why the file couldn't be read, or the first line that diverged
+fn replay_transcript(path: &str, state: &BasecalcState) -> Result<usize, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+    let mut entries: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix("> "), entries.last_mut()) {
+            (Some(input), _) => entries.push((input, Vec::new())),
+            (None, Some((_, recorded))) => recorded.push(line),
+            (None, None) => {}
+        }
+    }
+    if entries.is_empty() {
+        return Err(format!("No lines to replay in {}!", path));
+    }
+    let mut local_state = state.clone();
+    local_state.transcript = None;
+    let mut replayed = 0;
+    for (input, recorded) in entries {
+        // Replaying a replay would never finish
+        let command = input.trim_start().as_bytes();
+        if command.len() >= 7 && command[..7].eq_ignore_ascii_case(b":replay") {
+            continue;
+        }
+        let (output, _) = transcript_output(input, &mut local_state);
+        replayed += 1;
+        let lines = recorded.len().max(output.len());
+        for i in 0..lines {
+            let (then, now) = (recorded.get(i).copied(), output.get(i).map(String::as_str));
+            let alike = matches!((then, now), (Some(then), Some(now)) if shown_alike(then, now, &local_state));
+            if !alike {
+                return Err(format!(
+                    "Line {} diverged: {}\nrecorded {}\nreplayed {}",
+                    format_int(replayed, state.base as usize, state.balanced),
+                    input,
+                    then.unwrap_or("nothing"),
+                    now.unwrap_or("nothing")
+                ));
+            }
+        }
+    }
+    Ok(replayed)
+}
+/// What a line prints at the prompt, without colour, as `:record` writes it
+///
+/// # Returns
+/// * `(Vec<String>, bool)` - The lines printed, and whether the line ran without error
+fn transcript_output(line: &str, state: &mut BasecalcState) -> (Vec<String>, bool) {
+    let mut output = String::new();
+    let mut success = true;
+    for (offset, statement) in split_statements(line) {
+        match tokenize(statement, state) {
+            Ok(tokens) => {
+                let interval = interval_view(&tokens, state);
+                match evaluate_statement(&tokens, state) {
+                    Ok(result) => {
+                        let text: String = result_view(&tokens, &result, interval, state)
+                            .iter()
+                            .map(|part| &**part)
+                            .collect();
+                        output.push_str(&format!("{}\n", text));
+                        state.record(result);
+                    }
+                    Err(msg) => {
+                        output.push_str(&format!("{}\n", msg));
+                        success = false;
+                        break;
+                    }
+                }
+            }
+            Err((msg, usize::MAX)) => {
+                if !msg.is_empty() {
+                    output.push_str(&format!("{}\n", msg));
+                }
+            }
+            Err((msg, pos)) => {
+                output.push_str(&format!("  {}^\n{}\n", " ".repeat(offset + pos), msg));
+                success = false;
+                break;
+            }
+        }
+    }
+    (output.lines().map(|line| line.trim_end().to_string()).collect(), success)
+}
+/// Whether two printed lines agree, rounded numbers to within a unit in the last place
+/// either shows and exact ones exactly
+fn shown_alike(then: &str, now: &str, state: &BasecalcState) -> bool {
+    if then == now {
+        return true;
+    }
+    // Same layout of brackets and separators, with the numbers between compared by value
+    let is_separator = |c: char| "[]{},;=".contains(c);
+    let layout = |line: &str| line.chars().filter(|&c| is_separator(c)).collect::<String>();
+    if layout(then) != layout(now) {
+        return false;
+    }
+    then.split(is_separator).zip(now.split(is_separator)).all(|(then, now)| {
+        then.trim() == now.trim()
+            || match (shown_value(then, state), shown_value(now, state)) {
+                (Some((then, then_unit)), Some((now, now_unit))) => {
+                    match then_unit.into_iter().chain(now_unit).reduce(|a, b| a.max(&b)) {
+                        Some(tolerance) => (then - now).abs() <= tolerance,
+                        None => false,
+                    }
+                }
+                _ => false,
+            }
+    })
+}
+/// Reads back a real number as results print it, `~` and any `:exponent` included
+///
+/// # Returns
+/// * `Some((Float, Option<Float>))` - The number, and for one marked `~` as rounded
+///   the size of a unit in its last place
+/// * `None` - If the text isn't a real number
+fn shown_value(text: &str, state: &BasecalcState) -> Option<(Float, Option<Float>)> {
+    let rounded = text.contains('~');
+    let text = text.replace('~', "");
+    let (mantissa, exponent) = text.split_once(':').unwrap_or((&text, "0"));
+    let places = mantissa
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.chars().filter(|c| c.is_alphanumeric()).count());
+    let mut local_state = state.clone();
+    let mut value = |text: &str| -> Option<Float> {
+        let tokens = tokenize(text, &mut local_state).ok()?;
+        let value = evaluate_tokens(&tokens, &mut local_state).ok()?.value;
+        value.imag().is_zero().then(|| value.real().clone())
+    };
+    let mantissa = value(mantissa)?;
+    let exponent = value(exponent)?.to_i32_saturating()?;
+    let base = Float::with_val(state.precision, state.base);
+    let unit = rounded.then(|| base.clone().pow(exponent - places as i32));
+    Some((mantissa * base.pow(exponent), unit))
+}
+/// Shows the guaranteed error bound of an expression when `:interval` mode is on
+///
+/// Call this before evaluating the tokens, so assignments and `&` still see the
+/// values the expression is about to be evaluated with.
+///
+/// # Returns
+/// * `Some(String)` - The bound, or the reason there is none
+/// * `None` - If interval mode is off
+pub fn interval_view(tokens: &[Token], state: &mut BasecalcState) -> Option<String> {
+    if !state.interval {
+        return None;
+    }
+    Some(match error_bound(tokens, state) {
+        Ok(radius) if radius.is_zero() => "+/- 0".to_string(),
+        Ok(radius) => {
+            // Round up to two significant digits, so the bound shown still holds
+            let mut local_state = state.clone();
+            local_state.digits = 2;
+            let base = Float::with_val(state.precision, state.base);
+            let magnitude = Float::with_val(state.precision, radius.ln_ref()) / base.clone().ln();
+            let scale = base.pow(magnitude.floor().to_i32_saturating().unwrap_or(0) - 1);
+            let rounded = (Float::with_val(state.precision, &radius / &scale).ceil()) * scale;
+            format!("+/- {}", format_real(&rounded, &local_state))
+        }
+        Err(reason) => format!("No error bound thru {}", reason),
+    })
+}
+/// Precision of error bound arithmetic, which always rounds the bound up
+const BOUND_PRECISION: u32 = 64;
+/// A complex value with a radius that the exact value is guaranteed to lie within
+///
+/// `real` records that the exact value is known to be real, which keeps real
+/// arguments of `#sqrt` and `#ln` clear of the branch cut along the negative axis.
+struct Ball {
+    mid: Complex,
+    rad: Float,
+    real: bool,
+}
+impl Ball {
+    fn exact(mid: Complex) -> Self {
+        let real = mid.imag().is_zero();
+        Ball {
+            mid,
+            rad: Float::new(BOUND_PRECISION),
+            real,
+        }
+    }
+}
+/// Bounds the error of an expression's result for `:interval` mode
+///
+/// The tokens are evaluated a second time with ball arithmetic: every value
+/// carries a radius, widened by each operation's rounding and by how far the
+/// operation can stretch the radii of its operands. Variables and `&` count as
+/// exact, since their stored values are what the expression works with.
+///
+/// # Returns
+/// * `Ok(Float)` - A radius around the result that contains the exact value
+/// * `Err(String)` - Why no bound could be found, e.g. an unsupported operator
+fn error_bound(tokens: &[Token], state: &mut BasecalcState) -> Result<Float, String> {
+    if uses_lists(tokens, state) {
+        return Err("lists".to_string());
+    }
+    let tokens = if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        &tokens[2..]
+    } else {
+        tokens
+    };
+    let mut output_queue: Vec<Ball> = Vec::new();
+    let mut operator_stack: Vec<char> = Vec::new();
+    for token in tokens {
+        match token.operands {
+            0 => {
+                let mut value = operand_ball(token, state)?;
+                while let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) != Precedence::Unary {
+                        break;
+                    }
+                    value = unary_ball(operator_stack.pop().unwrap(), value, state)?;
+                }
+                output_queue.push(value);
+            }
+            1 if token.operator == '(' => operator_stack.push('('),
+            1 if token.operator == ')' => {
+                while let Some(op) = operator_stack.pop() {
+                    if op == '(' {
+                        break;
+                    }
+                    apply_ball_operator(&mut output_queue, op, state)?;
+                }
+                if let Some(&op) = operator_stack.last() {
+                    if get_precedence(op) == Precedence::Unary {
+                        apply_ball_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                    }
+                }
+            }
+            1 if token.operator == '!' => return Err("factorial".to_string()),
+            1 if token.operator == 'U' => return Err("units".to_string()),
+            1 => operator_stack.push(token.operator),
+            _ => {
+                while let Some(&op) = operator_stack.last() {
+                    if op == '(' || get_precedence(token.operator) > get_precedence(op) {
+                        break;
+                    }
+                    apply_ball_operator(&mut output_queue, operator_stack.pop().unwrap(), state)?;
+                }
+                operator_stack.push(token.operator);
+            }
+        }
+    }
+    while let Some(op) = operator_stack.pop() {
+        apply_ball_operator(&mut output_queue, op, state)?;
+    }
+    match output_queue.pop() {
+        Some(ball) if output_queue.is_empty() && ball.rad.is_finite() => Ok(ball.rad),
+        Some(_) if output_queue.is_empty() => Err("unbounded".to_string()),
+        _ => Err("invalid expression".to_string()),
+    }
+}
+fn operand_ball(token: &Token, state: &mut BasecalcState) -> Result<Ball, String> {
+    let value = match token.operator {
+        'u' => return Err("user functions".to_string()),
+        '∑' | '∏' => return Err("series".to_string()),
+        '∫' => return Err("integrals".to_string()),
+        '∂' => return Err("derivatives".to_string()),
+        '↻' => return Err("iteration".to_string()),
+        'r' | 'g' => return Err("random numbers".to_string()),
+        'W' => return Err("the clock".to_string()),
+        '⧗' | '⧖' => return Err("dates".to_string()),
+        '⁇' | '∧' | '∨' | '¬' => return Err("conditionals".to_string()),
+        'ϖ' => return Err("#pct".to_string()),
+        'Ŧ' => return Err("#atan2".to_string()),
+        'ℓ' => return Err("#logn".to_string()),
+        'ω' => return Err("#lambertw".to_string()),
+        'Ψ' => return Err("#polygamma".to_string()),
+        '⊏' => return Err("#clamp".to_string()),
+        '⊸' => return Err("#lerp".to_string()),
+        '↦' => return Err("#maprange".to_string()),
+        'U' | 'J' | '⏱' => return Err("units".to_string()),
+        _ => token2num(token, state),
+    };
+    let steps = match token.operator {
+        // Stored values are taken as they are
+        'v' | 'w' | '&' => return Ok(Ball::exact(value)),
+        // Correctly rounded, except for the golden ratio's add and divide
+        'G' | 'p' => 1,
+        'E' | 'P' => 3,
+        // Parsed once, or for derived ones a handful of correctly rounded steps
+        'Q' | 'd' => 16,
+        // Each digit of a literal costs a multiply or divide and an add
+        _ => {
+            let digits = token.real_integer.len()
+                + token.real_fraction.len()
+                + token.imaginary_integer.len()
+                + token.imaginary_fraction.len();
+            2 * digits as u32 + 1 + polar_steps(token, state)
+        }
+    };
+    Ok(Ball {
+        rad: rounding_error(&value, steps, state),
+        real: value.imag().is_zero(),
+        mid: value,
+    })
+}
+/// Rounding steps for turning a `[r; θ]` literal's angle into a point on the circle
+///
+/// The angle's own rounding error is relative to its size, and becomes error along
+/// the circle, so each half turn in it costs as much as the conversion itself.
+fn polar_steps(token: &Token, state: &mut BasecalcState) -> u32 {
+    if !token.polar {
+        return 0;
+    }
+    let rectangular = Token {
+        polar: false,
+        ..token.clone()
+    };
+    let angle = token2num(&rectangular, state).imag().clone();
+    let half_turns = up(angle.abs() / state.angle.half_turn(state.precision)).ceil();
+    half_turns
+        .to_u32_saturating()
+        .unwrap_or(u32::MAX)
+        .saturating_add(1)
+        .saturating_mul(16)
+}
+fn up<T>(value: T) -> Float
+where
+    Float: AssignRound<T, Round = rug::float::Round, Ordering = std::cmp::Ordering>,
+{
+    Float::with_val_round(BOUND_PRECISION, value, rug::float::Round::Up).0
+}
+fn down<T>(value: T) -> Float
+where
+    Float: AssignRound<T, Round = rug::float::Round, Ordering = std::cmp::Ordering>,
+{
+    Float::with_val_round(BOUND_PRECISION, value, rug::float::Round::Down).0
+}
+fn modulus_up(value: &Complex) -> Float {
+    up(value.abs_ref())
+}
+fn modulus_down(value: &Complex) -> Float {
+    down(value.abs_ref())
+}
+/// Bounds `steps` correctly rounded operations' worth of error on a value
+fn rounding_error(value: &Complex, steps: u32, state: &BasecalcState) -> Float {
+    // Each part rounds to within half an ulp, so 2^(1 - precision) covers the modulus
+    let ulp = Float::with_val(BOUND_PRECISION, Float::i_exp(1, 1 - state.precision as i32));
+    up(&up(&modulus_up(value) * &ulp) * steps)
+}
+/// Whether a ball that may be complex reaches the negative real axis
+fn crosses_branch_cut(ball: &Ball) -> bool {
+    !ball.real && *ball.mid.real() < 0 && down(ball.mid.imag().abs_ref()) <= ball.rad
+}
+fn operator_name(op: char) -> &'static str {
+    OPERATORS
+        .iter()
+        .find(|&&(_, symbol, _, _)| symbol == op)
+        .map(|&(name, _, _, _)| name)
+        .unwrap_or("this operator")
+}
+fn apply_ball_operator(
+    output_queue: &mut Vec<Ball>,
+    op: char,
+    state: &BasecalcState,
+) -> Result<(), String> {
+    if get_precedence(op) == Precedence::Unary {
+        let value = output_queue.pop().ok_or("invalid expression")?;
+        output_queue.push(unary_ball(op, value, state)?);
+        return Ok(());
+    }
+    let (b, a) = match (output_queue.pop(), output_queue.pop()) {
+        (Some(b), Some(a)) => (b, a),
+        _ => return Err("invalid expression".to_string()),
+    };
+    let result = match op {
+        '+' | '-' => {
+            let mid = if op == '+' {
+                Complex::with_val(state.precision, &a.mid + &b.mid)
+            } else {
+                Complex::with_val(state.precision, &a.mid - &b.mid)
+            };
+            let rad = up(&up(&a.rad + &b.rad) + &rounding_error(&mid, 1, state));
+            Ball {
+                mid,
+                rad,
+                real: a.real && b.real,
+            }
+        }
+        '⊕' | '⊖' => {
+            let mid = if op == '⊕' {
+                Complex::with_val(state.precision, 1 + &b.mid)
+            } else {
+                Complex::with_val(state.precision, 1 - &b.mid)
+            };
+            let rad = up(&b.rad + &rounding_error(&mid, 1, state));
+            let factor = Ball { mid, rad, real: b.real };
+            multiply_balls(&a, &factor, state)
+        }
+        '*' => multiply_balls(&a, &b, state),
+        '/' => divide_balls(&a, &b, state)?,
+        '^' => power_ball(&a, &b, state)?,
+        _ => return Err(operator_name(op).to_string()),
+    };
+    output_queue.push(result);
+    Ok(())
+}
+fn multiply_balls(a: &Ball, b: &Ball, state: &BasecalcState) -> Ball {
+    let mid = Complex::with_val(state.precision, &a.mid * &b.mid);
+    // |ab - a'b'| <= |a| rb + |b| ra + ra rb
+    let spread = up(&up(&modulus_up(&a.mid) * &b.rad) + &up(&modulus_up(&b.mid) * &a.rad));
+    let rad = up(&up(&spread + &up(&a.rad * &b.rad)) + &rounding_error(&mid, 1, state));
+    Ball {
+        mid,
+        rad,
+        real: a.real && b.real,
+    }
+}
+fn divide_balls(a: &Ball, b: &Ball, state: &BasecalcState) -> Result<Ball, String> {
+    let b_low = down(&modulus_down(&b.mid) - &b.rad);
+    if b_low <= 0 {
+        return Err("division by a value that may be zero".to_string());
+    }
+    let mid = Complex::with_val(state.precision, &a.mid / &b.mid);
+    // |a/b - a'/b'| <= (|a| rb + |b| ra) / (|b| (|b| - rb))
+    let spread = up(&up(&modulus_up(&a.mid) * &b.rad) + &up(&modulus_up(&b.mid) * &a.rad));
+    let stretch = up(&spread / &down(&modulus_down(&b.mid) * &b_low));
+    Ok(Ball {
+        rad: up(&stretch + &rounding_error(&mid, 1, state)),
+        mid,
+        real: a.real && b.real,
+    })
+}
+fn power_ball(a: &Ball, b: &Ball, state: &BasecalcState) -> Result<Ball, String> {
+    let integer_exponent = b.rad.is_zero() && b.real && b.mid.real().is_integer();
+    if integer_exponent && *b.mid.real() < 0 {
+        // a^-n = 1 / a^n
+        let positive = Ball::exact(Complex::with_val(state.precision, -&b.mid));
+        let one = Ball::exact(Complex::with_val(state.precision, 1));
+        let inverse = divide_balls(&one, &power_ball(a, &positive, state)?, state)?;
+        let mid = Complex::with_val(state.precision, (&a.mid).pow(&b.mid));
+        // The quotient's midpoint and pow's can differ by a rounding each
+        return Ok(Ball {
+            rad: up(&inverse.rad + &rounding_error(&mid, 2, state)),
+            real: a.real,
+            mid,
+        });
+    }
+    let mid = Complex::with_val(state.precision, (&a.mid).pow(&b.mid));
+    let stretch = if integer_exponent {
+        // (|a| + r)^n - |a|^n <= n r (|a| + r)^(n - 1)
+        let n = b.mid.real().to_u32_saturating().filter(|&n| n < u32::MAX).ok_or("an exponent that large")?;
+        if n == 0 {
+            Float::new(BOUND_PRECISION)
+        } else {
+            let reach = up(&modulus_up(&a.mid) + &a.rad);
+            up(&up(&up((&reach).pow(n - 1)) * n) * &a.rad)
+        }
+    } else {
+        // a^b = exp(b ln a)
+        let logarithm = unary_ball('l', a.clone(), state)?;
+        exp_ball(&multiply_balls(b, &logarithm, state), state).rad
+    };
+    Ok(Ball {
+        rad: up(&stretch + &rounding_error(&mid, 1, state)),
+        real: a.real && integer_exponent,
+        mid,
+    })
+}
+impl Clone for Ball {
+    fn clone(&self) -> Self {
+        Ball {
+            mid: self.mid.clone(),
+            rad: self.rad.clone(),
+            real: self.real,
+        }
+    }
+}
+fn exp_ball(value: &Ball, state: &BasecalcState) -> Ball {
+    let mid = Complex::with_val(state.precision, value.mid.exp_ref());
+    // |e^(z + d) - e^z| <= |e^z| (e^r - 1)
+    let stretch = up(&modulus_up(&mid) * &up(value.rad.exp_m1_ref()));
+    Ball {
+        rad: up(&stretch + &rounding_error(&mid, 1, state)),
+        real: value.real,
+        mid,
+    }
+}
+fn unary_ball(op: char, value: Ball, state: &BasecalcState) -> Result<Ball, String> {
+    let r = &value.rad;
+    let (mid, stretch, real) = match op {
+        'n' => (Complex::with_val(state.precision, -&value.mid), r.clone(), value.real),
+        'a' => (Complex::with_val(state.precision, value.mid.abs_ref()), r.clone(), true),
+        'e' => (Complex::with_val(state.precision, value.mid.real()), r.clone(), true),
+        'i' => (Complex::with_val(state.precision, value.mid.imag()), r.clone(), true),
+        '٪' => (Complex::with_val(state.precision, &value.mid / 100), up(&Float::with_val(state.precision, r / 100)), value.real),
+        // A lone value is a list of one, left as it is
+        '∑' | '∏' | 'μ' | 'm' | '↓' | '↑' => (value.mid.clone(), r.clone(), value.real),
+        'l' | 'L' | '₂' | '⏨' => {
+            let low = down(&modulus_down(&value.mid) - r);
+            if low <= 0 {
+                return Err("a logarithm of a value that may be zero".to_string());
+            }
+            if crosses_branch_cut(&value) {
+                return Err("a logarithm across the branch cut".to_string());
+            }
+            let positive = value.real && *value.mid.real() > 0;
+            // |ln(z + d) - ln z| <= r / (|z| - r)
+            let stretch = up(r / &low);
+            if op == 'l' {
+                (Complex::with_val(state.precision, value.mid.ln_ref()), stretch, positive)
+            } else {
+                let base_log = Float::with_val(state.precision, log_base(op, state)).ln();
+                let mid = Complex::with_val(state.precision, value.mid.ln_ref()) / &base_log;
+                // The logarithm of the base rounds too, so the quotient gets two more steps
+                let stretch = up(&up(&stretch / &down(&base_log)) + &rounding_error(&mid, 2, state));
+                (mid, stretch, positive)
+            }
+        }
+        'q' => {
+            if crosses_branch_cut(&value) {
+                return Err("a square root across the branch cut".to_string());
+            }
+            let low = down(&modulus_down(&value.mid) - r);
+            // Away from zero the slope is at most 1 / (2 sqrt(|z| - r)), near it both roots are small
+            let stretch = if low > 0 {
+                up(r / &down(&down(low.sqrt_ref()) * 2u32))
+            } else {
+                up(&up(up(r * 2u32).sqrt_ref()) * 2u32)
+            };
+            let real = value.real && *value.mid.real() >= *r;
+            (Complex::with_val(state.precision, value.mid.sqrt_ref()), stretch, real)
+        }
+        's' | 'o' | 't' if state.angle != AngleMode::Radians => {
+            // Degrees and gradians go thru value * pi / half turn, as in angle_to_radians
+            let pi = Complex::with_val(state.precision, rug::float::Constant::Pi);
+            let pi = Ball {
+                rad: rounding_error(&pi, 1, state),
+                mid: pi,
+                real: true,
+            };
+            let half_turn = Ball::exact(Complex::with_val(state.precision, state.angle.half_turn(state.precision)));
+            let radians = divide_balls(&multiply_balls(&value, &pi, state), &half_turn, state)?;
+            let mut local_state = state.clone();
+            local_state.angle = AngleMode::Radians;
+            return unary_ball(op, radians, &local_state);
+        }
+        's' | 'o' | 'h' | 'k' => {
+            let mid = match op {
+                's' => Complex::with_val(state.precision, value.mid.sin_ref()),
+                'o' => Complex::with_val(state.precision, value.mid.cos_ref()),
+                'h' => Complex::with_val(state.precision, value.mid.sinh_ref()),
+                _ => Complex::with_val(state.precision, value.mid.cosh_ref()),
+            };
+            // The slope is at most cosh of the imaginary (trig) or real (hyperbolic) reach
+            let part = if op == 's' || op == 'o' {
+                value.mid.imag()
+            } else {
+                value.mid.real()
+            };
+            let reach = up(&up(part.abs_ref()) + r);
+            (mid, up(r * &up(reach.cosh_ref())), value.real)
+        }
+        't' => {
+            let cosine = Complex::with_val(state.precision, value.mid.cos_ref());
+            let reach = up(&up(value.mid.imag().abs_ref()) + r);
+            let wobble = up(&up(r * &up(reach.cosh_ref())) + &rounding_error(&cosine, 1, state));
+            let low = down(&modulus_down(&cosine) - &wobble);
+            if low <= 0 {
+                return Err("a tangent near a pole".to_string());
+            }
+            // The slope is 1 / cos^2, and |cos| stays above low across the ball
+            let stretch = up(r / &down(low.square_ref()));
+            (Complex::with_val(state.precision, value.mid.tan_ref()), stretch, value.real)
+        }
+        _ => return Err(operator_name(op).to_string()),
+    };
+    Ok(Ball {
+        rad: up(&stretch + &rounding_error(&mid, 1, state)),
+        mid,
+        real,
+    })
+}
+/// Widest register `:width` will display, in bits
+const MAX_WIDTH: u32 = 1024;
+/// Shows the integer part of a result wrapped to the `:width` register size
+///
+/// # Returns
+/// * `Some(String)` - The unsigned and two's-complement signed values, in the current base
+/// * `None` - If no width is set or the value is not a finite real number
+pub fn width_view(value: &Complex, state: &BasecalcState) -> Option<String> {
+    let width = state.width?;
+    if !value.imag().is_zero() {
+        return None;
+    }
+    let n = integer_real_part(value)?;
+    let modulus = Integer::from(1) << width;
+    let unsigned = n.rem_euc(&modulus);
+    let signed = if unsigned >= Integer::from(&modulus >> 1) {
+        Integer::from(&unsigned - &modulus)
+    } else {
+        unsigned.clone()
+    };
+    let digits = format_integer(&Integer::from(&modulus - 1), state.base).len();
+    Some(digit_glyphs(
+        format!(
+            "unsigned {:0>digits$}  signed {}",
+            format_integer(&unsigned, state.base),
+            format_integer(&signed, state.base),
+            digits = digits
+        ),
+        state,
+    ))
+}
+/// Shows what a `#factor` or `#bernoulli` list stands for in the current base,
+/// a product as in 2^3 * 3 * 5 or a fraction as in -1/30
+///
+/// # Returns
+/// * `Some(String)` - The product or fraction, when the statement is just one of those calls
+/// * `None` - For any other statement
+pub fn call_view(tokens: &[Token], result: &EvalResult, state: &BasecalcState) -> Option<String> {
+    let tokens = if tokens.len() >= 2 && tokens[0].operator == 'v' && tokens[1].operator == '=' {
+        &tokens[2..]
+    } else {
+        tokens
+    };
+    if tokens.len() != 1 {
+        return None;
+    }
+    // Parts too big for the precision were rounded, and would show made-up digits
+    let exact = |part: &Float| match part.get_exp() {
+        Some(exponent) if exponent > state.precision as i32 => None,
+        _ => part.to_integer(),
+    };
+    if tokens[0].operator == 'ℬ' {
+        let parts: Option<Vec<String>> = result
+            .list
+            .as_ref()?
+            .iter()
+            .map(|part| Some(format_integer(&exact(part.real())?, state.base)))
+            .collect();
+        return Some(digit_glyphs(parts?.join("/"), state));
+    }
+    if tokens[0].operator != '⊠' {
+        return None;
+    }
+    let pairs = result.list.as_ref()?;
+    let powers: Option<Vec<String>> = pairs
+        .iter()
+        .map(|pair| {
+            let prime = format_integer(&exact(pair.real())?, state.base);
+            let count = exact(pair.imag())?;
+            Some(if count == 1 {
+                prime
+            } else {
+                format!("{}^{}", prime, format_integer(&count, state.base))
+            })
+        })
+        .collect();
+    Some(digit_glyphs(powers?.join(" * "), state))
+}
+/// Furthest from the point `:expthreshold` and `:fracdigits` can write numbers out
+const MAX_PLAIN_PLACES: usize = 1 << 10;
+/// Most digits `:group` puts in a group
+const MAX_GROUP: usize = 16;
+/// History entries kept until `:history max` says otherwise
+const DEFAULT_HISTORY_MAX: usize = 1000;
+/// Layout of the state file written, raised whenever a saved field changes meaning
+///
+/// Labels a build doesn't know are skipped, so adding one needs no new version. A
+/// version is for when old and new disagree about a label, and [`migrate_state`]
+/// carries older files forward a version at a time.
+///
+/// 1. The first layout. The angle is a `radians` flag, with a `gradians` flag added
+///    later for builds that know it.
+/// 2. The angle is an `angle` label naming the units. The flags are still written
+///    for version 1 readers.
+const STATE_VERSION: usize = 2;
+/// Oldest layout that can still read what this build writes, the file's backward version
+const STATE_READABLE_FROM: usize = 1;
+/// Largest shift `<<` and `>>` accept, in bits
+const MAX_SHIFT: u32 = 1 << 20;
+/// The integer part of a real operand for the bitwise operators
+fn bitwise_operand(z: &Complex) -> Result<Integer, String> {
+    if !z.imag().is_zero() {
+        return Err("Bitwise operators need real operands!".to_string());
+    }
+    integer_real_part(z).ok_or_else(|| "Bitwise operators need finite operands!".to_string())
+}
+/// The integer part of a real operand for `#gcd`, `#lcm` and `#egcd`
+fn whole_operand(z: &Complex, name: &str) -> Result<Integer, String> {
+    if !z.imag().is_zero() {
+        return Err(format!("{} needs real values!", name));
+    }
+    integer_real_part(z).ok_or_else(|| format!("{} needs finite values!", name))
+}
+/// Most rows `:pascal` prints, the last of them some 20 kB
+const MAX_PASCAL_ROWS: u32 = 256;
+/// Largest index `#fib` and `#lucas` take, whose results run to some 1.4 MB
+const MAX_FIBONACCI_INDEX: u32 = 1 << 24;
+/// Miller-Rabin witnesses that between them settle every n below 3.3·10²⁴
+const PRIME_WITNESSES: [u32; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+/// Miller-Rabin rounds for numbers too big for `PRIME_WITNESSES` to be certain
+const MILLER_RABIN_ROUNDS: u32 = 40;
+/// Whether n is prime, for certain below 2⁸¹ and with a vanishing chance of error past that
+fn is_prime(n: &Integer) -> bool {
+    if *n < 2 {
+        return false;
+    }
+    for &witness in &PRIME_WITNESSES {
+        if *n == witness {
+            return true;
+        }
+        if n.is_divisible_u(witness) {
+            return false;
+        }
+    }
+    if n.significant_bits() > 81 {
+        return n.is_probably_prime(MILLER_RABIN_ROUNDS) != integer::IsPrime::No;
+    }
+    // n - 1 = odd·2^twos
+    let minus_one = Integer::from(n - 1);
+    let twos = minus_one.find_one(0).unwrap_or(0);
+    let odd = Integer::from(&minus_one >> twos);
+    PRIME_WITNESSES.iter().all(|&witness| {
+        let mut x = match Integer::from(witness).pow_mod(&odd, n) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        if x == 1 || x == minus_one {
+            return true;
+        }
+        for _ in 1..twos {
+            x = x.square() % n;
+            if x == minus_one {
+                return true;
+            }
+        }
+        false
+    })
+}
+/// Primes below which `factorize` divides by trial rather than Pollard rho
+const TRIAL_DIVISION_LIMIT: u32 = 1 << 12;
+/// Pushes the prime factors of n > 0 onto `primes`, with repeats and in no particular order
+fn factorize(mut n: Integer, primes: &mut Vec<Integer>, state: &BasecalcState) -> Result<(), String> {
+    let mut divisor = 2;
+    while divisor < TRIAL_DIVISION_LIMIT && n > 1 {
+        while n.is_divisible_u(divisor) {
+            n /= divisor;
+            primes.push(Integer::from(divisor));
+        }
+        divisor += if divisor == 2 { 1 } else { 2 };
+    }
+    let mut pending = vec![n];
+    while let Some(n) = pending.pop() {
+        if n == 1 {
+            continue;
+        }
+        if is_prime(&n) {
+            primes.push(n);
+            continue;
+        }
+        let factor = pollard_rho(&n, state)?;
+        pending.push(Integer::from(&n / &factor));
+        pending.push(factor);
+    }
+    Ok(())
+}
+/// The distinct primes of n > 0 with how many times each divides it, smallest first
+fn prime_powers(n: Integer, state: &BasecalcState) -> Result<Vec<(Integer, u32)>, String> {
+    let mut primes = Vec::new();
+    factorize(n, &mut primes, state)?;
+    primes.sort();
+    let mut pairs: Vec<(Integer, u32)> = Vec::new();
+    for prime in primes {
+        match pairs.last_mut() {
+            Some((last, count)) if *last == prime => *count += 1,
+            _ => pairs.push((prime, 1)),
+        }
+    }
+    Ok(pairs)
+}
+/// Euler's totient (`φ`), sum (`σ`) and count (`τ`) of divisors and Möbius (`μ`) of n > 0
+fn divisor_function(op: char, n: Integer, state: &BasecalcState) -> Result<Integer, String> {
+    let pairs = prime_powers(n, state)?;
+    let mut result = Integer::from(1);
+    for (prime, count) in pairs {
+        match op {
+            '⊘' => result *= prime.clone().pow(count - 1) * (prime - 1u32),
+            '⊜' => result *= (prime.clone().pow(count + 1) - 1u32) / (prime - 1u32),
+            '⊛' => result *= count + 1,
+            _ if count > 1 => return Ok(Integer::new()),
+            _ => result = -result,
+        }
+    }
+    Ok(result)
+}
+/// Steps of Pollard rho between gcds, multiplying the differences together meanwhile
+const RHO_BATCH: u32 = 128;
+/// A nontrivial factor of the composite n, by Brent's variant of Pollard rho
+fn pollard_rho(n: &Integer, state: &BasecalcState) -> Result<Integer, String> {
+    for shift in 1u32.. {
+        let step = |x: &Integer| (Integer::from(x.square_ref()) + shift) % n;
+        let mut y = Integer::from(2);
+        let mut x = y.clone();
+        let mut saved = y.clone();
+        let mut product = Integer::from(1);
+        let mut factor = Integer::from(1);
+        let mut length = 1u32;
+        while factor == 1 {
+            check_interrupt(state)?;
+            x.clone_from(&y);
+            for _ in 0..length {
+                y = step(&y);
+            }
+            let mut done = 0;
+            while done < length && factor == 1 {
+                saved.clone_from(&y);
+                for _ in 0..RHO_BATCH.min(length - done) {
+                    y = step(&y);
+                    product = product * Integer::from(&x - &y).abs() % n;
+                }
+                factor = product.gcd_ref(n).into();
+                done += RHO_BATCH;
+            }
+            length *= 2;
+        }
+        // The batch overshot, so retrace it a step at a time
+        while factor == *n {
+            saved = step(&saved);
+            factor = Integer::from(&x - &saved).abs().gcd(n);
+        }
+        if factor != 1 {
+            return Ok(factor);
+        }
+    }
+    unreachable!()
+}
+/// The first prime above n, or below it if not `up`
+fn next_prime(mut n: Integer, up: bool) -> Result<Integer, String> {
+    if up && n < 2 {
+        return Ok(Integer::from(2));
+    }
+    if !up && n <= 2 {
+        return Err("There are no primes below 2!".to_string());
+    }
+    loop {
+        if up {
+            n += 1;
+        } else {
+            n -= 1;
+        }
+        if is_prime(&n) {
+            return Ok(n);
+        }
+    }
+}
+/// Most bits an exact `#choose` or `#perm` may run to, some 2 MB
+const MAX_COMBINATORICS_BITS: u64 = 1 << 24;
+/// Binomial coefficients (`C`) and permutations (`R`) of n things taken k at a time
+///
+/// Integer operands are counted exactly while the count stays under
+/// `MAX_COMBINATORICS_BITS`, anything else goes through the gamma function.
+fn combinatorics(op: char, n: &Complex, k: &Complex, state: &BasecalcState) -> Result<Complex, String> {
+    if let (Some(n), Some(k)) = (exact_integer(n), exact_integer(k)) {
+        if k < 0 {
+            // No ways to choose a negative count, but k < 0 permutations still follow gamma
+            if op == 'C' {
+                return Ok(Complex::with_val(state.precision, 0));
+            }
+        } else if let Some(k) = k.to_u32().filter(|&k| !combinatorics_too_large(op, &n, k)) {
+            let mut result = n.binomial(k);
+            if op == 'R' {
+                result *= Integer::from(Integer::factorial(k));
+            }
+            return Ok(Complex::with_val(state.precision, result));
+        } else if n < 0 {
+            // Gamma has poles at the negative integers, so there's no way round
+            return Err(format!("{} result too large!", operator_name(op)));
+        } else if k > n {
+            return Ok(Complex::with_val(state.precision, 0));
+        } else {
+            // Too big to count exactly, and Γ itself would overflow on the way, so by ln Γ
+            let work = state.precision + 64;
+            let ln_gamma = |x: Integer| Float::with_val(work, x + 1u32).ln_gamma();
+            let mut ln = ln_gamma(n.clone()) - ln_gamma(Integer::from(&n - &k));
+            if op == 'C' {
+                ln -= ln_gamma(k);
+            }
+            let result = ln.exp();
+            if result.is_infinite() {
+                return Err(format!("{} result too large!", operator_name(op)));
+            }
+            return Ok(Complex::with_val(state.precision, result));
+        }
+    }
+    let one = Complex::with_val(state.precision, 1);
+    let numerator = gamma(&Complex::with_val(state.precision, n + &one), state)?;
+    let remainder = gamma(&Complex::with_val(state.precision, n - k.clone() + &one), state)?;
+    let mut result = numerator / remainder;
+    if op == 'C' {
+        result /= gamma(&Complex::with_val(state.precision, k + &one), state)?;
+    }
+    Ok(result)
+}
+/// Whether the exact `n #choose k` or `n #perm k` would run past `MAX_COMBINATORICS_BITS`
+///
+/// Each of the k factors has at most as many bits as m, the largest of them, which
+/// settles most cases at once. Closer calls go by ln Γ.
+fn combinatorics_too_large(op: char, n: &Integer, k: u32) -> bool {
+    // The factors run down from n, or up in size from -n when n is negative
+    let m = if *n < 0 { Integer::from(-n) + k - 1u32 } else { n.clone() };
+    if m < k {
+        return false;
+    }
+    // C(m, k) = C(m, m - k), and GMP takes whichever is fewer factors
+    let factors = match op {
+        'C' => Integer::from(&m - k).min(Integer::from(k)),
+        _ => Integer::from(k),
+    };
+    if factors * m.significant_bits() <= MAX_COMBINATORICS_BITS {
+        return false;
+    }
+    if m.significant_bits() > 64 {
+        return true;
+    }
+    let ln_gamma = |x: Integer| Float::with_val(128, x + 1u32).ln_gamma();
+    let mut ln = ln_gamma(m.clone()) - ln_gamma(m - k);
+    if op == 'C' {
+        ln -= ln_gamma(Integer::from(k));
+    }
+    ln / std::f64::consts::LN_2 > MAX_COMBINATORICS_BITS
+}
+/// The value as an integer, if it is a real whole number
+fn exact_integer(z: &Complex) -> Option<Integer> {
+    if z.imag().is_zero() && z.real().is_integer() {
+        z.real().to_integer()
+    } else {
+        None
+    }
+}
+/// The gamma function, correctly rounded by MPFR for real arguments
+///
+/// Complex arguments use Spouge's approximation, with the reflection formula
+/// for the left half plane.
+fn gamma(z: &Complex, state: &BasecalcState) -> Result<Complex, String> {
+    if z.imag().is_zero() {
+        if z.real().is_integer() && *z.real() <= 0 {
+            return Err("Gamma is undefined at non-positive integers!".to_string());
+        }
+        return Ok(Complex::with_val(state.precision, z.real().clone().gamma()));
+    }
+    let pi = Float::with_val(state.precision, rug::float::Constant::Pi);
+    if *z.real() < 0.5 {
+        // Γ(z) = π / (sin(πz) Γ(1 - z))
+        let reflected = gamma(&Complex::with_val(state.precision, 1 - z), state)?;
+        let sine = Complex::with_val(state.precision, z * &pi).sin();
+        return Ok(pi / (sine * reflected));
+    }
+    // Spouge: Γ(x + 1) = (x + a)^(x + 1/2) e^-(x + a) (c0 + Σ ck / (x + k)), with x = z - 1.
+    // The relative error is below (2π)^-(a + 1/2), and the alternating ck cancel
+    // about two bits per term, so a scales with precision and the sum runs wider.
+    let a = (state.precision as f64 * 2f64.ln() / (2.0 * std::f64::consts::PI).ln()).ceil() as u32 + 1;
+    let work = state.precision + 2 * a;
+    let x = Complex::with_val(work, z - 1u32);
+    let mut sum = Complex::with_val(work, Float::with_val(work, rug::float::Constant::Pi) * 2u32);
+    sum.sqrt_mut();
+    // ck = (-1)^(k-1) (a - k)^(k - 1/2) e^(a - k) / (k - 1)!
+    let e = Float::with_val(work, 1).exp();
+    let mut exp_a_minus_k = Float::with_val(work, a - 1).exp();
+    let mut k_factorial = Float::with_val(work, 1);
+    for k in 1..a {
+        let a_minus_k = Float::with_val(work, a - k);
+        let mut ck = Float::with_val(work, a_minus_k.clone().pow(k - 1)) * a_minus_k.sqrt();
+        ck *= &exp_a_minus_k;
+        ck /= &k_factorial;
+        if k % 2 == 0 {
+            ck = -ck;
+        }
+        sum += Complex::with_val(work, &ck / Complex::with_val(work, &x + k));
+        exp_a_minus_k /= &e;
+        k_factorial *= k;
+    }
+    let shifted = Complex::with_val(work, &x + a);
+    let power = Complex::with_val(work, shifted.clone().pow(Complex::with_val(work, &x + 0.5)));
+    let result = power * Complex::with_val(work, -shifted).exp() * sum;
+    Ok(Complex::with_val(state.precision, result))
+}
+/// B(2j) / (2j)!, the Euler-Maclaurin coefficients, from ζ(2j) = (-1)^(j+1) B(2j) (2π)^2j / (2 (2j)!)
+fn euler_maclaurin_coefficient(j: u32, work: u32) -> Float {
+    let two_pi = Float::with_val(work, rug::float::Constant::Pi) * 2u32;
+    let coefficient = Float::with_val(work, 2 * j).zeta() * 2u32 / two_pi.pow(2 * j);
+    if j.is_multiple_of(2) {
+        -coefficient
+    } else {
+        coefficient
+    }
+}
+/// How many Euler-Maclaurin terms to take, and how far to sum directly first
+///
+/// Each correction term shrinks by about (2π n)^2 / |s + 2j|^2, so starting the
+/// tail at n past 2.55 (|s| + 2m) gains 8 bits a term over m terms.
+fn euler_maclaurin_size(s: &Complex, a: &Complex, work: u32) -> (u32, u32) {
+    let terms = work / 8 + 2;
+    let size = Complex::with_val(53, s.abs_ref()).real().to_f64();
+    let behind = (-a.real().to_f64()).max(0.0);
+    let start = (2.55 * (size + 2.0 * terms as f64) + behind).ceil() as u32 + 1;
+    (terms, start)
+}
+/// The Hurwitz zeta function ζ(s, a) = Σ (k + a)^-s, by Euler-Maclaurin summation
+///
+/// `s` must not be 1 and `a` not a non-positive integer. The result is at `work` bits,
+/// and only converges as written for Re(s) > 0 or whole s, where the sum itself is tame.
+fn hurwitz_zeta(s: &Complex, a: &Complex, work: u32, state: &BasecalcState) -> Result<Complex, String> {
+    let (terms, start) = euler_maclaurin_size(s, a, work);
+    let negative_s = Complex::with_val(work, -s);
+    let mut sum = Complex::with_val(work, 0);
+    for k in 0..start {
+        check_interrupt(state)?;
+        let base = Complex::with_val(work, a + k);
+        sum += base.pow(&negative_s);
+    }
+    let x = Complex::with_val(work, a + start);
+    let one_minus_s = Complex::with_val(work, 1 - s);
+    sum += x.clone().pow(&one_minus_s) / Complex::with_val(work, s - 1u32);
+    sum += x.clone().pow(&negative_s) / 2u32;
+    // B(2j)/(2j)! s (s + 1) ... (s + 2j - 2) x^(1 - s - 2j)
+    let mut rising = s.clone();
+    let mut power = x.clone().pow(Complex::with_val(work, &negative_s - 1u32));
+    let x_squared = Complex::with_val(work, x.square_ref());
+    let tolerance = Float::with_val(work, 1) >> work;
+    for j in 1..=terms {
+        let term = Complex::with_val(work, &rising * &power) * euler_maclaurin_coefficient(j, work);
+        sum += &term;
+        let magnitude = Complex::with_val(work, term.abs_ref()).real().clone();
+        if magnitude <= tolerance.clone() * Complex::with_val(work, sum.abs_ref()).real() {
+            break;
+        }
+        rising *= Complex::with_val(work, s + (2 * j - 1)) * Complex::with_val(work, s + 2 * j);
+        power /= &x_squared;
+    }
+    Ok(sum)
+}
+/// The Riemann zeta function, with real arguments left to MPFR
+fn zeta(s: &Complex, state: &BasecalcState) -> Result<Complex, String> {
+    if *s == 1 {
+        return Err("Zeta has a pole at 1!".to_string());
+    }
+    if s.imag().is_zero() {
+        return Ok(Complex::with_val(state.precision, s.real().clone().zeta()));
+    }
+    let work = state.precision + 32;
+    let one = Complex::with_val(work, 1);
+    if *s.real() >= 0 {
+        return Ok(Complex::with_val(state.precision, hurwitz_zeta(s, &one, work, state)?));
+    }
+    // ζ(s) = 2^s π^(s - 1) sin(πs/2) Γ(1 - s) ζ(1 - s)
+    let pi = Float::with_val(work, rug::float::Constant::Pi);
+    let reflected = Complex::with_val(work, 1 - s);
+    let power = Complex::with_val(work, 2).pow(s) * Complex::with_val(work, &pi).pow(Complex::with_val(work, s - 1u32));
+    let sine = (Complex::with_val(work, s * pi) / 2u32).sin();
+    let result = power * sine * gamma(&reflected, state)? * hurwitz_zeta(&reflected, &one, work, state)?;
+    Ok(Complex::with_val(state.precision, result))
+}
+/// Largest order `#polygamma` takes
+const MAX_POLYGAMMA_ORDER: u32 = 1 << 16;
+/// The polygamma function ψ⁽ᵐ⁾(z), the digamma function ψ(z) when m is 0
+///
+/// Higher orders are (-1)^(m + 1) m! ζ(m + 1, z). The digamma function sums
+/// 1/(z + k) until the asymptotic ln x - 1/2x - Σ B(2j) / (2j x^2j) takes over.
+fn polygamma(order: u32, z: &Complex, state: &BasecalcState) -> Result<Complex, String> {
+    if z.imag().is_zero() && z.real().is_integer() && *z.real() <= 0 {
+        let name = if order == 0 { "Digamma" } else { "Polygamma" };
+        return Err(format!("{} is undefined at non-positive integers!", name));
+    }
+    let work = state.precision + 32;
+    if order > 0 {
+        let s = Complex::with_val(work, order + 1);
+        let mut result = hurwitz_zeta(&s, z, work, state)? * Integer::from(Integer::factorial(order));
+        if order.is_multiple_of(2) {
+            result = -result;
+        }
+        if z.imag().is_zero() {
+            result = Complex::with_val(work, result.real());
+        }
+        return Ok(Complex::with_val(state.precision, result));
+    }
+    if z.imag().is_zero() {
+        return Ok(Complex::with_val(state.precision, z.real().clone().digamma()));
+    }
+    let (terms, start) = euler_maclaurin_size(&Complex::with_val(work, 1), z, work);
+    let mut sum = Complex::with_val(work, 0);
+    for k in 0..start {
+        check_interrupt(state)?;
+        sum -= Complex::with_val(work, z + k).recip();
+    }
+    let x = Complex::with_val(work, z + start);
+    sum += Complex::with_val(work, x.ln_ref()) - Complex::with_val(work, x.recip_ref()) / 2u32;
+    let x_squared = Complex::with_val(work, x.square_ref());
+    let mut power = x_squared.clone();
+    let mut factorial = Float::with_val(work, 2);
+    for j in 1..=terms {
+        // B(2j) / (2j x^2j), with B(2j) = (2j)! times the coefficient
+        let bernoulli = euler_maclaurin_coefficient(j, work) * &factorial;
+        sum -= Complex::with_val(work, bernoulli / (2 * j)) / &power;
+        power *= &x_squared;
+        factorial *= (2 * j + 1) * (2 * j + 2);
+    }
+    Ok(Complex::with_val(state.precision, sum))
+}
+/// The error function erf(z), or its complement erfc(z) = 1 - erf(z)
+///
+/// Real values go to MPFR. Complex ones use the Taylor series with enough extra
+/// bits to cover its terms growing to about e^|z|^2, or once that's more than the
+/// precision, the asymptotic series for erfc, whose smallest term is about e^-|z|^2.
+fn error_function(z: &Complex, complement: bool, state: &BasecalcState) -> Complex {
+    let precision = state.precision;
+    if z.imag().is_zero() {
+        let x = z.real().clone();
+        return Complex::with_val(precision, if complement { x.erfc() } else { x.erf() });
+    }
+    // erf(-z) = -erf(z) and erfc(-z) = 2 - erfc(z)
+    if z.real().is_sign_negative() {
+        let flipped = error_function(&Complex::with_val(precision, -z), complement, state);
+        return if complement { 2u32 - flipped } else { -flipped };
+    }
+    let growth = Complex::with_val(53, z.norm_ref()).real().to_f64() * std::f64::consts::LOG2_E;
+    let root_pi = Float::with_val(precision + 32, rug::float::Constant::Pi).sqrt();
+    if growth < (precision + 16) as f64 {
+        // 2/√π Σ (-1)^n z^(2n+1) / (n! (2n + 1)), which erfc then loses up to e^-|z|^2 from
+        let extra = growth.ceil() as u32 * if complement { 2 } else { 1 };
+        let work = precision + 32 + extra;
+        let z = Complex::with_val(work, z);
+        let minus_square = -Complex::with_val(work, z.square_ref());
+        let tolerance = Float::with_val(work, 1) >> work;
+        let mut term = z.clone();
+        let mut sum = z;
+        let mut n = 0u32;
+        loop {
+            n += 1;
+            term *= &minus_square;
+            term /= n;
+            let part = Complex::with_val(work, &term / (2 * n + 1));
+            sum += &part;
+            let small = Complex::with_val(work, part.abs_ref()).real().clone()
+                <= tolerance.clone() * Complex::with_val(work, sum.abs_ref()).real();
+            if n as f64 > growth && small {
+                break;
+            }
+        }
+        let erf = sum * 2u32 / Float::with_val(work, rug::float::Constant::Pi).sqrt();
+        return Complex::with_val(precision, if complement { 1u32 - erf } else { erf });
+    }
+    // erfc(z) = e^(-z^2) / (z √π) Σ (-1)^n (2n - 1)!! / (2z^2)^n, stopping at the smallest term
+    let work = precision + 32;
+    let z = Complex::with_val(work, z);
+    let twice_square = Complex::with_val(work, z.square_ref()) * 2u32;
+    let mut term = Complex::with_val(work, 1);
+    let mut sum = term.clone();
+    let mut last = Float::with_val(work, 1);
+    for n in 1..=precision {
+        term *= -(2.0 * n as f64 - 1.0);
+        term /= &twice_square;
+        let size = Complex::with_val(work, term.abs_ref()).real().clone();
+        if size >= last || size.is_zero() {
+            break;
+        }
+        sum += &term;
+        last = size;
+    }
+    let imaginary = z.real().is_zero();
+    let erfc = (-Complex::with_val(work, z.square_ref())).exp() / (z * root_pi) * sum;
+    let mut result = Complex::with_val(precision, if complement { erfc } else { 1u32 - erfc });
+    // erf(iy) is purely imaginary, though the 1 in erfc is far below the series' digits
+    if imaginary {
+        result.mut_real().assign(u32::from(complement));
+    }
+    result
+}
+/// The inverse error function of a real value between -1 and 1
+///
+/// Starts from Giles' single precision approximation, or the leading term of erfc
+/// far out in the tails, and polishes it with Newton's method, on erfc past 0.5 so
+/// that values close to 1 keep all their digits.
+fn inverse_error_function(x: &Complex, state: &BasecalcState) -> Result<Complex, String> {
+    let precision = state.precision;
+    if !x.imag().is_zero() || *Complex::with_val(precision, x.abs_ref()).real() >= 1 {
+        return Err("#erfinv needs a real value between -1 and 1!".to_string());
+    }
+    if x.is_zero() {
+        return Ok(Complex::with_val(precision, 0));
+    }
+    let work = precision + 32;
+    let negative = x.real().is_sign_negative();
+    let a = Float::with_val(work, x.real().abs_ref());
+    let tail = Float::with_val(work, 1 - &a);
+    let w = (Float::with_val(work, &tail * Float::with_val(work, 1 + &a)).ln() * -1i32).to_f64();
+    let p = if w > 25.0 {
+        // Past Giles' range, from erfc(y) ≈ e^(-y^2) / (y √π) with ln(tail) = ln 2 - w
+        let log = w - std::f64::consts::LN_2;
+        (log - 0.5 * (std::f64::consts::PI * log).ln()).sqrt()
+    } else if w < 5.0 {
+        let w = w - 2.5;
+        [
+            3.43273939e-07, -3.5233877e-06, -4.39150654e-06, 0.00021858087, -0.00125372503,
+            -0.00417768164, 0.246640727, 1.50140941,
+        ]
+        .iter()
+        .fold(2.81022636e-08, |p, c| c + p * w)
+    } else {
+        let w = w.sqrt() - 3.0;
+        [
+            0.000100950558, 0.00134934322, -0.00367342844, 0.00573950773, -0.0076224613,
+            0.00943887047, 1.00167406, 2.83297682,
+        ]
+        .iter()
+        .fold(-0.000200214257, |p, c| c + p * w)
+    };
+    let mut y = Float::with_val(work, p) * &a;
+    let half_root_pi = Float::with_val(work, rug::float::Constant::Pi).sqrt() / 2u32;
+    let tolerance = Float::with_val(work, 1) >> (precision + 8);
+    let erf_at = |y: &Float, complement: bool| {
+        let y = y.clone();
+        if complement {
+            y.erfc()
+        } else {
+            y.erf()
+        }
+    };
+    for _ in 0..100 {
+        check_interrupt(state)?;
+        // Newton on erf(y) - a, whose slope is 2/√π e^(-y^2)
+        let residual = if a > 0.5 {
+            Float::with_val(work, &tail - erf_at(&y, true))
+        } else {
+            Float::with_val(work, erf_at(&y, false) - &a)
+        };
+        let step = residual * &half_root_pi * Float::with_val(work, y.square_ref()).exp();
+        y -= &step;
+        if Float::with_val(work, step.abs_ref()) <= tolerance.clone() * Float::with_val(work, y.abs_ref()) {
+            break;
+        }
+    }
+    Ok(Complex::with_val(precision, if negative { -y } else { y }))
+}
+fn gaussian_ceil(z: &Complex) -> Complex {
+    Complex::with_val(z.prec(), (z.real().clone().ceil(), z.imag().clone().ceil()))
+}
+fn gaussian_floor(z: &Complex) -> Complex {
+    Complex::with_val(
+        z.prec(),
+        (z.real().clone().floor(), z.imag().clone().floor()),
+    )
+}
+fn fractional_part(z: &Complex) -> Complex {
+    z - gaussian_floor(z)
+}
+fn integer_part(z: &Complex) -> Complex {
+    gaussian_floor(z)
+}
+fn gaussian_round(z: &Complex) -> Complex {
+    Complex::with_val(
+        z.prec(),
+        (z.real().clone().round(), z.imag().clone().round()),
+    )
+}
+fn sign(z: &Complex) -> Complex {
+    if z.is_zero() {
+        z.clone()
+    } else {
+        z / z.clone().abs()
+    }
+}
+/// Parses a constant from the input
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The starting index in the input
+///
+/// # Returns
+/// * `Ok((Token, usize))` - The parsed constant token and the new index
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_constant(
+    input: &[u8],
+    mut index: usize,
+    state: &mut BasecalcState,
+) -> Result<(Token, usize), (String, usize)> {
+    // Skip leading whitespace
+    while index < input.len() && (input[index] == b' ' || input[index] == b'_' || input[index] == b'\t') {
+        index += 1;
+    }
+
+    // Physical constants, whose names can't clash with variables as those have no dots
+    if input[index..].to_ascii_lowercase().starts_with(b"@phys.") {
+        let name_start = index + 6;
+        let mut name_end = name_start;
+        while name_end < input.len() && (input[name_end].is_ascii_alphanumeric() || input[name_end] == b'_') {
+            name_end += 1;
+        }
+        let name = String::from_utf8_lossy(&input[name_start..name_end]).to_string();
+        return match find_physical_constant(&name) {
+            Some(position) => Ok((
+                Token {
+                    operator: 'Q',
+                    var_index: Some(position),
+                    ..Token::new()
+                },
+                name_end,
+            )),
+            None => {
+                let typed = name.replace('_', "").to_ascii_lowercase();
+                let suggestion = PHYSICAL_CONSTANTS
+                    .iter()
+                    .map(|(constant, ..)| {
+                        (edit_distance(&typed, &constant.replace('_', "").to_ascii_lowercase()), constant)
+                    })
+                    .filter(|&(distance, _)| distance <= 1 || (typed.len() > 4 && distance <= 2))
+                    .min_by_key(|&(distance, _)| distance);
+                let message = match suggestion {
+                    _ if typed.is_empty() => "Expected a physical constant, see :constants!".to_string(),
+                    Some((_, constant)) => format!(
+                        "Unknown physical constant '{}', did you mean @phys.{}?",
+                        name, constant
+                    ),
+                    _ => format!("Unknown physical constant '{}', see :constants!", name),
+                };
+                Err((message, name_start))
+            }
+        };
+    }
+
+    // First check for built-in constants
+    for &(name, op, _desc) in &CONSTANTS {
+        if input[index..]
+            .to_ascii_lowercase()
+            .starts_with(name.as_bytes())
+        {
+            if op == '&' {
+                return parse_result_reference(input, index, state);
+            }
+            return Ok((
+                Token {
+                    operator: op,
+                    ..Token::new()
+                },
+                index + name.len(),
+            ));
+        }
+    }
+
+    // Then check if this is a variable reference
+    if index < input.len() && input[index] == b'@' {
+        let mut var_name = String::new();
+        let mut curr_index = index + 1;
+        
+        // Skip whitespace after @
+        while curr_index < input.len() && (input[curr_index] == b' ' || input[curr_index] == b'_' || input[curr_index] == b'\t') {
+            curr_index += 1;
+        }
+        
+        // Parse variable name, allowing whitespace between characters
+        while curr_index < input.len() {
+            let c = input[curr_index];
+            
+            // Skip whitespace within variable name
+            if c == b' ' || c == b'_' || c == b'\t' {
+                curr_index += 1;
+                continue;
+            }
+            
+            if !c.is_ascii_alphanumeric() {
+                break;
+            }
+            
+            var_name.push(c.to_ascii_lowercase() as char);
+            curr_index += 1;
+        }
+
+        if var_name.is_empty() {
+            return Err(("Invalid variable name!".to_string(), index));
+        }
+
+        // Skip whitespace after variable name
+        while curr_index < input.len() && (input[curr_index] == b' ' || input[curr_index] == b'_' || input[curr_index] == b'\t') {
+            curr_index += 1;
+        }
+
+        // A name followed by parentheses calls a user-defined function
+        if curr_index < input.len() && input[curr_index] == b'(' {
+            if let Some(pos) = state.functions.iter().position(|f| f.name == var_name) {
+                let (arguments, new_index) = split_call_arguments(input, curr_index)?;
+                let expected = state.functions[pos].parameters.len();
+                if arguments.len() != expected {
+                    return Err((
+                        format!(
+                            "@{} expects {} argument{}!",
+                            var_name,
+                            format_int(expected, state.base as usize, state.balanced),
+                            if expected == 1 { "" } else { "s" }
+                        ),
+                        curr_index,
+                    ));
+                }
+                return Ok((
+                    Token {
+                        operator: 'u',
+                        var_index: Some(pos),
+                        arguments,
+                        ..Token::new()
+                    },
+                    new_index,
+                ));
+            }
+            if !state.variables.iter().any(|v| v.name.to_ascii_lowercase() == var_name)
+                && !state.constants.iter().any(|c| c.name == var_name)
+            {
+                return Err((format!("Undefined function '{}'!", var_name), index));
+            }
+        }
+
+        // Constants come before variables and can only be changed with :const
+        if let Some(pos) = state.constants.iter().position(|c| c.name == var_name) {
+            if curr_index < input.len() && input[curr_index] == b'=' && !matches!(input.get(curr_index + 1), Some(b'=' | b'~')) {
+                return Err((format!("@{} is a constant, change it with :const!", var_name), index));
+            }
+            return Ok((
+                Token {
+                    operator: 'w',
+                    var_index: Some(pos),
+                    ..Token::new()
+                },
+                curr_index,
+            ));
+        }
+
+        // Look for existing variable
+        if let Some(pos) = state.variables.iter().position(|v| v.name.to_ascii_lowercase() == var_name) {
+            let assigned = input.get(curr_index) == Some(&b'=') && !matches!(input.get(curr_index + 1), Some(b'~' | b'='));
+            // A locked `:=` variable still takes the values its formula works out
+            if assigned && state.variables[pos].locked && !state.resolving.contains(&var_name) {
+                return Err((format!("@{} is locked, :unlock it to change it!", var_name), index));
+            }
+            if state.variables[pos].formula.is_some() && !assigned {
+                refresh_dependent(pos, state).map_err(|msg| (msg, index))?;
+            }
+            return Ok((
+                Token {
+                    operator: 'v',
+                    var_index: Some(pos),
+                    ..Token::new()
+                },
+                curr_index,
+            ));
+        }
+
+        // Look ahead for assignment
+        let mut look_ahead = curr_index;
+        while look_ahead < input.len() && (input[look_ahead] == b' ' || input[look_ahead] == b'_' || input[look_ahead] == b'\t') {
+            look_ahead += 1;
+        }
+
+        if look_ahead < input.len() && input[look_ahead] == b'=' && input.get(look_ahead + 1) != Some(&b'=') {
+            // This is an assignment - create new variable
+            state.variables.push(Variable {
+                name: var_name,  // Already lowercase from parsing
+                value: Complex::with_val(state.precision, 0),
+                dimension: DIMENSIONLESS,
+                list: None,
+                formula: None,
+                note: None,
+                locked: false,
+            });
+            return Ok((
+                Token {
+                    operator: 'v',
+                    var_index: Some(state.variables.len() - 1),
+                    ..Token::new()
+                },
+                curr_index,
+            ));
+        }
+
+        // Variable doesn't exist and this isn't an assignment
+        return Err((format!("Undefined variable '{}'!", var_name), index));
+    }
+
+    Err((format!("Invalid constant!"), index))
+}
+/// Parses an angle in degrees, minutes and seconds, such as `12°34'56.7"`
+///
+/// Minutes and seconds are optional, and `′` and `″` work as well as `'` and `"`.
+/// The letters d, m and s belong to durations, see `parse_duration`. Like the
+/// results of `#asin`, the angle is in degrees or, in radians mode, radians.
+///
+/// # Returns
+/// * `None` - If there's no angle here
+/// * `Some(Ok((Token, usize)))` - The angle and the index after it
+/// * `Some(Err((String, usize)))` - An error message and the position of the error
+fn parse_dms(
+    input: &[u8],
+    index: usize,
+    base: u8,
+    balanced: bool,
+) -> Option<Result<(Token, usize), (String, usize)>> {
+    let is_digit = |c: u8| {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'z' => c - b'a' + 10,
+            b'A'..=b'Z' => c - b'A' + 10,
+            _ => return false,
+        };
+        is_base_digit(digit, base, balanced)
+    };
+    let number_end = |from: usize| {
+        let mut end = from;
+        while end < input.len() && (is_digit(input[end]) || input[end] == b'.') {
+            end += 1;
+        }
+        end
+    };
+    // The length of the degree, minute or second mark at `at`, if there is one
+    let mark_at = |at: usize, part: usize| -> Option<usize> {
+        let marks: [&[u8]; 2] = match part {
+            0 => ["°".as_bytes(), "°".as_bytes()],
+            1 => ["′".as_bytes(), b"'"],
+            _ => ["″".as_bytes(), b"\""],
+        };
+        marks.iter().find(|mark| input[at..].starts_with(mark)).map(|mark| mark.len())
+    };
+
+    let mut negative = false;
+    let mut start = index;
+    while start < input.len() && input[start] == b'-' {
+        negative = !negative;
+        start += 1;
+    }
+    let end = number_end(start);
+    if end == start {
+        return None;
+    }
+    let degree_mark = mark_at(end, 0)?;
+    let mut parts = vec![String::from_utf8_lossy(&input[start..end]).to_string(), String::new(), String::new()];
+    let mut starts = [start, 0, 0];
+    let mut at = end + degree_mark;
+    let mut part = 1;
+    while part < 3 {
+        let mut start = at;
+        while start < input.len() && (input[start] == b' ' || input[start] == b'_') {
+            start += 1;
+        }
+        let end = number_end(start);
+        if end == start {
+            break;
+        }
+        // Seconds may follow the degrees directly
+        let (found, length) = match (mark_at(end, part), mark_at(end, 2)) {
+            (Some(length), _) => (part, length),
+            (None, Some(length)) => (2, length),
+            (None, None) => return Some(Err(("Expected ' or \" after angle!".to_string(), end))),
+        };
+        parts[found] = String::from_utf8_lossy(&input[start..end]).to_string();
+        starts[found] = start;
+        at = end + length;
+        part = found + 1;
+    }
+    for (text, start) in parts.iter().zip(starts) {
+        if !text.is_empty() {
+            if let Err((msg, pos)) = parse_number(text.as_bytes(), base, balanced, 0) {
+                return Some(Err((msg, start + pos)));
+            }
+        }
+    }
+    Some(Ok((
+        Token {
+            operator: 'd',
+            sign: (negative, false),
+            arguments: parts,
+            ..Token::new()
+        },
+        at,
+    )))
+}
+/// Duration marks, largest first, with how many milliseconds each stands for
+const DURATION_MARKS: [(&str, u32); 5] = [("d", 86_400_000), ("h", 3_600_000), ("m", 60_000), ("s", 1000), ("ms", 1)];
+/// Parses a duration such as `3h15m30s`, `250ms` or `2d` into seconds
+///
+/// Parts go from days down to milliseconds, each at most once and with no spaces
+/// between them. The marks can only be used where they aren't digits in the current
+/// base, and a lone `3m` is left to mean metres.
+///
+/// # Returns
+/// * `None` - If there's no duration here
+/// * `Some(Ok((Token, usize)))` - The duration and the index after it
+/// * `Some(Err((String, usize)))` - An error message and the position of the error
+fn parse_duration(
+    input: &[u8],
+    index: usize,
+    base: u8,
+    balanced: bool,
+) -> Option<Result<(Token, usize), (String, usize)>> {
+    let is_digit = |c: u8| {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'z' => c - b'a' + 10,
+            b'A'..=b'Z' => c - b'A' + 10,
+            _ => return false,
+        };
+        is_base_digit(digit, base, balanced)
+    };
+    // Which mark is at `at`, as long as it isn't the start of a longer unit like min
+    let mark_at = |at: usize| {
+        DURATION_MARKS.iter().position(|(mark, _)| {
+            input[at..].starts_with(mark.as_bytes())
+                && !input.get(at + mark.len()).is_some_and(|c| c.is_ascii_alphabetic())
+        })
+    };
+
+    let mut negative = false;
+    let mut start = index;
+    while start < input.len() && input[start] == b'-' {
+        negative = !negative;
+        start += 1;
+    }
+    let mut parts = vec![String::new(); DURATION_MARKS.len()];
+    let mut found = Vec::new();
+    let mut at = start;
+    loop {
+        let mut end = at;
+        while end < input.len() && (is_digit(input[end]) || input[end] == b'.') {
+            end += 1;
+        }
+        let mark = match mark_at(end) {
+            Some(mark) if end > at => mark,
+            _ => break,
+        };
+        // Larger parts come first, so 1h30m but not 30m1h
+        if found.last().is_some_and(|&last| mark <= last) {
+            return Some(Err(("Duration parts must go from days down!".to_string(), end)));
+        }
+        if let Err((msg, pos)) = parse_number(&input[at..end], base, balanced, 0) {
+            return Some(Err((msg, at + pos)));
+        }
+        parts[mark] = String::from_utf8_lossy(&input[at..end]).to_string();
+        found.push(mark);
+        at = end + DURATION_MARKS[mark].0.len();
+    }
+    // A power belongs to a unit, as in 2s^2
+    if found.is_empty() || found == [2] || input.get(at) == Some(&b'^') {
+        return None;
+    }
+    Some(Ok((
+        Token {
+            operator: '⏱',
+            sign: (negative, false),
+            arguments: parts,
+            ..Token::new()
+        },
+        at,
+    )))
+}
+/// Parses a number from the input and updates the token
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `token` - The token to update with the parsed number
+/// * `base` - The current number base
+/// * `balanced` - Whether the base is balanced, taking Z, Y, ... as negative digits
+/// * `index` - The starting index in the input
+///
+/// # Returns
+/// * `Ok(usize)` - The new index after parsing the number
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_number(
+    input: &[u8],
+    base: u8,
+    balanced: bool,
+    mut index: usize,
+) -> Result<(Token, usize), (String, usize)> {
+    let mut complex = false;
+    let mut imaginary = false;
+    let mut integer = true;
+    let mut expect_sign = true;
+    let mut token = Token {
+        operator: 1 as char, // 1 denotes number
+        ..Token::new()
+    };
+    while index < input.len()
+        && (input[index] == b' ' || input[index] == b'_' || input[index] == b'\t')
+    {
+        index += 1;
+    }
+
+    // Check if we've reached the end of the input after skipping whitespace
+    if index >= input.len() {
+        return Err(("Incomplete expression!".to_string(), index));
+    }
+    while index < input.len() {
+        let c = input[index];
+
+        if c == b' ' || c == b'_' || c == b'\t' {
+            index += 1;
+            continue;
+        }
+
+        if c == b'[' {
+            if !token.real_integer.is_empty() || !token.real_fraction.is_empty() || complex {
+                return Err((format!("Unexpected '['!"), index));
+            }
+            complex = true;
+            expect_sign = true;
+            index += 1;
+            continue;
+        }
+
+        if expect_sign {
+            if c == b'-' {
+                if complex {
+                    if imaginary {
+                        token.sign.1 = !token.sign.1;
+                    } else {
+                        token.sign.0 = !token.sign.0;
+                    }
+                } else {
+                    token.sign.0 = !token.sign.0;
+                }
+                index += 1;
+                continue;
+            }
+        }
+
+        if c == b',' || c == b';' {
+            if !complex || imaginary {
+                return Err((format!("Unexpected '{}'!", c as char), index));
+            }
+            token.polar = c == b';';
+            imaginary = true;
+            integer = true;
+            expect_sign = true;
+            index += 1;
+            continue;
+        }
+
+        if c == b']' {
+            if !complex {
+                return Err((format!("Unexpected ']'!"), index));
+            }
+
+            let (first, second) = if token.polar {
+                ("magnitude", "angle")
+            } else {
+                ("real component", "imaginary component")
+            };
+            if token.real_integer.is_empty() && token.real_fraction.is_empty() {
+                return Err((format!("Missing {}!", first), index));
+            }
+            if token.imaginary_integer.is_empty() && token.imaginary_fraction.is_empty() {
+                return Err((format!("Missing {}!", second), index));
+            }
+            return Ok((token, index + 1));
+        }
+
+        if c == b'.' {
+            if !integer {
+                return Err((format!("Multiple decimals in number!"), index));
+            }
+            integer = false;
+            index += 1;
+            continue;
+        }
+
+        let digit = if c.is_ascii_digit() {
+            c - b'0'
+        } else if c.is_ascii_uppercase() {
+            c - b'A' + 10
+        } else if c.is_ascii_lowercase() {
+            c - b'a' + 10
+        } else {
+            if token.real_integer.is_empty()
+                && token.real_fraction.is_empty()
+                && token.imaginary_integer.is_empty()
+                && token.imaginary_fraction.is_empty()
+            {
+                return Err(("Invalid number!".to_string(), index));
+            }
+            return Ok((token, index));
+        };
+
+        // A unit after the number, as in 3 km, ends it
+        let has_digits = !token.real_integer.is_empty() || !token.real_fraction.is_empty();
+        let in_range = is_base_digit(digit, base, balanced);
+        if !in_range && has_digits && !complex && unit_at(input, index, base, balanced).is_some() {
+            return Ok((token, index));
+        }
+        if !in_range {
+            let base_char = if base > 9 {
+                (base - 10 + b'A') as char
+            } else {
+                (base + b'0') as char
+            };
+
+            if balanced {
+                return Err((
+                    format!(
+                        "Digit out of balanced {} ({}) range!",
+                        get_base_name(base).unwrap().to_ascii_lowercase(),
+                        base_char
+                    ),
+                    index,
+                ));
+            } else if base == 36 {
+                return Err((
+                    format!(
+                        "Digit out of {} (Z+1) range!",
+                        get_base_name(base).unwrap().to_ascii_lowercase()
+                    ),
+                    index,
+                ));
+            } else {
+                return Err((
+                    format!(
+                        "Digit out of {} ({}) range!",
+                        get_base_name(base).unwrap().to_ascii_lowercase(),
+                        base_char
+                    ),
+                    index,
+                ));
+            };
+        }
+        expect_sign = false;
+        if imaginary {
+            if integer {
+                token.imaginary_integer.push(digit);
+            } else {
+                token.imaginary_fraction.push(digit);
+            }
+        } else {
+            if integer {
+                token.real_integer.push(digit);
+            } else {
+                token.real_fraction.push(digit);
+            }
+        }
+
+        index += 1;
+    }
+
+    if complex {
+        return Err((format!("Unclosed complex number!"), index));
+    }
+
+    if token.real_integer.is_empty()
+        && token.real_fraction.is_empty()
+        && token.imaginary_integer.is_empty()
+        && token.imaginary_fraction.is_empty()
+    {
+        return Err(("Invalid number!".to_string(), index));
+    }
+
+    Ok((token, index))
+}
+/// Parses an operator from the input
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The starting index in the input
+///
+/// # Returns
+/// * `Ok((Token, usize))` - The parsed operator token and the new index
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_operator(input: &[u8], mut index: usize) -> (Token, usize) {
+    let mut token = Token::new();
+
+    if index < input.len() {
+        // First check for assignment operator, leaving `==` to the comparisons
+        if input[index] == b'=' && input.get(index + 1) != Some(&b'=') {
+            token.operator = '=';
+            token.operands = 2;
+            return (token, index + 1);
+        }
+
+        // Then check for other operators
+        for &(op_str, op_char, operands, _) in &OPERATORS {
+            if input[index..]
+                .to_ascii_lowercase()
+                .starts_with(op_str.as_bytes())
+            {
+                token.operator = op_char;
+                token.operands = operands;
+                index += op_str.len();
+                return (token, index);
+            }
+        }
+    }
+    (token, index)
+}
+enum CommandResult {
+    /// Command was successful, with a message to display
+    Success(String),
+    /// Command failed, with an error message and the position of the error
+    Error(String, usize),
+    /// Command was successful but requires no message (like :help)
+    Silent,
+}
+/// Parses a command from the input and updates calculator settings
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The starting index in the input
+/// * `base` - The current number base
+/// * `precision` - The current precision for calculations
+/// * `digits` - The number of digits to display in results
+/// * `radians` - Whether to use radians for trigonometric functions
+/// * `colours` - The colour scheme for output formatting
+/// * `rand_state` - The random state for random number generation
+/// * `prev_result` - The previous calculation result
+///
+/// # Returns
+/// * `CommandResult::Success(String)` - Command was successful, with a message to display
+/// * `CommandResult::Error(String, usize)` - Command failed, with an error message and the position of the error
+/// * `CommandResult::Silent` - Command was successful but requires no message (like :help)
+fn parse_command(input: &[u8], mut index: usize, state: &mut BasecalcState) -> CommandResult {
+    match &input[index..] {
+        s if s.eq_ignore_ascii_case(b"test") => {
+            let (passed, total) = run_tests();
+            // The tests toggle colour themselves, so restore the session's setting
+            apply_colour_setting(state);
+            CommandResult::Success(format!("{}/{} tests passed.", passed, total))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"base") => {
+            index += 4;
+            // Skip whitespace
+            while index < input.len()
+                && (input[index] == b' ' || input[index] == b'_' || input[index] == b'\t')
+            {
+                index += 1;
+            }
+
+            if input.get(index) == Some(&b'@') {
+                let name = String::from_utf8_lossy(&input[index..])
+                    .trim_end_matches([' ', '_', '\t'])
+                    .to_ascii_lowercase();
+                state.radix = match name.as_str() {
+                    "@phi" => Some(Radix::Phi),
+                    "@e" => Some(Radix::E),
+                    _ => {
+                        return CommandResult::Error(
+                            "Irrational base must be @phi or @e!".to_string(),
+                            index,
+                        )
+                    }
+                };
+                state.balanced = false;
+                return CommandResult::Success(format!(
+                    "Showing results in base {}, entry stays {} ({}).",
+                    state.radix.unwrap().name(),
+                    get_base_name(state.base).unwrap(),
+                    base_digit_char(state.base)
+                ));
+            }
+            let base = match parse_base_digit(input, index) {
+                Ok(base) => base,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            let base_index = index;
+
+            // Then the balanced option
+            index += 1;
+            while index < input.len()
+                && (input[index] == b' ' || input[index] == b'_' || input[index] == b'\t')
+            {
+                index += 1;
+            }
+            let balanced =
+                input.len() >= index + 8 && input[index..index + 8].eq_ignore_ascii_case(b"balanced");
+            if balanced {
+                if base % 2 == 0 {
+                    return CommandResult::Error("Balanced bases must be odd!".to_string(), base_index);
+                }
+                index += 8;
+            }
+            state.base = base;
+            state.balanced = balanced;
+            state.radix = None;
+
+            let base_char = match state.base {
+                0..=9 => (state.base as u8 + b'0') as char,
+                10..=35 => (state.base as u8 - 10 + b'A') as char,
+                36 => 'Z',
+                _ => '?',
+            };
+
+            state.set_precision();
+            let message = match get_base_name(state.base) {
+                Some(name) => {
+                    if state.base == 36 {
+                        format!("Base set to {} (Z+1).", name)
+                    } else if state.balanced {
+                        format!("Base set to Balanced {} ({}).", name.to_ascii_lowercase(), base_char)
+                    } else {
+                        format!("Base set to {} ({}).", name, base_char)
+                    }
+                }
+                None => format!("Base set to {}, unsupported base name.", base_char),
+            };
+
+            // Check for any trailing characters
+            while index < input.len() {
+                if input[index] != b' ' && input[index] != b'_' && input[index] != b'\t' {
+                    return CommandResult::Error(
+                        "Invalid characters after base value!".to_string(),
+                        index,
+                    );
+                }
+                index += 1;
+            }
+            CommandResult::Success(message)
+        }
+        s if s.len() >= 9 && s[..9].eq_ignore_ascii_case(b"digitcase") => {
+            let name = String::from_utf8_lossy(&input[index + 9..]).trim().to_ascii_lowercase();
+            match name.as_str() {
+                "" => {}
+                "upper" => state.lowercase = false,
+                "lower" => state.lowercase = true,
+                _ => {
+                    return CommandResult::Error(
+                        format!("Unknown digit case '{}', use upper or lower!", name),
+                        index + 9,
+                    )
+                }
+            }
+            CommandResult::Success(format!(
+                "Digits from ten up in {}case.",
+                if state.lowercase { "lower" } else { "upper" }
+            ))
+        }
+        // Ahead of :digits, which it starts with
+        s if s.len() >= 8 && s[..8].eq_ignore_ascii_case(b"digitset") => {
+            let name = String::from_utf8_lossy(&input[index + 8..]).trim().to_ascii_lowercase();
+            match name.as_str() {
+                "" => {}
+                "standard" => state.dozenal = false,
+                "dozenal" => state.dozenal = true,
+                _ => {
+                    return CommandResult::Error(
+                        format!("Unknown digit set '{}', use standard or dozenal!", name),
+                        index + 8,
+                    )
+                }
+            }
+            CommandResult::Success(format!(
+                "Digit set to {}.",
+                if state.dozenal { "dozenal" } else { "standard" }
+            ))
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"digits") => {
+            let token = Token::new();
+            let value;
+            let new_index;
+            match parse_number(input, state.base, state.balanced, index + 6) {
+                Ok((token, x)) => {
+                    new_index = x;
+                    if token.real_fraction.len() > 0
+                        || token.imaginary_integer.len() > 0
+                        || token.imaginary_fraction.len() > 0
+                        || token.sign.0
+                    {
+                        return CommandResult::Error(
+                            "Precision must be a positive real integer!".to_string(),
+                            index,
+                        );
+                    }
+
+                    value = token2num(&token, state).real().clone().round().to_f64() as usize;
+                    if value == 0 {
+                        return CommandResult::Error(
+                            "Precision must be a positive real integer!".to_string(),
+                            index,
+                        );
+                    }
+                }
+                Err((msg, pos)) => {
+                    return CommandResult::Error(msg, pos);
+                }
+            }
+            index = new_index;
+
+            // Check if there's anything after the number
+            if index < input.len() {
+                for i in index..input.len() {
+                    if input[i] != b' ' && input[i] != b'_' && input[i] != b'\t' {
+                        return CommandResult::Error(
+                            "Invalid characters after digits value!".to_string(),
+                            i,
+                        );
+                    }
+                }
+            }
+            state.digits = value;
+            state.set_precision();
+            if token.imaginary_integer.len() > 0 || token.imaginary_fraction.len() > 0 {
+                return CommandResult::Error(
+                    "Precision must be a real integer!".to_string(),
+                    index,
+                );
+            }
+            CommandResult::Success(format!(
+                "Precision set to {} digits.",
+                format_int(value, state.base as usize, state.balanced)
+            ))
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"degrees") => {
+            // Check if there's anything after the command
+            for i in index + 7..input.len() {
+                if input[i] != b' ' && input[i] != b'_' && input[i] != b'\t' {
+                    return CommandResult::Error(
+                        "Invalid characters after command!".to_string(),
+                        i,
+                    );
+                }
+            }
+            state.angle = AngleMode::Degrees;
+            CommandResult::Success("Angle units set to degrees.".to_string())
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"radians") => {
+            // Check if there's anything after the command
+            for i in index + 7..input.len() {
+                if input[i] != b' ' && input[i] != b'_' && input[i] != b'\t' {
+                    return CommandResult::Error(
+                        "Invalid characters after command!".to_string(),
+                        i,
+                    );
+                }
+            }
+            state.angle = AngleMode::Radians;
+            CommandResult::Success("Angle units set to radians.".to_string())
+        }
+        s if s.len() >= 8 && s[..8].eq_ignore_ascii_case(b"gradians") => {
+            if let Some(i) = find_trailing(input, index + 8) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            state.angle = AngleMode::Gradians;
+            CommandResult::Success("Angle units set to gradians.".to_string())
+        }
+        s if s.len() >= 3 && s[..3].eq_ignore_ascii_case(b"dms") => {
+            // Check if there's anything after the command
+            for i in index + 3..input.len() {
+                if input[i] != b' ' && input[i] != b'_' && input[i] != b'\t' {
+                    return CommandResult::Error(
+                        "Invalid characters after command!".to_string(),
+                        i,
+                    );
+                }
+            }
+            let dms = num2dms(&state.prev_result, state);
+            for block in dms {
+                print!("{}", block);
+            }
+            CommandResult::Success("".to_string())
+        }
+        s if s.len() >= 11 && s[..11].eq_ignore_ascii_case(b"sexagesimal")
+            || s.len() >= 3 && s[..3].eq_ignore_ascii_case(b"hms") =>
+        {
+            let length = if s[..3].eq_ignore_ascii_case(b"hms") { 3 } else { 11 };
+            if let Some(i) = find_trailing(input, index + length) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            let num = &state.prev_result;
+            if !num.imag().is_zero() || !num.real().is_finite() {
+                return CommandResult::Error(
+                    "Time needs a real previous result in seconds!".to_string(),
+                    index,
+                );
+            }
+            CommandResult::Success(format_hms(num.real(), state))
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"collatz") => {
+            if let Some(i) = find_trailing(input, index + 7) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            let mut n = match integer_real_part(&state.prev_result) {
+                Some(n) if n > 0 => n,
+                _ => {
+                    return CommandResult::Error(
+                        "Collatz needs a positive integer previous result!".to_string(),
+                        index,
+                    )
+                }
+            };
+            let mut steps = Integer::new();
+            let mut peak = n.clone();
+            while n != 1 {
+                if let Err(msg) = check_interrupt(state) {
+                    return CommandResult::Error(msg, index);
+                }
+                if n.is_even() {
+                    n >>= 1;
+                } else {
+                    n = n * 3 + 1;
+                }
+                if n > peak {
+                    peak = n.clone();
+                }
+                steps += 1;
+            }
+            CommandResult::Success(format!(
+                "Collatz trajectory: {} steps, peak {}.",
+                format_integer(&steps, state.base),
+                format_integer(&peak, state.base)
+            ))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"bits") => {
+            let mut format_index = index + 4;
+            while format_index < input.len()
+                && (input[format_index] == b' ' || input[format_index] == b'\t')
+            {
+                format_index += 1;
+            }
+            let format = &input[format_index..];
+            let single = if format.len() >= 3 && format[..3].eq_ignore_ascii_case(b"f32") {
+                true
+            } else if format.len() >= 3 && format[..3].eq_ignore_ascii_case(b"f64") {
+                false
+            } else {
+                return CommandResult::Error("Expected f32 or f64!".to_string(), format_index);
+            };
+            if let Some(i) = find_trailing(input, format_index + 3) {
+                return CommandResult::Error("Invalid characters after format!".to_string(), i);
+            }
+            if !state.prev_result.imag().is_zero() {
+                return CommandResult::Error("Previous result must be real!".to_string(), index);
+            }
+            CommandResult::Success(describe_float_bits(state.prev_result.real(), single, state))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"show") => {
+            let mut bases = Vec::new();
+            for i in index + 4..input.len() {
+                if input[i] == b' ' || input[i] == b'\t' {
+                    continue;
+                }
+                match parse_base_digit(input, i) {
+                    Ok(base) => bases.push(base),
+                    Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                }
+            }
+            if bases.is_empty() {
+                bases = vec![2, 8, 10, 12, 16];
+            }
+            let labels: Vec<String> = bases
+                .iter()
+                .map(|&base| match get_base_name(base) {
+                    Some(name) => name.to_string(),
+                    None => format!("Base {}", base_digit_char(base)),
+                })
+                .collect();
+            let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+            let mut local_state = state.clone();
+            let lines: Vec<String> = bases
+                .iter()
+                .zip(&labels)
+                .map(|(&base, label)| {
+                    // Same information in every base, so no digits beyond the working precision
+                    local_state.base = base;
+                    local_state.digits = (state.digits as f64 * (state.base as f64).ln()
+                        / (base as f64).ln())
+                    .round()
+                    .max(1.0) as usize;
+                    local_state.set_precision();
+                    format!(
+                        "{:<width$}{}",
+                        format!("{}:", label),
+                        coloured_vec_to_string(&num2string(&state.prev_result, &local_state)),
+                        width = label_width + 1
+                    )
+                })
+                .collect();
+            CommandResult::Success(lines.join("\n"))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"copy") => {
+            let (mut hex, mut plain) = (false, false);
+            let words = String::from_utf8_lossy(&input[index + 4..]).to_ascii_lowercase();
+            for word in words.split_whitespace() {
+                match word {
+                    "hex" => hex = true,
+                    "plain" => plain = true,
+                    _ => return CommandResult::Error("Expected hex or plain!".to_string(), index + 4),
+                }
+            }
+            let text = copy_text(&state.prev_result, hex, plain, state);
+            match copy_to_clipboard(&text) {
+                Ok(()) => CommandResult::Success(format!("Copied {} to the clipboard.", text)),
+                Err(msg) => CommandResult::Error(format!("Couldn't reach the clipboard: {}", msg), index),
+            }
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"latex") => {
+            let word = String::from_utf8_lossy(&input[index + 5..]).trim().to_ascii_lowercase();
+            let (result, approximate) = latex_number(&state.prev_result, state);
+            match word.as_str() {
+                "" if approximate => CommandResult::Success(format!("\\approx {}", result)),
+                "" => CommandResult::Success(result),
+                "input" => {
+                    // The line holding this command is in the history too, so it's passed over
+                    let expression = state
+                        .history
+                        .iter()
+                        .rev()
+                        .find(|entry| !entry.trim_start().starts_with(':'));
+                    let Some(expression) = expression else {
+                        return CommandResult::Error(
+                            "No expression in the history to show!".to_string(),
+                            index + 5,
+                        );
+                    };
+                    CommandResult::Success(format!(
+                        "{} {} {}",
+                        latex_expression(expression),
+                        if approximate { "\\approx" } else { "=" },
+                        result
+                    ))
+                }
+                _ => CommandResult::Error("Expected input!".to_string(), index + 5),
+            }
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"pascal") => {
+            let (rows, new_index) = match parse_integer_argument(input, index + 6, state) {
+                Ok(arg) => arg,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if let Some(i) = find_trailing(input, new_index) {
+                return CommandResult::Error("Invalid characters after row count!".to_string(), i);
+            }
+            let rows = match rows.to_u32() {
+                Some(rows) if rows > MAX_PASCAL_ROWS => {
+                    let most = format_int(MAX_PASCAL_ROWS as usize, state.base as usize, state.balanced);
+                    return CommandResult::Error(format!("Row count must be at most {}!", most), index + 6);
+                }
+                Some(rows) if rows > 0 => rows,
+                _ => {
+                    return CommandResult::Error(
+                        "Row count must be a positive integer!".to_string(),
+                        index + 6,
+                    )
+                }
+            };
+            // Each row from the one above, every entry the sum of the two over it
+            let mut row = vec![Integer::from(1)];
+            let mut lines = Vec::new();
+            for _ in 0..rows {
+                let line: Vec<String> = row.iter().map(|entry| format_whole(entry, state)).collect();
+                lines.push(line.join(" "));
+                let mut next = vec![Integer::from(1)];
+                next.extend(row.windows(2).map(|pair| Integer::from(&pair[0] + &pair[1])));
+                next.push(Integer::from(1));
+                row = next;
+            }
+            let width = lines.last().map_or(0, |line| line.chars().count());
+            let triangle: Vec<String> = lines
+                .iter()
+                .map(|line| format!("{}{}", " ".repeat((width - line.chars().count()) / 2), line))
+                .collect();
+            CommandResult::Success(triangle.join("\n"))
+        }
+        s if s.len() >= 3 && s[..3].eq_ignore_ascii_case(b"acc") => {
+            let mut op_index = index + 3;
+            while op_index < input.len()
+                && (input[op_index] == b' ' || input[op_index] == b'_' || input[op_index] == b'\t')
+            {
+                op_index += 1;
+            }
+            if input[op_index..].eq_ignore_ascii_case(b"off") {
+                state.acc_operator = None;
+                return match state.accumulator.take() {
+                    Some(total) => CommandResult::Success(format!(
+                        "Accumulator off, total:{}",
+                        coloured_vec_to_string(&num2string(&total, state))
+                    )),
+                    None => CommandResult::Success("Accumulator off.".to_string()),
+                };
+            }
+            let (token, new_index) = parse_operator(input, op_index);
+            if token.operands != 2 || token.operator == '=' {
+                return CommandResult::Error(
+                    "Expected a binary operator or 'off'!".to_string(),
+                    op_index,
+                );
+            }
+            if let Some(i) = find_trailing(input, new_index) {
+                return CommandResult::Error("Invalid characters after operator!".to_string(), i);
+            }
+            state.acc_operator = Some(token.operator);
+            state.accumulator = None;
+            let description = OPERATORS
+                .iter()
+                .find(|&&(_, symbol, _, _)| symbol == token.operator)
+                .map(|(_, _, _, description)| *description)
+                .unwrap_or("unknown operator");
+            CommandResult::Success(format!("Accumulating with {}.", description))
+        }
+        s if s.len() >= 5
+            && (s[..5].eq_ignore_ascii_case(b"color")
+                || s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"colour")) =>
+        {
+            let name_length = if s[..5].eq_ignore_ascii_case(b"color") { 5 } else { 6 };
+            let setting = String::from_utf8_lossy(&input[index + name_length..])
+                .trim()
+                .to_ascii_lowercase();
+            state.colour = match setting.as_str() {
+                "" => state.colour,
+                "on" => Some(true),
+                "off" => Some(false),
+                "auto" => None,
+                _ => {
+                    return CommandResult::Error(
+                        "Expected on, off or auto!".to_string(),
+                        index + name_length,
+                    )
+                }
+            };
+            apply_colour_setting(state);
+            CommandResult::Success(
+                match state.colour {
+                    Some(true) => "Colour on.",
+                    Some(false) => "Colour off.",
+                    None => "Colour automatic, off when piped or with NO_COLOR set.",
+                }
+                .to_string(),
+            )
+        }
+        s if s.len() >= 9 && s[..9].eq_ignore_ascii_case(b"constants") => {
+            if let Some(i) = find_trailing(input, index + 9) {
+                return CommandResult::Error("Invalid argument!".to_string(), i);
+            }
+            let listing: Vec<String> = PHYSICAL_CONSTANTS
+                .iter()
+                .enumerate()
+                .map(|(i, &(name, _, unit, description))| {
+                    let value = Complex::with_val(state.precision, physical_constant(i, state.precision));
+                    format!("@phys.{:<6}{:<34}{} {}", name, description, format_complex(&value, state), unit)
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            CommandResult::Success(listing.join("\n"))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"const") => {
+            index += 5;
+            while index < input.len() && (input[index] == b' ' || input[index] == b'\t') {
+                index += 1;
+            }
+            if index < input.len() && input[index] == b'@' {
+                index += 1;
+            }
+            let name_start = index;
+            while index < input.len() && input[index].is_ascii_alphanumeric() {
+                index += 1;
+            }
+            let name = String::from_utf8_lossy(&input[name_start..index]).to_ascii_lowercase();
+            if name.is_empty() {
+                if index < input.len() {
+                    return CommandResult::Error("Invalid constant name!".to_string(), index);
+                }
+                if state.constants.is_empty() {
+                    return CommandResult::Success("No constants defined.".to_string());
+                }
+                let listing: Vec<String> = state
+                    .constants
+                    .iter()
+                    .map(|constant| format!("@{} = {}", constant.name, format_complex(&constant.value, state)))
+                    .collect();
+                return CommandResult::Success(listing.join("\n"));
+            }
+            if !name.as_bytes()[0].is_ascii_alphabetic() {
+                return CommandResult::Error("Constant names must start with a letter!".to_string(), name_start);
+            }
+            if CONSTANTS.iter().any(|(built_in, ..)| built_in.eq_ignore_ascii_case(&format!("@{}", name))) {
+                return CommandResult::Error(format!("@{} is already built in!", name), name_start);
+            }
+            let existing = state.constants.iter().position(|c| c.name == name);
+            let rest = String::from_utf8_lossy(&input[index..]).to_string();
+            if rest.trim().is_empty() {
+                return match existing {
+                    Some(pos) => CommandResult::Success(format!(
+                        "@{} = {}",
+                        name,
+                        format_complex(&state.constants[pos].value, state)
+                    )),
+                    None => CommandResult::Error(format!("Undefined constant '{}'!", name), name_start),
+                };
+            }
+            if rest.trim().eq_ignore_ascii_case("off") {
+                return match existing {
+                    Some(pos) => {
+                        state.constants.remove(pos);
+                        CommandResult::Success(format!("Removed constant @{}.", name))
+                    }
+                    None => CommandResult::Error(format!("Undefined constant '{}'!", name), name_start),
+                };
+            }
+            let value = match evaluate_slice(&rest, index, state) {
+                Ok(value) => value,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            // A variable of the same name is promoted rather than left hidden behind it
+            state.variables.retain(|v| v.name.to_ascii_lowercase() != name);
+            let message = format!("Constant @{} = {}", name, format_complex(&value, state));
+            match existing {
+                Some(pos) => state.constants[pos].value = value,
+                None => state.constants.push(Variable {
+                    name,
+                    value,
+                    dimension: DIMENSIONLESS,
+                    list: None,
+                    formula: None,
+                    note: None,
+                    locked: false,
+                }),
+            }
+            CommandResult::Success(message)
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"theme") => {
+            let name = String::from_utf8_lossy(&input[index + 5..]).trim().to_ascii_lowercase();
+            if name.is_empty() {
+                let themes: Vec<String> = available_themes()
+                    .into_iter()
+                    .map(|theme| if theme == state.theme { format!("{} (current)", theme) } else { theme })
+                    .collect();
+                return CommandResult::Success(format!("Themes: {}", themes.join(", ")));
+            }
+            match load_theme(&name) {
+                Ok(colours) => {
+                    state.colours = colours;
+                    state.theme = name;
+                    CommandResult::Success(format!("Theme set to {}.", state.theme))
+                }
+                Err(msg) => CommandResult::Error(msg, index + 5),
+            }
+        }
+        s if s.len() >= 12 && s[..12].eq_ignore_ascii_case(b"expthreshold") => {
+            let arg_index = index + 12;
+            let rest = &input[arg_index..];
+            match rest.iter().position(|&c| c != b' ' && c != b'\t') {
+                None => {}
+                Some(start) if rest[start..].eq_ignore_ascii_case(b"off") => {
+                    state.exponent_range = None;
+                }
+                Some(_) => {
+                    let places = match parse_complex_arguments(input, arg_index, state) {
+                        Ok(places) => places,
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    };
+                    let places: Vec<Option<isize>> = places
+                        .iter()
+                        .map(|place| exact_integer(place).and_then(|place| place.to_isize()))
+                        .collect();
+                    let (low, high) = match places[..] {
+                        [Some(low), Some(high)] => (low, high),
+                        _ => {
+                            return CommandResult::Error(
+                                "Expected two whole thresholds: low high!".to_string(),
+                                arg_index,
+                            )
+                        }
+                    };
+                    let limit = MAX_PLAIN_PLACES as isize;
+                    state.exponent_range = match (low, high) {
+                        _ if -limit <= low && low < high && high <= limit => Some((low, high)),
+                        _ => {
+                            return CommandResult::Error(
+                                format!(
+                                    "Thresholds must run low to high, within {} places of the point!",
+                                    format_int(MAX_PLAIN_PLACES, state.base as usize, state.balanced)
+                                ),
+                                arg_index,
+                            )
+                        }
+                    };
+                }
+            }
+            let exponent = |place: isize| {
+                let sign = if place < 0 { "-" } else { "" };
+                format!("{}{}", sign, format_int(place.unsigned_abs(), state.base as usize, state.balanced))
+            };
+            CommandResult::Success(match state.exponent_range {
+                Some((low, high)) => format!(
+                    "Writing out numbers from :{} up to below :{}.",
+                    exponent(low),
+                    exponent(high)
+                ),
+                None => "Exponent thresholds back to default.".to_string(),
+            })
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"history") => {
+            if let Some(arg_index) = find_trailing(input, index + 7) {
+                let rest = &input[arg_index..];
+                if rest.len() < 3 || !rest[..3].eq_ignore_ascii_case(b"max") {
+                    return CommandResult::Error("Expected max!".to_string(), arg_index);
+                }
+                let (max, new_index) = match parse_integer_argument(input, arg_index + 3, state) {
+                    Ok(arg) => arg,
+                    Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                };
+                if let Some(i) = find_trailing(input, new_index) {
+                    return CommandResult::Error("Invalid characters after count!".to_string(), i);
+                }
+                state.history_max = match max.to_usize() {
+                    Some(max) => max,
+                    None => {
+                        return CommandResult::Error(
+                            "History max can't be negative!".to_string(),
+                            arg_index + 3,
+                        )
+                    }
+                };
+                state.prune_history();
+            }
+            CommandResult::Success(format!(
+                "Keeping the last {} history entries, {} so far.",
+                format_int(state.history_max, state.base as usize, state.balanced),
+                format_int(state.history.len(), state.base as usize, state.balanced)
+            ))
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"export") => {
+            let rest = String::from_utf8_lossy(&input[index + 6..]);
+            let (kind, path) = rest.trim().split_once([' ', '\t']).unwrap_or((rest.trim(), ""));
+            let kind = kind.to_ascii_lowercase();
+            if kind != "history" && kind != "md" && kind != "csv" {
+                return CommandResult::Error("Expected history, md or csv!".to_string(), index + 6);
+            }
+            let path = path.trim();
+            if path.is_empty() {
+                return CommandResult::Error(
+                    "Expected a file to export to!".to_string(),
+                    input.len(),
+                );
+            }
+            let (text, count, what) = if kind == "md" {
+                if state.session.is_empty() {
+                    return CommandResult::Error(
+                        "Nothing entered this session to export!".to_string(),
+                        index + 6,
+                    );
+                }
+                (session_markdown(&state.session), state.session.len(), "lines")
+            } else if kind == "csv" {
+                let Some(elements) = &state.last_list else {
+                    return CommandResult::Error("No list result to export!".to_string(), index + 6);
+                };
+                (list_csv(elements, state), elements.len(), "rows")
+            } else {
+                let text = state.history.iter().map(|entry| format!("{}\n", entry)).collect();
+                (text, state.history.len(), "history entries")
+            };
+            match fs::write(path, text) {
+                Ok(()) => CommandResult::Success(format!(
+                    "Exported {} {} to {}.",
+                    format_int(count, state.base as usize, state.balanced),
+                    what,
+                    path
+                )),
+                Err(e) => {
+                    CommandResult::Error(format!("Couldn't write {}: {}", path, e), index + 6)
+                }
+            }
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"record") => {
+            let path = String::from_utf8_lossy(&input[index + 6..]).trim().to_string();
+            if !path.is_empty() {
+                // Opened now so a bad path is caught here rather than after every line
+                if let Err(e) = fs::OpenOptions::new().create(true).append(true).open(&path) {
+                    return CommandResult::Error(
+                        format!("Couldn't open {}: {}", path, e),
+                        index + 6,
+                    );
+                }
+                state.transcript = Some(path);
+            }
+            CommandResult::Success(match &state.transcript {
+                Some(path) => format!("Recording to {}.", path),
+                None => "Not recording.".to_string(),
+            })
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"stop") => {
+            if let Some(i) = find_trailing(input, index + 4) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            match state.transcript.take() {
+                Some(path) => CommandResult::Success(format!("Stopped recording to {}.", path)),
+                None => CommandResult::Error("Not recording!".to_string(), index),
+            }
+        }
+        s if s.len() >= 9 && s[..9].eq_ignore_ascii_case(b"workspace") => {
+            let rest = String::from_utf8_lossy(&input[index + 9..]).trim().to_string();
+            if rest.is_empty() {
+                let mut workspaces: Vec<String> = fs::read_dir(get_workspace_dir())
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter_map(|entry| {
+                        let path = entry.path();
+                        match path.extension() {
+                            Some(extension) if extension == "vsf" => {
+                                path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect();
+                workspaces.sort();
+                return CommandResult::Success(if workspaces.is_empty() {
+                    "No workspaces saved.".to_string()
+                } else {
+                    format!("Workspaces: {}", workspaces.join(", "))
+                });
+            }
+            let (action, name) = rest.split_once([' ', '\t']).unwrap_or((&rest, ""));
+            let path = match workspace_path(name.trim()) {
+                Ok(path) => path,
+                Err(msg) => return CommandResult::Error(msg, index + 9),
+            };
+            let name = name.trim();
+            match action.to_ascii_lowercase().as_str() {
+                "save" => {
+                    let saved = fs::create_dir_all(get_workspace_dir())
+                        .and_then(|_| save_state_to(state, path));
+                    match saved {
+                        Ok(()) => CommandResult::Success(format!("Workspace {} saved.", name)),
+                        Err(e) => CommandResult::Error(
+                            format!("Couldn't save workspace {}: {}", name, e),
+                            index + 9,
+                        ),
+                    }
+                }
+                "load" => {
+                    let loaded = fs::read(&path).and_then(|data| read_vsf(&data));
+                    match loaded {
+                        Ok(mut loaded) => {
+                            // Carry on recording and answering Ctrl-C as before
+                            loaded.interrupt = state.interrupt.clone();
+                            loaded.transcript = state.transcript.take();
+                            loaded.session = std::mem::take(&mut state.session);
+                            *state = loaded;
+                            CommandResult::Success(format!("Workspace {} loaded.", name))
+                        }
+                        Err(e) if e.kind() == ErrorKind::NotFound => {
+                            CommandResult::Error(format!("Unknown workspace '{}'!", name), index + 9)
+                        }
+                        Err(e) => CommandResult::Error(
+                            format!("Couldn't load workspace {}: {}", name, e),
+                            index + 9,
+                        ),
+                    }
+                }
+                _ => CommandResult::Error("Expected save or load!".to_string(), index + 9),
+            }
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"save") => {
+            let Some(name_index) = find_trailing(input, index + 4) else {
+                return CommandResult::Error(
+                    "Expected a name to save under!".to_string(),
+                    input.len(),
+                );
+            };
+            let name_end = (name_index..input.len())
+                .find(|&i| input[i] == b' ' || input[i] == b'\t')
+                .unwrap_or(input.len());
+            let name = String::from_utf8_lossy(&input[name_index..name_end]).to_string();
+            let path = match sequence_path(&name) {
+                Ok(path) => path,
+                Err(msg) => return CommandResult::Error(msg, name_index),
+            };
+            let count = match find_trailing(input, name_end) {
+                None => 1,
+                Some(count_index) => {
+                    let (count, new_index) =
+                        match parse_integer_argument(input, count_index, state) {
+                            Ok(arg) => arg,
+                            Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                        };
+                    if let Some(i) = find_trailing(input, new_index) {
+                        return CommandResult::Error(
+                            "Invalid characters after count!".to_string(),
+                            i,
+                        );
+                    }
+                    match count.to_usize() {
+                        Some(count) if count > 0 => count,
+                        _ => {
+                            return CommandResult::Error(
+                                "Expected a positive count of entries!".to_string(),
+                                count_index,
+                            )
+                        }
+                    }
+                }
+            };
+            // The line holding this command is already in the history at the prompt
+            let command = String::from_utf8_lossy(input);
+            let mut history = &state.history[..];
+            if history.last().is_some_and(|entry| entry.contains(command.trim())) {
+                history = &history[..history.len() - 1];
+            }
+            if count > history.len() {
+                return CommandResult::Error(
+                    format!(
+                        "Only {} entries in the history!",
+                        format_int(history.len(), state.base as usize, state.balanced)
+                    ),
+                    index + 4,
+                );
+            }
+            let text: String = history[history.len() - count..]
+                .iter()
+                .map(|entry| format!("{}\n", entry))
+                .collect();
+            let saved = fs::create_dir_all(get_sequence_dir()).and_then(|_| fs::write(&path, text));
+            match saved {
+                Ok(()) => CommandResult::Success(format!(
+                    "Saved the last {} entries as {}.",
+                    format_int(count, state.base as usize, state.balanced),
+                    name
+                )),
+                Err(e) => {
+                    CommandResult::Error(format!("Couldn't save {}: {}", name, e), name_index)
+                }
+            }
+        }
+        s if s.len() >= 3 && s[..3].eq_ignore_ascii_case(b"run") => {
+            let name = String::from_utf8_lossy(&input[index + 3..]).trim().to_string();
+            if name.is_empty() {
+                let sequences = available_sequences();
+                return CommandResult::Success(if sequences.is_empty() {
+                    "No sequences saved.".to_string()
+                } else {
+                    format!("Sequences: {}", sequences.join(", "))
+                });
+            }
+            let text = match sequence_path(&name).and_then(|path| {
+                fs::read_to_string(path).map_err(|_| format!("Unknown sequence '{}'!", name))
+            }) {
+                Ok(text) => text,
+                Err(msg) => return CommandResult::Error(msg, index + 3),
+            };
+            let mut shown = Vec::new();
+            for entry in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                // A sequence running itself would never finish
+                if entry.len() >= 4 && entry[..4].eq_ignore_ascii_case(":run") {
+                    continue;
+                }
+                shown.push(format!("> {}", entry));
+                let (output, success) = transcript_output(entry, state);
+                shown.extend(output);
+                if !success {
+                    return CommandResult::Error(shown.join("\n"), index);
+                }
+            }
+            CommandResult::Success(shown.join("\n"))
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"replay") => {
+            let path = String::from_utf8_lossy(&input[index + 6..]).trim().to_string();
+            if path.is_empty() {
+                return CommandResult::Error(
+                    "Expected a transcript to replay!".to_string(),
+                    input.len(),
+                );
+            }
+            match replay_transcript(&path, state) {
+                Ok(lines) => CommandResult::Success(format!(
+                    "Replayed {} lines of {}, all agreeing.",
+                    format_int(lines, state.base as usize, state.balanced),
+                    path
+                )),
+                Err(msg) => CommandResult::Error(msg, index),
+            }
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"import") => {
+            let path = String::from_utf8_lossy(&input[index + 6..]).trim().to_string();
+            if path.is_empty() {
+                return CommandResult::Error(
+                    "Expected a file to import from!".to_string(),
+                    input.len(),
+                );
+            }
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    return CommandResult::Error(format!("Couldn't read {}: {}", path, e), index + 6)
+                }
+            };
+            let entries: Vec<&str> =
+                text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+            for entry in &entries {
+                state.add_history(entry.to_string());
+            }
+            CommandResult::Success(format!(
+                "Imported {} history entries from {}.",
+                format_int(entries.len(), state.base as usize, state.balanced),
+                path
+            ))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"fixed") => {
+            let arg_index = index + 5;
+            let rest = &input[arg_index..];
+            match rest.iter().position(|&c| c != b' ' && c != b'\t') {
+                None => {}
+                Some(start) if rest[start..].eq_ignore_ascii_case(b"off") => {
+                    state.fixed = None;
+                }
+                Some(_) => {
+                    let (places, new_index) = match parse_integer_argument(input, arg_index, state) {
+                        Ok(arg) => arg,
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    };
+                    if let Some(i) = find_trailing(input, new_index) {
+                        return CommandResult::Error("Invalid characters after places!".to_string(), i);
+                    }
+                    state.fixed = match places.to_usize() {
+                        Some(places) if places <= state.digits => Some(places),
+                        _ => {
+                            return CommandResult::Error(
+                                "Places must be between 0 and the digits shown!".to_string(),
+                                arg_index,
+                            )
+                        }
+                    };
+                }
+            }
+            CommandResult::Success(match state.fixed {
+                Some(places) => format!(
+                    "Showing {} places after the point.",
+                    format_int(places, state.base as usize, state.balanced)
+                ),
+                None => "Fixed places off.".to_string(),
+            })
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"group") => {
+            let mut grouping = state.grouping;
+            let mut at = index + 5;
+            while let Some(start) = find_trailing(input, at) {
+                let end = (start..input.len()).find(|&i| input[i] == b' ' || input[i] == b'\t').unwrap_or(input.len());
+                let word = String::from_utf8_lossy(&input[start..end]).to_ascii_lowercase();
+                match word.as_str() {
+                    "off" => grouping.size = 0,
+                    "space" => grouping.separator = ' ',
+                    "underscore" => grouping.separator = '_',
+                    "comma" | "," => grouping.separator = ',',
+                    "all" => grouping.fraction = true,
+                    "integer" => grouping.fraction = false,
+                    _ => match usize::from_str_radix(&word, state.base as u32) {
+                        Ok(size) if (1..=MAX_GROUP).contains(&size) => grouping.size = size,
+                        _ => return CommandResult::Error(format!("Unknown grouping option '{}'!", word), start),
+                    },
+                }
+                at = end;
+            }
+            state.grouping = grouping;
+            if grouping.size == 0 {
+                return CommandResult::Success("Digit grouping off.".to_string());
+            }
+            let separator = match grouping.separator {
+                ' ' => "spaces",
+                '_' => "underscores",
+                _ => "commas",
+            };
+            CommandResult::Success(format!(
+                "Grouping {} in {}s with {}.",
+                if grouping.fraction { "all digits" } else { "integer digits" },
+                format_int(grouping.size, state.base as usize, state.balanced),
+                separator
+            ))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"polar") => {
+            if let Some(i) = find_trailing(input, index + 5) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            state.polar = !state.polar;
+            CommandResult::Success(format!(
+                "Polar display {}",
+                if state.polar { "enabled" } else { "disabled" }
+            ))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"json") => {
+            let setting = String::from_utf8_lossy(&input[index + 4..]).trim().to_ascii_lowercase();
+            match setting.as_str() {
+                "" => {}
+                "on" => state.json = true,
+                "off" => state.json = false,
+                _ => return CommandResult::Error("Expected on or off!".to_string(), index + 4),
+            }
+            CommandResult::Success(format!("JSON output {}.", if state.json { "on" } else { "off" }))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"mouse") => {
+            if let Some(i) = find_trailing(input, index + 5) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            state.mouse = !state.mouse;
+            CommandResult::Success(format!(
+                "Mouse clicks at the prompt {}",
+                if state.mouse { "enabled" } else { "disabled" }
+            ))
+        }
+        s if s.len() >= 8 && s[..8].eq_ignore_ascii_case(b"interval") => {
+            if let Some(i) = find_trailing(input, index + 8) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            state.interval = !state.interval;
+            CommandResult::Success(format!(
+                "Interval mode {}",
+                if state.interval { "enabled" } else { "disabled" }
+            ))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"width") => {
+            let arg_index = index + 5;
+            let rest = &input[arg_index..];
+            match rest.iter().position(|&c| c != b' ' && c != b'\t') {
+                None => {}
+                Some(start) if rest[start..].eq_ignore_ascii_case(b"off") => {
+                    state.width = None;
+                }
+                Some(_) => {
+                    let (width, new_index) = match parse_integer_argument(input, arg_index, state) {
+                        Ok(arg) => arg,
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    };
+                    if let Some(i) = find_trailing(input, new_index) {
+                        return CommandResult::Error("Invalid characters after width!".to_string(), i);
+                    }
+                    state.width = match width.to_u32() {
+                        Some(width) if (1..=MAX_WIDTH).contains(&width) => Some(width),
+                        _ => {
+                            return CommandResult::Error(
+                                format!(
+                                    "Width must be between 1 and {} bits!",
+                                    format_int(MAX_WIDTH as usize, state.base as usize, state.balanced)
+                                ),
+                                arg_index,
+                            )
+                        }
+                    };
+                }
+            }
+            CommandResult::Success(match state.width {
+                Some(width) => format!(
+                    "Showing results as {}-bit registers.",
+                    format_int(width as usize, state.base as usize, state.balanced)
+                ),
+                None => "Register width display off.".to_string(),
+            })
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"repeat") => {
+            let arg_index = index + 6;
+            let rest = &input[arg_index..];
+            match rest.iter().position(|&c| c != b' ' && c != b'\t') {
+                None => {}
+                Some(start) if rest[start..].eq_ignore_ascii_case(b"off") => {
+                    state.period = None;
+                }
+                Some(_) => {
+                    let (period, new_index) = match parse_integer_argument(input, arg_index, state) {
+                        Ok(arg) => arg,
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    };
+                    if let Some(i) = find_trailing(input, new_index) {
+                        return CommandResult::Error("Invalid characters after period!".to_string(), i);
+                    }
+                    state.period = match period.to_usize() {
+                        Some(period) if period >= 1 => Some(period),
+                        _ => {
+                            return CommandResult::Error(
+                                "Period must be at least 1 digit!".to_string(),
+                                arg_index,
+                            )
+                        }
+                    };
+                }
+            }
+            CommandResult::Success(match state.period {
+                Some(period) => format!(
+                    "Showing repeating periods up to {} digits.",
+                    format_int(period, state.base as usize, state.balanced)
+                ),
+                None => "Repeating periods off.".to_string(),
+            })
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"luhn") => {
+            if let Some(i) = find_trailing(input, index + 4) {
+                return CommandResult::Error("Invalid characters after command!".to_string(), i);
+            }
+            let n = match integer_real_part(&state.prev_result) {
+                Some(n) if n >= 0 => n,
+                _ => {
+                    return CommandResult::Error(
+                        "Luhn needs a non-negative integer previous result!".to_string(),
+                        index,
+                    )
+                }
+            };
+            let digits = integer_digits(&n, state.base);
+            if luhn_sum(&digits, state.base) == 0 {
+                CommandResult::Success("Luhn checksum valid.".to_string())
+            } else {
+                // The check digit that would make the payload valid
+                let mut payload = digits[..digits.len() - 1].to_vec();
+                payload.push(0);
+                let check = (state.base - luhn_sum(&payload, state.base)) % state.base;
+                CommandResult::Success(format!(
+                    "Luhn checksum invalid, check digit should be {}.",
+                    format_int(check as usize, state.base as usize, state.balanced)
+                ))
+            }
+        }
+        s if s.len() >= 10 && s[..10].eq_ignore_ascii_case(b"checkdigit") => {
+            let (modulus, new_index) = match parse_integer_argument(input, index + 10, state) {
+                Ok(arg) => arg,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if let Some(i) = find_trailing(input, new_index) {
+                return CommandResult::Error("Invalid characters after modulus!".to_string(), i);
+            }
+            if modulus < 2 {
+                return CommandResult::Error(
+                    "Modulus must be at least 2!".to_string(),
+                    index + 10,
+                );
+            }
+            let n = match integer_real_part(&state.prev_result) {
+                Some(n) if n >= 0 => n,
+                _ => {
+                    return CommandResult::Error(
+                        "Check digit needs a non-negative integer previous result!".to_string(),
+                        index,
+                    )
+                }
+            };
+            // A modulus above the base takes as many check digits as it has itself
+            let mut places = 1;
+            let mut scale = Integer::from(state.base);
+            while scale < modulus {
+                scale *= state.base;
+                places += 1;
+            }
+            // Appending the check digits makes the whole number divisible by the modulus
+            let shifted = n * scale;
+            let check = (modulus.clone() - shifted.modulo(&modulus)).modulo(&modulus);
+            CommandResult::Success(format!(
+                "Check digit{}: {:0>places$}.",
+                if places == 1 { "" } else { "s" },
+                format_integer(&check, state.base),
+                places = places
+            ))
+        }
+        s if s.len() >= 9 && s[..9].eq_ignore_ascii_case(b"numdigits") => {
+            let mut base_index = index + 9;
+            while base_index < input.len()
+                && (input[base_index] == b' ' || input[base_index] == b'_' || input[base_index] == b'\t')
+            {
+                base_index += 1;
+            }
+            let target_base = match parse_base_digit(input, base_index) {
+                Ok(base) => base,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if let Some(i) = find_trailing(input, base_index + 1) {
+                return CommandResult::Error("Invalid characters after base value!".to_string(), i);
+            }
+            let n = match integer_real_part(&state.prev_result) {
+                Some(n) => n,
+                None => {
+                    return CommandResult::Error(
+                        "Previous result has no integer part!".to_string(),
+                        index,
+                    )
+                }
+            };
+            let count = integer_digits(&n, target_base).len();
+            CommandResult::Success(format!(
+                "{} {} digit{}.",
+                format_int(count, state.base as usize, state.balanced),
+                get_base_name(target_base).unwrap().to_ascii_lowercase(),
+                if count == 1 { "" } else { "s" }
+            ))
+        }
+        s if s.len() >= 10 && s[..10].eq_ignore_ascii_case(b"fracdigits") => {
+            let (places, new_index) = match parse_integer_argument(input, index + 10, state) {
+                Ok(arg) => arg,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if let Some(i) = find_trailing(input, new_index) {
+                return CommandResult::Error("Invalid characters after digit count!".to_string(), i);
+            }
+            let places = match places.to_usize() {
+                Some(places) => places,
+                None => {
+                    return CommandResult::Error(
+                        "Digit count must be a non-negative integer!".to_string(),
+                        index + 10,
+                    )
+                }
+            };
+            if places > MAX_PLAIN_PLACES {
+                return CommandResult::Error(
+                    format!(
+                        "Digit count must be at most {}!",
+                        format_int(MAX_PLAIN_PLACES, state.base as usize, state.balanced)
+                    ),
+                    index + 10,
+                );
+            }
+            let num = &state.prev_result;
+            if !num.real().is_finite() || !num.imag().is_finite() {
+                return CommandResult::Error("Previous result is NaN!".to_string(), index);
+            }
+            let real = format_fixed(num.real(), places, state.base);
+            let mut message = if num.imag().is_zero() {
+                real
+            } else {
+                format!("[{} , {}]", real, format_fixed(num.imag(), places, state.base))
+            };
+            // Digits the working precision can actually vouch for after the point
+            let magnitude = num.real().clone().abs().max(&num.imag().clone().abs());
+            let integer_digits = match magnitude.to_integer() {
+                Some(n) if n > 0 => integer_digits(&n, state.base).len(),
+                _ => 0,
+            };
+            let available = ((state.precision - state.padding) as f64
+                / (state.base as f64).log2())
+            .floor() as usize;
+            let available = available.saturating_sub(integer_digits);
+            if places > available {
+                message.push_str(&format!(
+                    "\nOnly {} fractional digits are within working precision.",
+                    format_int(available, state.base as usize, state.balanced)
+                ));
+            }
+            CommandResult::Success(message)
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"mobius") => {
+            let coefficients = match parse_complex_arguments(input, index + 6, state) {
+                Ok(coefficients) => coefficients,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if coefficients.len() != 4 {
+                return CommandResult::Error(
+                    "Expected four coefficients: a b c d!".to_string(),
+                    index + 6,
+                );
+            }
+            let (a, b, c, d) = (
+                coefficients[0].clone(),
+                coefficients[1].clone(),
+                coefficients[2].clone(),
+                coefficients[3].clone(),
+            );
+            if (a.clone() * &d - b.clone() * &c).is_zero() {
+                return CommandResult::Error(
+                    "Degenerate transform, a*d - b*c is zero!".to_string(),
+                    index + 6,
+                );
+            }
+            let z = state.prev_result.clone();
+            let mut output_queue = vec![a, z.clone()];
+            let mut result = apply_binary_operator(&mut output_queue, '*')
+                .and_then(|_| {
+                    output_queue.push(b);
+                    apply_binary_operator(&mut output_queue, '+')
+                })
+                .and_then(|_| {
+                    output_queue.push(c);
+                    output_queue.push(z);
+                    apply_binary_operator(&mut output_queue, '*')
+                })
+                .and_then(|_| {
+                    output_queue.push(d);
+                    apply_binary_operator(&mut output_queue, '+')
+                });
+            if result.is_ok() {
+                // The pole maps to the point at infinity
+                if output_queue[1].is_zero() {
+                    output_queue.pop();
+                    output_queue[0] = Complex::with_val(
+                        state.precision,
+                        (rug::float::Special::Infinity, 0),
+                    );
+                } else {
+                    result = apply_binary_operator(&mut output_queue, '/');
+                }
+            }
+            if let Err(msg) = result {
+                return CommandResult::Error(msg, index);
+            }
+            state.record_result(output_queue.pop().unwrap());
+            CommandResult::Success(coloured_vec_to_string(&num2string(
+                &state.prev_result,
+                state,
+            )))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"solve") => {
+            index += 5;
+            // The variable and the guess are the last two words, the expression everything before
+            let is_space = |c: u8| c == b' ' || c == b'\t';
+            let mut guess_end = input.len();
+            while guess_end > index && is_space(input[guess_end - 1]) {
+                guess_end -= 1;
+            }
+            let mut guess_start = guess_end;
+            while guess_start > index && !is_space(input[guess_start - 1]) {
+                guess_start -= 1;
+            }
+            let mut name_end = guess_start;
+            while name_end > index && is_space(input[name_end - 1]) {
+                name_end -= 1;
+            }
+            let mut name_start = name_end;
+            while name_start > index && !is_space(input[name_start - 1]) {
+                name_start -= 1;
+            }
+            let expression = String::from_utf8_lossy(&input[index..name_start]).to_string();
+            if guess_start == guess_end || name_start == name_end || expression.trim().is_empty() {
+                return CommandResult::Error(
+                    "Expected an expression, a variable and a guess!".to_string(),
+                    index,
+                );
+            }
+            if input[name_start] != b'@' {
+                return CommandResult::Error(
+                    "Expected an expression, a variable and a guess!".to_string(),
+                    name_start,
+                );
+            }
+            let name = String::from_utf8_lossy(&input[name_start + 1..name_end]).to_ascii_lowercase();
+            if name.is_empty()
+                || !name.as_bytes()[0].is_ascii_alphabetic()
+                || !name.bytes().all(|c| c.is_ascii_alphanumeric())
+            {
+                return CommandResult::Error("Invalid variable name!".to_string(), name_start);
+            }
+            let guess_str = String::from_utf8_lossy(&input[guess_start..guess_end]).to_string();
+            let guess = match evaluate_slice(&guess_str, guess_start, state) {
+                Ok(guess) => guess,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            let result = with_bound_variable(&expression, &name, state, |tokens, state| {
+                solve_root(tokens, guess, state)
+            });
+            let (root, residual) = match result {
+                Ok(solution) => solution,
+                Err(msg) => return CommandResult::Error(msg, index),
+            };
+            let message = format!(
+                "@{} = {}, residual {}",
+                name,
+                format_complex(&root, state),
+                format_complex(&residual, state)
+            );
+            state.record_result(root);
+            CommandResult::Success(message)
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"note") => {
+            let (name, start, end) = read_variable_name(input, index + 4);
+            if name.is_empty() {
+                return CommandResult::Error("Expected a variable to note!".to_string(), start);
+            }
+            let Some(pos) = state.variables.iter().position(|v| v.name.to_ascii_lowercase() == name) else {
+                return CommandResult::Error(format!("Undefined variable '{}'!", name), start);
+            };
+            let rest = String::from_utf8_lossy(&input[end..]).trim().to_string();
+            if rest.is_empty() {
+                return CommandResult::Success(match &state.variables[pos].note {
+                    Some(note) => format!("@{}: {}", name, note),
+                    None => format!("@{} has no note.", name),
+                });
+            }
+            if rest.eq_ignore_ascii_case("off") {
+                state.variables[pos].note = None;
+                return CommandResult::Success(format!("Removed the note on @{}.", name));
+            }
+            let note = match rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+                Some(note) if !note.is_empty() && !note.contains('"') => note.to_string(),
+                _ => return CommandResult::Error("Expected a note in quotes!".to_string(), end),
+            };
+            state.variables[pos].note = Some(note);
+            CommandResult::Success(format!("Noted @{}.", name))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"vars") => {
+            if let Some(i) = find_trailing(input, index + 4) {
+                return CommandResult::Error("Invalid characters after :vars!".to_string(), i);
+            }
+            if state.variables.is_empty() {
+                return CommandResult::Success("No variables defined.".to_string());
+            }
+            let listing: Vec<String> = state
+                .variables
+                .iter()
+                .map(|variable| {
+                    let value = match &variable.list {
+                        Some(elements) => {
+                            let elements: Vec<String> =
+                                elements.iter().map(|element| format_complex(element, state)).collect();
+                            format!("{{ {} }}", elements.join(", "))
+                        }
+                        None => format_complex(&variable.value, state),
+                    };
+                    let mut line = format!("@{} = {}", variable.name, value);
+                    if let Some(formula) = &variable.formula {
+                        line.push_str(&format!(" := {}", formula));
+                    }
+                    if variable.locked {
+                        line.push_str("  (locked)");
+                    }
+                    if let Some(note) = &variable.note {
+                        line.push_str(&format!("  \"{}\"", note));
+                    }
+                    line
+                })
+                .collect();
+            CommandResult::Success(listing.join("\n"))
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"lock")
+            || s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"unlock") =>
+        {
+            let lock = s[..4].eq_ignore_ascii_case(b"lock");
+            let (name, start, end) = read_variable_name(input, index + if lock { 4 } else { 6 });
+            if name.is_empty() {
+                // Bare `:lock` lists the locked variables
+                if !lock || find_trailing(input, start).is_some() {
+                    return CommandResult::Error("Expected a variable name!".to_string(), start);
+                }
+                let listing: Vec<String> = state
+                    .variables
+                    .iter()
+                    .filter(|variable| variable.locked)
+                    .map(|variable| format!("@{}", variable.name))
+                    .collect();
+                if listing.is_empty() {
+                    return CommandResult::Success("No variables are locked.".to_string());
+                }
+                return CommandResult::Success(format!("Locked: {}", listing.join(", ")));
+            }
+            if let Some(i) = find_trailing(input, end) {
+                return CommandResult::Error("Invalid characters after variable name!".to_string(), i);
+            }
+            let Some(pos) = state.variables.iter().position(|v| v.name.to_ascii_lowercase() == name) else {
+                return CommandResult::Error(format!("Undefined variable '{}'!", name), start);
+            };
+            state.variables[pos].locked = lock;
+            CommandResult::Success(format!("@{} {}.", name, if lock { "locked" } else { "unlocked" }))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"unset") => {
+            let (name, start, end) = read_variable_name(input, index + 5);
+            if name.is_empty() {
+                return CommandResult::Error("Expected a variable to unset!".to_string(), start);
+            }
+            if let Some(i) = find_trailing(input, end) {
+                return CommandResult::Error("Invalid characters after variable name!".to_string(), i);
+            }
+            match state.variables.iter().position(|v| v.name.to_ascii_lowercase() == name) {
+                Some(pos) if state.variables[pos].locked => {
+                    CommandResult::Error(format!("@{} is locked, :unlock it to remove it!", name), start)
+                }
+                Some(pos) => {
+                    state.variables.remove(pos);
+                    CommandResult::Success(format!("Removed @{}.", name))
+                }
+                None => CommandResult::Error(format!("Undefined variable '{}'!", name), start),
+            }
+        }
+        s if s.len() >= 6 && s[..6].eq_ignore_ascii_case(b"rename") => {
+            let (old, old_start, old_end) = read_variable_name(input, index + 6);
+            let (new, new_start, new_end) = read_variable_name(input, old_end);
+            if old.is_empty() || new.is_empty() {
+                return CommandResult::Error(
+                    "Expected the old and new names!".to_string(),
+                    if old.is_empty() { old_start } else { new_start },
+                );
+            }
+            if let Some(i) = find_trailing(input, new_end) {
+                return CommandResult::Error("Invalid characters after the new name!".to_string(), i);
+            }
+            let Some(pos) = state.variables.iter().position(|v| v.name.to_ascii_lowercase() == old) else {
+                return CommandResult::Error(format!("Undefined variable '{}'!", old), old_start);
+            };
+            if !new.as_bytes()[0].is_ascii_alphabetic() {
+                return CommandResult::Error("Variable names must start with a letter!".to_string(), new_start);
+            }
+            if CONSTANTS.iter().any(|(built_in, ..)| built_in.eq_ignore_ascii_case(&format!("@{}", new))) {
+                return CommandResult::Error(format!("@{} is already built in!", new), new_start);
+            }
+            if state.constants.iter().any(|c| c.name == new) {
+                return CommandResult::Error(format!("@{} is a constant!", new), new_start);
+            }
+            if state.variables.iter().any(|v| v.name.to_ascii_lowercase() == new) {
+                return CommandResult::Error(format!("@{} already exists!", new), new_start);
+            }
+            state.variables[pos].name = new.clone();
+            // Formulas and watches follow the variable to its new name
+            for variable in &mut state.variables {
+                if let Some(formula) = &variable.formula {
+                    variable.formula = Some(rename_references(formula, &old, &new));
+                }
+            }
+            for watch in &mut state.watches {
+                *watch = rename_references(watch, &old, &new);
+            }
+            CommandResult::Success(format!("Renamed @{} to @{}.", old, new))
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"watch") => {
+            let expression = String::from_utf8_lossy(&input[index + 5..]).trim().to_string();
+            if expression.is_empty() {
+                if state.watches.is_empty() {
+                    return CommandResult::Success("Nothing is being watched.".to_string());
+                }
+                return CommandResult::Success(format!("Watching {}", state.watches.join(", ")));
+            }
+            if expression.eq_ignore_ascii_case("clear") {
+                state.watches.clear();
+                return CommandResult::Success("Watches cleared.".to_string());
+            }
+            if expression.starts_with(':') {
+                return CommandResult::Error("Commands can't be watched!".to_string(), index + 5);
+            }
+            let message = format!("Watching {}", expression);
+            if !state.watches.contains(&expression) {
+                state.watches.push(expression);
+            }
+            CommandResult::Success(message)
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"spark") => {
+            let (count, new_index) = match parse_integer_argument(input, index + 5, state) {
+                Ok(arg) => arg,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            if let Some(i) = find_trailing(input, new_index) {
+                return CommandResult::Error("Invalid characters after result count!".to_string(), i);
+            }
+            let count = match count.to_usize() {
+                Some(count) if count > 0 => count,
+                _ => {
+                    return CommandResult::Error(
+                        "Result count must be a positive integer!".to_string(),
+                        index + 5,
+                    )
+                }
+            };
+            if state.results.is_empty() {
+                return CommandResult::Error("No results yet!".to_string(), index + 5);
+            }
+            let recent = &state.results[state.results.len().saturating_sub(count)..];
+            CommandResult::Success(sparkline(recent, state))
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"fractal") => {
+            index += 7;
+            while index < input.len() && (input[index] == b' ' || input[index] == b'\t') {
+                index += 1;
+            }
+            let julia = input.len() >= index + 5 && input[index..index + 5].eq_ignore_ascii_case(b"julia");
+            if julia {
+                index += 5;
+            }
+            let mut arguments = match parse_complex_arguments(input, index, state) {
+                Ok(arguments) => arguments,
+                Err((msg, pos)) => return CommandResult::Error(msg, pos),
+            };
+            let c = if julia {
+                if arguments.is_empty() {
+                    return CommandResult::Error("Expected the Julia constant!".to_string(), index);
+                }
+                Some(arguments.remove(0))
+            } else {
+                None
+            };
+            if arguments.len() > 2 {
+                return CommandResult::Error("Expected a center and a zoom!".to_string(), index);
+            }
+            let center = arguments.first().cloned().unwrap_or_else(|| {
+                let re = if julia { 0.0 } else { -0.5 };
+                Complex::with_val(state.precision, (re, 0))
+            });
+            let zoom = arguments.get(1).cloned().unwrap_or_else(|| Complex::with_val(state.precision, 1));
+            if !zoom.imag().is_zero() || *zoom.real() <= 0 || !zoom.real().is_finite() {
+                return CommandResult::Error("The zoom must be real and above zero!".to_string(), index);
+            }
+            match render_fractal(&center, zoom.real(), c.as_ref(), state) {
+                Ok(plot) => CommandResult::Success(plot),
+                Err(msg) => CommandResult::Error(msg, index),
+            }
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"cplot") => {
+            index += 5;
+            let text = String::from_utf8_lossy(&input[index..]).to_string();
+            let usage = "Expected an expression and a variable!";
+            let (head, corners) = match (find_word(&text, "from"), find_word(&text, "to")) {
+                (None, None) => (text.as_str(), None),
+                (Some(from), Some(to)) if from < to => (&text[..from], Some((from, to))),
+                _ => return CommandResult::Error("Expected from and to!".to_string(), index),
+            };
+            let (expression, name) = match split_bound_variable(head, usage) {
+                Ok(split) => split,
+                Err((msg, pos)) => return CommandResult::Error(msg, index + pos),
+            };
+            let (mut low, mut high) = (
+                Complex::with_val(state.precision, (-2, -2)),
+                Complex::with_val(state.precision, (2, 2)),
+            );
+            if let Some((from, to)) = corners {
+                let mut ends = Vec::new();
+                for (start, end) in [(from + 4, to), (to + 2, text.len())] {
+                    match evaluate_slice(&text[start..end], index + start, state) {
+                        Ok(value) if value.real().is_finite() && value.imag().is_finite() => ends.push(value),
+                        Ok(_) => {
+                            return CommandResult::Error(
+                                "The corners must be finite!".to_string(),
+                                index + start,
+                            )
+                        }
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    }
+                }
+                high = ends.pop().unwrap();
+                low = ends.pop().unwrap();
+                if low.real() >= high.real() || low.imag() >= high.imag() {
+                    return CommandResult::Error(
+                        "The corners must run from lower left to upper right!".to_string(),
+                        index + from,
+                    );
+                }
+            }
+            let plot = with_bound_variable(expression, &name, state, |tokens, state| {
+                cplot_function(tokens, &low, &high, state)
+            });
+            match plot {
+                Ok(plot) => CommandResult::Success(plot),
+                Err(msg) => CommandResult::Error(msg, index),
+            }
+        }
+        s if s.len() >= 4 && s[..4].eq_ignore_ascii_case(b"plot") => {
+            index += 4;
+            let text = String::from_utf8_lossy(&input[index..]).to_string();
+            let usage = "Expected an expression, a variable, from and to!";
+            let (from, to) = match (find_word(&text, "from"), find_word(&text, "to")) {
+                (Some(from), Some(to)) if from < to => (from, to),
+                _ => return CommandResult::Error(usage.to_string(), index),
+            };
+            let (expression, name) = match split_bound_variable(&text[..from], usage) {
+                Ok(split) => split,
+                Err((msg, pos)) => return CommandResult::Error(msg, index + pos),
+            };
+            let mut ends = Vec::new();
+            for (start, end) in [(from + 4, to), (to + 2, text.len())] {
+                match evaluate_slice(&text[start..end], index + start, state) {
+                    Ok(value) if value.imag().is_zero() && value.real().is_finite() => {
+                        ends.push(value.real().clone())
+                    }
+                    Ok(_) => {
+                        return CommandResult::Error(
+                            "The range must be real!".to_string(),
+                            index + start,
+                        )
+                    }
+                    Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                }
+            }
+            if ends[0] >= ends[1] {
+                return CommandResult::Error(
+                    "The range must run from low to high!".to_string(),
+                    index + from,
+                );
+            }
+            let plot = with_bound_variable(expression, &name, state, |tokens, state| {
+                plot_function(tokens, &ends[0], &ends[1], state)
+            });
+            match plot {
+                Ok(plot) => CommandResult::Success(plot),
+                Err(msg) => CommandResult::Error(msg, index),
+            }
+        }
+        s if s.len() >= 7 && s[..7].eq_ignore_ascii_case(b"epsilon") => {
+            index += 7;
+            let rest = &input[index..];
+            let trimmed = rest.iter().position(|&c| c != b' ' && c != b'\t');
+            match trimmed {
+                None => {}
+                Some(start) if rest[start..].eq_ignore_ascii_case(b"auto") => {
+                    state.epsilon = None;
+                }
+                Some(_) => {
+                    let arguments = match parse_complex_arguments(input, index, state) {
+                        Ok(arguments) => arguments,
+                        Err((msg, pos)) => return CommandResult::Error(msg, pos),
+                    };
+                    let epsilon = &arguments[0];
+                    if arguments.len() != 1
+                        || !epsilon.imag().is_zero()
+                        || !epsilon.real().is_finite()
+                        || *epsilon.real() <= 0
+                    {
+                        return CommandResult::Error(
+                            "Epsilon must be a positive real number!".to_string(),
+                            index,
+                        );
+                    }
+                    state.epsilon = Some(epsilon.real().clone());
+                }
+            }
+            CommandResult::Success(match &state.epsilon {
+                Some(epsilon) => format!("Epsilon set to {}.", format_real(epsilon, state)),
+                None => format!(
+                    "Epsilon is automatic, currently {}.",
+                    format_real(&default_epsilon(state), state)
+                ),
+            })
+        }
+        s if s.eq_ignore_ascii_case(b"help") => {
+            let help_text = get_help_text(&state);
+            for line in help_text {
+                print!("{}", line);
+            }
+            println!("\n");
+            print_settings(state);
+            CommandResult::Silent
+        }
+        s if s.len() >= 5 && s[..5].eq_ignore_ascii_case(b"debug") => {
+            // Toggle debug mode
+            let new_state = !DEBUG.load(Ordering::Relaxed);
+            DEBUG.store(new_state, Ordering::Relaxed);
+            CommandResult::Success(format!(
+                "Debug {}",
+                if new_state { "enabled" } else { "disabled" }
+            ))
+        }
+        _ => CommandResult::Error("Unknown command!".to_string(), index),
+    }
+}
+/// Parses a single base digit as used by `:base`, where 0 stands for 36 (Z+1)
+///
+/// # Returns
+/// * `Ok(u8)` - The base, between 2 and 36
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_base_digit(input: &[u8], index: usize) -> Result<u8, (String, usize)> {
+    if index >= input.len() {
+        return Err(("Missing base value!".to_string(), index));
+    }
+
+    let digit = input[index];
+    let new_base = if digit.is_ascii_digit() {
+        digit - b'0'
+    } else if digit.is_ascii_uppercase() {
+        digit - b'A' + 10
+    } else if digit.is_ascii_lowercase() {
+        digit - b'a' + 10
+    } else {
+        return Err(("Invalid base value!".to_string(), index));
+    };
+    if new_base == 1 || new_base > 36 {
+        return Err((
+            "Base must be between 2 and 36!\nUse ':base 0' for base 36 (Z+1)".to_string(),
+            index,
+        ));
+    }
+    Ok(if new_base == 0 { 36 } else { new_base })
+}
+/// Reads a variable name for a command, the `@` optional, skipping spaces before it
+///
+/// # Returns
+/// * `(String, usize, usize)` - The lowercased name, where it starts and the index after it
+fn read_variable_name(input: &[u8], mut index: usize) -> (String, usize, usize) {
+    while index < input.len() && (input[index] == b' ' || input[index] == b'\t') {
+        index += 1;
+    }
+    if index < input.len() && input[index] == b'@' {
+        index += 1;
+    }
+    let start = index;
+    while index < input.len() && input[index].is_ascii_alphanumeric() {
+        index += 1;
+    }
+    (String::from_utf8_lossy(&input[start..index]).to_ascii_lowercase(), start, index)
+}
+/// Rewrites every `@old` in an expression as `@new`, leaving other names alone
+fn rename_references(text: &str, old: &str, new: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = String::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        index += 1;
+        // Everything up to the next @ is copied whole, whatever characters it holds
+        if bytes[start] != b'@' || text[start..].to_ascii_lowercase().starts_with("@phys.") {
+            while index < bytes.len() && bytes[index] != b'@' {
+                index += 1;
+            }
+            result.push_str(&text[start..index]);
+            continue;
+        }
+        // Spaces and underscores inside a name are skipped, as `parse_constant` does
+        let mut name = String::new();
+        let mut end = index;
+        while index < bytes.len() && (bytes[index].is_ascii_alphanumeric() || matches!(bytes[index], b' ' | b'_' | b'\t')) {
+            if bytes[index].is_ascii_alphanumeric() {
+                name.push(bytes[index].to_ascii_lowercase() as char);
+                end = index + 1;
+            }
+            index += 1;
+        }
+        if name == old {
+            result.push('@');
+            result.push_str(new);
+        } else {
+            result.push_str(&text[start..end]);
+        }
+        index = end;
+    }
+    result
+}
+/// Parses a real integer argument of a command in the current base
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The index where the argument may start
+/// * `state` - The current calculator state
+///
+/// # Returns
+/// * `Ok((Integer, usize))` - The parsed integer and the index after it
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_integer_argument(
+    input: &[u8],
+    index: usize,
+    state: &mut BasecalcState,
+) -> Result<(Integer, usize), (String, usize)> {
+    let (token, new_index) = parse_number(input, state.base, state.balanced, index)?;
+    if !token.real_fraction.is_empty()
+        || !token.imaginary_integer.is_empty()
+        || !token.imaginary_fraction.is_empty()
+    {
+        return Err(("Argument must be a real integer!".to_string(), index));
+    }
+    match integer_real_part(&token2num(&token, state)) {
+        Some(value) => Ok((value, new_index)),
+        None => Err(("Argument must be a real integer!".to_string(), index)),
+    }
+}
+/// Parses whitespace separated number or constant arguments of a command
+///
+/// Spaces inside square brackets belong to the complex number, so
+/// `1 [0, -1] @pi` yields three arguments.
+///
+/// # Arguments
+/// * `input` - The input byte slice
+/// * `index` - The index where the arguments start
+/// * `state` - The current calculator state
+///
+/// # Returns
+/// * `Ok(Vec<Complex>)` - The parsed arguments in order
+/// * `Err((String, usize))` - An error message and the position of the error
+fn parse_complex_arguments(
+    input: &[u8],
+    mut index: usize,
+    state: &mut BasecalcState,
+) -> Result<Vec<Complex>, (String, usize)> {
+    let mut arguments = Vec::new();
+    loop {
+        while index < input.len() && (input[index] == b' ' || input[index] == b'\t') {
+            index += 1;
+        }
+        if index >= input.len() {
+            return Ok(arguments);
+        }
+        let mut end = index;
+        let mut depth = 0;
+        while end < input.len() && (depth > 0 || (input[end] != b' ' && input[end] != b'\t')) {
+            match input[end] {
+                b'[' => depth += 1,
+                b']' if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            end += 1;
+        }
+        let argument = &input[..end];
+        let (token, new_index) = match parse_constant(argument, index, state) {
+            Ok(parsed) => parsed,
+            Err(_) => parse_number(argument, state.base, state.balanced, index)?,
+        };
+        if let Some(i) = find_trailing(argument, new_index) {
+            return Err(("Invalid argument!".to_string(), i));
+        }
+        arguments.push(token2num(&token, state));
+        index = end;
+    }
+}
+/// Finds the first non-whitespace character at or after `index`
+///
+/// # Returns
+/// * `Some(usize)` - The position of the unexpected character
+/// * `None` - If only spaces, tabs and underscores remain
+fn find_trailing(input: &[u8], index: usize) -> Option<usize> {
+    (index..input.len()).find(|&i| input[i] != b' ' && input[i] != b'_' && input[i] != b'\t')
+}
+fn get_help_text(global_state: &BasecalcState) -> Vec<ColoredString> {
+    let mut local_state = global_state.clone();
+    let mut help_text: Vec<ColoredString> = Vec::new();
+
+    // Geeky Intro
+    help_text.push("Welcome to basecalc!\n".truecolor(
+        local_state.colours.decimal.0,
+        local_state.colours.decimal.1,
+        local_state.colours.decimal.2,
+    ));
+    help_text.push("
+Greetings, intrepid mathematical explorer!  This isn't just any ordinary number-crunching gizmo - it's your towel in the cosmos!
+
+Whether you're calculating the odds of successfully navigating an asteroid field, determining the exact amount of Pangalactic Gargleblasters needed for a party of trans-dimensional beings, or just trying to split the bill at the Restaurant at the End of the Universe, basecalc has got you covered!
+
+Remember, DON'T PANIC! With basecalc, you're always just a few keystrokes away from mathematical enlightenment. So grab your towel, keep your wits about you, and prepare to compute where no one has computed before!
+".normal());
+
+    // Commands
+    help_text.push("\nCommands:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    let commands = [
+        (
+            ":base ",
+            "<digit>  ",
+            "Set number base (2 to Z+1, 0 for Z+1)",
+        ),
+        (":base ", "<digit> balanced", "Odd base with digits Z, Y, ... for -1, -2, ..."),
+        (":base ", "<@phi|@e>", "Show results in an irrational base (experimental)"),
+        (":digits ", "<value>", "Adjust display precision"),
+        (":digitcase ", "<upper|lower>", "Write digits from ten up as A B C or a b c"),
+        (":digitset ", "<standard|dozenal>", "Show ten and eleven in base 12 as A B or ↊ ↋"),
+        (
+            ":radians       ",
+            "",
+            "Switch to radians (for the cool kids)",
+        ),
+        (":degrees       ", "", "Switch to degrees (if you must)"),
+        (":gradians      ", "", "Switch to gradians, 400 to a turn"),
+        (":help          ", "", "You're looking at it!"),
+        (":debug         ", "", "Toggle inspection mode"),
+        (":test          ", "", "Ensure calculator isn't a lemon"),
+        (":collatz       ", "", "Collatz steps and peak of previous result"),
+        (":pascal ", "<rows>   ", "Print rows of Pascal's triangle"),
+        (":acc ", "<op|off>   ", "Combine each result into a running tally"),
+        (":luhn          ", "", "Verify Luhn checksum of previous result"),
+        (":checkdigit ", "<n>", "Check digits making previous result divisible by n"),
+        (":numdigits ", "<digit>", "Count digits of previous result in another base"),
+        (":fracdigits ", "<n>", "Show previous result with exactly n fractional digits"),
+        (":hms           ", "", "Previous result in seconds as days hours:minutes:seconds"),
+        (":mobius ", "<a b c d>", "Apply (a*z+b)/(c*z+d) to previous result"),
+        (":vars          ", "", "List the variables with their formulas and notes"),
+        (":note ", "<name> <\"text\"|off>", "Describe a variable, shown by :vars and saved with it"),
+        (":unset ", "<name>", "Remove a variable"),
+        (":lock ", "<name>", "Refuse to change a variable until :unlock"),
+        (":rename ", "<old> <new>", "Give a variable a new name, formulas and watches following it"),
+        (":watch ", "<expr|clear>", "Show the expression's value after every line"),
+        (":spark ", "<count>", "Sparkline of the magnitudes of the last so many results"),
+        (":fractal ", "<julia c> <center> <zoom>", "Render the Mandelbrot set, or a Julia set, at the working precision"),
+        (":cplot ", "<expr @z from a to b>", "Domain colour the expression over a rectangle of the complex plane"),
+        (":plot ", "<expr @x from a to b>", "Draw the expression over a range in braille"),
+        (":solve ", "<expr @x guess>", "Find a root of expr near the guess"),
+        (":epsilon ", "<value|auto>", "Tolerance for 'actual =~ expected' checks"),
+        (":width ", "<bits|off>", "Also show results as wrapped fixed-width integers"),
+        (":repeat ", "<digits|off>", "Show repeating fractions like 0.1(6) up to that period"),
+        (":bits ", "<f32|f64>", "IEEE-754 fields and rounding error of previous result"),
+        (":show ", "<digits>", "Previous result in several bases at once"),
+        (":copy ", "<hex|plain>", "Previous result onto the clipboard"),
+        (":latex ", "<input>", "The previous result, or the last expression and it, as LaTeX"),
+        (":interval      ", "", "Toggle guaranteed error bounds on results"),
+        (":polar         ", "", "Toggle showing complex results as [magnitude; angle]"),
+        (":mouse         ", "", "Toggle clicking in the entry to move the cursor"),
+        (":json ", "<on|off>", "Show results as lines of JSON for other programs"),
+        (":history ", "<max count>", "How many entries the history keeps"),
+        (":export history ", "<file>", "Write the history to a text file, one entry a line"),
+        (":export md ", "<file>", "Write this session's lines and results as Markdown"),
+        (":export csv ", "<file>", "Write the last list result as a spreadsheet, a row each"),
+        (":import ", "<file>", "Add the lines of a text file to the history"),
+        (":record ", "<file>", "Add each line and its results to a transcript file"),
+        (":stop          ", "", "Stop recording the transcript"),
+        (":replay ", "<file>", "Re-run a transcript, stopping where results differ"),
+        (":save ", "<name> <count>", "Keep the last few history entries as a sequence"),
+        (":run ", "<name>", "Run a saved sequence on the current variables"),
+        (":workspace ", "<save|load> <name>", "Keep or switch to a whole separate state"),
+        (":expthreshold ", "<low> <high>|off", "Write out numbers from base^low to base^high"),
+        (":fixed ", "<places|off>", "Always show that many digits after the point"),
+        (":group ", "<size|off> <space|underscore|comma> <all|integer>", "Set how result digits are grouped"),
+        (":theme ", "<name>   ", "Switch colour theme, or list them"),
+        (":color ", "<on|off|auto>", "Force coloured output on or off"),
+        (":const ", "<name value>", "Define a saved constant, or list them"),
+        (":constants     ", "", "Physical constants available as @phys.<name>"),
+    ];
+
+    for (cmd, alt, desc) in commands.iter() {
+        help_text.push(format!("  {}", cmd).truecolor(
+            local_state.colours.lone_integer.0,
+            local_state.colours.lone_integer.1,
+            local_state.colours.lone_integer.2,
+        ));
+        help_text.push(alt.truecolor(
+            local_state.colours.nan.0,
+            local_state.colours.nan.1,
+            local_state.colours.nan.2,
+        ));
+        help_text.push(format!(" - {}\n", desc).truecolor(
+            local_state.colours.lone_fraction.0,
+            local_state.colours.lone_fraction.1,
+            local_state.colours.lone_fraction.2,
+        ));
+    }
+
+    // Constants
+    help_text.push("\nConstants:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    for &(name, symbol, description) in CONSTANTS.iter() {
+        let token = Token {
+            operator: symbol,
+            ..Token::new()
+        };
+        let value = token2num(&token, &mut local_state);
+        let value_string = num2string(&value, &local_state);
+
+        help_text.push(format!("  {:<7}", name).truecolor(
+            local_state.colours.lone_integer.0,
+            local_state.colours.lone_integer.1,
+            local_state.colours.lone_integer.2,
+        ));
+        help_text.push(format!("- {} ", description).truecolor(
+            local_state.colours.lone_fraction.0,
+            local_state.colours.lone_fraction.1,
+            local_state.colours.lone_fraction.2,
+        ));
+        for part in value_string {
+            help_text.push(part);
+        }
+        help_text.push("\n".truecolor(
+            local_state.colours.brackets.0,
+            local_state.colours.brackets.1,
+            local_state.colours.brackets.2,
+        ));
+    }
+
+    // Operators and Functions
+    help_text.push("\nUnary Operators:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    for &(name, _, operands, description) in OPERATORS.iter() {
+        if operands == 1 && name != "(" && name != ")" {
+            help_text.push(format!("  {:<8}", name).truecolor(
+                local_state.colours.lone_integer.0,
+                local_state.colours.lone_integer.1,
+                local_state.colours.lone_integer.2,
+            ));
+            let capitalized_description = description[0..1].to_uppercase() + &description[1..];
+            help_text.push(format!("- {}\n", capitalized_description).truecolor(
+                local_state.colours.lone_fraction.0,
+                local_state.colours.lone_fraction.1,
+                local_state.colours.lone_fraction.2,
+            ));
+        }
+    }
+
+    help_text.push(format!("  {:<8}", "x!").truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Factorial\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+
+    help_text.push("\nBinary Operators:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    for &(name, _, operands, description) in OPERATORS.iter() {
+        if operands == 2 {
+            help_text.push(format!("  {:<7}", name).truecolor(
+                local_state.colours.lone_integer.0,
+                local_state.colours.lone_integer.1,
+                local_state.colours.lone_integer.2,
+            ));
+            let capitalized_description = description[0..1].to_uppercase() + &description[1..];
+            help_text.push(format!("- {}\n", capitalized_description).truecolor(
+                local_state.colours.lone_fraction.0,
+                local_state.colours.lone_fraction.1,
+                local_state.colours.lone_fraction.2,
+            ));
+        }
+    }
+
+    // Grouping
+    help_text.push("\nGrouping:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    help_text.push("  ( )   ".truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Parentheses for grouping expressions\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+
+    // Variable assignment and usage
+    help_text.push("\nVariables:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    help_text.push("  @name=value  ".truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Assign value to variable\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+    help_text.push("  @name        ".truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Use variable in expression\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+    help_text.push("  @f(x)=expr   ".truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Define function with parameters\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+    help_text.push("  @f(value)    ".truecolor(
+        local_state.colours.lone_integer.0,
+        local_state.colours.lone_integer.1,
+        local_state.colours.lone_integer.2,
+    ));
+    help_text.push("- Call function\n".truecolor(
+        local_state.colours.lone_fraction.0,
+        local_state.colours.lone_fraction.1,
+        local_state.colours.lone_fraction.2,
+    ));
+
+    // Examples
+    help_text.push("\nExamples:\n".truecolor(
+        local_state.colours.brackets.0,
+        local_state.colours.brackets.1,
+        local_state.colours.brackets.2,
+    ));
+    let examples = [
+        ("2 + 2", "The meaning of life? Not quite, but it's a start."),
+        (":base D", "Switch to base 13, because 12 bases are never enough."),
+        ("6 * 9", "In Tridecimal, this might surprise you..."),
+        ("#sin(@pi/4)", "For when your spaceship needs to make a 45, I mean 36-degree turn."),
+        ("[3, 4] * [1, -1]", "Multiplying complex numbers: it's not rocket science, but it's close."),
+        ("#sqrt-1", "The imaginary unit: i before @e, except after #sqrt."),
+        ("1/2", "But why tho?"),
+        (":base C", "Switch to base 12, see, tridecimal is weird."),
+        ("1/2", "Ah, much better."),
+        (":digits 10", "Adjust precision: for when you need to calculate the cost of a Pan Galactic Gargle Blaster to a dozen digits."),
+        ("-6^(@pi/2) * #ln-2 + #sqrtB / #sin(2*@pi)", "Looks complex? That's because it is!"),
+        (":base A", "Back to decimal. Phew!"),
+        ("42", "The Answer. But what was the Question?"),
+        ("&", "Use the previous result. Handy for building on your last calculation."),
+        ("& + 1", "The Answer plus one. For those who always need a little extra."),
+        ("@pi * 2", "Once around the universe."),
+        ("#cos(2*@pi)", "Whoa, we've gone full circle!"),
+        ("@e$@e", "Natural log of e - as natural as it gets!"),
+        ("@rand", "Random number: perfect for simulating quantum improbability."),
+        ("@grand", "Gaussian random: for when your probability needs to be normally distributed."),
+        ("#floor(3.14159)", "Rounding down: because sometimes you need to be grounded."),
+        ("@numfish=17%5", "Modulus: for when you need to know how many Babel fish are left."),
+        ("#ceil(@numfish$2)", "How many bits needed for storing the number of fish? Let's find out!"),
+        (":base G", "Hexadecimal: for the really hoopy froods."),
+        ("FF", "The darkest shade in hex, or just 255 for the less cool."),
+        ("FF$F", "And in nibbles, that's 2!"),
+        (":base A", "And we're back to decimal. What a journey!"),
+        ("&", "See?, 255.")
+    ];
+
+    for (example, desc) in examples.iter() {
+        help_text.push(format!("- {}\n", desc).truecolor(
+            local_state.colours.comma.0,
+            local_state.colours.comma.1,
+            local_state.colours.comma.2,
+        ));
+        help_text.push(format!("  {}\n", example).truecolor(
+            local_state.colours.decimal.0,
+            local_state.colours.decimal.1,
+            local_state.colours.decimal.2,
+        ));
+        if example.starts_with(':') {
+            // Handle commands
+            match parse_command(example.as_bytes(), 1, &mut local_state) {
+                CommandResult::Success(msg) => {
+                    help_text.push(format!("  {}\n", msg).truecolor(
+                        local_state.colours.message.0,
+                        local_state.colours.message.1,
+                        local_state.colours.message.2,
+                    ));
+                }
+                CommandResult::Error(msg, _) => {
+                    help_text.push(format!("  Error: {}\n", msg).truecolor(
+                        local_state.colours.error.0,
+                        local_state.colours.error.1,
+                        local_state.colours.error.2,
+                    ));
+                }
+                CommandResult::Silent => {
+                    // Do nothing for silent commands
+                }
+            }
+        } else {
+            // Handle expressions
+            match tokenize(example, &mut local_state) {
+                Ok(tokens) => {
+                    match evaluate_tokens(&tokens, &mut local_state) {
+                        Ok(result) => {
+                            help_text.push("  ".normal());
+                            let result_string = if let Some(var_idx) = result.assignment {
+                                let mut vec = vec![format!("@{} = ", local_state.variables[var_idx].name)
+                                    .truecolor(
+                                        local_state.colours.message.0,
+                                        local_state.colours.message.1,
+                                        local_state.colours.message.2,
+                                    )];
+                                vec.extend(result2string(&result, &local_state));
+                                vec
+                            } else {
+                                result2string(&result, &local_state)
+                            };
+                            for part in result_string {
+                                help_text.push(part);
+                            }
+                            if let Some(unit) = &result.unit {
+                                help_text.push(format!(" {}", unit).truecolor(
+                                    local_state.colours.message.0,
+                                    local_state.colours.message.1,
+                                    local_state.colours.message.2,
+                                ));
+                            }
+                            help_text.push("\n".normal());
+                            if result.list.is_none() {
+                                local_state.record_result(result.value); // Update local_prev_result for & usage
+                            }
+                        }
+                        Err(err) => {
+                            help_text.push(format!("  Error: {}\n", err).truecolor(
+                                local_state.colours.error.0,
+                                local_state.colours.error.1,
+                                local_state.colours.error.2,
+                            ));
+                        }
+                    }
+                }
+                Err((msg, _)) => {
+                    help_text.push(format!("  Error: {}\n", msg).truecolor(
+                        local_state.colours.error.0,
+                        local_state.colours.error.1,
+                        local_state.colours.error.2,
+                    ));
+                }
+            }
+        }
+        help_text.push("\n".normal());
+    }
+
+    help_text.push(
+        "\nFor more information, comments, neat fractal renders, questions or or why 42, contact nick spiker."
+            .normal(),
+    );
+
+    help_text
+}
+fn generate_random(precision: u32, rand_state: &mut rug::rand::RandState) -> Complex {
+    let real = Float::with_val(precision, Float::random_cont(rand_state));
+    Complex::with_val(precision, (real, 0))
+}
+fn gaussian_complex_random(precision: u32, rand_state: &mut rug::rand::RandState) -> Complex {
+    // Box-Muller transform to generate Gaussian random numbers
+    let u1 = Float::with_val(precision, Float::random_cont(rand_state));
+    let u2 = Float::with_val(precision, Float::random_cont(rand_state));
+
+    let two = Float::with_val(precision, 2);
+    let pi = Float::with_val(precision, rug::float::Constant::Pi);
+
+    let r = (Float::with_val(precision, -two.clone() * u1.ln())).sqrt();
+    let theta = two * pi * u2;
+
+    let real = &r * theta.clone().cos();
+    let imag = &r * theta.sin();
+
+    Complex::with_val(precision, (real, imag))
+}
+/// Converts a token to a complex number
+///
+/// # Arguments
+/// * `token` - The token to convert
+/// * `state` - The current calculator state
+///
+/// # Returns
+/// * `Complex` - The complex number representation of the token
+/// Converts the magnitude and angle of a `[r; θ]` literal to rectangular form
+///
+/// The angle is in the current angle units. Whole quarter turns come out exact,
+/// where going thru pi would leave a tiny cosine at 90 degrees.
+fn polar_to_complex(magnitude: Float, angle: Float, state: &BasecalcState) -> Complex {
+    let quarters = Float::with_val(state.precision, &angle * 2u32)
+        / state.angle.half_turn(state.precision);
+    if quarters.is_integer() {
+        let (real, imaginary) = match quarters.to_integer().map_or(0, |q| q.mod_u(4)) {
+            0 => (1, 0),
+            1 => (0, 1),
+            2 => (-1, 0),
+            _ => (0, -1),
+        };
+        return Complex::with_val(
+            state.precision,
+            (Float::with_val(state.precision, &magnitude * real), magnitude * imaginary),
+        );
+    }
+    let angle = angle_to_radians(Complex::with_val(state.precision, angle), state);
+    Complex::with_val(state.precision, angle * Complex::with_val(state.precision, (0, 1))).exp()
+        * magnitude
+}
+fn token2num(token: &Token, state: &mut BasecalcState) -> Complex {
+    match token.operator {
+        // User-defined constants
+        'v' => {
+            if let Some(index) = token.var_index {
+                state.variables[index].value.clone()
+            } else {
+                Complex::with_val(state.precision, 0)
+            }
+        }
+        // Angles entered as degrees, minutes and seconds, see parse_dms
+        'd' => {
+            let mut degrees = Complex::with_val(state.precision, 0);
+            for (part, scale) in token.arguments.iter().zip([1, 60, 3600]) {
+                if let Ok((number, _)) = parse_number(part.as_bytes(), state.base, state.balanced, 0) {
+                    degrees += token2num(&number, state) / scale;
+                }
+            }
+            if state.angle != AngleMode::Degrees {
+                degrees *= state.angle.half_turn(state.precision) / 180;
+            }
+            if token.sign.0 {
+                -degrees
+            } else {
+                degrees
+            }
+        }
+        'Q' => Complex::with_val(
+            state.precision,
+            physical_constant(token.var_index.unwrap_or(0), state.precision),
+        ),
+        'w' => {
+            if let Some(index) = token.var_index {
+                state.constants[index].value.clone()
+            } else {
+                Complex::with_val(state.precision, 0)
+            }
+        }
+        // Built-in constants
+        'E' => Complex::with_val(state.precision, Float::with_val(state.precision, 1).exp()),
+        'G' => Complex::with_val(state.precision, rug::float::Constant::Euler),
+        'p' => Complex::with_val(state.precision, rug::float::Constant::Pi),
+        'P' => {
+            let prec = state.precision;
+            let one = Float::with_val(prec, 1);
+            let five = Float::with_val(prec, 5);
+            let sqrt5 = five.sqrt();
+            Complex::with_val(prec, (one + sqrt5) / 2)
+        }
+        'r' => generate_random(state.precision, &mut state.rand_state),
+        'g' => gaussian_complex_random(state.precision, &mut state.rand_state),
+        'W' => {
+            let now = chrono::Utc::now();
+            let seconds = Float::with_val(state.precision, now.timestamp())
+                + Float::with_val(state.precision, now.timestamp_subsec_nanos()) / 1_000_000_000;
+            Complex::with_val(state.precision, seconds)
+        }
+        '&' => match token.var_index {
+            Some(index) => state.results[index].clone(),
+            None => state.prev_result.clone(),
+        },
+
+        // Regular numbers
+        _ => {
+            let mut real_int = Float::with_val(state.precision, 0);
+            for &digit in &token.real_integer {
+                real_int *= state.base;
+                real_int += digit_value(digit, state);
+            }
+            let mut real_frac = Float::with_val(state.precision, 0);
+            for &digit in token.real_fraction.iter().rev() {
+                real_frac += digit_value(digit, state) as f64;
+                real_frac /= state.base as f64;
+            }
+
+            let mut imag_int = Float::with_val(state.precision, 0);
+            for &digit in &token.imaginary_integer {
+                imag_int *= state.base;
+                imag_int += digit_value(digit, state);
+            }
+            let mut imag_frac = Float::with_val(state.precision, 0);
+            for &digit in token.imaginary_fraction.iter().rev() {
+                imag_frac += digit_value(digit, state) as f64;
+                imag_frac /= state.base as f64;
+            }
+
+            let mut real = Float::with_val(state.precision, &real_int + &real_frac);
+            let mut imaginary = Float::with_val(state.precision, &imag_int + &imag_frac);
+
+            if token.sign.0 {
+                real = -real;
+            }
+            if token.sign.1 {
+                imaginary = -imaginary;
+            }
+
+            if token.polar {
+                return polar_to_complex(real, imaginary, state);
+            }
+            Complex::with_val(state.precision, (real, imaginary))
+        }
+    }
+}
+/// Converts a complex number to a vector of coloured strings for display
+///
+/// # Arguments
+/// * `num` - The complex number to convert
+/// * `base` - The current number base
+/// * `digits` - The number of digits to display
+/// * `colours` - The colour scheme for output formatting
+///
+/// # Returns
+/// * `Vec<ColoredString>` - A vector of coloured strings representing the number
+pub fn num2string(num: &Complex, state: &BasecalcState) -> Vec<ColoredString> {
+    let mut result = Vec::new();
+
+    if num.real().is_nan()
+        || num.imag().is_nan()
+        || num.real().is_infinite()
+        || num.imag().is_infinite()
+    {
+        result.push("NaN".truecolor(
+            state.colours.nan.0,
+            state.colours.nan.1,
+            state.colours.nan.2,
+        ));
+        return result;
+    }
+
+    if num.imag().is_zero() {
+        result.push(" ".normal());
+        result.extend(format_part(num.real(), state, true, true));
+    } else if state.polar {
+        let magnitude = Float::with_val(state.precision, num.abs_ref());
+        let angle = angle_from_radians(Complex::with_val(state.precision, num.arg_ref()), state);
+        result.push("[".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+        result.extend(format_part(&magnitude, state, true, false));
+        result.push(" ;".truecolor(
+            state.colours.comma.0,
+            state.colours.comma.1,
+            state.colours.comma.2,
+        ));
+        result.extend(format_part(angle.real(), state, false, false));
+        result.push(" ]".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+    } else {
+        result.push("[".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+        result.extend(format_part(num.real(), state, true, false));
+        result.push(" ,".truecolor(
+            state.colours.comma.0,
+            state.colours.comma.1,
+            state.colours.comma.2,
+        ));
+        result.extend(format_part(num.imag(), state, false, false));
+        result.push(" ]".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+    }
+
+    result
+}
+/// Converts a list to a vector of coloured strings for display, as `{ 1. , 2. }`
+///
+/// Real elements are laid out like the parts of a complex number, others as
+/// `num2string` would show them on their own.
+pub fn list2string(elements: &[Complex], state: &BasecalcState) -> Vec<ColoredString> {
+    let mut result = vec!["{".truecolor(
+        state.colours.brackets.0,
+        state.colours.brackets.1,
+        state.colours.brackets.2,
+    )];
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            result.push(" ,".truecolor(
+                state.colours.comma.0,
+                state.colours.comma.1,
+                state.colours.comma.2,
+            ));
+        }
+        if element.imag().is_zero() && element.real().is_finite() {
+            result.extend(format_part(element.real(), state, true, false));
+        } else {
+            result.extend(num2string(element, state));
+        }
+    }
+    result.push(" }".truecolor(
+        state.colours.brackets.0,
+        state.colours.brackets.1,
+        state.colours.brackets.2,
+    ));
+    result
+}
+/// Everything printed for a result: any assignment, the value, its unit and error
+/// bound, then the register and call views on lines of their own
+///
+/// `interval` is what [`interval_view`] gave for the same tokens.
+pub fn result_view(
+    tokens: &[Token],
+    result: &EvalResult,
+    interval: Option<String>,
+    state: &BasecalcState,
+) -> Vec<ColoredString> {
+    if state.json {
+        return vec![result_json(result, state).normal()];
+    }
+    let message = |text: String| {
+        text.truecolor(state.colours.message.0, state.colours.message.1, state.colours.message.2)
+    };
+    let mut result_vec = Vec::new();
+    if let Some(var_idx) = result.assignment {
+        result_vec.push(message(format!("@{} = ", state.variables[var_idx].name)));
+    }
+    result_vec.extend(result2string(result, state));
+    if let Some(unit) = &result.unit {
+        result_vec.push(message(format!(" {}", unit)));
+    }
+    if let Some(interval) = interval {
+        result_vec.push(message(format!(" {}", interval)));
+    }
+    if let Some(view) = width_view(&result.value, state).filter(|_| result.list.is_none()) {
+        result_vec.push(message(format!("\n{}", view)));
+    }
+    if let Some(view) = call_view(tokens, result, state) {
+        result_vec.push(message(format!("\n{}", view)));
+    }
+    result_vec
+}
+/// The `:watch` expressions and their current values, one line each
+///
+/// They're evaluated on a copy of the state, so a watch can't disturb the
+/// variables or results it is watching. Errors show in place of a value.
+/// While `:json` is on each line is a JSON object holding the watch and either
+/// its result or its error.
+pub fn watch_view(state: &BasecalcState) -> Vec<ColoredString> {
+    let mut scratch = state.clone();
+    let mut view = Vec::new();
+    for watch in &state.watches {
+        let evaluated = tokenize(watch, &mut scratch)
+            .and_then(|tokens| evaluate_tokens(&tokens, &mut scratch).map_err(|msg| (msg, 0)));
+        if state.json {
+            let shown = match evaluated {
+                Ok(result) => format!("\"result\":{}", result_json(&result, state)),
+                Err((msg, usize::MAX)) => format!("\"message\":{}", json_string(&msg)),
+                Err((msg, _)) => format!("\"error\":{}", json_string(&msg)),
+            };
+            view.push(format!("{{\"watch\":{},{}}}\n", json_string(watch), shown).normal());
+            continue;
+        }
+        view.push(format!("{} → ", watch).truecolor(
+            state.colours.message.0,
+            state.colours.message.1,
+            state.colours.message.2,
+        ));
+        match evaluated {
+            Ok(result) => view.extend(result2string(&result, state)),
+            Err((msg, usize::MAX)) => view.push(msg.truecolor(
+                state.colours.message.0,
+                state.colours.message.1,
+                state.colours.message.2,
+            )),
+            Err((msg, _)) => view.push(msg.truecolor(
+                state.colours.error.0,
+                state.colours.error.1,
+                state.colours.error.2,
+            )),
+        }
+        view.push("\n".normal());
+    }
+    view
+}
+/// A result as one line of JSON, shown in place of the usual view while `:json` is on
+///
+/// Each part is written out as a string in the current base, as `:copy plain` would
+/// give it, so no precision is lost to a float on the way.
+fn result_json(result: &EvalResult, state: &BasecalcState) -> String {
+    let mut fields = Vec::new();
+    if let Some(index) = result.assignment {
+        fields.push(format!("\"name\":{}", json_string(&state.variables[index].name)));
+    }
+    match &result.list {
+        Some(elements) => {
+            let elements: Vec<String> = elements
+                .iter()
+                .map(|element| format!("{{{}}}", complex_json(element, state)))
+                .collect();
+            fields.push(format!("\"list\":[{}]", elements.join(",")));
+        }
+        None => fields.push(complex_json(&result.value, state)),
+    }
+    if let Some(unit) = &result.unit {
+        fields.push(format!("\"unit\":{}", json_string(unit)));
+    }
+    fields.push(format!("\"base\":{}", state.base));
+    fields.push(format!("\"digits\":{}", state.digits));
+    format!("{{{}}}", fields.join(","))
+}
+/// The real and imaginary parts of a number as JSON fields, and whether either was
+/// rounded to fit the digits shown
+fn complex_json(num: &Complex, state: &BasecalcState) -> String {
+    let part = |value: &Float| {
+        copy_text(&Complex::with_val(state.precision, (value, 0)), false, true, state)
+    };
+    let shown: String = num2string(num, state).iter().map(|part| &**part).collect();
+    format!(
+        "\"real\":{},\"imag\":{},\"approximate\":{}",
+        json_string(&part(num.real())),
+        json_string(&part(num.imag())),
+        shown.contains('~')
+    )
+}
+/// Quotes text as a JSON string
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+/// Converts the result of an evaluation for display, whether a number or a list
+pub fn result2string(result: &EvalResult, state: &BasecalcState) -> Vec<ColoredString> {
+    match &result.list {
+        Some(elements) => list2string(elements, state),
+        None => num2string(&result.value, state),
+    }
+}
+/// Converts a complex number to a vector of DMS coloured strings for display
+///
+/// # Arguments
+/// * `num` - The complex number to convert
+/// * `base` - The current number base
+/// * `digits` - The number of digits to display
+/// * `colours` - The colour scheme for output formatting
+///
+/// # Returns
+/// * `Vec<ColoredString>` - A vector of coloured strings representing the number
+fn num2dms(num: &Complex, state: &BasecalcState) -> Vec<ColoredString> {
+    let mut result = Vec::new();
+
+    if num.real().is_nan()
+        || num.imag().is_nan()
+        || num.real().is_infinite()
+        || num.imag().is_infinite()
+    {
+        result.push("NaN".truecolor(
+            state.colours.nan.0,
+            state.colours.nan.1,
+            state.colours.nan.2,
+        ));
+        return result;
+    }
+
+    if num.imag().is_zero() {
+        result.push(" ".normal());
+        result.extend(format_dms(num.real(), state, true, true));
+    } else {
+        result.push("[".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+        result.extend(format_dms(num.real(), state, true, false));
+        result.push(" ,".truecolor(
+            state.colours.comma.0,
+            state.colours.comma.1,
+            state.colours.comma.2,
+        ));
+        result.extend(format_dms(num.imag(), state, false, false));
+        result.push(" ]".truecolor(
+            state.colours.brackets.0,
+            state.colours.brackets.1,
+            state.colours.brackets.2,
+        ));
+    }
+
+    result
+}
+/// Formats a part of a complex number (real or imaginary) as a vector of coloured strings
+///
+/// # Arguments
+/// * `num` - The float number to format
+/// * `base` - The current number base
+/// * `num_digits` - The number of digits to display
+/// * `colours` - The colour scheme for output formatting
+/// * `is_real` - Whether this is the real part of a complex number
+/// * `is_lone` - Whether this is a standalone number (not part of a complex number)
+///
+/// # Returns
+/// * `Vec<ColoredString>` - A vector of coloured strings representing the formatted number
+fn format_part(
+    num: &rug::Float,
+    state: &BasecalcState,
+    is_real: bool,
+    is_lone: bool,
+) -> Vec<ColoredString> {
+    let mut result = Vec::new();
+
+    if num.is_zero() {
+        result.push(" ".normal());
+        result.push("0".truecolor(
+            state.colours.lone_integer.0,
+            state.colours.lone_integer.1,
+            state.colours.lone_integer.2,
+        ));
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        return result;
+    }
+    if num.is_nan() || num.is_infinite() {
+        result.push("NaN".truecolor(
+            state.colours.nan.0,
+            state.colours.nan.1,
+            state.colours.nan.2,
+        ));
+        return result;
+    }
+
+    // Balanced digits carry the sign themselves
+    let is_positive = num.is_sign_positive() || state.balanced;
+    if is_positive {
+        result.push(" ".normal());
+    } else {
+        result.push("-".truecolor(
+            state.colours.sign.0,
+            state.colours.sign.1,
+            state.colours.sign.2,
+        ));
+    }
+
+    let (int_colour, frac_colour) = if is_lone {
+        (state.colours.lone_integer, state.colours.lone_fraction)
+    } else if is_real {
+        (state.colours.real_integer, state.colours.real_fraction)
+    } else {
+        (
+            state.colours.imaginary_integer,
+            state.colours.imaginary_fraction,
+        )
+    };
+    if let Some((integer, fraction, exact)) = fixed_places(num, state) {
+        result.push(digit_glyphs(integer, state).truecolor(int_colour.0, int_colour.1, int_colour.2));
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        result.push(digit_glyphs(fraction, state).truecolor(frac_colour.0, frac_colour.1, frac_colour.2));
+        if exact {
+            result.push(" ".normal());
+        } else {
+            result.push("~".truecolor(
+                state.colours.tilde.0,
+                state.colours.tilde.1,
+                state.colours.tilde.2,
+            ));
+        }
+        return result;
+    }
+    if let Some((integer, fraction, start)) = repeating_fraction(num, state) {
+        let integer = format_integer(&integer, state.base);
+        let mut grouped = String::new();
+        for (i, c) in integer.chars().enumerate() {
+            if i > 0 && state.grouping.after(i as isize + 1 - integer.len() as isize) {
+                grouped.push(state.grouping.separator);
+            }
+            grouped.push(c);
+        }
+        result.push(digit_glyphs(grouped, state).truecolor(int_colour.0, int_colour.1, int_colour.2));
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        let mut digits = String::new();
+        for (i, &digit) in fraction.iter().enumerate() {
+            if i == start {
+                result.push(digit_glyphs(digits, state).truecolor(frac_colour.0, frac_colour.1, frac_colour.2));
+                result.push("(".truecolor(
+                    state.colours.tilde.0,
+                    state.colours.tilde.1,
+                    state.colours.tilde.2,
+                ));
+                digits = String::new();
+            }
+            digits.push(if digit < 10 {
+                (digit + b'0') as char
+            } else {
+                ((digit - 10) + b'A') as char
+            });
+            if state.grouping.after(i as isize + 2) && i + 1 < fraction.len() {
+                digits.push(state.grouping.separator);
+            }
+        }
+        result.push(digit_glyphs(digits, state).truecolor(frac_colour.0, frac_colour.1, frac_colour.2));
+        result.push(")".truecolor(
+            state.colours.tilde.0,
+            state.colours.tilde.1,
+            state.colours.tilde.2,
+        ));
+        result.push(" ".normal());
+        return result;
+    }
+
+    let radix = display_radix(state, num.prec());
+    let mut num_abs = num.clone().abs();
+    let mut decimal_place = (num_abs.clone().log2() / radix.clone().log2())
+    .floor()
+    .to_f64() as isize;
+    if state.balanced {
+        // Rounding each digit to the nearest rounds the last one too, and a
+        // leading digit past k carries into a new place
+        num_abs = num.clone() / (Float::with_val(num.prec(), state.base)).pow(decimal_place);
+        if num_abs.clone().abs() * 2 >= state.base {
+            decimal_place += 1;
+            num_abs /= state.base;
+        }
+    } else {
+        num_abs = num_abs / radix.clone().pow(decimal_place);
+        num_abs += radix.clone().pow(-(state.digits as isize - 1)) / 2;
+        if num_abs > radix {
+            num_abs = num.clone().abs();
+            decimal_place += 1;
+            num_abs = num_abs / radix.clone().pow(decimal_place);
+            num_abs += radix.clone().pow(-(state.digits as isize - 1)) / 2;
+        }
+    }
+
+    // By default everything from 0.1 up to the digits shown is written out
+    let (low, high) = state.exponent_range.unwrap_or((-1, state.digits as isize));
+    let decimal = low <= decimal_place && decimal_place < high;
+    let mut integer_part = String::new();
+    let mut place = 0;
+    let mut offset = place as isize - decimal_place;
+    while offset <= 0 && (place < state.digits || decimal) {
+        place += 1;
+        // Places past the digits shown are padded out with zeros
+        let digit_char = if place <= state.digits { take_digit(&mut num_abs, &radix, state) } else { '0' };
+        integer_part.push(digit_char);
+        offset = place as isize - decimal_place;
+        if state.grouping.after(offset) && offset != 1 {
+            integer_part.push(state.grouping.separator)
+        }
+    }
+    let mut fractional_part = String::new();
+    if decimal {
+        // Leading zeros after the point, for small numbers written out
+        for zero in 2..=offset {
+            fractional_part.push('0');
+            if state.grouping.after(zero) {
+                fractional_part.push(state.grouping.separator);
+            }
+        }
+    }
+    while offset > 0 && place < state.digits {
+        place += 1;
+        let digit_char = take_digit(&mut num_abs, &radix, state);
+        fractional_part.push(digit_char);
+        offset = place as isize - decimal_place;
+        if state.grouping.after(offset) {
+            fractional_part.push(state.grouping.separator)
+        }
+    }
+    let prec = num_abs.prec();
+    let tilde = if state.balanced {
+        num_abs.abs() > 2f64.pow(-16)
+    } else {
+        (num_abs * Float::with_val(prec, 2) - radix).abs() > 2f64.pow(-16)
+    };
+    if decimal {
+        if integer_part.is_empty() {
+            result.push("0".truecolor(int_colour.0, int_colour.1, int_colour.2));
+        } else {
+            result.push(digit_glyphs(integer_part, state).truecolor(int_colour.0, int_colour.1, int_colour.2));
+        }
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        result.push(digit_glyphs(trim_zeros(fractional_part), state).truecolor(
+            frac_colour.0,
+            frac_colour.1,
+            frac_colour.2,
+        ));
+        if tilde {
+            result.push("~".truecolor(
+                state.colours.tilde.0,
+                state.colours.tilde.1,
+                state.colours.tilde.2,
+            ));
+        } else {
+            result.push(" ".normal());
+        }
+    } else {
+        if integer_part.is_empty() {
+            let mut number = trim_zeros(fractional_part);
+            let first = number.as_bytes()[0];
+            let is_space = first == state.grouping.separator as u8;
+            if is_space {
+                let mut new_number = "".to_owned();
+                new_number.push(number.as_bytes()[1] as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(2).1);
+                number = new_number;
+            } else {
+                let mut new_number = "".to_owned();
+                new_number.push(first as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(1).1);
+                number = new_number;
+            }
+            result.push(digit_glyphs(number, state).truecolor(frac_colour.0, frac_colour.1, frac_colour.2));
+            if tilde {
+                result.push("~".truecolor(
+                    state.colours.tilde.0,
+                    state.colours.tilde.1,
+                    state.colours.tilde.2,
+                ));
+            } else {
+                result.push(" ".normal());
+            }
+            result.push(" :".truecolor(
+                state.colours.colon.0,
+                state.colours.colon.1,
+                state.colours.colon.2,
+            ));
+            if decimal_place < 0 {
+                let mut exponent = "-".to_owned();
+                exponent.push_str(&format_int(
+                    (-decimal_place) as usize,
+                    state.base as usize,
+                    state.balanced,
+                ));
+                result.push(digit_glyphs(exponent, state).truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            } else {
+                let mut exponent = " ".to_owned();
+                exponent.push_str(&format_int(decimal_place as usize, state.base as usize, state.balanced));
+                result.push(digit_glyphs(exponent, state).truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            }
+        } else {
+            // Digits past the units one when the exponent starts low
+            if !fractional_part.is_empty() && state.grouping.after(1) {
+                integer_part.push(state.grouping.separator);
+            }
+            let mut number = trim_zeros(integer_part + &fractional_part);
+            let first = number.as_bytes()[0];
+            let is_space = first == state.grouping.separator as u8;
+            if is_space {
+                let mut new_number = "".to_owned();
+                new_number.push(number.as_bytes()[1] as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(2).1);
+                number = new_number;
+            } else {
+                let mut new_number = "".to_owned();
+                new_number.push(first as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(1).1);
+                number = new_number;
+            }
+            result.push(digit_glyphs(number, state).truecolor(int_colour.0, int_colour.1, int_colour.2));
+            if tilde {
+                result.push("~".truecolor(
+                    state.colours.tilde.0,
+                    state.colours.tilde.1,
+                    state.colours.tilde.2,
+                ));
+            } else {
+                result.push(" ".normal());
+            }
+            result.push(" :".truecolor(
+                state.colours.colon.0,
+                state.colours.colon.1,
+                state.colours.colon.2,
+            ));
+            if decimal_place < 0 {
+                let mut exponent = "-".to_owned();
+                exponent.push_str(&format_int(
+                    (-decimal_place) as usize,
+                    state.base as usize,
+                    state.balanced,
+                ));
+                result.push(digit_glyphs(exponent, state).truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            } else {
+                let mut exponent = " ".to_owned();
+                exponent.push_str(&format_int(decimal_place as usize, state.base as usize, state.balanced));
+                result.push(digit_glyphs(exponent, state).truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            }
+        }
+    }
+    result
+}
+/// Formats a part of a complex number (real or imaginary) as a vector of coloured strings
+///
+/// # Arguments
+/// * `num` - The float number to format
+/// * `base` - The current number base
+/// * `num_digits` - The number of digits to display
+/// * `colours` - The colour scheme for output formatting
+/// * `is_real` - Whether this is the real part of a complex number
+/// * `is_lone` - Whether this is a standalone number (not part of a complex number)
+///
+/// # Returns
+/// * `Vec<ColoredString>` - A vector of coloured strings representing the formatted DMS part
+fn format_dms(
+    num: &rug::Float,
+    state: &BasecalcState,
+    is_real: bool,
+    is_lone: bool,
+) -> Vec<ColoredString> {
+    let mut result = Vec::new();
+
+    if num.is_zero() {
+        result.push(" ".normal());
+        result.push("Zil".truecolor(
+            state.colours.lone_integer.0,
+            state.colours.lone_integer.1,
+            state.colours.lone_integer.2,
+        ));
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        return result;
+    }
+    if num.is_nan() || num.is_infinite() {
+        result.push("NaN".truecolor(
+            state.colours.nan.0,
+            state.colours.nan.1,
+            state.colours.nan.2,
+        ));
+        return result;
+    }
+
+    let is_positive = num.is_sign_positive();
+    if is_positive {
+        result.push(" ".normal());
+    } else {
+        result.push("-".truecolor(
+            state.colours.sign.0,
+            state.colours.sign.1,
+            state.colours.sign.2,
+        ));
+    }
+
+    let mut num_abs = num.clone().abs();
+    let mut decimal_place = (num_abs.clone().log2() / (Float::with_val(num.prec(), 12)).log2())
+        .floor()
+        .to_f64() as isize;
+    num_abs = num_abs / (Float::with_val(num.prec(), 12)).pow(decimal_place);
+    num_abs += (Float::with_val(num.prec(), 12)).pow(-(state.digits as isize - 1)) / 2;
+    if num_abs > 12 {
+        num_abs = num.clone().abs();
+        decimal_place += 1;
+        num_abs = num_abs / (Float::with_val(num.prec(), 12)).pow(decimal_place);
+        num_abs += (Float::with_val(num.prec(), 12)).pow(-(state.digits as isize - 1)) / 2;
+    }
+
+    let mut integer_part = String::new();
+    let mut decimal = false;
+    let mut place = 0;
+    let mut offset = place as isize - decimal_place;
+    while offset <= 0 && place < state.digits {
+        place += 1;
+        let digit: u8 = num_abs.clone().floor().cast();
+        num_abs = num_abs - digit;
+        num_abs *= 12;
+        let name = match digit {
+            0 => "Zil",
+            1 => "Zila",
+            2 => "Zilor",
+            3 => "Ter",
+            4 => "Tera",
+            5 => "Teror",
+            6 => "Lun",
+            7 => "Luna",
+            8 => "Lunor",
+            9 => "Stel",
+            10 => "Stela",
+            11 => "Stelor",
+            _ => "NaN",
+        };
+        integer_part.extend(name.chars());
+        offset = place as isize - decimal_place;
+        if offset.rem_euc(3) == 1 && offset != 1 {
+            //&& place != num_digits - 1
+            integer_part.push(' ')
+        }
+    }
+    if offset == 1 {
+        decimal = true;
+    }
+    let mut fractional_part = String::new();
+    while offset > 0 && place < state.digits {
+        place += 1;
+        let digit: u8 = num_abs.clone().floor().cast();
+        num_abs = num_abs - digit;
+        num_abs *= 12;
+        let name = match digit {
+            0 => "Zil",
+            1 => "Zila",
+            2 => "Zilor",
+            3 => "Ter",
+            4 => "Tera",
+            5 => "Teror",
+            6 => "Lun",
+            7 => "Luna",
+            8 => "Lunor",
+            9 => "Stel",
+            10 => "Stela",
+            11 => "Stelor",
+            _ => "NaN",
+        };
+        fractional_part.extend(name.chars());
+        offset = place as isize - decimal_place;
+        if offset.rem_euc(3) == 1 {
+            //} && place != num_digits - 1 {
+            fractional_part.push(' ')
+        }
+    }
+    let (int_colour, frac_colour) = if is_lone {
+        (state.colours.lone_integer, state.colours.lone_fraction)
+    } else if is_real {
+        (state.colours.real_integer, state.colours.real_fraction)
+    } else {
+        (
+            state.colours.imaginary_integer,
+            state.colours.imaginary_fraction,
+        )
+    };
+    let prec = num_abs.prec();
+    let tilde =
+        (num_abs * Float::with_val(prec, 2) - Float::with_val(prec, 12)).abs() > 2f64.pow(-16);
+    if decimal {
+        if integer_part.is_empty() {
+            result.push("Zil".truecolor(int_colour.0, int_colour.1, int_colour.2));
+        } else {
+            result.push(integer_part.truecolor(int_colour.0, int_colour.1, int_colour.2));
+        }
+        result.push(".".truecolor(
+            state.colours.decimal.0,
+            state.colours.decimal.1,
+            state.colours.decimal.2,
+        ));
+        result.push(trim_zeros(fractional_part).truecolor(
+            frac_colour.0,
+            frac_colour.1,
+            frac_colour.2,
+        ));
+        if tilde {
+            result.push("~".truecolor(
+                state.colours.tilde.0,
+                state.colours.tilde.1,
+                state.colours.tilde.2,
+            ));
+        } else {
+            result.push(" ".normal());
+        }
+    } else {
+        if integer_part.is_empty() {
+            let mut number = trim_zeros(fractional_part);
+            let first = number.as_bytes()[0];
+            let is_space = first == b' ';
+            if is_space {
+                let mut new_number = "".to_owned();
+                new_number.push(number.as_bytes()[1] as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(2).1);
+                number = new_number;
+            } else {
+                let mut new_number = "".to_owned();
+                new_number.push(first as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(1).1);
+                number = new_number;
+            }
+            result.push(number.truecolor(frac_colour.0, frac_colour.1, frac_colour.2));
+            if tilde {
+                result.push("~".truecolor(
+                    state.colours.tilde.0,
+                    state.colours.tilde.1,
+                    state.colours.tilde.2,
+                ));
+            } else {
+                result.push(" ".normal());
+            }
+            result.push(" :".truecolor(
+                state.colours.colon.0,
+                state.colours.colon.1,
+                state.colours.colon.2,
+            ));
+            if decimal_place < 0 {
+                let mut exponent = "-".to_owned();
+                exponent.push_str(&format_int((-decimal_place) as usize, 12 as usize, false));
+                result.push(exponent.truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            } else {
+                let mut exponent = " ".to_owned();
+                exponent.push_str(&format_int(decimal_place as usize, 12 as usize, false));
+                result.push(exponent.truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            }
+        } else {
+            let mut number = trim_zeros(integer_part);
+            let first = number.as_bytes()[0];
+            let is_space = first == b' ';
+            if is_space {
+                let mut new_number = "".to_owned();
+                new_number.push(number.as_bytes()[1] as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(2).1);
+                number = new_number;
+            } else {
+                let mut new_number = "".to_owned();
+                new_number.push(first as char);
+                new_number.push('.');
+                new_number.push_str(number.split_at(1).1);
+                number = new_number;
+            }
+            result.push(number.truecolor(int_colour.0, int_colour.1, int_colour.2));
+            if tilde {
+                result.push("~".truecolor(
+                    state.colours.tilde.0,
+                    state.colours.tilde.1,
+                    state.colours.tilde.2,
+                ));
+            } else {
+                result.push(" ".normal());
+            }
+            result.push(" :".truecolor(
+                state.colours.colon.0,
+                state.colours.colon.1,
+                state.colours.colon.2,
+            ));
+            if decimal_place < 0 {
+                let mut exponent = "-".to_owned();
+                exponent.push_str(&format_int((-decimal_place) as usize, 12 as usize, false));
+                result.push(exponent.truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            } else {
+                let mut exponent = " ".to_owned();
+                exponent.push_str(&format_int(decimal_place as usize, 12 as usize, false));
+                result.push(exponent.truecolor(
+                    state.colours.exponent.0,
+                    state.colours.exponent.1,
+                    state.colours.exponent.2,
+                ));
+            }
+        }
+    }
+    result
+}
+/// Finds the repeating expansion of a value that is a fraction with a small
+/// denominator, for showing `1/6` as `0.1(6)` once `:repeat` is set
+///
+/// The fraction comes from the continued fraction of the value, accepted only if
+/// it matches to within the padding bits and its denominator uses at most half
+/// the displayed bits, so irrationals are not mistaken for fractions.
+///
+/// # Returns
+/// * `Some((Integer, Vec<u8>, usize))` - The integer part, the fractional digits
+///   and the index of the digit where the period starts
+/// * `None` - If the value terminates, isn't such a fraction, its period is
+///   too long to show in the current digits, or the base is balanced or irrational
+fn repeating_fraction(num: &Float, state: &BasecalcState) -> Option<(Integer, Vec<u8>, usize)> {
+    let max_period = state.period?;
+    if !num.is_finite() || num.is_zero() || state.balanced || state.radix.is_some() {
+        return None;
+    }
+    let shown_bits = state.precision - state.padding;
+    let target = num.clone().abs();
+    let tolerance = target.clone() >> (state.precision - state.padding / 2);
+    let mut x = target.clone();
+    let (mut p0, mut q0) = (Integer::from(0), Integer::from(1));
+    let (mut p1, mut q1) = (Integer::from(1), Integer::from(0));
+    let (numerator, denominator) = loop {
+        let a = x.clone().floor().to_integer()?;
+        let p2 = a.clone() * &p1 + &p0;
+        let q2 = a * &q1 + &q0;
+        if q2.significant_bits() > shown_bits / 2 {
+            return None;
+        }
+        let error = (Float::with_val(target.prec(), &p2) / &q2 - &target).abs();
+        if error <= tolerance {
+            break (p2, q2);
+        }
+        let fraction = x.clone() - x.clone().floor();
+        if fraction.is_zero() {
+            return None;
+        }
+        x = fraction.recip();
+        (p0, q0, p1, q1) = (p1, q1, p2, q2);
+    };
+    let (integer, mut remainder) = numerator.div_rem(denominator.clone());
+    let integer_digits = if integer.is_zero() { 1 } else { format_integer(&integer, state.base).len() };
+    let mut remainders: Vec<Integer> = Vec::new();
+    let mut fraction = Vec::new();
+    while !remainder.is_zero() {
+        if let Some(start) = remainders.iter().position(|seen| *seen == remainder) {
+            return (fraction.len() - start <= max_period).then_some((integer, fraction, start));
+        }
+        if integer_digits + fraction.len() >= state.digits {
+            return None;
+        }
+        remainders.push(remainder.clone());
+        remainder *= state.base as u32;
+        let (digit, rest) = remainder.div_rem(denominator.clone());
+        fraction.push(digit.to_u8().unwrap());
+        remainder = rest;
+    }
+    None
+}
+/// The grouped integer and fractional digits of |num| rounded to the `:fixed`
+/// places, and whether that's exact
+///
+/// Numbers whose integer part would run past the digits shown keep their
+/// exponent, as do balanced and irrational bases.
+fn fixed_places(num: &Float, state: &BasecalcState) -> Option<(String, String, bool)> {
+    let places = state.fixed?;
+    if !num.is_finite() || state.balanced || state.radix.is_some() {
+        return None;
+    }
+    let scale = Float::with_val(num.prec(), state.base).pow(places as u32);
+    let scaled = Float::with_val(num.prec(), num.abs_ref()) * scale;
+    let rounded = scaled.clone().round();
+    let digits = format_integer(&rounded.to_integer()?, state.base);
+    let digits = "0".repeat((places + 1).saturating_sub(digits.len())) + &digits;
+    if digits.len() - places > state.digits {
+        return None;
+    }
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    let mut integer = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && state.grouping.after(i as isize + 1 - whole.len() as isize) {
+            integer.push(state.grouping.separator);
+        }
+        integer.push(c);
+    }
+    let mut grouped = String::new();
+    for (i, c) in fraction.chars().enumerate() {
+        grouped.push(c);
+        if state.grouping.after(i as isize + 2) && i + 1 < fraction.len() {
+            grouped.push(state.grouping.separator);
+        }
+    }
+    Some((integer, grouped, rounded == scaled))
+}
+/// The base results are shown in, which is the irrational one from `:base @phi`
+/// or `:base @e` when set
+fn display_radix(state: &BasecalcState, precision: u32) -> Float {
+    match state.radix {
+        Some(radix) => radix.value(precision),
+        None => Float::with_val(precision, state.base),
+    }
+}
+/// Takes the leading digit off a value scaled to have it in the units place,
+/// leaving the rest scaled up by `radix` for the next digit
+///
+/// Balanced bases round to the nearest digit, which may be negative, where
+/// others take the floor. An irrational radix gives the greedy expansion.
+fn take_digit(num: &mut Float, radix: &Float, state: &BasecalcState) -> char {
+    if state.balanced {
+        // A half rounds away from zero, which could step past the largest digit
+        let largest = state.base as i32 / 2;
+        let digit: i32 = num.clone().round().cast();
+        let digit = digit.clamp(-largest, largest);
+        *num -= digit;
+        *num *= state.base;
+        return balanced_digit_char(digit);
+    }
+    let digit: u8 = num.clone().floor().cast();
+    *num -= digit;
+    *num *= radix;
+    if digit < 10 {
+        (digit + b'0') as char
+    } else {
+        ((digit - 10) + b'A') as char
+    }
+}
+/// The glyph for a balanced digit: 0 to 9 and A.. as usual, then Z for -1, Y for -2 and so on
+fn balanced_digit_char(digit: i32) -> char {
+    match digit {
+        0..=9 => (digit as u8 + b'0') as char,
+        10.. => (digit as u8 - 10 + b'A') as char,
+        _ => (b'Z' + 1 - (-digit) as u8) as char,
+    }
+}
+/// Whether a glyph, valued 0 for '0' up to 35 for 'Z', is a digit of the base
+///
+/// Balanced bases use the top of the alphabet for their negative digits, so
+/// balanced ternary's digits are 0, 1 and Z.
+fn is_base_digit(glyph: u8, base: u8, balanced: bool) -> bool {
+    if balanced {
+        glyph <= base / 2 || glyph >= 36 - base / 2
+    } else {
+        glyph < base
+    }
+}
+/// The value of a glyph parsed by [`parse_number`], negative for the upper
+/// glyphs of a balanced base
+fn digit_value(glyph: u8, state: &BasecalcState) -> i32 {
+    if state.balanced && glyph > state.base / 2 {
+        glyph as i32 - 36
+    } else {
+        glyph as i32
+    }
+}
+/// Writes digits from ten up as `:digitset` and `:digitcase` ask, ten and
+/// eleven being ↊ and ↋ for dozenal in base 12
+fn digit_glyphs(text: String, state: &BasecalcState) -> String {
+    if state.dozenal && state.base == 12 && state.radix.is_none() {
+        text.replace('A', "↊").replace('B', "↋")
+    } else if state.lowercase {
+        text.to_ascii_lowercase()
+    } else {
+        text
+    }
+}
+fn trim_zeros(mut number: String) -> String {
+    let mut index = number.len();
+    while index > 0 {
+        // Trailing zeros go, and any group separators they leave behind
+        if !matches!(number.as_bytes()[index - 1], b'0' | b' ' | b'_' | b',') {
+            break;
+        }
+        index -= 1;
+    }
+    number.truncate(index);
+    number
+}
+/// Formats an integer in the specified base as a string
+///
+/// # Arguments
+/// * `num` - The integer to format
+/// * `base` - The base to use for formatting (2 to 36)
+/// * `balanced` - Whether to use balanced digits, so 2 in ternary is `1Z`
+///
+/// # Returns
+/// * `String` - The formatted integer as a string
+///
+/// # Notes
+/// - For bases > 10, uses uppercase letters A-Z for digits 10-35
+/// - Returns "0" if the input is 0
+/// - Does not handle negative numbers
+fn format_int(mut num: usize, base: usize, balanced: bool) -> String {
+    if num == 0 {
+        return "0".to_owned();
+    }
+    let mut number = "".to_owned();
+    while num != 0 {
+        let mut digit = (num % base) as u8;
+        num = num / base;
+        if balanced && digit as usize > base / 2 {
+            number.push(balanced_digit_char(digit as i32 - base as i32));
+            num += 1;
+            continue;
+        }
+        if digit < 10 {
+            digit += b'0'
+        } else {
+            digit += b'A' - 10
+        }
+        number.push(digit as char);
+    }
+    number.chars().rev().collect()
+}
+/// Formats a non-negative integer of any size in the state's base, balanced or
+/// not, with the digits `:digitset` and `:digitcase` ask for
+fn format_whole(num: &Integer, state: &BasecalcState) -> String {
+    let text = match num.to_usize() {
+        Some(num) => format_int(num, state.base as usize, state.balanced),
+        None if state.balanced => {
+            // Digits above half the base borrow one from the next place up
+            let base = state.base as i32;
+            let mut number = String::new();
+            let mut carry = 0;
+            for &digit in integer_digits(num, state.base).iter().rev() {
+                let mut digit = digit as i32 + carry;
+                carry = (digit > base / 2) as i32;
+                digit -= carry * base;
+                number.push(balanced_digit_char(digit));
+            }
+            if carry > 0 {
+                number.push('1');
+            }
+            number.chars().rev().collect()
+        }
+        None => format_integer(num, state.base),
+    };
+    digit_glyphs(text, state)
+}
+/// Formats a float with exactly `places` fractional digits, rounded to nearest
+///
+/// # Arguments
+/// * `num` - The float to format
+/// * `places` - The number of digits after the point, zero padded
+/// * `base` - The base to use for formatting (2 to 36)
+///
+/// # Returns
+/// * `String` - The fixed point representation, without digit grouping
+fn format_fixed(num: &Float, places: usize, base: u8) -> String {
+    let scale = Float::with_val(num.prec(), base).pow(places as u32);
+    let scaled = (num.clone().abs() * scale).round().to_integer().unwrap();
+    let mut digits = format_integer(&scaled, base);
+    if digits.len() <= places {
+        digits = "0".repeat(places + 1 - digits.len()) + &digits;
+    }
+    let (integer, fraction) = digits.split_at(digits.len() - places);
+    let sign = if num.is_sign_negative() && !scaled.is_zero() { "-" } else { "" };
+    format!("{}{}.{}", sign, integer, fraction)
+}
+/// Formats a number of seconds as `1d 02:03:04.5`, the days only if there are any
+///
+/// Hours, minutes and whole seconds are integers in the base, padded to the
+/// width of 23 or 59. The seconds carry every fractional digit the working
+/// precision can vouch for, less trailing zeros.
+fn format_hms(seconds: &Float, state: &BasecalcState) -> String {
+    let whole = seconds.clone().abs().floor().to_integer().unwrap();
+    let integer_digits = if whole.is_zero() { 0 } else { integer_digits(&whole, state.base).len() };
+    let available =
+        ((state.precision - state.padding) as f64 / (state.base as f64).log2()).floor() as usize;
+    let places = available.saturating_sub(integer_digits);
+    // Round once, in units of the last place, so 59.999... carries into the minutes
+    let unit = Integer::from(state.base).pow(places as u32);
+    let scaled = (seconds.clone().abs() * &unit).round().to_integer().unwrap();
+    let (total, fraction) = scaled.clone().div_rem(unit);
+    let (minutes, second) = total.div_rem(Integer::from(60));
+    let (hours, minute) = minutes.div_rem(Integer::from(60));
+    let (days, hour) = hours.div_rem(Integer::from(24));
+    let pad = |value: &Integer, largest: u32| {
+        let width = format_integer(&Integer::from(largest), state.base).len();
+        format!("{:0>width$}", format_integer(value, state.base), width = width)
+    };
+    let mut text = String::new();
+    if seconds.is_sign_negative() && !scaled.is_zero() {
+        text.push('-');
+    }
+    if !days.is_zero() {
+        text.push_str(&format!("{}d ", format_integer(&days, state.base)));
+    }
+    text.push_str(&format!("{}:{}:{}", pad(&hour, 23), pad(&minute, 59), pad(&second, 59)));
+    let fraction = format!("{:0>places$}", format_integer(&fraction, state.base), places = places);
+    let fraction = fraction.trim_end_matches('0');
+    if !fraction.is_empty() {
+        text.push('.');
+        text.push_str(fraction);
+    }
+    text
+}
+/// Formats an arbitrary size integer in the specified base as a string
+///
+/// # Arguments
+/// * `num` - The integer to format
+/// * `base` - The base to use for formatting (2 to 36)
+///
+/// # Returns
+/// * `String` - The formatted integer, with a leading '-' if negative
+fn format_integer(num: &Integer, base: u8) -> String {
+    num.to_string_radix(base as i32).to_ascii_uppercase()
+}
+/// Returns the integer part of the real component, truncated toward zero
+///
+/// # Returns
+/// * `Some(Integer)` - The exact integer part
+/// * `None` - If the real component is NaN or infinite
+fn integer_real_part(num: &Complex) -> Option<Integer> {
+    num.real().clone().trunc().to_integer()
+}
+/// Splits a non-negative integer into its digits in the given base
+///
+/// # Returns
+/// * `Vec<u8>` - The digits, most significant first, `[0]` for zero
+fn integer_digits(num: &Integer, base: u8) -> Vec<u8> {
+    if num.is_zero() {
+        return vec![0];
+    }
+    let mut digits = Vec::new();
+    let mut n = num.clone().abs();
+    while !n.is_zero() {
+        let (quotient, digit) = n.div_rem_euc(Integer::from(base));
+        digits.push(digit.to_u8().unwrap());
+        n = quotient;
+    }
+    digits.reverse();
+    digits
+}
+/// Puts digits in the given base back together, most significant first
+fn digits_integer(digits: &[u8], base: u8) -> Integer {
+    let mut n = Integer::new();
+    for &digit in digits {
+        n *= base;
+        n += digit;
+    }
+    n
+}
+/// Computes the Luhn mod N checksum of a digit sequence, zero when valid
+fn luhn_sum(digits: &[u8], base: u8) -> u8 {
+    let base = base as usize;
+    let mut sum = 0;
+    for (i, &digit) in digits.iter().rev().enumerate() {
+        let mut value = digit as usize;
+        if i % 2 == 1 {
+            value *= 2;
+            value = value / base + value % base;
+        }
+        sum += value;
+    }
+    (sum % base) as u8
+}
+/// The digit that names a base in `:base`, with `0` for base 36
+fn base_digit_char(base: u8) -> char {
+    match base {
+        2..=9 => (base + b'0') as char,
+        10..=35 => (base - 10 + b'A') as char,
+        _ => '0',
+    }
+}
+fn get_base_name(base: u8) -> Option<&'static str> {
+    match base {
+        2 => Some("Binary"),
+        3 => Some("Ternary"),
+        4 => Some("Quaternary"),
+        5 => Some("Quinary"),
+        6 => Some("Senary"),
+        7 => Some("Septenary"),
+        8 => Some("Octal"),
+        9 => Some("Nonary"),
+        10 => Some("Decimal"),
+        11 => Some("Undecimal"),
+        12 => Some("Dozenal"),
+        13 => Some("Tridecimal"),
+        14 => Some("Tetradecimal"),
+        15 => Some("Pentadecimal"),
+        16 => Some("Hexadecimal"),
+        17 => Some("Heptadecimal"),
+        18 => Some("Octodecimal"),
+        19 => Some("Enneadecimal"),
+        20 => Some("Vigesimal"),
+        21 => Some("Unvigesimal"),
+        22 => Some("Duovigesimal"),
+        23 => Some("Trivigesimal"),
+        24 => Some("Tetravigesimal"),
+        25 => Some("Pentavigesimal"),
+        26 => Some("Hexavigesimal"),
+        27 => Some("Heptavigesimal"),
+        28 => Some("Octovigesimal"),
+        29 => Some("Enneabigesimal"),
+        30 => Some("Trigesimal"),
+        31 => Some("Untrigesimal"),
+        32 => Some("Duotrigesimal"),
+        33 => Some("Tritrigesimal"),
+        34 => Some("Tetratrigesimal"),
+        35 => Some("Pentatrigesimal"),
+        36 => Some("Hexatrigesimal"),
+        _ => None,
+    }
+}
+pub fn debug_println(msg: &str) {
+    if DEBUG.load(Ordering::Relaxed) {
+        println!("{}", msg);
+    }
+}
+fn run_tests() -> (usize, usize) {
+    let mut state = BasecalcState::new();
+    let tests = vec![
+        (":baSE C", "Base set to Dozenal (C)."),
+        (":DIGits    \t__\t\t2  0", "Precision set to 20 digits."),
+        // (":debug", "Debug enabled"),
+        (
+            "---1+2*(3+4*(5+6))^(-1/0.3)",
+            " -0.BBB BBA 939 245 70A 7B2 93B B06~",
+        ),
+        ("5^-25", "  1.86 BA3 547 200 980 95A 405 483~ :-17"),
+        ("(1+2)*3", "  9."),
+        ("--1+2*3", "  7."),
+        ("(1+2)*(3+4)", "  19."),
+        ("1+2*(3+4)", "  13."),
+        ("((1+2)*3)+4", "  11."),
+        ("1+(2*3)+4", "  B."),
+        ("2^(3^2)", "  368."),
+        ("(2^3)^2", "  54."),
+        ("1/(1+1/(1+1/(1+1/2)))", "  0.76"),
+        ("(((1+2)+3)+4)", "  A."),
+        ("1+(2+(3+4))", "  A."),
+        ("(1+2+3+4)", "  A."),
+        ("1 2 + 3", "  15."),
+        ("-3", " -3."),
+        ("--3", "  3."),
+        ("---3", " -3."),
+        ("----3", "  3."),
+        ("1-3", " -2."),
+        ("1--3", "  4."),
+        ("1---3", " -2."),
+        ("1----3", "  4."),
+        ("1/3+1/3+1/3-1", "  0."),
+        ("1 2 3 4 5", "  12 345."),
+        (
+            "5^-25*[-3.24,-4.1b]",
+            "[-5.58 BA6 424 28A 6A9 238 829 27A~ :-17 ,-7.17 49A 618 591 429 757 6B6 512~ :-17 ]",
+        ),
+        ("#sqrt-1", "[ 0. , 1.  ]"),
+        (
+            "#sqrt(#sqrt-1)",
+            "[ 0.859 A69 650 3BA 297 996 256 428~ , 0.859 A69 650 3BA 297 996 256 428~ ]",
+        ),
+        (
+            "#sqrt#sqrt-1",
+            "[ 0.859 A69 650 3BA 297 996 256 428~ , 0.859 A69 650 3BA 297 996 256 428~ ]",
+        ),
+        ("#sqrt(-1-1)", "[ 0. , 1.4B7 917 0A0 7B8 573 770 4B0 85~ ]"),
+        ("#sqrt-1-1", "[-1.  , 1.  ]"),
+        ("-#sIn(@pi/2)", " -1."),
+        ("#sin(@pi/4)", "  0.859 A69 650 3BA 297 996 256 428~"),
+        (":deGreEs", "Angle units set to degrees."),
+        ("#sin76", "  1."), // In degrees
+        (":radiAns", "Angle units set to radians."),
+        ("#sin76", "  0.A88 9AB 897 724 376 B81 A25 541~"), // In radians
+        (":gradians", "Angle units set to gradians."),
+        ("#sin84", "  1."), // A quarter turn is 100 gradians
+        ("#asin1", "  84."),
+        ("#acos-1", "  148."),
+        ("#atan1", "  42."),
+        ("90°", "  A0."),
+        ("#dms 84", "  76."),
+        (":gradians x", "Invalid characters after command!"),
+        (":radians", "Angle units set to radians."),
+        ("#sin#cos@pi", " -0.A12 08A A92 234 12B 470 074 934~"),
+        ("-#cos#sin0", " -1."),
+        ("#cos-#sin0", "  1."),
+        ("#cos#sin-0", "  1."),
+        ("---#cos---@pi", "  1."),
+        ("#log(100)/2", "  1."),
+        ("(@pi+@e)^2", "  2A.408 353 754 8B8 38B 235 632 3~"),
+        ("#sqrt(1+2+3)+)", "Mismatched parentheses!"),
+        ("[12,34.56,]", "Unexpected ','!"),
+        ("[12, 34. 56,", "Unexpected ','!"),
+        ("[ 12 ,34.56", "Unclosed complex number!"),
+        ("[-12.,34.56[1,2]]", "Unexpected '['!"),
+        ("[ 1 2..,34.56]", "Multiple decimals in number!"),
+        ("[,1234.56 ]", "Missing real component!"),
+        ("( (())1+2 ( ()))", "Expected number!"),
+        ("(1+2))", "Mismatched parentheses!"),
+        ("(1+2", "Mismatched parentheses!"),
+        ("1+*2", "Invalid number!"),
+        (" #sin()", "Expected number!"),
+        ("#sin", "Incomplete expression!"),
+        ("#sin(#cos())", "Expected number!"),
+        ("1/0", "NaN"),
+        ("[0,-1]/0", "NaN"),
+        ("1.2.3", "Multiple decimals in number!"),
+        ("(  1+2)*(3+4", "Mismatched parentheses!"),
+        ("#log(0)", "NaN"),
+        ("@pi@e", "Invalid operator!"),
+        ("#sin()#cos ( )", "Expected number!"),
+        ("1++2", "Invalid number!"),
+        ("((1  + 2  ) *3", "Mismatched parentheses!"),
+        ("1+(2*3", "Mismatched parentheses!"),
+        ("1 2 3 +", "Incomplete expression!"),
+        ("1 *  + 2", "Invalid number!"),
+        ("#funky(1)", "Unknown operator '#funky'!"),
+        ("1 / (2-2)", "NaN"),
+        ("(((1+2)*(3+4))+5", "Mismatched parentheses!"),
+        ("*1", "Invalid number!"),
+        ("1*", "Incomplete expression!"),
+        ("()", "Expected number!"),
+        ("#sin", "Incomplete expression!"),
+        ("12345 678 9abcdef", "Digit out of dozenal (C) range!"),
+        ("7", "  7."),
+        ("&", "  7."),
+        ("&+&", "  12."),
+        (":BaSe0", "Base set to Hexatrigesimal (Z+1)."),
+        ("#aCoS#SiGn1", "  0."),
+        ("#aCoS(#SiGn1)", "  0."),
+        (
+            "#aCoS#SiGn[1,2]",
+            "[ 1.8MV CO2 534 S9U VVE RVY UOO 25~ ,-0.UBU UDT BMM E9G 8UA I4H 8G8 32J~ ]",
+        ),
+        (
+            "#aCoS(#SiGn[1,2])",
+            "[ 1.8MV CO2 534 S9U VVE RVY UOO 25~ ,-0.UBU UDT BMM E9G 8UA I4H 8G8 32J~ ]",
+        ),
+        ("#aCoS#SiGn#sin(@pi/2)", "  0."),
+        ("#aCoS#SiGn#sin(@pi/2)", "  0."),
+        (
+            "#abs(-3*g)+#sqrt(y)/5",
+            "  1D.5ZD S0P CPH DKF GU1 V0S NUV S~",
+        ),
+        // Complex nested functions with constants
+        ("#sin#cos#tan3^2+1", "  1.P5N M5R ZCQ 6RZ NW6 FIS 23Y NV~"),
+        ("@1=4+1", "@1 =   5."),
+        ("5/@1", "  1."),
+        (":base A", "Base set to Decimal (A)."),
+        ("6", "  6."),
+        (":collatz", "Collatz trajectory: 8 steps, peak 16."),
+        ("27", "  27."),
+        (":collatz", "Collatz trajectory: 111 steps, peak 9232."),
+        ("-5", " -5."),
+        (":collatz", "Collatz needs a positive integer previous result!"),
+        (":pascal 5", "    1\n   1 1\n  1 2 1\n 1 3 3 1\n1 4 6 4 1"),
+        (":pascal 0", "Row count must be a positive integer!"),
+        (":pascal 2.5", "Argument must be a real integer!"),
+        (":pascal 257", "Row count must be at most 256!"),
+        (":base 3 balanced; :pascal 11; :base A", "Base set to Balanced ternary (3).\n    1\n   1 1\n 1 1Z 1\n1 10 10 1\nBase set to Decimal (A)."),
+        (":acc *", "Accumulating with multiplication."),
+        ("2", "  2."),
+        ("3", "  6."),
+        ("4", "  24."),
+        ("5", "  120."),
+        ("&/4", "  3 600."),
+        (":acc off", "Accumulator off, total:  3 600."),
+        ("5", "  5."),
+        (":acc #sin", "Expected a binary operator or 'off'!"),
+        (":acc +; #atan2(1, 1) - @pi/4; 2; #if(1 < 2, 3, 4) + #sum(k, 1, 2, k); :acc off", "Accumulating with addition.\n  0.\n  2.\n  8.\nAccumulator off, total:  8."),
+        ("79927398713", "  79 927 398 713."),
+        (":luhn", "Luhn checksum valid."),
+        ("79927398710", "  79 927 398 710."),
+        (":luhn", "Luhn checksum invalid, check digit should be 3."),
+        ("123", "  123."),
+        (":checkdigit 7", "Check digit: 2."),
+        ("1232/7", "  176."),
+        (":checkdigit 1", "Modulus must be at least 2!"),
+        (":checkdigit 13", "Check digits: 02."),
+        ("255", "  255."),
+        (":numdigits G", "2 hexadecimal digits."),
+        ("256", "  256."),
+        (":numdigits G", "3 hexadecimal digits."),
+        (":numdigits 2", "9 binary digits."),
+        ("0", "  0."),
+        (":numdigits 0", "1 hexatrigesimal digit."),
+        (":numdigits 1", "Base must be between 2 and 36!\nUse ':base 0' for base 36 (Z+1)"),
+        (":base C", "Base set to Dozenal (C)."),
+        ("1/3", "  0.4"),
+        (":fracdigits 8", "0.40000000"),
+        ("-2/7", " -0.351 86A 351 86A 351 86A 351 86A~"),
+        (":fracdigits 4", "-0.3519"),
+        ("[0.6,-1]", "[ 0.6  ,-1.  ]"),
+        (":fracdigits 2", "[0.60 , -1.00]"),
+        (
+            ":fracdigits 30",
+            "[0.600000000000000000000000000000000000 , -1.000000000000000000000000000000000000]\nOnly 1B fractional digits are within working precision.",
+        ),
+        (":base A", "Base set to Decimal (A)."),
+        (":fracdigits 4294967296", "Digit count must be at most 1024!"),
+        ("1", "  1."),
+        (":mobius 1 [0,-1] 1 [0, 1]", "[ 0. ,-1.  ]"),
+        (":mobius 1 [0,-1] 1 [0, 1]", "NaN"),
+        ("0", "  0."),
+        (":mobius 1 [0,-1] 1 [0, 1]", " -1."),
+        ("[0,1]", "[ 0. , 1.  ]"),
+        (":mobius 1 [0,-1] 1 [0, 1]", "  0."),
+        ("2", "  2."),
+        (":mobius 0 1 1 0", "  0.5"),
+        (":mobius 2 4 1 2", "Degenerate transform, a*d - b*c is zero!"),
+        (":mobius 1 2 3", "Expected four coefficients: a b c d!"),
+        ("@f(x, y) = x^2 + #sin(y)", "Defined @f(x, y)."),
+        ("@f(3, 0)", "  9."),
+        ("@f(3, @pi/2)", "  10."),
+        ("@f(1+1, 0)*2", "  8."),
+        ("@sq(@a) = @a*a", "Defined @sq(a)."),
+        ("@sq(@sq(3))", "  81."),
+        ("@hyp(a, b) = #sqrt(@sq(a) + @sq(b))", "Defined @hyp(a, b)."),
+        ("@hyp(3, 4)", "  5."),
+        ("@x=7", "@x =   7."),
+        ("@f(1, 0) + @x", "  8."),
+        ("@x", "  7."),
+        ("@f(1)", "@f expects 2 arguments!"),
+        ("@nope(1)", "Undefined function 'nope'!"),
+        ("@r(x) = @r(x)", "Defined @r(x)."),
+        ("@r(1)", "In @r: Recursion too deep!"),
+        ("@bad(x) = x +", "Incomplete expression!"),
+        ("@bad(2)", "Undefined function 'bad'!"),
+        ("1/3 =~ 0.333333333333", "Fail, absolute error 3.33 333 333 333 333 333 333 398~ :-13, relative error 1. 000 000 000 001 000 000 000 19~ :-12, epsilon 1.  :-24"),
+        ("&", "  0.333 333 333 333 333 333 333 333~"),
+        ("@pi =~ 22/7", "Fail, absolute error 1. 264 489 267 349 618 680 213 76~ :-3, relative error 4.02 337 494 156 696 852 795 287~ :-4, epsilon 1.  :-24"),
+        (":epsilon 0.01", "Epsilon set to 1.  :-2."),
+        ("@pi =~ 22/7", "Pass, absolute error 1. 264 489 267 349 618 680 213 76~ :-3, relative error 4.02 337 494 156 696 852 795 287~ :-4"),
+        ("[0,0.001] =~ 0", "Pass, absolute error 1.  :-3"),
+        (":epsilon [0,1]", "Epsilon must be a positive real number!"),
+        (":epsilon auto", "Epsilon is automatic, currently 1.  :-24."),
+        ("@e =~ 2.71828182845904523536028747", "Pass, absolute error 1. 352 662 259 372 797 758 401 38~ :-27, relative error 4.97 616 636 071 765 408 048 273~ :-28"),
+        ("1 =~ 1 +", "Incomplete expression!"),
+        ("10", "  10."),
+        ("20", "  20."),
+        ("30", "  30."),
+        ("&1", "  30."),
+        ("&3 + &2", "  50."),
+        ("&1", "  50."),
+        ("&6", "  10."),
+        ("&0", "Result references start at &1!"),
+        (":base G", "Base set to Hexadecimal (G)."),
+        ("&A", "  3.243 F6A 888 5A3 08D 313 198 A3~"),
+        (":base A", "Base set to Decimal (A)."),
+        ("#sinh 0", "  0."),
+        ("#cosh 0", "  1."),
+        ("#sinh 1", "  1.175 201 193 643 801 456 882 38~"),
+        ("#tanh 1", "  0.761 594 155 955 764 888 119 458~"),
+        ("#asinh #sinh 2", "  2."),
+        ("#acosh #cosh 2", "  2."),
+        ("#atanh 0.5", "  0.549 306 144 334 054 845 697 623~"),
+        ("#atanh 2", "[ 0.549 306 144 334 054 845 697 623~ , 1.570 796 326 794 896 619 231 32~ ]"),
+        ("#acosh 0", "[ 0. , 1.570 796 326 794 896 619 231 32~ ]"),
+        ("#cosh [0,1]", "  0.540 302 305 868 139 717 400 937~"),
+        (":degrees", "Angle units set to degrees."),
+        ("#sinh 1", "  1.175 201 193 643 801 456 882 38~"),
+        (":radians", "Angle units set to radians."),
+        ("5!", "  120."),
+        ("3!!", "  720."),
+        ("(2+1)!", "  6."),
+        ("#sqrt 4!", "  4.898 979 485 566 356 196 394 57~"),
+        ("0!", "  1."),
+        ("0.5!", "  0.886 226 925 452 758 013 649 084~"),
+        ("#gamma 0.5 - #sqrt @pi", "  0."),
+        ("#gamma [1,1]", "[ 0.498 015 668 118 356 042 713 691~ ,-0.154 949 828 301 810 685 124 955~ ]"),
+        ("#round((#gamma [1,1] * #gamma [0,-1] * [0,1] + @pi / #sinh @pi) * 10^30)", "  0."),
+        ("#gamma [-0.5,2]", "[-3.9 038 849 162 115 518 792 155 1~ :-2 ,-3.5 167 876 062 686 938 209 085 1~ :-2 ]"),
+        ("#gamma 0", "Gamma is undefined at non-positive integers!"),
+        ("(0-2)!", "Factorial is undefined for negative integers!"),
+        ("!3", "Invalid number!"),
+        ("3!2", "Invalid operator!"),
+        ("#sinh(1) - #sinh 1", "  0."),
+        ("#atanh(0.5) - #atanh 0.5", "  0."),
+        ("#gamma(5)", "  24."),
+        ("5 #choose 2", "  10."),
+        ("5 #perm 2", "  20."),
+        ("52 #choose 5", "  2 598 960."),
+        ("100 #choose 50", "  1.00 891 344 545 564 193 334 812~ : 29"),
+        ("2 #choose 5", "  0."),
+        ("-3 #choose 2", "  6."),
+        ("5 #choose -1", "  0."),
+        ("5 #perm 0", "  1."),
+        ("10 #perm 3 + 1", "  721."),
+        ("2 * 5 #choose 2", "  45."),
+        ("0.5 #choose 2", " -0.125"),
+        ("4.5 #perm 2", "  15.75"),
+        ("[4,1] #choose 2", "[ 5.5  , 3.5  ]"),
+        ("10^9 #choose (5*10^8)", "  1.1 639 149 768 938 399 382 394 3~ : 301029991"),
+        ("10^9 #perm (5*10^8)", "#perm result too large!"),
+        ("10^9 #choose (10^9 - 2)", "  499 999 999 500 000 000."),
+        ("-10^9 #choose (5*10^8)", "#choose result too large!"),
+        (":base 2", "Base set to Binary (2)."),
+        ("1100 #and 1010", "  1 000."),
+        ("1100 #or 1010", "  1 110."),
+        ("1100 #xor 1010", "  110."),
+        ("#not 0", " -1."),
+        ("1 << 100", "  10 000."),
+        ("10 000 >> 10", "  100."),
+        ("1 << -1", "  0."),
+        ("1 << 1 + 1", "  100."),
+        ("1 #or 10 #and 11", "  11."),
+        (":base G", "Base set to Hexadecimal (G)."),
+        ("FF #and #not F", "  F0."),
+        ("AB.CD #xor F", "  A4."),
+        ("-1 >> 4", " -1."),
+        ("[1,1] #and 1", "Bitwise operators need real operands!"),
+        ("1 << 1000000", "Shift amount too large!"),
+        (":base A", "Base set to Decimal (A)."),
+        (":acc #xor", "Accumulating with bitwise exclusive or."),
+        ("12", "  12."),
+        ("10", "  6."),
+        (":acc off", "Accumulator off, total:  6."),
+        (":base G", "Base set to Hexadecimal (G)."),
+        (":width 8", "Showing results as 8-bit registers."),
+        ("FE", "  FE. \nunsigned FE  signed -2"),
+        ("-1", " -1. \nunsigned FF  signed -1"),
+        ("7F + 1", "  80. \nunsigned 80  signed -80"),
+        ("123", "  123. \nunsigned 23  signed 23"),
+        ("5.9", "  5.9 \nunsigned 05  signed 5"),
+        ("[1,1]", "[ 1.  , 1.  ]"),
+        (":width 10", "Showing results as 10-bit registers."),
+        ("#not 0", " -1. \nunsigned FFFF  signed -1"),
+        (":base 2", "Base set to Binary (2)."),
+        (":width 100", "Showing results as 100-bit registers."),
+        ("101", "  101. \nunsigned 0101  signed 101"),
+        (":width 0", "Width must be between 1 and 10000000000 bits!"),
+        (":width off", "Register width display off."),
+        ("101", "  101."),
+        (":base A", "Base set to Decimal (A)."),
+        ("0.1", "  0.1"),
+        (":bits f64", "f64 0x3FB999999999999A\nsign 0, exponent 0x3FB (2^-4), mantissa 0x999999999999A\nrounding error 5. 551 115 123 125 782 697 303 33~ :-18, relative 5.5 511 151 231 257 826 973 033 3~ :-17"),
+        (":bits f32", "f32 0x3DCCCCCD\nsign 0, exponent 0x7B (2^-4), mantissa 0x4CCCCD\nrounding error 1. 490 116 119 384 765 625~ :-9, relative 1.4 901 161 193 847 656 25~ :-8"),
+        ("-1.5", " -1.5"),
+        (":bits F64", "f64 0xBFF8000000000000\nsign 1, exponent 0x3FF (2^0), mantissa 0x8000000000000\nrounding error 0., relative 0."),
+        ("0", "  0."),
+        (":bits f32", "f32 0x00000000\nsign 0, exponent 0x0 (zero), mantissa 0x000000\nrounding error 0."),
+        ("10^40", "  1.  : 40"),
+        (":bits f32", "f32 0x7F800000\nsign 0, exponent 0xFF (infinity), mantissa 0x000000\nOut of range, rounds to infinity."),
+        ("2^-140", "  7.17 464 813 734 306 340 312 95~ :-43"),
+        (":bits f32", "f32 0x00000200\nsign 0, exponent 0x0 (subnormal, 2^-126), mantissa 0x000200\nrounding error 0., relative 0."),
+        ("[1,1]", "[ 1.  , 1.  ]"),
+        (":bits f64", "Previous result must be real!"),
+        (":bits f16", "Expected f32 or f64!"),
+        (":base G", "Base set to Hexadecimal (G)."),
+        ("#fromf64 3FB999999999999A", "  0.199 999 999 999 9A"),
+        ("#fromf32 3DCCCCCD", "  0.199 999 A"),
+        ("#fromf64 BFF8000000000000", " -1.8"),
+        ("#fromf32 7F800000", "NaN"),
+        ("#fromf32 7FC00000", "NaN"),
+        ("#fromf32 1", "  8.  :-26"),
+        ("#fromf32 100000000", "Bit pattern must fit in 32 unsigned bits!"),
+        ("#fromf64 -1", "Bit pattern must fit in 64 unsigned bits!"),
+        (":base A", "Base set to Decimal (A)."),
+        ("#fromf64 4614256656552045848 - @pi", " -1.22 464 679 914 735 317 635 888~ :-16"),
+        ("255", "  255."),
+        (":show", "Binary:       11 111 111.\nOctal:        377.\nDecimal:      255.\nDozenal:      193.\nHexadecimal:  FF."),
+        (":show 2 G", "Binary:       11 111 111.\nHexadecimal:  FF."),
+        ("-1/3", " -0.333 333 333 333 333 333 333 333~"),
+        (":show 3 0 A", "Ternary:        -0.1\nHexatrigesimal: -0.C\nDecimal:        -0.333 333 333 333 333 333 333 333~"),
+        (":show 1", "Base must be between 2 and 36!\nUse ':base 0' for base 36 (Z+1)"),
+        (":show 2 #", "Invalid base value!"),
+        (":copy octal", "Expected hex or plain!"),
+        ("&", " -0.333 333 333 333 333 333 333 333~"),
+        (":interval", "Interval mode enabled"),
+        ("1/3", "  0.333 333 333 333 333 333 333 333~ +/- 9.  :-34"),
+        ("#sqrt 2 * #sin(@pi/4)", "  1.  +/- 3. 9  :-33"),
+        ("#ln(-1)", "[ 0. , 3.141 592 653 589 793 238 462 64~ ] +/- 2. 4  :-33"),
+        ("&", "[ 0. , 3.141 592 653 589 793 238 462 64~ ] +/- 0"),
+        ("2^-3", "  0.125  +/- 1. 1  :-33"),
+        ("#tan(@pi/2)", "  2.3 063 235 587 371 561 727 662~ : 34 No error bound thru a tangent near a pole"),
+        ("5!", "  120.  No error bound thru factorial"),
+        ("7 % 4", "  3.  No error bound thru %"),
+        (":interval x", "Invalid characters after command!"),
+        (":interval", "Interval mode disabled"),
+        ("1/3", "  0.333 333 333 333 333 333 333 333~"),
+        ("#sine 1", "Unknown operator '#sine', did you mean #sin?"),
+        ("#SQR 4", "Unknown operator '#sqr', did you mean #sqrt?"),
+        ("2 #chose 1", "Unknown operator '#chose', did you mean #choose?"),
+        ("#fromf33 1", "Unknown operator '#fromf33', did you mean #fromf32?"),
+        ("#nonsense 2", "Unknown operator '#nonsense'!"),
+        ("@py", "Unknown name '@py', did you mean @pi?"),
+        ("@gama", "Unknown name '@gama', did you mean @gamma?"),
+        ("@total = 5", "@total =   5."),
+        ("@totl + 1", "Unknown name '@totl', did you mean @total?"),
+        ("@q", "Undefined variable 'q'!"),
+        ("#sin1e", "Digit out of decimal (A) range!"),
+        ("@a = 3; @b = 4; #sqrt(@a^2 + @b^2)", "@a =   3.\n@b =   4.\n  5."),
+        ("1 + 1;", "  2."),
+        (":digits 5; 1/3", "Precision set to 5 digits.\n  0.333 33~"),
+        ("2; 3+; 4", "  2.\nIncomplete expression!"),
+        ("@h(x) = x^2; @h(3)", "Defined @h(x).\n  9."),
+        (":digits 24", "Precision set to 24 digits."),
+        (":theme LIGHT", "Theme set to light."),
+        (":theme no such", "Invalid theme name 'no such'!"),
+        (":theme nope", "Unknown theme 'nope'!"),
+        (":theme dark", "Theme set to dark."),
+        (":group 4 underscore; 1 234 567.891 011", "Grouping all digits in 4s with underscores.\n  123_4567.8910_11"),
+        (":group 3 comma integer; 1234567.891011", "Grouping integer digits in 3s with commas.\n  1,234,567.891011"),
+        (":group off; 1234567.89", "Digit grouping off.\n  1234567.89"),
+        (":repeat 9; :group 2 space all; 1000/7; :repeat off", "Showing repeating periods up to 9 digits.\nGrouping all digits in 2s with spaces.\n  1 42.(85 71 42)\nRepeating periods off."),
+        (":group 0", "Unknown grouping option '0'!"),
+        (":group 3; 1234567.891011", "Grouping all digits in 3s with spaces.\n  1 234 567.891 011"),
+        (":fixed 2; 1 234.5; 1/3; 3; [1.5, -2.25]", "Showing 2 places after the point.\n  1 234.50\n  0.33~\n  3.00\n[ 1.50  ,-2.25  ]"),
+        ("10^23; 10^24; 10^-30", "  100 000 000 000 000 000 000 000.00\n  1.  : 24\n  0.00~"),
+        (":fixed 0; 2.5", "Showing 0 places after the point.\n  3.~"),
+        (":fixed 30", "Places must be between 0 and the digits shown!"),
+        (":fixed off; 1.5", "Fixed places off.\n  1.5"),
+        (":expthreshold -6 30; 0.05; 0.000 123; 0.000 000 1; 10^25", "Writing out numbers from :-6 up to below :30.\n  0.05\n  0.000 123\n  1.  :-7\n  10 000 000 000 000 000 000 000 000."),
+        (":expthreshold 0 3; 0.5; 123; 1 234.567 8", "Writing out numbers from :0 up to below :3.\n  5.  :-1\n  123.\n  1. 234 567 8  : 3"),
+        (":expthreshold 3 1", "Thresholds must run low to high, within 1024 places of the point!"),
+        (":expthreshold 1.5 3", "Expected two whole thresholds: low high!"),
+        (":expthreshold off; 0.05", "Exponent thresholds back to default.\n  5.  :-2"),
+        (":digitset", "Digit set to standard."),
+        (":digitset Dozenal", "Digit set to dozenal."),
+        (":digitset roman", "Unknown digit set 'roman', use standard or dozenal!"),
+        (":digitset standard", "Digit set to standard."),
+        (":base C; ↋ + 1; ↊↋ - 1; 1↊ * 2; :base A", "Base set to Dozenal (C).\n  10.\n  AA.\n  38.\nBase set to Decimal (A)."),
+        (":digitcase", "Digits from ten up in uppercase."),
+        (":digitcase Lower; :base G; FF + ab; -C0 / 2; :base A", "Digits from ten up in lowercase.\nBase set to Hexadecimal (G).\n  1aa.\n -60.\nBase set to Decimal (A)."),
+        (":digitcase title", "Unknown digit case 'title', use upper or lower!"),
+        (":digitcase upper", "Digits from ten up in uppercase."),
+        (":color off", "Colour off."),
+        ("1/4", "  0.25"),
+        (":COLOUR on", "Colour on."),
+        (":color maybe", "Expected on, off or auto!"),
+        (":color", "Colour on."),
+        (":color auto", "Colour automatic, off when piped or with NO_COLOR set."),
+        (":const", "No constants defined."),
+        (":const vf 0.7", "Constant @vf = 0.7"),
+        (":const @Cal 1/4 + 1", "Constant @cal = 1.25"),
+        ("@vf * 2", "  1.4"),
+        ("@cal", "  1.25"),
+        ("@vf = 3", "@vf is a constant, change it with :const!"),
+        ("@vf(2)", "Expected operator!"),
+        (":const", "@vf = 0.7\n@cal = 1.25"),
+        (":const vf", "@vf = 0.7"),
+        (":const vf 0.75", "Constant @vf = 0.75"),
+        ("@vf", "  0.75"),
+        (":const pi 3", "@pi is already built in!"),
+        (":const 2x 3", "Constant names must start with a letter!"),
+        (":const vf 3+", "Incomplete expression!"),
+        ("@w = 2", "@w =   2."),
+        (":const w @w^2", "Constant @w = 4."),
+        ("@w", "  4."),
+        ("@vff", "Unknown name '@vff', did you mean @vf?"),
+        (":const vf off", "Removed constant @vf."),
+        (":const cal off", "Removed constant @cal."),
+        (":const w off", "Removed constant @w."),
+        (":const vf off", "Undefined constant 'vf'!"),
+        ("@vf", "Unknown name '@vf', did you mean @f?"),
+        ("@phys.c", "  299 792 458."),
+        ("@phys.k_B * @phys.N_A - @phys.R", "  0."),
+        ("@phys.hbar * 2 * @pi - @phys.h", "  0."),
+        ("@PHYS.kb", "  1.3 806 49  :-23"),
+        ("@phys.sigma", "  5.6 703 744 191 844 294 539 71~ :-8"),
+        ("2 * @phys.g_n", "  19.613 3"),
+        ("@phys.hbr", "Unknown physical constant 'hbr', did you mean @phys.hbar?"),
+        ("@phys.planck", "Unknown physical constant 'planck', see :constants!"),
+        ("@phys.", "Expected a physical constant, see :constants!"),
+        (":base 2; @phys.atm; :base A", "Base set to Binary (2).\n  11 000 101 111 001 101.\nBase set to Decimal (A)."),
+        (":constants", "@phys.c     Speed of light in vacuum          299 792 458. m/s\n@phys.h     Planck constant                   6.62 607 015  :-34 J s\n@phys.hbar  Reduced Planck constant           1.05 457 181 764 615 639 126 243~ :-34 J s\n@phys.e     Elementary charge                 1.60 217 663 4  :-19 C\n@phys.k_B   Boltzmann constant                1.3 806 49  :-23 J/K\n@phys.N_A   Avogadro constant                 602 214 076 000 000 000 000 000. 1/mol\n@phys.R     Molar gas constant                8.314 462 618 153 24 J/(mol K)\n@phys.sigma Stefan-Boltzmann constant         5.6 703 744 191 844 294 539 71~ :-8 W/(m^2 K^4)\n@phys.G     Newtonian constant of gravitation 6.6 743  :-11 m^3/(kg s^2)\n@phys.g_n   Standard acceleration of gravity  9.806 65 m/s^2\n@phys.atm   Standard atmosphere               101 325. Pa\n@phys.m_e   Electron mass                     9.10 938 370 15  :-31 kg\n@phys.m_p   Proton mass                       1. 672 621 923 69  :-27 kg\n@phys.m_n   Neutron mass                      1. 674 927 498 04  :-27 kg\n@phys.m_u   Atomic mass constant              1. 660 539 066 6  :-27 kg\n@phys.eps0  Vacuum electric permittivity      8. 854 187 812 8  :-12 F/m\n@phys.mu0   Vacuum magnetic permeability      1. 256 637 062 12  :-6 N/A^2\n@phys.alpha Fine-structure constant           7. 297 352 569 3  :-3"),
+        (":constants now", "Invalid argument!"),
+        ("3 m / 2 s", "  1.5  m/s"),
+        ("5 kg * 9.81 m/s^2", "  49.05  N"),
+        ("#to(60 km/h, \"mph\")", "  37.282 271 534 240 038 177 046 1~ mph"),
+        ("#to(1 kWh, \"J\")", "  3 600 000.  J"),
+        ("2 m^2 * 3 m", "  6.  m^3"),
+        ("#sqrt(16 m^2)", "  4.  m"),
+        ("3 km + 200 m", "  3 200.  m"),
+        ("1 m / 4 m", "  0.25"),
+        ("3 \"J/(mol K)\" * 2 mol", "  6.  kg m^2/(s^2 K)"),
+        ("@dist = 3 ft", "@dist =   0.914 4  m"),
+        ("@dist / 2 s", "  0.457 2  m/s"),
+        ("@dist = 5", "@dist =   5."),
+        ("@dist * 2", "  10."),
+        ("1 m + 2 s", "Units don't match, m and s!"),
+        ("#sin(3 m)", "#sin needs a value without units, not m!"),
+        ("3 m ^ 2.5", "Units can only be raised to whole powers!"),
+        ("3 m^2000000000 * 1 m^2000000000", "Unit exponent too large!"),
+        ("(2 m^2)^(10^9)", "Unit exponent too large!"),
+        ("1 \"m^-2147483648\"", "Unit exponent too large!"),
+        ("#to(3 m, \"s\")", "Can't convert m to s!"),
+        ("#to(3 m)", "#to expects a value and a unit!"),
+        ("3 \"xyz\"", "Unknown unit 'xyz'!"),
+        ("3 xyz", "Digit out of decimal (A) range!"),
+        (":interval; 3 m; :interval", "Interval mode enabled\n  3.  m No error bound thru units\nInterval mode disabled"),
+        (":base G; 10 \"m\"; 10 s; :base A", "Base set to Hexadecimal (G).\n  10.  m\n  10.  s\nBase set to Decimal (A)."),
+        (":degrees", "Angle units set to degrees."),
+        ("12°34'56.7\"", "  12.582 416 666 666 666 666 666 7~"),
+        ("12d34m56.7s", "  1 038 896.7  s"),
+        ("-12°30'", " -12.5"),
+        ("1°30\"", "  1.008 333 333 333 333 333 333 33~"),
+        ("#sin 30°", "  0.5"),
+        ("#dms 12°34'56.7\"", "  12.345 67"),
+        ("#dms(1/3)", "  0.2"),
+        ("#dms -12.5", " -12.3"),
+        ("#dms [1, 2]", "#dms needs a real angle!"),
+        ("12° 30", "Expected ' or \" after angle!"),
+        ("12°3.4.5'", "Multiple decimals in number!"),
+        (":radians; 180°; #dms @pi; :degrees", "Angle units set to radians.\n  3.141 592 653 589 793 238 462 64~\n  180.\nAngle units set to degrees."),
+        (":base G; #dms 12.8; 12°1E'; :base A", "Base set to Hexadecimal (G).\n  12.1E\n  12.8\nBase set to Decimal (A)."),
+        (":base 2; #dms 1; :base A", "Base set to Binary (2).\n#dms needs base 8 or above!"),
+        // Polar literals and display, the angle in the current units
+        (":base A", "Base set to Decimal (A)."),
+        ("[2; 90]", "[ 0. , 2.  ]"),
+        ("[2; 30]", "[ 1.732 050 807 568 877 293 527 45~ , 1.  ]"),
+        ("[-1; 45]", "[-0.707 106 781 186 547 524 400 844~ ,-0.707 106 781 186 547 524 400 844~ ]"),
+        ("[1; -180]", " -1."),
+        ("[3,4]; [1; 2]", "[ 3.  , 4.  ]\n[ 0.999 390 827 019 095 730 006 243~ , 3.4 899 496 702 500 971 645 995 2~ :-2 ]"),
+        ("[1; 2; 3]", "Unexpected ';'!"),
+        ("[1, 2; 3]", "Unexpected ';'!"),
+        ("[1;]", "Missing angle!"),
+        ("[;1]", "Missing magnitude!"),
+        (":polar", "Polar display enabled"),
+        ("[3,4]", "[ 5.  ; 53.130 102 354 155 978 703 144 4~ ]"),
+        ("[-1,-1]", "[ 1.414 213 562 373 095 048 801 69~ ;-135.  ]"),
+        ("[2; 30] * [3; 60]", "[ 6.  ; 90.  ]"),
+        ("-5", " -5."),
+        (":gradians; [0,1]; :degrees", "Angle units set to gradians.\n[ 1.  ; 100.  ]\nAngle units set to degrees."),
+        (":polar x", "Invalid characters after command!"),
+        (":polar", "Polar display disabled"),
+        (":mouse", "Mouse clicks at the prompt disabled"),
+        (":Mouse x", "Invalid characters after command!"),
+        (":mouse", "Mouse clicks at the prompt enabled"),
+        (":json", "JSON output off."),
+        (":json on", "JSON output on."),
+        ("@j = 1/4", "{\"name\":\"j\",\"real\":\"0.25\",\"imag\":\"0\",\"approximate\":false,\"base\":10,\"digits\":24}"),
+        ("1/3 + [0, 2]", "{\"real\":\"0.333333333333333333333333\",\"imag\":\"2\",\"approximate\":true,\"base\":10,\"digits\":24}"),
+        ("{1, -@j}", "{\"list\":[{\"real\":\"1\",\"imag\":\"0\",\"approximate\":false},{\"real\":\"-0.25\",\"imag\":\"0\",\"approximate\":false}],\"base\":10,\"digits\":24}"),
+        (":JSON maybe", "Expected on or off!"),
+        (":json off", "JSON output off."),
+        (":latex", "\\approx \\left[0.333333333333333333333333,\\ 2\\right]"),
+        ("-1/8", " -0.125"),
+        (":latex", "-0.125"),
+        ("5^-25", "  3. 355 443 2  :-18"),
+        (":LaTeX", "3.3554432 \\times 10^{-18}"),
+        (":latex input", "No expression in the history to show!"),
+        (":latex output", "Expected input!"),
+        (":history", "Keeping the last 1000 history entries, 0 so far."),
+        (":history max 50", "Keeping the last 50 history entries, 0 so far."),
+        (":history max -1", "History max can't be negative!"),
+        (":history min 5", "Expected max!"),
+        (":export", "Expected history, md or csv!"),
+        (":export csv", "Expected a file to export to!"),
+        ("@sq := @side^2", "In @sq: Undefined variable 'side'!"),
+        ("@pi := 3", "@pi is already built in!"),
+        ("@side = 3", "@side =   3."),
+        ("@sq := @side^2", "@sq := @side^2, now 9."),
+        ("@side = 4", "@side =   4."),
+        ("@sq + 1", "  17."),
+        ("@side := @sq - 1", "Circular definition of @side!"),
+        ("@side", "  4."),
+        ("@sq = 2", "@sq =   2."),
+        ("@side = 5", "@side =   5."),
+        ("@sq", "  2."),
+        ("@sq := @side^2", "@sq := @side^2, now 25."),
+        (":rename side span", "Renamed @side to @span."),
+        ("@span = 6", "@span =   6."),
+        ("@sq", "  36."),
+        ("@side", "Undefined variable 'side'!"),
+        (":rename sq span", "@span already exists!"),
+        (":unset span", "Removed @span."),
+        ("@span", "Undefined variable 'span'!"),
+        ("@sq", "In @sq: Undefined variable 'span'!"),
+        (":unset @sq", "Removed @sq."),
+        (":unset sq", "Undefined variable 'sq'!"),
+        ("@wd = 2; @wd_len = 10; @ar := @wd_len + @wd + 0*1°", "@wd =   2.\n@wdlen =   10.\n@ar := @wd_len + @wd + 0*1°, now 12."),
+        (":rename wd ht; @wd_len = 20; @ar", "Renamed @wd to @ht.\n@wdlen =   20.\n  22."),
+        (":unset ar; :unset wdlen; :unset ht", "Removed @ar.\nRemoved @wdlen.\nRemoved @ht."),
+        ("@k1 = 3", "@k1 =   3."),
+        (":note k1 \"clock divider ratio\"", "Noted @k1."),
+        (":note @k1", "@k1: clock divider ratio"),
+        (":note k1 \"ratio; see datasheet\"; :note k1", "Noted @k1.\n@k1: ratio; see datasheet"),
+        (":note k1 divider", "Expected a note in quotes!"),
+        (":note k1 off", "Removed the note on @k1."),
+        (":note k1", "@k1 has no note."),
+        (":note nope \"x\"", "Undefined variable 'nope'!"),
+        (":unset k1", "Removed @k1."),
+        ("@cal = 1.5", "@cal =   1.5"),
+        (":lock cal", "@cal locked."),
+        ("@cal = 2", "@cal is locked, :unlock it to change it!"),
+        ("@cal := 2", "@cal is locked, :unlock it to change it!"),
+        (":unset cal", "@cal is locked, :unlock it to remove it!"),
+        ("@cal * 2", "  3."),
+        (":lock", "Locked: @cal"),
+        (":unlock @cal", "@cal unlocked."),
+        (":lock", "No variables are locked."),
+        (":unset cal", "Removed @cal."),
+        ("@re, @im = #sqrt(-4) + 3", "@re = 3., @im = 2."),
+        ("@im - @re", " -1."),
+        ("@re, @re = 1", "The two parts need different variables!"),
+        ("@re, @im = {1, 2}", "Expected a single value, not a list!"),
+        ("2 + 3 < 1 + 5", "  1."),
+        ("3 > 4", "  0."),
+        ("0.1 + 0.2 == 0.3", "  1."),
+        ("10^-30 > 0; 10^-30 == 0; 1 + 10^-30 != 1", "  1.\n  0.\n  1."),
+        ("3! != 6", "  0."),
+        ("2 <= 2", "  1."),
+        ("-1 >= -2", "  1."),
+        ("1 << 4", "  16."),
+        ("[1, 1] == [1, 1]", "  1."),
+        ("[1, 1] < 2", "< needs real numbers, complex ones have no order!"),
+        ("{1, 2, 3} > 2", "{ 0. , 0. , 1.  }"),
+        ("3 m < 2 s", "Units don't match, m and s!"),
+        (":watch", "Nothing is being watched."),
+        (":watch :base 2", "Commands can't be watched!"),
+        (":watch @x + 1", "Watching @x + 1"),
+        (":watch", "Watching @x + 1"),
+        (":watch clear", "Watches cleared."),
+        ("2", "  2."),
+        ("-8", " -8."),
+        ("[3, 4]", "[ 3.  , 4.  ]"),
+        (":spark 3", "▁█▅  2. to 8."),
+        (":spark 0", "Result count must be a positive integer!"),
+        (":fractal 1 0", "The zoom must be real and above zero!"),
+        (":fractal julia", "Expected the Julia constant!"),
+        (":fractal 1 2 3", "Expected a center and a zoom!"),
+        (":cplot @z", "Expected an expression and a variable!"),
+        (":cplot @z @z from [1, 0]", "Expected from and to!"),
+        (":cplot @z @z from [1, 0] to [0, 2]", "The corners must run from lower left to upper right!"),
+        (":plot @x from 0 to 1", "Expected an expression, a variable, from and to!"),
+        (":plot @x @x from 1 to 0", "The range must run from low to high!"),
+        (":plot @x @x from [0, 1] to 1", "The range must be real!"),
+        (":plot #ln(@x) @x from -2 to -1", "Nothing to plot, the expression isn't real anywhere in the range!"),
+        (":export md", "Expected a file to export to!"),
+        (":export MD notes.md", "Nothing entered this session to export!"),
+        (":export history", "Expected a file to export to!"),
+        (":import  ", "Expected a file to import from!"),
+        (":record", "Not recording."),
+        (":stop", "Not recording!"),
+        (":replay", "Expected a transcript to replay!"),
+        (":save", "Expected a name to save under!"),
+        (":save x/y", "Invalid sequence name 'x/y'!"),
+        (":save sequence 0", "Expected a positive count of entries!"),
+        (":run ../state", "Invalid sequence name '../state'!"),
+        (":workspace load", "Invalid workspace name ''!"),
+        (":workspace swap hex", "Expected save or load!"),
+        (":history max 1 000", "Keeping the last 1000 history entries, 0 so far."),
+        (":interval; [1; 1000000]; :interval", "Interval mode enabled\n[ 0.173 648 177 666 930 348 851 717~ ,-0.984 807 753 012 208 059 366 743~ ] +/- 3.5  :-29\nInterval mode disabled"),
+        (":radians", "Angle units set to radians."),
+        ("[1; @pi/2]", "Invalid operator!"),
+        // Lists, element by element
+        ("{1, 2, 3}", "{ 1.  , 2.  , 3.  }"),
+        ("{1, 2, 3} * 2", "{ 2.  , 4.  , 6.  }"),
+        ("{1, 2} + {10, 20}", "{ 11.  , 22.  }"),
+        ("1 - {1, [0, 1]}", "{ 0. ,[ 1.  ,-1.  ] }"),
+        ("#sqrt{4, -1}", "{ 2.  ,[ 0. , 1.  ] }"),
+        ("(1 + {1, 2})!", "{ 2.  , 6.  }"),
+        ("-{1, 2}^2", "{ 1.  , 4.  }"),
+        ("@lv = {1, 2, 3}", "@lv = { 1.  , 2.  , 3.  }"),
+        ("@lv / @lv", "{ 1.  , 1.  , 1.  }"),
+        ("5; {1, 2}; &", "  5.\n{ 1.  , 2.  }\n  5."),
+        ("@lv = 5", "@lv =   5."),
+        ("{1, 2} + {1, 2, 3}", "Lists have different lengths, 2 and 3!"),
+        ("{}", "Empty list!"),
+        ("{1,,2}", "Missing element!"),
+        ("{1, 2", "Mismatched braces!"),
+        ("{{1, 2}, 3}", "Lists can't hold other lists!"),
+        ("{1 m, 2}", "Lists can't hold values with units!"),
+        ("{1, 2} m", "Lists can't hold values with units!"),
+        ("@lf(x) = x + 1; @lf({1, 2})", "Defined @lf(x).\nFunctions take and give lone values, not lists!"),
+        ("{1, 2} =~ {1, 2}", "Expected a single value, not a list!"),
+        (":interval; {1, 2}; :interval", "Interval mode enabled\n{ 1.  , 2.  } No error bound thru lists\nInterval mode disabled"),
+        // Statistics over lists, and ranges to make them from
+        ("{1..5}", "{ 1.  , 2.  , 3.  , 4.  , 5.  }"),
+        ("{3..1, 10}", "{ 3.  , 2.  , 1.  , 10.  }"),
+        ("#sum{1..10}", "  55."),
+        ("#prod{1..5}", "  120."),
+        ("#mean{1, 2, 3, 4}", "  2.5"),
+        ("#median{3, 1, 2}", "  2."),
+        ("#median{4, 1, 3, 2}", "  2.5"),
+        ("#var{1, 2, 3, 4}", "  1.666 666 666 666 666 666 666 67~"),
+        ("#stdev{2, 4, 4, 4, 5, 5, 7, 9}", "  2.138 089 935 299 395 077 476 43~"),
+        ("#min{3, -1, 2}", " -1."),
+        ("#max{3, -1, 2}", "  3."),
+        ("#sum{[1, 1], [2, -1]}", "  3."),
+        ("#sum({1, 2} * 2) + 1", "  7."),
+        ("{#sum{1..3}, 2}", "{ 6.  , 2.  }"),
+        ("#sum 5", "  5."),
+        ("#stdev 5", "#stdev needs at least two values!"),
+        ("#max{[1, 1], 2}", "#max needs real values!"),
+        ("{1..}", "Ranges need a start and an end!"),
+        ("{1.5..3}", "Ranges need whole numbers at each end!"),
+        ("{1..2000000}", "Range too long!"),
+        // Series over an index
+        ("#sum(k, 1, 100, 1/k^2)", "  1.634 983 900 184 892 865 077 17~"),
+        ("#prod(k, 1, 5, k)", "  120."),
+        ("#sum(k, 1, 0, k); #prod(k, 1, 0, k)", "  0.\n  1."),
+        ("#sum(@k, 1, 3, @k^2)", "  14."),
+        (":degrees; @f(x) = #sin(x + 30°); @f(60); #sum(k, 1, 2, k*90°); :radians", "Angle units set to degrees.\nDefined @f(x).\n  1.\n  270.\nAngle units set to radians."),
+        ("#sum(i, 1, 3, #sum(j, 1, i, j))", "  10."),
+        ("@sf(x) = #sum(k, 1, x, k*x); @sf(3)", "Defined @sf(x).\n  18."),
+        ("@k = 7; #sum(k, 1, 3, k); @k", "@k =   7.\n  6.\n  7."),
+        ("#sum(k, 1, 3, k) * 2", "  12."),
+        ("#sum(1, 2)", "#sum expects a list, or an index, start, end and term!"),
+        ("#sum(k+1, 1, 3, k)", "Invalid index name 'k+1'!"),
+        ("#sum(k, 1.5, 3, k)", "#sum bounds must be whole numbers!"),
+        ("#sum(k, 1, 3, {k, 1})", "Series terms must be lone values, not lists!"),
+        (":interval; #sum(k, 1, 3, k); :interval", "Interval mode enabled\n  6.  No error bound thru series\nInterval mode disabled"),
+        // Root finding
+        (":solve @x^2 - 2 @x 1; &^2", "@x = 1.414 213 562 373 095 048 801 69~, residual 0.\n  2."),
+        (":solve #cos(@x) - @x @x 1", "@x = 0.739 085 133 215 160 641 655 312~, residual -1.92 592 994 438 723 585 305 598~ :-34"),
+        (":solve @x^3 - @x - 2 @x -3", "@x = 1.521 379 706 804 567 569 604 08~, residual 4.09 067 520 187 848 895 189 09~ :-31"),
+        (":solve #atan(@x) @x 3", "@x = 3.82 883 565 758 756 201 070 082~ :-73, residual 3.82 883 565 758 756 201 070 082~ :-73"),
+        (":solve @x^2 + 1 @x [1,1]", "@x = [ 0. , 1.  ], residual 0."),
+        ("@x = 9; :solve @x - 1 @x 0; @x", "@x =   9.\n@x = 1., residual 0.\n  9."),
+        (":solve @x^2 + 1 @x 1", "No root found near the guess!"),
+        (":solve @x^2 - 2 @x", "Expected an expression, a variable and a guess!"),
+        (":solve @x^2 - 2 @1x 1", "Invalid variable name!"),
+        (":solve @z^2 - 2 @y 1", "Undefined variable 'z'!"),
+        (":solve {@x, 1} @x 1", "Expected a single value, not a list!"),
+        // Integrals
+        ("#integrate(@x^2, @x, 0, 1)", "  0.333 333 333 333 333 333 333 333~"),
+        ("#integrate(#sin(x), x, 0, @pi)", "  2."),
+        ("#integrate(4/(1+@x^2), @x, 0, 1)", "  3.141 592 653 589 793 238 462 64~"),
+        ("#integrate(1/#sqrt(@x), @x, 0, 1)", "  2."),
+        ("#integrate(#ln(@x), @x, 0, 1)", " -1."),
+        ("#integrate(1/@z, @z, 1, [0,1])", "[ 0. , 1.570 796 326 794 896 619 231 32~ ]"),
+        ("#integrate(@x, @x, 2, 0); #integrate(@x, @x, 1, 1)", " -2.\n  0."),
+        ("#integrate(#integrate(@x*@y, @y, 0, 1), @x, 0, 2)", "  1."),
+        ("#integrate(1/@x, @x, 0, 1)", "#integrate didn't converge!"),
+        ("#integrate(@x, 1+x, 0, 1)", "Invalid variable name '1+x'!"),
+        ("#integrate(@x, @x, 0)", "#integrate expects an expression, variable, start and end!"),
+        ("#integrate(@x, @x, 0, 1/0)", "#integrate bounds must be finite!"),
+        ("#integrate({@x, 1}, @x, 0, 1)", "Expected a single value, not a list!"),
+        // Derivatives
+        ("#deriv(@x^3, @x, 2)", "  12."),
+        ("#deriv(#sin(x), x, 1) - #cos(1)", "  0."),
+        ("#deriv(#ln(@x), @x, 0.001)", "  1 000."),
+        ("#deriv(#gamma(@x), @x, 1)", " -0.577 215 664 901 532 860 606 512~"),
+        ("#deriv(@x^2, @x, [1,1])", "[ 2.  , 2.  ]"),
+        ("#deriv(#deriv(@x^3, @x, @y), @y, 1)", "  6."),
+        ("#deriv(1/@x, @x, 0)", "#deriv didn't converge, error about 1 024.!"),
+        ("#deriv(@x, @x)", "#deriv expects an expression, variable and point!"),
+        ("#deriv(@x, 2x, 1)", "Invalid variable name '2x'!"),
+        (":interval; #deriv(@x^2, @x, 1); :interval", "Interval mode enabled\n  2.  No error bound thru derivatives\nInterval mode disabled"),
+        // Iteration
+        ("#iterate(#cos(@x), @x, 1, 100)", "  0.739 085 133 215 160 643 515 701~"),
+        ("#iterate((@x + 2/@x)/2, @x, 1, 6)", "  1.414 213 562 373 095 048 801 69~"),
+        ("#iterate(@x+1, @x, 0, 0)", "  0."),
+        ("#orbit(@x^2, @x, 2, 4)", "{ 2.  , 4.  , 16.  , 256.  , 65 536.  }"),
+        ("#orbit(3.5*@x*(1-@x), @x, 0.5, 3)", "{ 0.5  , 0.875  , 0.382 812 5  , 0.826 934 814 453 125  }"),
+        ("#sum(#orbit(@x*2, @x, 1, 10))", "  2 047."),
+        ("@f(n) = #iterate(@x+n, @x, 0, 3); @f(2)", "Defined @f(n).\n  6."),
+        ("#iterate(@x+1, @x, 0, -1)", "#iterate count must be a whole number!"),
+        ("#orbit(@x+1, @x, 0, 2.5)", "#orbit count must be a whole number!"),
+        ("#iterate(@x, @x, 0)", "#iterate expects an expression, variable, start and count!"),
+        ("#orbit(@x, @x, 0, 2000000)", "Orbit too long!"),
+        ("#iterate({@x}, @x, 0, 3)", "Expected a single value, not a list!"),
+        (":interval; #iterate(@x+1, @x, 0, 3); :interval", "Interval mode enabled\n  3.  No error bound thru iteration\nInterval mode disabled"),
+        // Repeating fractions
+        (":repeat 6", "Showing repeating periods up to 6 digits."),
+        ("1/3", "  0.(3)"),
+        ("1/6", "  0.1(6)"),
+        ("-22/7", " -3.(142 857)"),
+        ("1/70", "  0.0(14 285 7)"),
+        ("1/8", "  0.125"),
+        ("(1 - #sqrt-1)/6", "[ 0.1(6)  ,-0.1(6)  ]"),
+        ("{1/3, 2/3}", "{ 0.(3)  , 0.(6)  }"),
+        ("1/13", "  0.(076 923)"),
+        (":repeat 5; 1/7", "Showing repeating periods up to 5 digits.\n  0.142 857 142 857 142 857 142 857~"),
+        (":repeat 0", "Period must be at least 1 digit!"),
+        (":repeat off", "Repeating periods off."),
+        ("1/3", "  0.333 333 333 333 333 333 333 333~"),
+        // Balanced bases
+        (":base 3 balanced", "Base set to Balanced ternary (3)."),
+        ("1Z", "  1Z."),
+        ("Z11", "  Z11."),
+        ("-1Z", "  Z1."),
+        ("1Z + 1", "  10."),
+        ("1Z.1", "  1Z.1"),
+        ("0.1 * 10", "  1."),
+        ("10^1010", "  1.  : 1010"),
+        ("1/1000", "  1.  :-10"),
+        ("2", "Digit out of balanced ternary (3) range!"),
+        (":base 4 balanced", "Balanced bases must be odd!"),
+        (":base 3 balanced x", "Invalid characters after base value!"),
+        (":base A", "Base set to Decimal (A)."),
+        ("-5", " -5."),
+        // Irrational bases
+        (":base @phi", "Showing results in base @phi, entry stays Decimal (A)."),
+        ("2", "  10.01"),
+        ("3", "  100.01"),
+        ("-4", " -101.01"),
+        ("@phi", "  10."),
+        ("@phi^-2", "  1.  :-2"),
+        (":base @e", "Showing results in base @e, entry stays Decimal (A)."),
+        ("@e^2 + 1", "  101."),
+        (":repeat 6; 1/3", "Showing repeating periods up to 6 digits.\n  2.1 012 102 010 201 001 010 000 1~ :-2"),
+        (":repeat off", "Repeating periods off."),
+        (":base @pi", "Irrational base must be @phi or @e!"),
+        (":base A", "Base set to Decimal (A)."),
+        ("2", "  2."),
+        // Times
+        ("93784.5; :hms", "  93 784.5\n1d 02:03:04.5"),
+        ("-61; :sexagesimal", " -61.\n-00:01:01"),
+        ("3*24*3600; :hms", "  259 200.\n3d 00:00:00"),
+        ("1/4; :hms", "  0.25\n00:00:00.25"),
+        ("59.9999999999999999999999999; :hms", "  60.~\n00:01:00"),
+        (":base C; 2151; :hms; :base A", "Base set to Dozenal (C).\n  2 151.\n01:01:01\nBase set to Decimal (A)."),
+        ("[1, 1]; :hms", "[ 1.  , 1.  ]\nTime needs a real previous result in seconds!"),
+        (":hms x", "Invalid characters after command!"),
+        // Dates
+        ("#date(2000, 1, 1)", "  946 684 800."),
+        ("#civil(946684800)", "{ 2 000.  , 1.  , 1.  }"),
+        ("#civil(-1)", "{ 1 969.  , 12.  , 31.  }"),
+        ("(#date(2024, 3, 1) - #date(2024, 2, 1)) / 86400", "  29."),
+        ("#jd #date(2000, 1, 1)", "  2 451 544.5"),
+        ("#unix 2440588", "  43 200."),
+        ("#date(2023, 2, 29)", "Invalid date!"),
+        ("#date(1.5, 1, 1)", "#date needs a whole year, month and day!"),
+        ("#date(2000, 1)", "#date expects a year, month and day!"),
+        ("#date 1", "#date expects a year, month and day in parentheses!"),
+        ("#civil(1, 2)", "#civil expects a Unix time!"),
+        // Durations
+        ("3h15m30s; :hms", "  11 730.  s\n03:15:30"),
+        ("250ms", "  0.25  s"),
+        ("2d", "  172 800.  s"),
+        ("-1h30m", " -5 400.  s"),
+        ("1h + 250ms", "  3 600.25  s"),
+        ("3m; 15min; 2s^2", "  3.  m\n  900.  s\n  2.  s^2"),
+        (":base G; 2d; 1h; :base A", "Base set to Hexadecimal (G).\n  2D.\n  E10.  s\nBase set to Decimal (A)."),
+        ("30m1h", "Duration parts must go from days down!"),
+        ("1h1.2.3m", "Multiple decimals in number!"),
+        // Percentages
+        ("120 + 15%", "  138."),
+        ("120 - 15%", "  102."),
+        ("200 * 15%; 15%", "  30.\n  0.15"),
+        ("1 + 2*60 + 15%", "  139.15"),
+        ("(120 + 15%) * 2", "  276."),
+        ("100 + (10+5)%", "  115."),
+        ("{100, 200} + 10%", "{ 110.  , 220.  }"),
+        ("3 m + 10%", "  3.3  m"),
+        ("17 % 5; 17 % -5", "  2.\n -3."),
+        ("#pct(30, 120)", "  25."),
+        ("#pct(1, 0)", "#pct of zero is undefined!"),
+        ("#pct(3, 4, 5)", "#pct expects a part and a whole!"),
+        ("#if(2 > 1, 10, 20)", "  10."),
+        ("#if(0, 10, 20) + 1", "  21."),
+        ("#if(1, 5, #pct(1, 0))", "  5."),
+        ("@fa(n) = #if(n < 1, 1, n*@fa(n - 1)); @fa(5)", "Defined @fa(n).\n  120."),
+        ("@tax(x) = #if(x < 100, x/10, 10 + (x - 100)/5); @tax(50); @tax(200)", "Defined @tax(x).\n  5.\n  30."),
+        ("#if(1/0, 1, 2)", "#if condition is undefined!"),
+        ("#if(1, 2)", "#if expects a condition, a then and an else!"),
+        ("@x = 0.5; #and(@x > 0, @x < 1)", "@x =   0.5\n  1."),
+        ("#and(1, 2, 0)", "  0."),
+        ("#or(0, 0)", "  0."),
+        ("#or(0, -3)", "  1."),
+        ("#not(0); #not (2 == 2)", "  1.\n  0."),
+        ("#and(0, #pct(1, 0))", "  0."),
+        ("#or(1, #pct(1, 0))", "  1."),
+        ("#if(#and (2 > 1, 3 > 2), 7, 8)", "  7."),
+        ("2#not(0)", "  2."),
+        ("#and(1)", "#and expects two or more conditions!"),
+        ("#not(1, 0)", "#not expects one condition!"),
+        ("#or(1/0, 1)", "#or condition is undefined!"),
+        ("12 #and 10; 12#or(3); 12 #and (10); #not 0", "  8.\n  15.\n  8.\n -1."),
+        ("#clamp(5, 0, 3); #clamp(-2, 0, 3); #clamp(1.5, 0, 3)", "  3.\n  0.\n  1.5"),
+        ("#clamp(1, 3, 0)", "#clamp low is above the high!"),
+        ("#clamp([1, 1], 0, 3)", "#clamp needs real values!"),
+        ("#lerp(10, 20, 0.25); #lerp(10, 20, 1.5)", "  12.5\n  25."),
+        ("#lerp([0, 0], [2, 4], 0.5)", "[ 1.  , 2.  ]"),
+        ("#maprange(5, 0, 10, 100, 200)", "  150."),
+        ("#maprange(0, -1, 1, 0, 255)", "  127.5"),
+        ("#maprange(1, 2, 2, 0, 1)", "#maprange can't map from an empty range!"),
+        ("#lerp(1, 2)", "#lerp expects a start, an end and a fraction!"),
+        // Implicit multiplication
+        ("2@pi", "  6.283 185 307 179 586 476 925 29~"),
+        ("3(4+5)", "  27."),
+        ("2#sqrt4", "  4."),
+        ("@x = 3; 2@x^2", "@x =   3.\n  18."),
+        ("-2#sum{1, 2}", " -6."),
+        ("1/2@pi", "  1.570 796 326 794 896 619 231 32~"),
+        ("6#choose2; 2#or 1", "  15.\n  3."),
+        (":base G; A(2); :base A", "Base set to Hexadecimal (G).\n  14.\nBase set to Decimal (A)."),
+        ("2 @pi", "Invalid operator!"),
+        ("(2)(3)", "Expected operator!"),
+        // atan2
+        ("#atan2(1, 1) - @pi/4", "  0."),
+        ("#atan2(0, -1)", "  3.141 592 653 589 793 238 462 64~"),
+        (":degrees; #atan2(1, -1); #atan2(-1, -1); #atan2(-2, 0); :radians", "Angle units set to degrees.\n  135.\n -135.\n -90.\nAngle units set to radians."),
+        ("#atan2(0, 0)", "  0."),
+        ("#atan2([1,1], 1)", "#atan2 needs real values!"),
+        ("#atan2(1)", "#atan2 expects a y and an x!"),
+        // Logarithms in fixed bases
+        ("#log2 8; #log10 1000", "  3.\n  3."),
+        ("#logn(81, 3)", "  4."),
+        (":base G; #log10 3E8; #log2 100; #log 100; :base A", "Base set to Hexadecimal (G).\n  3.\n  8.\n  2.\nBase set to Decimal (A)."),
+        ("#logn(8, 1/2)", " -3."),
+        ("#logn(8, 1)", "#logn base can't be 0 or 1!"),
+        ("#logn(8)", "#logn expects a value and a base!"),
+        // Lambert W
+        ("#lambertw(1)", "  0.567 143 290 409 783 872 999 969~"),
+        ("@w = #lambertw(2); @w*@e^@w", "@w =   0.852 605 502 013 725 491 346 472~\n  2."),
+        ("#lambertw(-0.1, -1)", " -3.577 152 063 957 297 218 409 39~"),
+        ("#lambertw(-1/@e); #lambertw(-1/@e, -1); #lambertw(@e)", " -1.\n -1.\n  1."),
+        ("#lambertw(0, 1)", "#lambertw is infinite at 0 off the principal branch!"),
+        ("#lambertw(2, 0.5)", "#lambertw branch must be a whole number!"),
+        ("#lambertw(1, 2, 3)", "#lambertw expects a value and optionally a branch!"),
+        // Zeta and polygamma
+        ("#zeta 2", "  1.644 934 066 848 226 436 472 42~"),
+        ("#zeta -1", " -8.3 333 333 333 333 333 333 333 3~ :-2"),
+        ("#zeta [2,1]", "[ 1.150 355 703 254 902 671 742 85~ ,-0.437 530 865 919 607 881 117 528~ ]"),
+        ("#zeta [0.5, 100]", "[ 2.692 619 885 681 324 090 476 1~ ,-2.0 386 029 602 598 161 770 726 9~ :-2 ]"),
+        ("#zeta [-2, 1]", "[ 2.9 195 593 243 464 278 758 642 8~ :-2 ,-1.7 787 106 970 842 081 752 693 3~ :-2 ]"),
+        ("#digamma 1 + @gamma", "  0."),
+        ("#digamma [1,1]", "[ 9.4 650 320 622 476 977 271 878 5~ :-2 , 1.076 674 047 468 581 174 134 05~ ]"),
+        ("#polygamma(1, 1) - @pi^2/6", "  0."),
+        ("#polygamma(2, 1)", " -2.404 113 806 319 188 570 799 48~"),
+        ("#polygamma(1, [1,1])", "[ 0.463 000 096 622 763 786 298 327~ ,-0.794 233 542 759 318 865 583 014~ ]"),
+        ("#polygamma(0, 2)", "  0.422 784 335 098 467 139 393 488~"),
+        ("#zeta 1", "Zeta has a pole at 1!"),
+        ("#digamma -2", "Digamma is undefined at non-positive integers!"),
+        ("#polygamma(1, 0)", "Polygamma is undefined at non-positive integers!"),
+        ("#polygamma(0.5, 1)", "#polygamma order must be a whole number!"),
+        ("#polygamma(10^9, 1)", "#polygamma order too large!"),
+        // Error functions
+        ("#erf 1", "  0.842 700 792 949 714 869 341 221~"),
+        ("#erf [1,1]", "[ 1.316 151 281 697 947 644 880 27~ , 0.190 453 469 237 834 686 284 109~ ]"),
+        ("#erfc [-1,1]", "[ 2.316 151 281 697 947 644 880 27~ ,-0.190 453 469 237 834 686 284 109~ ]"),
+        ("#erf [0,3]", "[ 0. , 1 629.994 622 601 565 651 061 65~ ]"),
+        ("#erfc [20, 1]", "[-1.03 018 000 796 986 192 301 893~ :-175 ,-1.04 151 550 732 820 361 960 157~ :-175 ]"),
+        ("#erfc 10", "  2. 088 487 583 762 544 757 000 79~ :-45"),
+        ("#erf 1 + 1", "  1.842 700 792 949 714 869 341 22~"),
+        ("#erfinv 0.5", "  0.476 936 276 204 469 873 381 418~"),
+        ("#erfinv -0.999 999 999 999 999 999 999", " -6.771 891 961 886 579 049 410 49~"),
+        ("#erf #erfinv 0.3", "  0.3"),
+        ("#phi 1.96", "  0.975 002 104 851 779 565 863 416~"),
+        ("#phi -10", "  7. 619 853 024 160 526 065 973 34~ :-24"),
+        ("#erfinv 1", "#erfinv needs a real value between -1 and 1!"),
+        // GCD and LCM
+        ("12 #gcd 18", "  6."),
+        ("12 #lcm 18", "  36."),
+        ("-12 #gcd 18", "  6."),
+        ("12.7 #gcd 18.2", "  6."),
+        ("2 * 3 #gcd 4", "  2."),
+        ("2 #lcm 3 + 1", "  7."),
+        ("0 #gcd 0", "  0."),
+        ("#egcd(240, 46)", "{ 2.  ,-9.  , 47.  }"),
+        ("#egcd(0, 5)", "{ 5.  , 0. , 1.  }"),
+        ("[1,1] #gcd 2", "#gcd needs real values!"),
+        ("#egcd(3)", "#egcd expects two integers!"),
+        // Primes
+        ("#isprime 97", "  1."),
+        ("#isprime 1", "  0."),
+        ("#isprime -7", "  0."),
+        ("#isprime(2^61 - 1)", "  1."),
+        ("#isprime 3 215 031 751", "  0."),
+        ("#isprime 16 412 292 043 871 650 369", "  1."),
+        ("#isprime(7 * 13 * 19 * 37 * 73 * 109 * 127 * 163 * 181 * 271 * 433)", "  0."),
+        ("#nextprime 800 000 000", "  800 000 011."),
+        ("#nextprime 13.9", "  17."),
+        ("#nextprime -5", "  2."),
+        ("#prevprime 100", "  97."),
+        ("#prevprime 3", "  2."),
+        ("#prevprime 2", "There are no primes below 2!"),
+        // Factorization
+        ("#factor(360)", "{[ 2.  , 3.  ] ,[ 3.  , 2.  ] ,[ 5.  , 1.  ] }\n2^3 * 3^2 * 5"),
+        ("#factor(-12)", "{[-1.  , 1.  ] ,[ 2.  , 2.  ] ,[ 3.  , 1.  ] }\n-1 * 2^2 * 3"),
+        ("#factor(1)", "{[ 1.  , 1.  ] }\n1"),
+        ("#factor(600 851 475 143)", "{[ 71.  , 1.  ] ,[ 839.  , 1.  ] ,[ 1 471.  , 1.  ] ,[ 6 857.  , 1.  ] }\n71 * 839 * 1471 * 6857"),
+        ("#factor(999 999 000 001 * 1 000 003)", "{[ 1 000 003.  , 1.  ] ,[ 999 999 000 001.  , 1.  ] }\n1000003 * 999999000001"),
+        ("@f = #factor(1001); #re @f", "@f = {[ 7.  , 1.  ] ,[ 11.  , 1.  ] ,[ 13.  , 1.  ] }\n7 * 11 * 13\n{ 7.  , 11.  , 13.  }"),
+        (":base G; #factor(FF); :base A", "Base set to Hexadecimal (G).\n{[ 3.  , 1.  ] ,[ 5.  , 1.  ] ,[ 11.  , 1.  ] }\n3 * 5 * 11\nBase set to Decimal (A)."),
+        ("#factor(0)", "#factor needs a nonzero integer!"),
+        // Divisor functions
+        ("#totient 36", "  12."),
+        ("#totient 97", "  96."),
+        ("#totient 1", "  1."),
+        ("#totient(10^12)", "  400 000 000 000."),
+        ("#sigma 12", "  28."),
+        ("#sigma 1", "  1."),
+        ("#tau 360", "  24."),
+        ("2#tau 12", "  12."),
+        ("#mobius 30", " -1."),
+        ("#mobius 12", "  0."),
+        ("#mobius 1", "  1."),
+        ("#tau 0", "#tau needs a positive integer!"),
+        ("#sigma -3", "#sigma needs a positive integer!"),
+        // Fibonacci, Lucas and Bernoulli numbers
+        ("#fib 10", "  55."),
+        ("#fib 0", "  0."),
+        ("#fib -8", " -21."),
+        ("#fib -7", "  13."),
+        ("#lucas 10", "  123."),
+        ("#lucas -5", " -11."),
+        ("#lucas -4", "  7."),
+        ("#fib 99 999 999", "#fib index too large!"),
+        ("#bernoulli(0)", "{ 1.  , 1.  }\n1/1"),
+        ("#bernoulli(1)", "{-1.  , 2.  }\n-1/2"),
+        ("#bernoulli(3)", "{ 0. , 1.  }\n0/1"),
+        ("#bernoulli(12)", "{-691.  , 2 730.  }\n-691/2730"),
+        ("#bernoulli(20)", "{-174 611.  , 330.  }\n-174611/330"),
+        ("#bernoulli(50)", "{ 4.95 057 205 241 079 648 212 478~ : 26 , 66.  }\n495057205241079648212477525/66"),
+        ("#bernoulli(60)", "{-1. 215 233 140 483 755 572 040 3~ : 42 , 56 786 730.  }"),
+        ("#bernoulli(-1)", "#bernoulli needs a non-negative index!"),
+        // Bits and digits
+        ("#bitlen 255", "  8."),
+        ("#bitlen 256", "  9."),
+        ("#bitlen 0", "  0."),
+        ("#bitlen -8", "  4."),
+        ("#popcount 255", "  8."),
+        ("#popcount -7", "  3."),
+        ("#digitsum 12 345", "  15."),
+        ("#digitsum(2^100)", "  115."),
+        ("#digitalroot 12 345", "  6."),
+        ("#digitalroot 9", "  9."),
+        ("#digitalroot 0", "  0."),
+        (":base G; #digitsum FF; #digitalroot FF; :base A", "Base set to Hexadecimal (G).\n  1E.\n  F.\nBase set to Decimal (A)."),
+        // Digit manipulation
+        ("#reverse 12 340", "  4 321."),
+        ("#reverse -123", " -321."),
+        ("#ndigits 12 345", "  5."),
+        ("#ndigits 0", "  1."),
+        ("#ndigits -99.9", "  2."),
+        ("12 345 #rotl 1", "  23 451."),
+        ("12 345 #rotr 2", "  45 123."),
+        ("12 345 #rotl -1", "  51 234."),
+        ("12 345 #rotl 12", "  34 512."),
+        ("-12 345 #rotr 1", " -51 234."),
+        ("1 + 12 345 #rotl 1", "  23 461."),
+        (":base 2; 1011 #rotl 1; #reverse 1011; :base A", "Base set to Binary (2).\n  111.\n  1 101.\nBase set to Decimal (A)."),
+        (":history max 2; 40; 50; &2; &3", "Keeping the last 2 history entries, 0 so far.\n  40.\n  50.\n  40.\nOnly 2 results kept!"),
+        (":history max 1000", "Keeping the last 1000 history entries, 0 so far."),
+    ];
+    let mut passed = 0;
+    let total = tests.len();
+    for (input, expected) in tests {
+        println!("> {}", input);
+
+        // Statements separated by ';' each add a line to the result
+        let mut coloured_result = Vec::new();
+        let mut results = Vec::new();
+        for (_, statement) in split_statements(input) {
+            let (coloured_statement, result, failed) = match tokenize(statement, &mut state) {
+                Ok(tokens) => match (interval_view(&tokens, &mut state), evaluate_statement(&tokens, &mut state)) {
+                    (interval, Ok(result)) => {
+                        let coloured_vec = result_view(&tokens, &result, interval, &state);
+                        state.record(result);
+                        (coloured_vec.clone(), coloured_vec_to_string(&coloured_vec), false)
+                    }
+                    (_, Err(err)) => (vec![err.red()], err, true),
+                },
+                Err((msg, pos)) => (
+                    vec![msg.truecolor(
+                        state.colours.message.0,
+                        state.colours.message.1,
+                        state.colours.message.2,
+                    )],
+                    msg,
+                    pos != usize::MAX,
+                ),
+            };
+            if !coloured_result.is_empty() {
+                coloured_result.push("\n".normal());
+            }
+            coloured_result.extend(coloured_statement);
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        let result = results.join("\n");
+
+        for coloured_string in &coloured_result {
+            print!("{}", coloured_string);
+        }
+        println!();
+
+        if result == expected {
+            println!("{}", "Pass!".green());
+            passed += 1;
+        } else {
+            println!("{}", "fail!".red());
+            println!("Sposta: '{}'", expected);
+            println!("Gots  : '{}'", result);
+        }
+
+        println!();
+    }
+    (passed, total)
+}
+/// A list result as CSV for `:export csv`, one element a row
+///
+/// Numbers are written as `:copy plain` gives them, in the current base, which the
+/// header names when it isn't ten. A second column holds the imaginary parts if
+/// any element has one, so `#factor` pairs come out as primes and exponents.
+fn list_csv(elements: &[Complex], state: &BasecalcState) -> String {
+    let part = |value: &Float| {
+        copy_text(&Complex::with_val(state.precision, (value, 0)), false, true, state)
+    };
+    let base = match state.radix {
+        Some(radix) => format!(" (base {})", radix.name()),
+        None if state.base == 10 && !state.balanced => String::new(),
+        None => format!(" (base {})", state.base),
+    };
+    let complex = elements.iter().any(|element| !element.imag().is_zero());
+    let mut csv = if complex {
+        format!("real{},imaginary{}\n", base, base)
+    } else {
+        format!("value{}\n", base)
+    };
+    for element in elements {
+        if complex {
+            csv.push_str(&format!("{},{}\n", part(element.real()), part(element.imag())));
+        } else {
+            csv.push_str(&format!("{}\n", part(element.real())));
+        }
+    }
+    csv
+}
+/// The session as a Markdown document for `:export md`
+///
+/// Lines go in code blocks as they appeared at the prompt, under a heading naming
+/// the base whenever it changes, so digits can't be mistaken for another base's.
+fn session_markdown(session: &[SessionLine]) -> String {
+    let mut markdown = format!(
+        "# basecalc session\n\n{}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    );
+    let mut base = None;
+    for line in session {
+        if base != Some(&line.base) {
+            if base.is_some() {
+                markdown.push_str("```\n");
+            }
+            markdown.push_str(&format!("\n## {}\n\n```\n", line.base));
+            base = Some(&line.base);
+        }
+        markdown.push_str(&format!("> {}\n{}", line.input, line.output));
+    }
+    markdown.push_str("```\n");
+    markdown
+}
+/// A number as LaTeX for `:latex`, in the current base with the base as a subscript
+/// unless it's ten, and a complex one in basecalc's `[real, imaginary]` brackets
+///
+/// # Returns
+/// * `(String, bool)` - The LaTeX, and whether the number was rounded to fit
+fn latex_number(num: &Complex, state: &BasecalcState) -> (String, bool) {
+    let shown: String = num2string(num, state).iter().map(|part| &**part).collect();
+    let part = |value: &Float| {
+        let text = copy_text(&Complex::with_val(state.precision, (value, 0)), false, true, state);
+        match text.split_once(':') {
+            _ if text == "NaN" => "\\text{NaN}".to_string(),
+            Some((mantissa, exponent)) => format!("{} \\times 10^{{{}}}", mantissa, exponent),
+            None => text,
+        }
+    };
+    let mut latex = if num.imag().is_zero() {
+        part(num.real())
+    } else {
+        format!("\\left[{},\\ {}\\right]", part(num.real()), part(num.imag()))
+    };
+    if state.base != 10 || state.balanced || state.radix.is_some() {
+        let base = match state.radix {
+            Some(radix) => radix.name().replace('@', "").replace("phi", "\\varphi"),
+            None if state.balanced => format!("\\bar{{{}}}", state.base),
+            None => state.base.to_string(),
+        };
+        if latex.contains("\\times") && !latex.starts_with("\\left[") {
+            latex = format!("\\left({}\\right)", latex);
+        }
+        latex = format!("{}_{{{}}}", latex, base);
+    }
+    (latex, shown.contains('~'))
+}
+/// An entered expression as LaTeX for `:latex input`
+///
+/// Only the notation is translated, `*` to a dot, `^` to a raised group, functions
+/// and constants to their usual symbols, and the numbers are left as typed.
+fn latex_expression(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut latex = String::new();
+    let mut i = 0;
+    // The text of a bracketed group starting at `start`, and where it ends
+    let group = |start: usize| -> (String, usize) {
+        let mut depth = 0;
+        for (j, &c) in chars.iter().enumerate().skip(start) {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return (chars[start + 1..j].iter().collect(), j + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (chars[(start + 1).min(chars.len())..].iter().collect(), chars.len())
+    };
+    let word = |start: usize| -> (String, usize) {
+        let end = (start..chars.len())
+            .find(|&j| !(chars[j].is_alphanumeric() || chars[j] == '_'))
+            .unwrap_or(chars.len());
+        (chars[start..end].iter().collect(), end)
+    };
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                latex.push_str(" \\cdot ");
+                i += 1;
+            }
+            '^' => {
+                let mut start = i + 1;
+                while chars.get(start) == Some(&' ') {
+                    start += 1;
+                }
+                let (exponent, end) = match chars.get(start) {
+                    Some('(') => group(start),
+                    Some('-') => {
+                        let (rest, end) = word(start + 1);
+                        (format!("-{}", rest), end)
+                    }
+                    _ => word(start),
+                };
+                latex.push_str(&format!("^{{{}}}", latex_expression(&exponent)));
+                i = end;
+            }
+            '#' => {
+                let (name, end) = word(i + 1);
+                let name = name.to_ascii_lowercase();
+                let argument = (chars.get(end) == Some(&'(')).then(|| group(end));
+                match (name.as_str(), argument) {
+                    ("sqrt", Some((inner, end))) => {
+                        latex.push_str(&format!("\\sqrt{{{}}}", latex_expression(&inner)));
+                        i = end;
+                    }
+                    ("abs", Some((inner, end))) => {
+                        latex.push_str(&format!("\\left|{}\\right|", latex_expression(&inner)));
+                        i = end;
+                    }
+                    (name, _) => {
+                        let known = [
+                            "sin", "cos", "tan", "sinh", "cosh", "tanh", "arcsin", "arccos",
+                            "arctan", "ln", "log", "exp", "gcd", "min", "max",
+                        ];
+                        if known.contains(&name) {
+                            latex.push_str(&format!("\\{} ", name));
+                        } else {
+                            latex.push_str(&format!("\\operatorname{{{}}}", name));
+                        }
+                        i = end;
+                    }
+                }
+            }
+            '@' => {
+                let (name, end) = word(i + 1);
+                latex.push_str(&match name.to_ascii_lowercase().as_str() {
+                    "pi" => "\\pi ".to_string(),
+                    "tau" => "\\tau ".to_string(),
+                    "phi" => "\\varphi ".to_string(),
+                    "e" => "e".to_string(),
+                    _ if name.chars().count() == 1 => name,
+                    _ => format!("\\mathit{{{}}}", name),
+                });
+                i = end;
+            }
+            '[' => {
+                latex.push_str("\\left[");
+                i += 1;
+            }
+            ']' => {
+                latex.push_str("\\right]");
+                i += 1;
+            }
+            '(' => {
+                latex.push_str("\\left(");
+                i += 1;
+            }
+            ')' => {
+                latex.push_str("\\right)");
+                i += 1;
+            }
+            c => {
+                latex.push(c);
+                i += 1;
+            }
+        }
+    }
+    latex.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+/// Clipboard kept open for the whole session, as on X11 and Wayland the copied
+/// text is served by its owner and would vanish as soon as it was dropped
+static CLIPBOARD: std::sync::Mutex<Option<arboard::Clipboard>> = std::sync::Mutex::new(None);
+
+/// Places text on the system clipboard for `:copy`
+fn copy_to_clipboard(text: &str) -> Result<(), String> {
+    let mut clipboard = CLIPBOARD.lock().map_err(|e| e.to_string())?;
+    if clipboard.is_none() {
+        *clipboard = Some(arboard::Clipboard::new().map_err(|e| e.to_string())?);
+    }
+    clipboard
+        .as_mut()
+        .expect("clipboard was just opened")
+        .set_text(text)
+        .map_err(|e| e.to_string())
+}
+
+/// Writes a number as `:copy` puts it on the clipboard, without colour
+///
+/// `hex` writes it in hexadecimal to the same precision, and `plain` leaves out
+/// the digit grouping, approximation mark and trailing point so it pastes
+/// cleanly into other programs.
+fn copy_text(num: &Complex, hex: bool, plain: bool, state: &BasecalcState) -> String {
+    let mut local_state = state.clone();
+    if hex {
+        local_state.base = 16;
+        local_state.balanced = false;
+        local_state.radix = None;
+        local_state.digits = (state.digits as f64 * (state.base as f64).ln() / 16f64.ln())
+            .round()
+            .max(1.0) as usize;
+        local_state.set_precision();
+    }
+    if plain {
+        local_state.grouping.size = 0;
+    }
+    let text: String = num2string(num, &local_state).iter().map(|part| &**part).collect();
+    if !plain {
+        return text.trim().to_string();
+    }
+    let chars: Vec<char> = text.chars().filter(|&c| c != '~').collect();
+    let mut plain_text = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let next = chars[i + 1..].iter().find(|c| **c != ' ');
+        let bare_point = c == '.' && !chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+        let padding = c == ' '
+            && (plain_text.is_empty()
+                || plain_text.ends_with([' ', '[', ':'])
+                || next.is_none_or(|&c| ", ;:]".contains(c)));
+        if !bare_point && !padding {
+            plain_text.push(c);
+        }
+    }
+    plain_text
+}
+
+pub fn coloured_vec_to_string(coloured_vec: &Vec<ColoredString>) -> String {
+    let mut result = String::new();
+    for coloured_string in coloured_vec {
+        for c in coloured_string.chars() {
+            if c.is_ascii() {
+                result.push(c);
+            }
+        }
+    }
+    result.trim_end().to_owned()
+}